@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{runtime::ExecutionContext, Book, Function, Page};
+use crate::runtime::ContainerSource;
+
+/// Version of the bundle format, bundles of newer ones are refused.
+pub(crate) const BUNDLE_VERSION: u32 = 1;
+/// Where the assets of the loaded bundles are extracted.
+pub(crate) const ASSETS_DIR: &str = "~/.config/robopages/state/assets";
+// assets are referenced as asset:<sha256>/<file name>
+const ASSET_PREFIX: &str = "asset:";
+
+/// A book compiled to a single YAML or JSON file, with the files referenced by its pages inlined.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Bundle {
+    pub robopages_bundle: u32,
+    pub robopages_version: String,
+    /// Pages by path relative to the root of the book, with their names, categories and
+    /// namespaces resolved.
+    pub pages: BTreeMap<String, Page>,
+    /// Base64 encoded contents of the referenced files, by SHA256.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub assets: BTreeMap<String, String>,
+}
+
+// the files referenced by the function: wasm modules, Dockerfiles and uploads that don't depend
+// on the arguments
+fn asset_paths(function: &mut Function) -> Vec<&mut String> {
+    let mut paths = Vec::new();
+    if let ExecutionContext::Wasm(wasm) = &mut function.execution {
+        paths.push(&mut wasm.module);
+    }
+    if let Some(container) = &mut function.container {
+        if let ContainerSource::Build(build) = &mut container.source {
+            paths.push(&mut build.path);
+        }
+    }
+    for transfer in &mut function.files.upload {
+        if !transfer.local.contains("${") {
+            paths.push(&mut transfer.local);
+        }
+    }
+    paths
+}
+
+// files of the build context are not bundled, only the Dockerfile is
+fn copies_files(dockerfile: &str) -> bool {
+    dockerfile.lines().any(|line| {
+        let line = line.trim_start().to_uppercase();
+        line.starts_with("COPY ") || line.starts_with("ADD ")
+    })
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+impl Bundle {
+    /// Bundle the pages of the book loaded from the path.
+    pub fn new(path: &Utf8Path, book: &Book) -> anyhow::Result<Self> {
+        let root = Utf8PathBuf::from(
+            shellexpand::full(path.as_str())
+                .map_err(|e| anyhow::anyhow!("failed to expand path: {}", e))?
+                .into_owned(),
+        )
+        .canonicalize_utf8()
+        .map_err(|e| anyhow::anyhow!("failed to canonicalize path: {}", e))?;
+        let root = if root.is_file() {
+            root.parent().unwrap_or(&root).to_path_buf()
+        } else {
+            root
+        };
+
+        let mut pages = BTreeMap::new();
+        let mut assets = BTreeMap::new();
+        for (page_path, page) in &book.pages {
+            let page_dir = page_path.parent().unwrap_or(&root);
+            let mut page = page.clone();
+
+            for (name, function) in page.functions.iter_mut() {
+                if let Some(container) = &mut function.container {
+                    if let ContainerSource::Build(build) = &mut container.source {
+                        let dockerfile =
+                            std::fs::read_to_string(page_dir.join(&build.path)).unwrap_or_default();
+                        if copies_files(&dockerfile) {
+                            log::warn!(
+                                "the build context of {} is not bundled, build its image on the hosts of the bundle",
+                                name
+                            );
+                        }
+                        // the extracted Dockerfile is built in its own directory
+                        build.context = None;
+                    }
+                }
+
+                for asset in asset_paths(function) {
+                    let file = page_dir.join(&*asset);
+                    let data = std::fs::read(&file).map_err(|e| {
+                        anyhow::anyhow!("error while reading {} of {}: {}", file, name, e)
+                    })?;
+                    let hash = sha256(&data);
+                    *asset = format!(
+                        "{}{}/{}",
+                        ASSET_PREFIX,
+                        &hash,
+                        file.file_name().unwrap_or("asset")
+                    );
+                    assets.insert(hash, STANDARD.encode(&data));
+                }
+            }
+
+            let relative = page_path
+                .strip_prefix(&root)
+                .map(|relative| relative.to_string())
+                .unwrap_or_else(|_| page_path.to_string());
+            pages.insert(relative, page);
+        }
+
+        Ok(Self {
+            robopages_bundle: BUNDLE_VERSION,
+            robopages_version: env!("CARGO_PKG_VERSION").to_string(),
+            pages,
+            assets,
+        })
+    }
+
+    /// Returns the bundle in the file, or None if it's not one.
+    pub fn from_path(path: &Utf8Path) -> anyhow::Result<Option<Self>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("error while reading {}: {}", path, e))?;
+        // errors of regular pages are reported when parsing them
+        let Ok(value) = serde_yaml::from_str::<serde_yaml::Value>(&text) else {
+            return Ok(None);
+        };
+        if value.get("robopages_bundle").is_none() {
+            return Ok(None);
+        }
+
+        let bundle: Self = serde_yaml::from_value(value)
+            .map_err(|e| anyhow::anyhow!("error while parsing bundle {}: {}", path, e))?;
+        if bundle.robopages_bundle > BUNDLE_VERSION {
+            return Err(anyhow::anyhow!(
+                "bundle {} was created by robopages {} with an unsupported format, upgrade to load it",
+                path,
+                bundle.robopages_version
+            ));
+        }
+        Ok(Some(bundle))
+    }
+
+    /// Extract the assets to the directory, returns the pages referencing the extracted files.
+    pub fn into_pages(self, assets_dir: &Utf8Path) -> anyhow::Result<BTreeMap<String, Page>> {
+        let mut pages = self.pages;
+        for page in pages.values_mut() {
+            for function in page.functions.values_mut() {
+                for asset in asset_paths(function) {
+                    let Some(reference) = asset.strip_prefix(ASSET_PREFIX) else {
+                        continue;
+                    };
+                    let (hash, name) = reference
+                        .split_once('/')
+                        .filter(|(hash, name)| {
+                            hash.len() == 64
+                                && hash.chars().all(|c| c.is_ascii_hexdigit())
+                                && !name.contains(['/', '\\'])
+                                && *name != ".."
+                        })
+                        .ok_or_else(|| anyhow::anyhow!("invalid asset reference {}", asset))?;
+
+                    let path = assets_dir.join(hash).join(name);
+                    if !path.exists() {
+                        let data = self
+                            .assets
+                            .get(hash)
+                            .ok_or_else(|| anyhow::anyhow!("asset {} not found in bundle", hash))?;
+                        let data = STANDARD
+                            .decode(data)
+                            .map_err(|e| anyhow::anyhow!("invalid asset {}: {}", hash, e))?;
+                        if sha256(&data) != hash {
+                            return Err(anyhow::anyhow!("asset {} does not match its hash", hash));
+                        }
+
+                        std::fs::create_dir_all(assets_dir.join(hash))?;
+                        std::fs::write(&path, data)
+                            .map_err(|e| anyhow::anyhow!("error while writing {}: {}", path, e))?;
+                    }
+                    *asset = path.to_string();
+                }
+            }
+        }
+        Ok(pages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let root = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!("robopages-bundle-{}", fastrand::u64(..)));
+        std::fs::create_dir_all(root.join("net")).unwrap();
+        std::fs::write(root.join("net/probe.wasm"), b"\0asm").unwrap();
+        std::fs::write(
+            root.join("net/probe.yml"),
+            r#"
+functions:
+  probe:
+    description: Probe a host.
+    parameters:
+      host:
+        type: string
+        description: The host.
+    wasm:
+      module: probe.wasm
+      args: ["${host}"]
+"#,
+        )
+        .unwrap();
+
+        let book = Book::from_path(root.clone(), None).unwrap();
+        let bundle = Bundle::new(&root, &book).unwrap();
+        let page = &bundle.pages["net/probe.yml"];
+        assert_eq!(page.categories, vec!["net"]);
+        let hash = sha256(b"\0asm");
+        assert!(bundle.assets.contains_key(&hash));
+
+        let path = root.join("book.bundle.yml");
+        std::fs::write(&path, serde_yaml::to_string(&bundle).unwrap()).unwrap();
+        assert!(Bundle::from_path(&root.join("net/probe.yml"))
+            .unwrap()
+            .is_none());
+
+        let bundle = Bundle::from_path(&path).unwrap().unwrap();
+        let pages = bundle.into_pages(&root.join("assets")).unwrap();
+        let ExecutionContext::Wasm(wasm) = &pages["net/probe.yml"].functions["probe"].execution
+        else {
+            panic!("not a wasm function");
+        };
+        assert_eq!(
+            wasm.module,
+            root.join("assets").join(&hash).join("probe.wasm").as_str()
+        );
+        assert_eq!(std::fs::read(&wasm.module).unwrap(), b"\0asm");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_copies_files() {
+        assert!(copies_files("FROM alpine\ncopy . /app\n"));
+        assert!(!copies_files("FROM alpine\nRUN apk add nmap\n"));
+    }
+}
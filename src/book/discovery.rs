@@ -0,0 +1,200 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use super::{
+    package::PACKAGE_MANIFEST,
+    trace::{LoadTrace, SkipReason},
+};
+
+/// Controls how pages are discovered in a directory.
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    /// Descend into symlinked directories, loops are detected and skipped.
+    pub follow_symlinks: bool,
+    /// Maximum number of directory levels to descend below the base path.
+    pub max_depth: Option<usize>,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self {
+            follow_symlinks: true,
+            max_depth: None,
+        }
+    }
+}
+
+impl Discovery {
+    /// Returns the .yml files in the directory, recording in the trace the ones skipped and why.
+    pub fn find_pages(
+        &self,
+        root: &Utf8Path,
+        filter: Option<&str>,
+        trace: &mut LoadTrace,
+    ) -> anyhow::Result<Vec<Utf8PathBuf>> {
+        let mut visited = HashSet::new();
+        visited.insert(root.canonicalize()?);
+
+        let mut pages = Vec::new();
+        self.walk(root, 0, filter, &mut visited, &mut pages, trace)?;
+        Ok(pages)
+    }
+
+    fn walk(
+        &self,
+        dir: &Utf8Path,
+        depth: usize,
+        filter: Option<&str>,
+        visited: &mut HashSet<PathBuf>,
+        pages: &mut Vec<Utf8PathBuf>,
+        trace: &mut LoadTrace,
+    ) -> anyhow::Result<()> {
+        let mut entries = dir
+            .read_dir_utf8()
+            .map_err(|e| anyhow::anyhow!("failed to read {:?}: {}", dir, e))?
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+
+        for entry in entries {
+            let path = entry.path().to_path_buf();
+
+            // skip hidden files and directories (starting with .)
+            if entry.file_name().starts_with('.') {
+                log::debug!("skipping hidden file/directory {:?}", path);
+                trace.skip(path, SkipReason::Hidden);
+                continue;
+            }
+
+            if path.is_dir() {
+                if !self.follow_symlinks && entry.file_type()?.is_symlink() {
+                    trace.skip(path, SkipReason::Symlink);
+                    continue;
+                }
+
+                // only symlinks can lead to the same directory twice
+                if !visited.insert(path.canonicalize()?) {
+                    log::warn!("skipping already visited directory {:?}", path);
+                    trace.skip(path, SkipReason::SymlinkLoop);
+                    continue;
+                }
+
+                if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                    trace.skip(path, SkipReason::Depth);
+                    continue;
+                }
+
+                self.walk(&path, depth + 1, filter, visited, pages, trace)?;
+            } else if path.extension() != Some("yml") {
+                trace.skip(path, SkipReason::Extension);
+            } else if entry.file_name() == PACKAGE_MANIFEST {
+                trace.skip(path, SkipReason::Manifest);
+            } else if filter.is_some_and(|filter| !path.as_str().contains(filter)) {
+                trace.skip(path, SkipReason::Filter);
+            } else {
+                log::debug!("found page {:?}", path);
+                pages.push(path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("a/b/c")).unwrap();
+        std::fs::create_dir_all(root.join(".hidden")).unwrap();
+        for page in [
+            "root.yml",
+            "a/a.yml",
+            "a/b/b.yml",
+            "a/b/c/c.yml",
+            ".hidden/h.yml",
+        ] {
+            std::fs::write(root.join(page), "functions: {}").unwrap();
+        }
+        std::fs::write(root.join("a/README.md"), "").unwrap();
+        std::fs::write(root.join(PACKAGE_MANIFEST), "name: book").unwrap();
+        dir
+    }
+
+    fn names(pages: &[Utf8PathBuf]) -> Vec<&str> {
+        pages.iter().map(|p| p.file_name().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_find_pages() {
+        let dir = create_tree();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+        let mut trace = LoadTrace::default();
+
+        let pages = Discovery::default()
+            .find_pages(root, None, &mut trace)
+            .unwrap();
+        assert_eq!(names(&pages), vec!["a.yml", "b.yml", "c.yml", "root.yml"]);
+        assert!(trace
+            .skipped
+            .iter()
+            .any(|(p, r)| p.ends_with(".hidden") && *r == SkipReason::Hidden));
+        assert!(trace
+            .skipped
+            .iter()
+            .any(|(p, r)| p.ends_with("README.md") && *r == SkipReason::Extension));
+        assert!(trace
+            .skipped
+            .iter()
+            .any(|(p, r)| p.ends_with(PACKAGE_MANIFEST) && *r == SkipReason::Manifest));
+    }
+
+    #[test]
+    fn test_find_pages_max_depth() {
+        let dir = create_tree();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+
+        let discovery = Discovery {
+            follow_symlinks: true,
+            max_depth: Some(1),
+        };
+        let pages = discovery
+            .find_pages(root, None, &mut LoadTrace::default())
+            .unwrap();
+        assert_eq!(names(&pages), vec!["a.yml", "root.yml"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_pages_symlinks() {
+        let dir = create_tree();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+        // a loop back to the root
+        std::os::unix::fs::symlink(root, root.join("a/loop")).unwrap();
+
+        let mut trace = LoadTrace::default();
+        let pages = Discovery::default()
+            .find_pages(root, None, &mut trace)
+            .unwrap();
+        assert_eq!(pages.len(), 4);
+        assert!(trace
+            .skipped
+            .iter()
+            .any(|(p, r)| p.ends_with("loop") && *r == SkipReason::SymlinkLoop));
+
+        let discovery = Discovery {
+            follow_symlinks: false,
+            max_depth: None,
+        };
+        let mut trace = LoadTrace::default();
+        let pages = discovery.find_pages(root, None, &mut trace).unwrap();
+        assert_eq!(pages.len(), 4);
+        assert!(trace
+            .skipped
+            .iter()
+            .any(|(p, r)| p.ends_with("loop") && *r == SkipReason::Symlink));
+    }
+}
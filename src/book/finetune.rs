@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{flavors::openai, Function};
+
+/// A training sample in the chat format of fine-tuning APIs: the schema of the function, a
+/// request of the user and the call the model is expected to make.
+#[derive(Debug, Serialize)]
+pub(crate) struct Sample {
+    pub messages: Vec<Value>,
+    pub tools: Vec<openai::Tool>,
+}
+
+// the first sentence of the description, as a task to ask for
+fn task(description: &str) -> String {
+    let sentence = description
+        .split(". ")
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_end_matches('.');
+
+    // keep acronyms as they are
+    let mut chars = sentence.chars();
+    match (chars.next(), chars.next()) {
+        (Some(first), Some(second)) if !second.is_uppercase() => first
+            .to_lowercase()
+            .chain(sentence.chars().skip(1))
+            .collect(),
+        _ => sentence.to_string(),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(value_text).collect::<Vec<_>>().join(", "),
+        value => value.to_string(),
+    }
+}
+
+/// A request of the user for the call, phrased differently for each variant.
+fn request(function: &Function, arguments: &BTreeMap<String, Value>, variant: usize) -> String {
+    let task = task(&function.description);
+    let mut values: Vec<String> = arguments
+        .iter()
+        .map(|(name, value)| format!("{} {}", name.replace('_', " "), value_text(value)))
+        .collect();
+    let values = match values.pop() {
+        Some(last) if values.is_empty() => last,
+        Some(last) => format!("{} and {}", values.join(", "), last),
+        None => String::new(),
+    };
+
+    match (variant % 3, values.is_empty()) {
+        (0, true) => format!("{}.", capitalize(&task)),
+        (0, false) => format!("{} with {}.", capitalize(&task), values),
+        (1, true) => format!("Can you {}?", task),
+        (1, false) => format!("Can you {} for {}?", task, values),
+        (_, true) => format!("I need to {}.", task),
+        (_, false) => format!("I need to {}, use {}.", task, values),
+    }
+}
+
+// arguments made of the examples of each parameter, the first choice of the required ones
+// without examples, or none if a required one has neither
+fn parameter_examples(function: &Function) -> Vec<BTreeMap<String, Value>> {
+    let count = function
+        .parameters
+        .values()
+        .filter_map(|param| param.examples.as_ref().map(Vec::len))
+        .max()
+        .unwrap_or(0);
+
+    (0..count)
+        .filter_map(|index| {
+            let mut arguments = BTreeMap::new();
+            for (name, param) in &function.parameters {
+                let value = match param.examples.as_ref().filter(|e| !e.is_empty()) {
+                    Some(examples) => param
+                        .constraints
+                        .schema_value(&param.param_type, &examples[index % examples.len()]),
+                    None if param.required => param.constraints.choices.first()?.clone(),
+                    None => continue,
+                };
+                arguments.insert(name.clone(), value);
+            }
+            Some(arguments)
+        })
+        .collect()
+}
+
+/// The samples of the function, one for each distinct set of arguments of its examples, of its
+/// tests and of the examples of its parameters.
+pub(crate) fn samples(name: &str, function: &Function) -> Vec<Sample> {
+    let mut seen = BTreeSet::new();
+    let arguments: Vec<BTreeMap<String, Value>> = function
+        .examples
+        .iter()
+        .flatten()
+        .cloned()
+        .chain(function.tests.iter().map(|test| test.arguments.clone()))
+        .chain(parameter_examples(function))
+        .filter(|arguments| seen.insert(serde_json::to_string(arguments).unwrap_or_default()))
+        .collect();
+
+    arguments
+        .iter()
+        .enumerate()
+        .map(|(index, arguments)| Sample {
+            messages: vec![
+                serde_json::json!({
+                    "role": "user",
+                    "content": request(function, arguments, index),
+                }),
+                serde_json::json!({
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": format!("call_{}", index),
+                        "type": "function",
+                        "function": {
+                            "name": name,
+                            "arguments": serde_json::to_string(arguments).unwrap_or_default(),
+                        },
+                    }],
+                }),
+            ],
+            tools: vec![openai::Tool {
+                tool_type: "function".to_string(),
+                function: openai::Function {
+                    name: name.to_string(),
+                    description: function.description_with_examples(),
+                    parameters: function.into(),
+                },
+            }],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: Scan the TCP ports of a host. Requires root.
+parameters:
+  target:
+    type: target
+    description: The host.
+    examples: [10.0.0.1, scanme.nmap.org]
+  ports:
+    type: array
+    items: integer
+    description: The ports.
+    required: false
+examples:
+  - target: 10.0.0.1
+    ports: [22, 80]
+cmdline: [nmap, "${target}"]
+tests:
+  - arguments: {target: 10.0.0.1, ports: [22, 80]}
+"#,
+        )
+        .unwrap();
+
+        let samples = samples("nmap_scan", &function);
+        // the test duplicates the example
+        assert_eq!(samples.len(), 3);
+
+        assert_eq!(
+            samples[0].messages[0]["content"],
+            "Scan the TCP ports of a host with ports 22, 80 and target 10.0.0.1."
+        );
+        assert_eq!(
+            samples[0].messages[1]["tool_calls"][0]["function"]["arguments"],
+            r#"{"ports":[22,80],"target":"10.0.0.1"}"#
+        );
+        assert_eq!(
+            samples[2].messages[0]["content"],
+            "I need to scan the TCP ports of a host, use target scanme.nmap.org."
+        );
+        assert_eq!(samples[2].tools[0].function.name, "nmap_scan");
+    }
+
+    #[test]
+    fn test_task() {
+        assert_eq!(task("Scan ports. Requires root."), "scan ports");
+        assert_eq!(task("HTTP probe of a host"), "HTTP probe of a host");
+    }
+}
@@ -0,0 +1,27 @@
+use serde::Serialize;
+
+use crate::book::Page;
+
+use super::openai::Parameters;
+
+// https://docs.anthropic.com/en/docs/build-with-claude/tool-use
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Tool {
+    pub name: String,
+    pub description: String,
+    pub input_schema: Parameters,
+}
+
+impl From<&Page> for Vec<Tool> {
+    fn from(page: &Page) -> Self {
+        page.functions
+            .iter()
+            .map(|(func_name, func)| Tool {
+                name: func_name.clone(),
+                description: func.description_with_examples(),
+                input_schema: func.into(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,131 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::book::Page;
+
+use super::openai::Parameters;
+
+// https://ai.google.dev/gemini-api/docs/function-calling
+
+#[derive(Debug, Serialize)]
+pub(crate) struct FunctionDeclaration {
+    pub name: String,
+    pub description: String,
+    pub parameters: Parameters,
+}
+
+fn stringify(value: &mut Value) {
+    match value {
+        Value::String(_) => {}
+        Value::Array(items) => items.iter_mut().for_each(stringify),
+        value => *value = Value::String(value.to_string()),
+    }
+}
+
+// gemini only accepts enums of strings, the values of other types are passed as strings and
+// coerced back when the function is called, returns true if the values are now strings
+fn stringify_enum(param_type: &mut String, keywords: &mut serde_json::Map<String, Value>) -> bool {
+    let Some(Value::Array(choices)) = keywords.get_mut("enum") else {
+        return false;
+    };
+    if param_type == "string" {
+        return false;
+    }
+
+    choices.iter_mut().for_each(stringify);
+    *param_type = "string".to_string();
+    keywords.remove("minimum");
+    keywords.remove("maximum");
+    true
+}
+
+impl From<&Page> for Vec<FunctionDeclaration> {
+    fn from(page: &Page) -> Self {
+        page.functions
+            .iter()
+            .map(|(func_name, func)| {
+                let mut parameters: Parameters = func.into();
+                for param in parameters.properties.values_mut() {
+                    let mut stringified =
+                        stringify_enum(&mut param.param_type, &mut param.keywords);
+                    if let Some(Value::Object(items)) = param.keywords.get_mut("items") {
+                        let mut item_type = items
+                            .get("type")
+                            .and_then(Value::as_str)
+                            .unwrap_or("string")
+                            .to_string();
+                        stringified |= stringify_enum(&mut item_type, items);
+                        items.insert("type".to_string(), item_type.into());
+                    }
+                    // and so are the default value and the examples
+                    if stringified {
+                        for keyword in ["default", "examples"] {
+                            if let Some(value) = param.keywords.get_mut(keyword) {
+                                stringify(value);
+                            }
+                        }
+                    }
+                    // the schema of gemini has a single example
+                    if let Some(Value::Array(examples)) = param.keywords.remove("examples") {
+                        if let Some(example) = examples.into_iter().next() {
+                            param.keywords.insert("example".to_string(), example);
+                        }
+                    }
+                }
+
+                FunctionDeclaration {
+                    name: func_name.clone(),
+                    description: func.description_with_examples(),
+                    parameters,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enums_of_strings() {
+        let page: Page = serde_yaml::from_str(
+            r#"
+functions:
+  scan:
+    description: Scan a port.
+    parameters:
+      port:
+        type: integer
+        description: The port.
+        choices: [22, 80, 443]
+      protocol:
+        type: enum
+        description: The protocol.
+        choices: [tcp, udp]
+      ports:
+        type: array
+        items: integer
+        description: More ports.
+        choices: [8080, 8443]
+    cmdline: [scan, "${port}"]
+"#,
+        )
+        .unwrap();
+
+        let declarations: Vec<FunctionDeclaration> = (&page).into();
+        let properties = serde_json::to_value(&declarations[0].parameters.properties).unwrap();
+        assert_eq!(
+            properties["port"],
+            serde_json::json!({"type": "string", "description": "The port.", "enum": ["22", "80", "443"]})
+        );
+        assert_eq!(
+            properties["protocol"],
+            serde_json::json!({"type": "string", "description": "The protocol.", "enum": ["tcp", "udp"]})
+        );
+        assert_eq!(
+            properties["ports"]["items"],
+            serde_json::json!({"type": "string", "enum": ["8080", "8443"]})
+        );
+    }
+}
@@ -1,22 +1,73 @@
 use std::collections::HashMap;
 
+use serde::Serialize;
+
+use super::Page;
+
+pub(crate) mod anthropic;
+pub(crate) mod gemini;
 pub(crate) mod nerve;
 pub(crate) mod openai;
 pub(crate) mod rigging;
 
-#[derive(Default, Debug)]
+// rough average for JSON with the most common tokenizers
+pub(crate) const BYTES_PER_TOKEN: usize = 4;
+
+/// Size of a serialized tool listing, as it would be sent to a model.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub(crate) struct ListingSize {
+    pub bytes: usize,
+    /// Approximate number of tokens.
+    pub tokens: usize,
+}
+
+fn tools_of<'a, T: Serialize + 'a>(
+    pages: impl Iterator<Item = &'a Page>,
+) -> anyhow::Result<serde_json::Value>
+where
+    Vec<T>: From<&'a Page>,
+{
+    let tools: Vec<T> = pages.flat_map(Vec::<T>::from).collect();
+    Ok(serde_json::to_value(tools)?)
+}
+
+#[derive(Default, Debug, Clone, Copy)]
 pub(crate) enum Flavor {
     #[default]
     OpenAI,
+    Anthropic,
+    Gemini,
     Nerve,
     Rigging,
 }
 
 #[allow(dead_code)]
 impl Flavor {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Flavor::OpenAI,
+            Flavor::Anthropic,
+            Flavor::Gemini,
+            Flavor::Nerve,
+            Flavor::Rigging,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Flavor::OpenAI => "openai",
+            Flavor::Anthropic => "anthropic",
+            Flavor::Gemini => "gemini",
+            Flavor::Nerve => "nerve",
+            Flavor::Rigging => "rigging",
+        }
+    }
+
     pub fn from_string(s: &str) -> anyhow::Result<Self> {
         match s.to_lowercase().as_str() {
             "openai" => Ok(Flavor::OpenAI),
+            "anthropic" => Ok(Flavor::Anthropic),
+            "gemini" => Ok(Flavor::Gemini),
             "nerve" => Ok(Flavor::Nerve),
             "rigging" => Ok(Flavor::Rigging),
             _ => Err(anyhow!("unknown flavor: {}", s)),
@@ -24,9 +75,39 @@ impl Flavor {
     }
 
     pub fn from_map_or_default(query: &HashMap<String, String>) -> anyhow::Result<Self> {
+        Self::from_map_or(query, Flavor::default())
+    }
+
+    pub fn from_map_or(query: &HashMap<String, String>, default: Flavor) -> anyhow::Result<Self> {
         query
             .get("flavor")
-            .map_or(Ok(Flavor::default()), |s| Self::from_string(s))
+            .map_or(Ok(default), |s| Self::from_string(s))
+    }
+
+    /// Tool listing of the pages in this flavor.
+    pub fn tools<'a>(
+        &self,
+        pages: impl Iterator<Item = &'a Page>,
+    ) -> anyhow::Result<serde_json::Value> {
+        match self {
+            Flavor::OpenAI => tools_of::<openai::Tool>(pages),
+            Flavor::Anthropic => tools_of::<anthropic::Tool>(pages),
+            Flavor::Gemini => tools_of::<gemini::FunctionDeclaration>(pages),
+            Flavor::Nerve => tools_of::<nerve::FunctionGroup>(pages),
+            Flavor::Rigging => tools_of::<rigging::Tool>(pages),
+        }
+    }
+
+    /// Size of the tool listing of the pages in this flavor.
+    pub fn listing_size<'a>(
+        &self,
+        pages: impl Iterator<Item = &'a Page>,
+    ) -> anyhow::Result<ListingSize> {
+        let bytes = serde_json::to_vec(&self.tools(pages)?)?.len();
+        Ok(ListingSize {
+            bytes,
+            tokens: bytes.div_ceil(BYTES_PER_TOKEN),
+        })
     }
 
     pub fn is_openai(&self) -> bool {
@@ -73,6 +154,16 @@ mod tests {
         assert!(Flavor::from_string("").is_err());
     }
 
+    #[test]
+    fn test_flavor_names_roundtrip() {
+        for flavor in Flavor::all() {
+            assert_eq!(
+                Flavor::from_string(flavor.name()).unwrap().name(),
+                flavor.name()
+            );
+        }
+    }
+
     #[test]
     fn test_flavor_from_map_or_default() {
         let mut map = HashMap::new();
@@ -98,4 +189,47 @@ mod tests {
         map.insert("flavor".to_string(), "".to_string());
         assert!(Flavor::from_map_or_default(&map).is_err());
     }
+
+    #[test]
+    fn test_flavor_from_map_or() {
+        let mut map = HashMap::new();
+        assert!(matches!(
+            Flavor::from_map_or(&map, Flavor::Nerve),
+            Ok(Flavor::Nerve)
+        ));
+
+        // explicit flavor wins over the default
+        map.insert("flavor".to_string(), "rigging".to_string());
+        assert!(matches!(
+            Flavor::from_map_or(&map, Flavor::Nerve),
+            Ok(Flavor::Rigging)
+        ));
+    }
+
+    #[test]
+    fn test_listing_size() {
+        let page: Page = serde_yaml::from_str(
+            r#"
+functions:
+  test_function:
+    description: A test function.
+    parameters:
+      target:
+        type: string
+        description: The target.
+    cmdline: [echo, "${target}"]
+"#,
+        )
+        .unwrap();
+
+        for flavor in Flavor::all() {
+            let empty = flavor.listing_size(std::iter::empty()).unwrap();
+            let one = flavor.listing_size(std::iter::once(&page)).unwrap();
+            let two = flavor.listing_size([&page, &page].into_iter()).unwrap();
+
+            assert!(one.bytes > empty.bytes, "{}", flavor.name());
+            assert!(two.bytes > one.bytes, "{}", flavor.name());
+            assert_eq!(one.tokens, one.bytes.div_ceil(BYTES_PER_TOKEN));
+        }
+    }
 }
@@ -32,15 +32,17 @@ impl From<&Page> for Vec<FunctionGroup> {
 
         for (func_name, func) in &page.functions {
             let mut args = HashMap::new();
+            let defaults = func.parameter_defaults();
             for (param_name, param) in &func.parameters {
-                args.insert(param_name.clone(), param.description.clone());
+                let default = defaults.get(param_name).map(String::as_str);
+                args.insert(param_name.clone(), param.description_with_values(default));
             }
 
             group.actions.push(Action {
                 name: func_name.clone(),
-                description: func.description.clone(),
+                description: func.description_with_format(),
                 args: Some(args),
-                example_payload: None,
+                example_payload: func.examples_json().into_iter().next(),
                 tool: format!("{}.{}@robopages", page.name, func_name),
             });
         }
@@ -65,12 +67,27 @@ mod tests {
                 description: "Test parameter".to_string(),
                 required: true,
                 examples: None,
+                flag: None,
+                separator: None,
+                sensitive: false,
+                output: false,
+                constraints: Default::default(),
             },
         );
 
         functions.insert(
             "test_function".to_string(),
             Function {
+                parser: None,
+                destructive: false,
+                diff_previous: false,
+                client_env: Vec::new(),
+                files: Default::default(),
+                tests: Vec::new(),
+                output_format: None,
+                remote: None,
+                examples: None,
+                priority: None,
                 description: "A test function".to_string(),
                 parameters,
                 execution: crate::book::runtime::ExecutionContext::CommandLine(vec![
@@ -78,10 +95,15 @@ mod tests {
                     "test".to_string(),
                 ]),
                 container: None,
+                executor: None,
+                max_concurrency: None,
+                exclusive: false,
+                cache: None,
             },
         );
 
         Page {
+            metadata: Default::default(),
             name: "TestPage".to_string(),
             description: Some("A test page".to_string()),
             categories: vec!["test".to_string()],
@@ -114,6 +136,7 @@ mod tests {
     #[test]
     fn test_empty_page() {
         let page = Page {
+            metadata: Default::default(),
             name: "EmptyPage".to_string(),
             description: None,
             categories: vec![],
@@ -136,6 +159,16 @@ mod tests {
         page.functions.insert(
             "another_function".to_string(),
             Function {
+                parser: None,
+                destructive: false,
+                diff_previous: false,
+                client_env: Vec::new(),
+                files: Default::default(),
+                tests: Vec::new(),
+                output_format: None,
+                remote: None,
+                examples: None,
+                priority: None,
                 description: "Another test function".to_string(),
                 parameters: BTreeMap::new(),
                 execution: crate::book::runtime::ExecutionContext::CommandLine(vec![
@@ -143,6 +176,10 @@ mod tests {
                     "another".to_string(),
                 ]),
                 container: None,
+                executor: None,
+                max_concurrency: None,
+                exclusive: false,
+                cache: None,
             },
         );
 
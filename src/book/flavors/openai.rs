@@ -1,8 +1,11 @@
 use std::collections::BTreeMap;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
 
-use crate::book::Page;
+use crate::book::{Page, Priority};
+use crate::runtime::{audit::Provenance, receipt::Receipt, Execution};
 
 // https://platform.openai.com/docs/guides/function-calling
 
@@ -35,76 +38,249 @@ pub(crate) struct Parameter {
     #[serde(rename = "type")]
     pub param_type: String,
     pub description: String,
+    /// Constraints on the values, as JSON schema keywords.
+    #[serde(flatten)]
+    pub keywords: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<&crate::book::Function> for Parameters {
+    /// JSON schema of the arguments of the function, also used by the other JSON schema flavors.
+    fn from(func: &crate::book::Function) -> Self {
+        let mut properties = BTreeMap::new();
+        let mut required = Vec::new();
+        let defaults = func.parameter_defaults();
+
+        for (param_name, param) in &func.parameters {
+            properties.insert(
+                param_name.clone(),
+                Parameter {
+                    param_type: param.schema_type().to_string(),
+                    description: param.description.clone(),
+                    keywords: param.schema_keywords(defaults.get(param_name).map(String::as_str)),
+                },
+            );
+
+            if param.required {
+                required.push(param_name.clone());
+            }
+        }
+
+        Parameters {
+            params_type: "object".to_string(),
+            properties,
+            required,
+        }
+    }
 }
 
 impl From<&Page> for Vec<Tool> {
     fn from(page: &Page) -> Self {
         page.functions
             .iter()
-            .map(|(func_name, func)| {
-                let mut properties = BTreeMap::new();
-                let mut required = Vec::new();
-
-                for (param_name, param) in &func.parameters {
-                    properties.insert(
-                        param_name.clone(),
-                        Parameter {
-                            param_type: param.param_type.clone(),
-                            description: param.description.clone(),
-                        },
-                    );
-
-                    if param.required {
-                        required.push(param_name.clone());
-                    }
-                }
-
-                // NOTE: it'd be nice if we could add examples
-
-                Tool {
-                    tool_type: "function".to_string(),
-                    function: Function {
-                        name: func_name.clone(),
-                        description: func.description.clone(),
-                        parameters: Parameters {
-                            params_type: "object".to_string(),
-                            properties,
-                            required,
-                        },
-                    },
-                }
+            .map(|(func_name, func)| Tool {
+                tool_type: "function".to_string(),
+                function: Function {
+                    name: func_name.clone(),
+                    // there's no dedicated field for examples
+                    description: func.description_with_examples(),
+                    parameters: func.into(),
+                },
             })
             .collect()
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
 pub(crate) struct FunctionCall {
     pub name: String,
-    pub arguments: BTreeMap<String, String>,
+    /// Raw argument values, coerced to strings according to the declared parameter types.
+    #[serde(deserialize_with = "deserialize_arguments")]
+    pub arguments: BTreeMap<String, Value>,
+}
+
+// chat completions responses encode the arguments as a JSON string
+fn deserialize_arguments<'de, D>(deserializer: D) -> Result<BTreeMap<String, Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(s) if s.trim().is_empty() => Ok(BTreeMap::new()),
+        Value::String(s) => serde_json::from_str(&s).map_err(serde::de::Error::custom),
+        Value::Null => Ok(BTreeMap::new()),
+        value => serde_json::from_value(value).map_err(serde::de::Error::custom),
+    }
 }
 
 type CallId = String;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
 pub(crate) struct Call {
+    #[schema(value_type = Option<String>)]
     pub id: Option<CallId>,
     #[serde(rename = "type")]
     #[serde(default = "default_call_type")]
     pub call_type: String,
     pub function: FunctionCall,
+    /// Overrides the default priority of the function.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    /// Address of the client that sent the call, set by the server.
+    #[serde(skip)]
+    pub caller: Option<String>,
+    /// Environment variables for this call, limited to the client_env of the function.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Execute the function on each of these SSH targets or inventory groups.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    /// Where the arguments come from if not from the model, set by the command line tools.
+    #[serde(skip)]
+    pub provenance: BTreeMap<String, Provenance>,
+}
+
+// the values of the arguments and variables can be sensitive, only their names are printed
+impl std::fmt::Debug for FunctionCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FunctionCall")
+            .field("name", &self.name)
+            .field("arguments", &self.arguments.keys())
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for Call {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Call")
+            .field("id", &self.id)
+            .field("call_type", &self.call_type)
+            .field("function", &self.function)
+            .field("priority", &self.priority)
+            .field("caller", &self.caller)
+            .field("env", &self.env.keys())
+            .field("hosts", &self.hosts)
+            .field("provenance", &self.provenance)
+            .finish()
+    }
 }
 
 fn default_call_type() -> String {
     "function".to_string()
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Call {
+    fn new(id: Option<CallId>, name: String, arguments: Value) -> anyhow::Result<Self> {
+        Ok(Self {
+            id,
+            call_type: default_call_type(),
+            caller: None,
+            env: BTreeMap::new(),
+            hosts: Vec::new(),
+            provenance: BTreeMap::new(),
+            function: FunctionCall {
+                name,
+                arguments: deserialize_arguments(arguments)?,
+            },
+            priority: None,
+        })
+    }
+}
+
+/// Parse the tool calls from a payload in any of the supported shapes, auto detecting the format:
+///
+/// * a list of calls in our own format, or OpenAI `tool_calls` with JSON encoded arguments.
+/// * an OpenAI chat completion response or message (`choices` / `tool_calls`).
+/// * Anthropic `tool_use` content blocks, or a message containing them.
+/// * Gemini `functionCall` parts, or a response / content containing them.
+///
+/// Payloads other than an empty list must contain at least one call.
+pub(crate) fn parse_calls(payload: Value) -> anyhow::Result<Vec<Call>> {
+    let empty = payload.as_array().is_some_and(Vec::is_empty);
+    let mut calls = Vec::new();
+    collect_calls(payload, &mut calls)?;
+    if calls.is_empty() && !empty {
+        return Err(anyhow::anyhow!("unrecognized tool call payload"));
+    }
+    Ok(calls)
+}
+
+fn collect_calls(payload: Value, calls: &mut Vec<Call>) -> anyhow::Result<()> {
+    match payload {
+        Value::Array(items) => {
+            for item in items {
+                collect_calls(item, calls)?;
+            }
+        }
+        Value::Object(mut obj) => {
+            // a single call
+            if obj.contains_key("function") {
+                calls.push(serde_json::from_value(Value::Object(obj))?);
+            } else if obj.get("type").and_then(Value::as_str) == Some("tool_use") {
+                let name = take_string(&mut obj, "name")?;
+                let id = obj
+                    .remove("id")
+                    .and_then(|id| id.as_str().map(String::from));
+                let input = obj.remove("input").unwrap_or(Value::Null);
+                calls.push(Call::new(id, name, input)?);
+            } else if let Some(mut call) = obj
+                .remove("functionCall")
+                .or_else(|| obj.remove("function_call"))
+            {
+                let call = call
+                    .as_object_mut()
+                    .ok_or_else(|| anyhow::anyhow!("functionCall must be an object"))?;
+                let name = take_string(call, "name")?;
+                let id = call
+                    .remove("id")
+                    .and_then(|id| id.as_str().map(String::from));
+                // legacy OpenAI function_call encodes the arguments as a JSON string
+                let args = call
+                    .remove("args")
+                    .or_else(|| call.remove("arguments"))
+                    .unwrap_or(Value::Null);
+                calls.push(Call::new(id, name, args)?);
+            }
+            // containers of calls, only tool calls are collected from them
+            else if let Some(inner) = [
+                "choices",
+                "message",
+                "tool_calls",
+                "candidates",
+                "content",
+                "parts",
+            ]
+            .iter()
+            .find_map(|key| obj.remove(*key))
+            {
+                collect_calls(inner, calls)?;
+            }
+        }
+        // text content blocks and the likes
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn take_string(obj: &mut serde_json::Map<String, Value>, key: &str) -> anyhow::Result<String> {
+    match obj.remove(key) {
+        Some(Value::String(s)) => Ok(s),
+        _ => Err(anyhow::anyhow!("missing or invalid '{}' field", key)),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub(crate) struct CallResultMessage {
     #[serde(default = "default_result_message_role")]
     pub role: String,
+    #[schema(value_type = Option<String>)]
     pub call_id: Option<CallId>,
     pub content: String,
+    /// Where and with which tool version the output was produced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Receipt>,
+    /// How the command was executed, only reported on the terminal.
+    #[serde(skip)]
+    pub execution: Option<Execution>,
 }
 
 fn default_result_message_role() -> String {
@@ -118,8 +294,8 @@ mod tests {
     #[test]
     fn test_function_call() {
         let mut arguments = BTreeMap::new();
-        arguments.insert("arg1".to_string(), "value1".to_string());
-        arguments.insert("arg2".to_string(), "value2".to_string());
+        arguments.insert("arg1".to_string(), "value1".into());
+        arguments.insert("arg2".to_string(), "value2".into());
 
         let function_call = FunctionCall {
             name: "test_function".to_string(),
@@ -128,16 +304,172 @@ mod tests {
 
         assert_eq!(function_call.name, "test_function");
         assert_eq!(function_call.arguments.len(), 2);
+        assert_eq!(function_call.arguments.get("arg1"), Some(&"value1".into()));
+        assert_eq!(function_call.arguments.get("arg2"), Some(&"value2".into()));
+    }
+
+    #[test]
+    fn test_function_call_with_json_types() {
+        let call: Call = serde_json::from_str(
+            r#"{"function": {"name": "scan", "arguments": {"port": 443, "verbose": true, "hosts": ["a", "b"]}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(call.call_type, "function");
+        assert_eq!(call.function.arguments.get("port"), Some(&443.into()));
+        assert_eq!(call.function.arguments.get("verbose"), Some(&true.into()));
+        assert!(call.function.arguments.get("hosts").unwrap().is_array());
+    }
+
+    #[test]
+    fn test_parameters_defaults_and_examples() {
+        let function: crate::book::Function = serde_yaml::from_str(
+            r#"
+description: Scan ports.
+parameters:
+  target:
+    type: target
+    description: The target.
+    examples: [10.0.0.1, scanme.nmap.org]
+  ports:
+    type: array
+    items: integer
+    description: The ports.
+    required: false
+cmdline: [nmap, "-p", "${ports or 22,80}", "${target}"]
+"#,
+        )
+        .unwrap();
+
+        let parameters = serde_json::to_value(Parameters::from(&function)).unwrap();
         assert_eq!(
-            function_call.arguments.get("arg1"),
-            Some(&"value1".to_string())
+            parameters["properties"]["target"]["examples"],
+            serde_json::json!(["10.0.0.1", "scanme.nmap.org"])
         );
+        assert!(parameters["properties"]["target"].get("default").is_none());
         assert_eq!(
-            function_call.arguments.get("arg2"),
-            Some(&"value2".to_string())
+            parameters["properties"]["ports"]["default"],
+            serde_json::json!([22, 80])
         );
     }
 
+    #[test]
+    fn test_parse_calls_native() {
+        let calls = parse_calls(serde_json::json!([
+            {"id": "1", "function": {"name": "a", "arguments": {"x": "y"}}},
+            {"id": "2", "type": "function", "function": {"name": "b", "arguments": "{\"port\": 443}"}}
+        ]))
+        .unwrap();
+
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].function.arguments.get("x"), Some(&"y".into()));
+        assert_eq!(calls[1].id, Some("2".to_string()));
+        assert_eq!(calls[1].function.arguments.get("port"), Some(&443.into()));
+    }
+
+    #[test]
+    fn test_parse_calls_with_priority() {
+        let calls = parse_calls(serde_json::json!([
+            {"function": {"name": "a", "arguments": {}}, "priority": "high"},
+            {"function": {"name": "b", "arguments": {}}}
+        ]))
+        .unwrap();
+
+        assert_eq!(calls[0].priority, Some(Priority::High));
+        assert_eq!(calls[1].priority, None);
+    }
+
+    #[test]
+    fn test_parse_calls_openai_response() {
+        let calls = parse_calls(serde_json::json!({
+            "choices": [{"message": {"role": "assistant", "content": null, "tool_calls": [
+                {"id": "call_1", "type": "function", "function": {"name": "a", "arguments": "{}"}}
+            ]}}]
+        }))
+        .unwrap();
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, Some("call_1".to_string()));
+        assert_eq!(calls[0].function.name, "a");
+    }
+
+    #[test]
+    fn test_parse_calls_anthropic() {
+        let calls = parse_calls(serde_json::json!({
+            "role": "assistant",
+            "content": [
+                {"type": "text", "text": "let me check"},
+                {"type": "tool_use", "id": "toolu_1", "name": "a", "input": {"target": "x"}}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, Some("toolu_1".to_string()));
+        assert_eq!(calls[0].function.arguments.get("target"), Some(&"x".into()));
+    }
+
+    #[test]
+    fn test_parse_calls_gemini() {
+        let calls = parse_calls(serde_json::json!({
+            "candidates": [{"content": {"role": "model", "parts": [
+                {"functionCall": {"name": "a", "args": {"target": "x"}}}
+            ]}}]
+        }))
+        .unwrap();
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, None);
+        assert_eq!(calls[0].function.name, "a");
+        assert_eq!(calls[0].function.arguments.get("target"), Some(&"x".into()));
+    }
+
+    #[test]
+    fn test_parse_calls_legacy_function_call() {
+        let calls = parse_calls(serde_json::json!({
+            "choices": [{"message": {"role": "assistant", "function_call": {
+                "name": "a", "arguments": "{\"target\": \"x\"}"
+            }}}]
+        }))
+        .unwrap();
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.name, "a");
+        assert_eq!(calls[0].function.arguments.get("target"), Some(&"x".into()));
+
+        let calls = parse_calls(serde_json::json!({
+            "function_call": {"name": "a", "arguments": {"target": "y"}}
+        }))
+        .unwrap();
+        assert_eq!(calls[0].function.arguments.get("target"), Some(&"y".into()));
+    }
+
+    #[test]
+    fn test_parse_calls_invalid() {
+        assert!(parse_calls(serde_json::json!([{"type": "tool_use", "input": {}}])).is_err());
+        assert!(parse_calls(serde_json::json!([
+            {"function": {"name": "a", "arguments": "not json"}}
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_calls_unrecognized() {
+        assert!(parse_calls(serde_json::json!([])).unwrap().is_empty());
+        for payload in [
+            serde_json::json!({}),
+            serde_json::json!({"name": "a", "arguments": {}}),
+            serde_json::json!([{"name": "a", "arguments": {}}]),
+            serde_json::json!({"role": "assistant", "content": "no tools needed"}),
+            serde_json::json!("a"),
+        ] {
+            assert_eq!(
+                parse_calls(payload).unwrap_err().to_string(),
+                "unrecognized tool call payload"
+            );
+        }
+    }
+
     #[test]
     fn test_call() {
         let function_call = FunctionCall {
@@ -148,7 +480,12 @@ mod tests {
         let call = Call {
             id: Some("test_id".to_string()),
             call_type: "function".to_string(),
+            caller: None,
+            env: BTreeMap::new(),
+            hosts: Vec::new(),
+            provenance: BTreeMap::new(),
             function: function_call,
+            priority: None,
         };
 
         assert_eq!(call.id, Some("test_id".to_string()));
@@ -166,18 +503,49 @@ mod tests {
         let call = Call {
             id: None,
             call_type: default_call_type(),
+            caller: None,
+            env: BTreeMap::new(),
+            hosts: Vec::new(),
+            provenance: BTreeMap::new(),
             function: function_call,
+            priority: None,
         };
 
         assert_eq!(call.call_type, "function");
     }
 
+    #[test]
+    fn test_call_debug() {
+        let call = Call {
+            id: None,
+            call_type: default_call_type(),
+            caller: None,
+            env: BTreeMap::from([("API_KEY".to_string(), "s3cr3t".to_string())]),
+            hosts: Vec::new(),
+            provenance: BTreeMap::new(),
+            function: FunctionCall {
+                name: "test_function".to_string(),
+                arguments: BTreeMap::from([("password".to_string(), "hunter2".into())]),
+            },
+            priority: None,
+        };
+
+        let debug = format!("{:?}", call);
+        assert!(debug.contains("test_function"));
+        assert!(debug.contains("password"));
+        assert!(debug.contains("API_KEY"));
+        assert!(!debug.contains("hunter2"));
+        assert!(!debug.contains("s3cr3t"));
+    }
+
     #[test]
     fn test_call_result_message() {
         let message = CallResultMessage {
             role: "custom_role".to_string(),
             call_id: Some("test_id".to_string()),
             content: "Test content".to_string(),
+            receipt: None,
+            execution: None,
         };
 
         assert_eq!(message.role, "custom_role");
@@ -191,6 +559,8 @@ mod tests {
             role: default_result_message_role(),
             call_id: None,
             content: "Test content".to_string(),
+            receipt: None,
+            execution: None,
         };
 
         assert_eq!(message.role, "tool");
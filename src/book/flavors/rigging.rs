@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::book::Page;
@@ -27,6 +29,8 @@ pub(crate) struct Function {
     name: String,
     description: String,
     parameters: Vec<Parameter>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    examples: Vec<BTreeMap<String, serde_json::Value>>,
 }
 
 #[derive(Default, Serialize, Deserialize, Debug)]
@@ -45,19 +49,23 @@ impl From<&Page> for Vec<Tool> {
         };
 
         for (func_name, func) in &page.functions {
+            let defaults = func.parameter_defaults();
             tool.functions.push(Function {
                 name: func_name.clone(),
-                description: func.description.clone(),
+                description: func.description_with_format(),
                 parameters: func
                     .parameters
                     .iter()
                     .map(|p| Parameter {
                         name: p.0.clone(),
-                        param_type: rigging_param_type(&p.1.param_type),
-                        description: p.1.description.clone(),
+                        param_type: rigging_param_type(p.1.schema_type()),
+                        description: p
+                            .1
+                            .description_with_values(defaults.get(p.0).map(String::as_str)),
                         examples: p.1.examples.clone().unwrap_or_default(),
                     })
                     .collect(),
+                examples: func.examples.clone().unwrap_or_default(),
             });
         }
 
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+
+use camino::Utf8PathBuf;
+
+use super::Book;
+
+// descriptions shorter than this rarely tell the model when to use a function
+const MIN_FUNCTION_DESCRIPTION_LEN: usize = 20;
+const MIN_PARAMETER_DESCRIPTION_LEN: usize = 10;
+
+// names that say nothing about what the parameter is
+const AMBIGUOUS_NAMES: &[&str] = &[
+    "arg",
+    "args",
+    "argument",
+    "param",
+    "parameter",
+    "value",
+    "val",
+    "input",
+    "data",
+    "opt",
+    "option",
+    "flag",
+    "var",
+    "x",
+    "foo",
+    "bar",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LintKind {
+    ShortDescription,
+    MissingExamples,
+    AmbiguousName,
+    DuplicateDescription,
+}
+
+impl std::fmt::Display for LintKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintKind::ShortDescription => write!(f, "short description"),
+            LintKind::MissingExamples => write!(f, "missing examples"),
+            LintKind::AmbiguousName => write!(f, "ambiguous name"),
+            LintKind::DuplicateDescription => write!(f, "duplicate description"),
+        }
+    }
+}
+
+/// A model-friendliness issue, not preventing the page from working.
+#[derive(Debug)]
+pub(crate) struct Finding {
+    pub path: Utf8PathBuf,
+    pub function: String,
+    pub parameter: Option<String>,
+    pub kind: LintKind,
+    pub suggestion: String,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.function)?;
+        if let Some(parameter) = &self.parameter {
+            write!(f, ".{}", parameter)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+fn is_ambiguous_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    // arg1, param_2, ...
+    let base = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_');
+    base.len() <= 1 || AMBIGUOUS_NAMES.contains(&base)
+}
+
+fn normalize(description: &str) -> String {
+    description
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Check how well the functions of the book are described for a model.
+pub(crate) fn lint(book: &Book) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut descriptions: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+
+    for (path, page) in &book.pages {
+        for (func_name, function) in &page.functions {
+            let finding = |parameter: Option<&String>, kind, suggestion: String| Finding {
+                path: path.clone(),
+                function: func_name.clone(),
+                parameter: parameter.cloned(),
+                kind,
+                suggestion,
+            };
+
+            if function.description.trim().len() < MIN_FUNCTION_DESCRIPTION_LEN {
+                findings.push(finding(
+                    None,
+                    LintKind::ShortDescription,
+                    format!(
+                        "describe what {} does, when to use it and what it returns in at least {} characters",
+                        func_name, MIN_FUNCTION_DESCRIPTION_LEN
+                    ),
+                ));
+            }
+
+            descriptions
+                .entry(normalize(&function.description))
+                .or_default()
+                .push(func_name);
+
+            for (param_name, param) in &function.parameters {
+                if param.description.trim().len() < MIN_PARAMETER_DESCRIPTION_LEN {
+                    findings.push(finding(
+                        Some(param_name),
+                        LintKind::ShortDescription,
+                        format!(
+                            "describe the expected format of {} in at least {} characters",
+                            param_name, MIN_PARAMETER_DESCRIPTION_LEN
+                        ),
+                    ));
+                }
+
+                if param
+                    .examples
+                    .as_ref()
+                    .is_none_or(|examples| examples.is_empty())
+                {
+                    findings.push(finding(
+                        Some(param_name),
+                        LintKind::MissingExamples,
+                        format!("add one or more examples: values for {}", param_name),
+                    ));
+                }
+
+                if is_ambiguous_name(param_name) {
+                    findings.push(finding(
+                        Some(param_name),
+                        LintKind::AmbiguousName,
+                        format!(
+                            "rename {} after what it contains, for instance target, port or wordlist",
+                            param_name
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    // the model can't tell apart functions with the same description
+    for (path, page) in &book.pages {
+        for (func_name, function) in &page.functions {
+            let same = &descriptions[&normalize(&function.description)];
+            if same.len() > 1 {
+                let others: Vec<&str> = same
+                    .iter()
+                    .filter(|other| **other != func_name)
+                    .copied()
+                    .collect();
+                findings.push(Finding {
+                    path: path.clone(),
+                    function: func_name.clone(),
+                    parameter: None,
+                    kind: LintKind::DuplicateDescription,
+                    suggestion: format!(
+                        "explain how {} differs from {}",
+                        func_name,
+                        others.join(", ")
+                    ),
+                });
+            }
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Page;
+
+    fn book(yaml: &str) -> Book {
+        let page: Page = serde_yaml::from_str(yaml).unwrap();
+        Book {
+            pages: BTreeMap::from([(Utf8PathBuf::from("test.yml"), page)]),
+        }
+    }
+
+    fn kinds(findings: &[Finding]) -> Vec<(String, Option<String>, LintKind)> {
+        findings
+            .iter()
+            .map(|f| (f.function.clone(), f.parameter.clone(), f.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_clean() {
+        let book = book(
+            r#"
+functions:
+  nmap_scan:
+    description: Scan the TCP ports of a target host with nmap.
+    parameters:
+      target:
+        type: string
+        description: The IP address or hostname to scan.
+        examples: [192.168.1.1]
+    cmdline: [nmap, "${target}"]
+"#,
+        );
+        assert!(lint(&book).is_empty());
+    }
+
+    #[test]
+    fn test_lint_findings() {
+        let book = book(
+            r#"
+functions:
+  first:
+    description: Run it.
+    parameters:
+      arg1:
+        type: string
+        description: The value.
+    cmdline: [echo, "${arg1}"]
+  second:
+    description: run   IT.
+    parameters: {}
+    cmdline: [echo]
+"#,
+        );
+
+        let findings = kinds(&lint(&book));
+        let arg1 = Some("arg1".to_string());
+        for expected in [
+            ("first".to_string(), None, LintKind::ShortDescription),
+            ("first".to_string(), arg1.clone(), LintKind::MissingExamples),
+            ("first".to_string(), arg1, LintKind::AmbiguousName),
+            ("first".to_string(), None, LintKind::DuplicateDescription),
+            ("second".to_string(), None, LintKind::DuplicateDescription),
+        ] {
+            assert!(findings.contains(&expected), "{:?} not found", expected);
+        }
+    }
+
+    #[test]
+    fn test_is_ambiguous_name() {
+        assert!(is_ambiguous_name("arg1"));
+        assert!(is_ambiguous_name("Param_2"));
+        assert!(is_ambiguous_name("x"));
+        assert!(!is_ambiguous_name("target"));
+        assert!(!is_ambiguous_name("wordlist"));
+    }
+}
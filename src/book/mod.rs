@@ -1,14 +1,36 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use camino::Utf8PathBuf;
-use glob::glob;
 use serde::{Deserialize, Serialize};
-
-use crate::runtime::{get_container_runtime, CommandLine, ContainerSource};
-
+use utoipa::ToSchema;
+
+pub(crate) use discovery::Discovery;
+
+use crate::runtime::{
+    backend::{self, ContainerBackend},
+    cache::CachePolicy,
+    get_container_runtime, is_windows_platform, pinned_digest, session,
+    signing::{self, Signature},
+    translate_guest_path, CommandLine, ContainerSource, TrackedContainer, Volume, FUNCTION_LABEL,
+    SESSION_LABEL,
+};
+
+pub(crate) mod bundle;
+mod discovery;
+pub(crate) mod finetune;
 pub(crate) mod flavors;
+pub(crate) mod lint;
+pub(crate) mod oci;
+pub(crate) mod package;
+pub(crate) mod parsers;
+pub(crate) mod remote;
+pub(crate) mod revision;
 pub(crate) mod runtime;
+pub(crate) mod sources;
 pub(crate) mod templates;
+pub(crate) mod trace;
+pub(crate) mod trust;
+pub(crate) mod types;
 
 macro_rules! eval_if_in_filter {
     ($path:expr, $filter:expr, $action:expr) => {
@@ -27,7 +49,7 @@ macro_rules! eval_if_in_filter {
     };
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Parameter {
     #[serde(rename = "type")]
     pub param_type: String,
@@ -36,13 +58,105 @@ pub struct Parameter {
     pub required: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub examples: Option<Vec<String>>,
+    /// Arrays only, each item is passed after this flag, as in --target a --target b.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flag: Option<String>,
+    /// Arrays only, the items are joined with this separator, a comma by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+    /// Secrets such as API keys, masked in logs and errors and only passed to containers through
+    /// their environment, as the upper case name of the parameter.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub sensitive: bool,
+    /// Paths only, written by the command so mounted read-write in containers.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub output: bool,
+    #[serde(flatten)]
+    pub constraints: types::Constraints,
+}
+
+impl Parameter {
+    /// JSON schema type of the parameter, semantic types such as target and enum are strings.
+    pub fn schema_type(&self) -> &str {
+        match self.param_type.as_str() {
+            crate::runtime::scope::TARGET_TYPE | types::PATH_TYPE | "enum" => "string",
+            param_type => param_type,
+        }
+    }
+
+    /// Returns the description followed by the allowed values, the default value and the
+    /// examples, for flavors without a schema.
+    pub fn description_with_values(&self, default: Option<&str>) -> String {
+        let mut description = self.description.clone();
+        if !self.constraints.choices.is_empty() {
+            description += &format!(
+                " One of: {}.",
+                self.constraints
+                    .choices
+                    .iter()
+                    .map(types::choice_to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        if let Some(default) = default {
+            description += &format!(" Defaults to {}.", default);
+        }
+        if let Some(examples) = self.examples.as_ref().filter(|e| !e.is_empty()) {
+            description += &format!(" Examples: {}.", examples.join(", "));
+        }
+        description
+    }
+
+    /// JSON schema keywords of the parameter besides its type and description: the constraints,
+    /// the default value and the examples.
+    pub fn schema_keywords(
+        &self,
+        default: Option<&str>,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        let mut keywords = self.constraints.schema_keywords(&self.param_type);
+        if let Some(default) = default {
+            keywords.insert(
+                "default".to_string(),
+                self.constraints.schema_value(&self.param_type, default),
+            );
+        }
+        if let Some(examples) = self.examples.as_ref().filter(|e| !e.is_empty()) {
+            keywords.insert(
+                "examples".to_string(),
+                examples
+                    .iter()
+                    .map(|example| self.constraints.schema_value(&self.param_type, example))
+                    .collect(),
+            );
+        }
+        keywords
+    }
+
+    /// Returns an error if the type is unknown or the constraints and the array options can't
+    /// apply to it.
+    pub fn check_definition(&self) -> anyhow::Result<()> {
+        self.constraints.check_definition(&self.param_type)?;
+        if self.param_type != "array" && (self.flag.is_some() || self.separator.is_some()) {
+            return Err(anyhow::anyhow!(
+                "flag and separator are only valid for arrays"
+            ));
+        }
+        if self.flag.is_some() && self.separator.is_some() {
+            return Err(anyhow::anyhow!("flag and separator are mutually exclusive"));
+        }
+        if self.output && self.param_type != types::PATH_TYPE {
+            return Err(anyhow::anyhow!("output is only valid for paths"));
+        }
+        Ok(())
+    }
 }
 
 fn default_required() -> bool {
     true
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Container {
     #[serde(flatten)]
     pub source: ContainerSource,
@@ -58,8 +172,49 @@ pub struct Container {
     pub preserve_app: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workdir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+    /// Keep a container running for the function and execute each call in it.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub persistent: bool,
+    /// How long a persistent container is kept without calls, such as 10m.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_timeout: Option<String>,
+    /// Mount the root filesystem of the container as read only, with a writable /tmp.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub read_only: bool,
+    /// User to run the tool as, such as nobody or 1000:1000.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Linux capabilities to drop, such as ALL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cap_drop: Option<Vec<String>>,
+    /// Linux capabilities the tool needs, such as NET_RAW.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cap_add: Option<Vec<String>>,
+    /// Security options of the container runtime, such as no-new-privileges.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub security_opt: Option<Vec<String>>,
+    /// Only run the image if it is pinned to a digest, the container runtime checks the content
+    /// of images pulled and run by digest.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub verify: bool,
+    /// Check the signature of the image with cosign before it's used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
 }
 
+// where the host paths passed as arguments are mounted in containers
+const PATHS_GUEST_DIR: &str = "/robopages/paths";
+
+// persistent containers are removed after this long without calls
+const DEFAULT_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(600);
+
 fn is_false(b: &bool) -> bool {
     !(*b)
 }
@@ -73,7 +228,14 @@ fn default_preserve_app() -> bool {
 }
 
 impl Container {
-    pub fn wrap(&self, cmdline: CommandLine) -> anyhow::Result<CommandLine> {
+    pub fn wrap(&self, function_name: &str, cmdline: CommandLine) -> anyhow::Result<CommandLine> {
+        if backend::get() == Some(ContainerBackend::K8s) {
+            return self.wrap_pod(function_name, cmdline);
+        }
+
+        let mut cmdline = cmdline;
+        let mounts = self.mount_paths(&mut cmdline)?;
+
         let runtime = get_container_runtime();
         let mut dockerized = CommandLine {
             sudo: false,
@@ -84,77 +246,651 @@ impl Container {
             app_in_path: true,
             args: vec!["run".to_string(), "--rm".to_string()],
             env: BTreeMap::new(),
+            stdin: None,
+            secrets: cmdline.secrets.clone(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
 
         // handle environment variables if present
-        if !cmdline.env.is_empty() {
-            let mut env_contents = String::new();
-            for (key, value) in &cmdline.env {
-                env_contents.push_str(&format!("{}={}\n", key, value));
-            }
+        if let Some(env_file) = Self::env_file(&cmdline.env)? {
+            dockerized
+                .args
+                .push(format!("--env-file={}", env_file.path().display()));
+
+            // keep temp file alive until docker run completes
+            dockerized.temp_env_file = Some(env_file);
+        }
+
+        // name and label the container so it can be attributed to its function and cleaned up
+        let tracked = TrackedContainer::new();
+        dockerized.args.push(format!("--name={}", &tracked.name));
+        dockerized.container = Some(tracked);
+        dockerized
+            .args
+            .push(format!("-l{}={}", FUNCTION_LABEL, function_name));
 
-            // create temp file
-            let temp_file = tempfile::NamedTempFile::new()
-                .map_err(|e| anyhow::anyhow!("failed to create temp env file: {}", e))?;
+        dockerized.args.extend(self.run_options());
+        for mount in mounts {
+            dockerized.args.push(format!("-v{}", mount));
+        }
+
+        // add image
+        dockerized.args.push(self.run_image()?);
+
+        if self.preserve_app {
+            // add the original app to the args
+            dockerized.args.push(cmdline.app.clone());
+        }
 
-            // write env vars
-            std::fs::write(temp_file.path(), env_contents)
-                .map_err(|e| anyhow::anyhow!("failed to write env file: {}", e))?;
+        // add the original arguments
+        dockerized.args.extend(cmdline.args);
+
+        Ok(dockerized)
+    }
+
+    /// Wrap the command line to be executed in a pod of the current kubectl context.
+    fn wrap_pod(&self, function_name: &str, cmdline: CommandLine) -> anyhow::Result<CommandLine> {
+        // kubectl run only takes variables as arguments, which any user of the host can read
+        if cmdline.secrets.iter().any(|secret| !secret.is_empty()) {
+            return Err(anyhow::anyhow!(
+                "sensitive arguments of function {} can't be passed to k8s pods",
+                function_name
+            ));
+        }
 
-            // add env-file arg
+        let mut kubectl = CommandLine {
+            sudo: false,
+            app: which::which("kubectl")
+                .map_err(|e| anyhow::anyhow!("kubectl executable not found: {}", e))?
+                .to_string_lossy()
+                .to_string(),
+            app_in_path: true,
+            args: vec![
+                "run".to_string(),
+                "--rm".to_string(),
+                "-i".to_string(),
+                "--quiet".to_string(),
+                "--restart=Never".to_string(),
+            ],
+            env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
+            temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
+        };
+
+        let tracked = TrackedContainer::new();
+        kubectl.args.push(tracked.name.clone());
+        kubectl.container = Some(tracked);
+
+        let mut labels = vec![
+            format!("{}={}", FUNCTION_LABEL, function_name),
+            format!("{}={}", SESSION_LABEL, session()),
+        ];
+        if let Some(extra) = &self.labels {
+            labels.extend(
+                extra
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value)),
+            );
+        }
+        kubectl.args.push(format!("--labels={}", labels.join(",")));
+        kubectl.args.push(format!("--image={}", self.run_image()?));
+
+        for (key, value) in &cmdline.env {
+            kubectl.args.push(format!("--env={}={}", key, value));
+        }
+
+        if self.args.is_some() || self.workdir.is_some() || self.platform.is_some() {
+            log::warn!(
+                "container args, workdir and platform of function {} are ignored by the k8s backend",
+                function_name
+            );
+        }
+        if !self.hardening_options().is_empty() {
+            log::warn!(
+                "container hardening options of function {} are ignored by the k8s backend",
+                function_name
+            );
+        }
+        if !cmdline.paths.is_empty() {
+            log::warn!(
+                "path arguments of function {} are not mounted by the k8s backend",
+                function_name
+            );
+        }
+
+        // like docker run, the arguments are passed to the entrypoint of the image
+        kubectl.args.push("--".to_string());
+        if self.preserve_app {
+            kubectl.args.push(cmdline.app.clone());
+        }
+        kubectl.args.extend(cmdline.args);
+
+        Ok(kubectl)
+    }
+
+    /// Wrap the command line to be executed via exec in an already running container.
+    pub fn wrap_exec(
+        &self,
+        name: &str,
+        entrypoint: &[String],
+        cmdline: CommandLine,
+    ) -> anyhow::Result<CommandLine> {
+        let runtime = get_container_runtime();
+        let mut dockerized = CommandLine {
+            sudo: false,
+            app: which::which(&runtime)
+                .map_err(|e| anyhow::anyhow!("{} executable not found: {}", runtime, e))?
+                .to_string_lossy()
+                .to_string(),
+            app_in_path: true,
+            args: vec!["exec".to_string()],
+            env: BTreeMap::new(),
+            stdin: None,
+            secrets: cmdline.secrets.clone(),
+            temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
+        };
+
+        if let Some(env_file) = Self::env_file(&cmdline.env)? {
             dockerized
                 .args
-                .push(format!("--env-file={}", temp_file.path().display()));
+                .push(format!("--env-file={}", env_file.path().display()));
+            dockerized.temp_env_file = Some(env_file);
+        }
 
-            // keep temp file alive until docker run completes
-            dockerized.temp_env_file = Some(temp_file);
+        dockerized.args.push(name.to_string());
+
+        // exec bypasses the image entrypoint, so it needs to be explicit
+        dockerized.args.extend(entrypoint.iter().cloned());
+
+        if self.preserve_app {
+            dockerized.args.push(cmdline.app.clone());
+        }
+
+        dockerized.args.extend(cmdline.args);
+
+        Ok(dockerized)
+    }
+
+    /// Options for the container runtime shared by every container started for this function.
+    pub fn run_options(&self) -> Vec<String> {
+        let mut options = vec![format!("-l{}={}", SESSION_LABEL, session())];
+        // the same platform the image was pulled for
+        if let Some(platform) = &self.platform {
+            options.push(format!("--platform={}", platform));
+        }
+        if let Some(labels) = &self.labels {
+            for (key, value) in labels {
+                options.push(format!("-l{}={}", key, value));
+            }
+        }
+
+        let windows = self.is_windows();
+
+        // set the working directory if any
+        if let Some(workdir) = &self.workdir {
+            options.push(format!("-w{}", translate_guest_path(workdir, windows)));
         }
 
         // add volumes if any
         if let Some(volumes) = &self.volumes {
             for volume in volumes {
-                dockerized.args.push(format!("-v{}", volume));
+                options.push(format!("-v{}", Volume::parse(volume).translate(windows)));
             }
         }
 
+        options.extend(self.hardening_options());
+
         // add any additional args
         if let Some(args) = &self.args {
-            dockerized.args.extend(args.clone());
+            options.extend(args.clone());
         }
 
-        // add image
-        dockerized.args.push(self.source.image().to_string());
+        // last, so that the limits of the sandbox profile take precedence
+        options.extend(crate::runtime::sandbox::container_options());
 
-        if self.preserve_app {
-            // add the original app to the args
-            dockerized.args.push(cmdline.app.clone());
+        options
+    }
+
+    /// Returns the volumes mounting the host paths passed as arguments, which are rewritten to
+    /// their path in the container.
+    pub fn mount_paths(&self, cmdline: &mut CommandLine) -> anyhow::Result<Vec<String>> {
+        let windows = self.is_windows();
+        let mut mounts = Vec::new();
+
+        for (index, path) in std::mem::take(&mut cmdline.paths).iter().enumerate() {
+            let host = crate::runtime::paths::check(&path.value, path.output)?;
+            let name = host
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| "root".to_string());
+            let dir = format!("{}/{}", PATHS_GUEST_DIR, index);
+            let guest = translate_guest_path(&format!("{}/{}", dir, name), windows);
+
+            // outputs that don't exist yet are created in their mounted directory
+            let mount = if path.output && !host.exists() {
+                let parent = host.parent().unwrap_or(&host);
+                format!(
+                    "{}:{}",
+                    parent.display(),
+                    translate_guest_path(&dir, windows)
+                )
+            } else if path.output {
+                format!("{}:{}", host.display(), guest)
+            } else {
+                format!("{}:{}:ro", host.display(), guest)
+            };
+            mounts.push(Volume::parse(&mount).translate(windows));
+
+            // the path is either an argument or the value of an option
+            for arg in cmdline.args.iter_mut() {
+                if *arg == path.value {
+                    *arg = guest.clone();
+                } else if let Some(option) = arg.strip_suffix(path.value.as_str()) {
+                    if option.ends_with('=') {
+                        *arg = format!("{}{}", option, guest);
+                    }
+                }
+            }
         }
 
-        // add the original arguments
-        dockerized.args.extend(cmdline.args);
+        Ok(mounts)
+    }
 
-        Ok(dockerized)
+    /// Options restricting the privileges of the container.
+    pub fn hardening_options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        if self.read_only {
+            // most tools still need somewhere to write their temporary files
+            options.extend(["--read-only".to_string(), "--tmpfs=/tmp".to_string()]);
+        }
+        if let Some(user) = &self.user {
+            options.push(format!("--user={}", user));
+        }
+        for cap in self.cap_drop.iter().flatten() {
+            options.push(format!("--cap-drop={}", cap));
+        }
+        for cap in self.cap_add.iter().flatten() {
+            options.push(format!("--cap-add={}", cap));
+        }
+        for opt in self.security_opt.iter().flatten() {
+            options.push(format!("--security-opt={}", opt));
+        }
+        options
+    }
+
+    // write the environment variables to a temporary file, to keep them out of the process list
+    fn env_file(env: &BTreeMap<String, String>) -> anyhow::Result<Option<tempfile::NamedTempFile>> {
+        if env.is_empty() {
+            return Ok(None);
+        }
+
+        let mut env_contents = String::new();
+        for (key, value) in env {
+            env_contents.push_str(&format!("{}={}\n", key, value));
+        }
+
+        // create temp file
+        let temp_file = tempfile::NamedTempFile::new()
+            .map_err(|e| anyhow::anyhow!("failed to create temp env file: {}", e))?;
+
+        // write env vars
+        std::fs::write(temp_file.path(), env_contents)
+            .map_err(|e| anyhow::anyhow!("failed to write env file: {}", e))?;
+
+        Ok(Some(temp_file))
     }
 
     pub async fn resolve(&self) -> anyhow::Result<()> {
-        self.source.resolve(self.platform.clone()).await
+        backend::check(self)?;
+        self.source.resolve(self.platform.clone()).await?;
+        signing::verify(self).await?;
+
+        // fail closed, the image is not used unless it's referenced by its digest
+        self.check_pinning()
+    }
+
+    /// The image reference to run, pinned to the digest its signature was verified at if signed.
+    pub fn run_image(&self) -> anyhow::Result<String> {
+        if self.signature.is_none() {
+            return Ok(self.source.image().to_string());
+        }
+        signing::verified(self).ok_or_else(|| {
+            anyhow::anyhow!(
+                "signature of image {} has not been verified",
+                self.source.image()
+            )
+        })
+    }
+
+    /// Returns an error if the image must be verified but is not pinned to a digest.
+    pub fn check_pinning(&self) -> anyhow::Result<()> {
+        if !self.verify {
+            return Ok(());
+        }
+        match &self.source {
+            ContainerSource::Image(image) => pinned_digest(image).map(|_| ()),
+            ContainerSource::Build(build) => Err(anyhow::anyhow!(
+                "image {} is built and can't be verified",
+                &build.name
+            )),
+        }
+    }
+
+    /// Returns true if this container runs Windows.
+    pub fn is_windows(&self) -> bool {
+        is_windows_platform(self.platform.as_deref())
+    }
+
+    /// How long the persistent container is kept without calls.
+    pub fn idle_timeout(&self) -> anyhow::Result<std::time::Duration> {
+        match &self.idle_timeout {
+            Some(timeout) => humantime::parse_duration(timeout)
+                .map_err(|e| anyhow::anyhow!("invalid idle_timeout {}: {}", timeout, e)),
+            None => Ok(DEFAULT_IDLE_TIMEOUT),
+        }
+    }
+}
+
+/// Scheduling priority, when all workers are busy higher priority calls are executed first.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn all() -> [Self; 3] {
+        [Priority::Low, Priority::Normal, Priority::High]
+    }
+}
+
+/// A test of a function, executed by `robopages ci`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FunctionTest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: BTreeMap<String, serde_json::Value>,
+    /// Expected exit code of the command.
+    #[serde(default)]
+    pub exit_code: i64,
+    /// Strings the output must contain.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub contains: Vec<String>,
+    /// Regular expression the output must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<String>,
+}
+
+impl FunctionTest {
+    /// Check the output of the function against the expectations of the test.
+    pub fn check(&self, output: &str) -> anyhow::Result<()> {
+        let exit_code = crate::runtime::exit_code(output).unwrap_or(-1);
+        if exit_code != self.exit_code {
+            return Err(anyhow::anyhow!(
+                "exit code {}, expected {}",
+                exit_code,
+                self.exit_code
+            ));
+        }
+
+        for expected in &self.contains {
+            if !output.contains(expected) {
+                return Err(anyhow::anyhow!("output does not contain {:?}", expected));
+            }
+        }
+
+        if let Some(pattern) = &self.matches {
+            let regex = regex::Regex::new(pattern)
+                .map_err(|e| anyhow::anyhow!("invalid pattern {}: {}", pattern, e))?;
+            if !regex.is_match(output) {
+                return Err(anyhow::anyhow!("output does not match {}", pattern));
+            }
+        }
+
+        Ok(())
     }
 }
 
-// TODO: add optional parsers to reduce output tokens
+/// A file copied between this host and the SSH host, paths can reference arguments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transfer {
+    /// Path on this host, relative to the page for uploads.
+    pub local: String,
+    pub remote: String,
+}
+
+/// Files uploaded to the SSH host before the execution and downloaded after it.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Files {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub upload: Vec<Transfer>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub download: Vec<Transfer>,
+}
+
+impl Files {
+    pub fn is_empty(&self) -> bool {
+        self.upload.is_empty() && self.download.is_empty()
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Function {
     pub description: String,
     pub parameters: BTreeMap<String, Parameter>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub container: Option<Container>,
+    /// Name of a registered executor running the command instead of this host, a container or
+    /// the ssh host.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executor: Option<String>,
+    /// Default priority for calls to this function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    /// Maximum number of calls of this function running at the same time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// Only run one call of this function at a time, such as a scanner saturating the network.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub exclusive: bool,
+    /// Examples of complete sets of arguments.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub examples: Option<Vec<BTreeMap<String, serde_json::Value>>>,
+    /// Whether the function modifies or disrupts its target, operators can be notified when it runs.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub destructive: bool,
+    /// Only return the changes since the last run with the same arguments.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub diff_previous: bool,
+    /// Reuse the result of identical calls for a while instead of executing them again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CachePolicy>,
+    /// Environment variables that clients can set for their calls, such as an auth token, instead
+    /// of passing them as arguments visible to the model.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub client_env: Vec<String>,
+    /// Files to stage on the SSH host, such as wordlists, and artifacts to collect.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Files::is_empty")]
+    pub files: Files,
+    /// Tests executed by `robopages ci`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<FunctionTest>,
+    /// Format of the output, hinted to the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<parsers::OutputFormat>,
+    /// Optional parser to reduce the output tokens.
+    #[serde(default, deserialize_with = "parsers::deserialize")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parser: Option<parsers::Parser>,
+    /// Set for functions of remote servers, calls are forwarded to them.
+    #[serde(skip)]
+    pub remote: Option<remote::RemoteFunction>,
     #[serde(flatten)]
     pub execution: runtime::ExecutionContext,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Function {
+    /// Returns the examples as JSON objects.
+    pub fn examples_json(&self) -> Vec<String> {
+        self.examples
+            .iter()
+            .flatten()
+            .filter_map(|example| serde_json::to_string(example).ok())
+            .collect()
+    }
+
+    /// Default values of the optional parameters, as set by the placeholders of the command line.
+    pub fn parameter_defaults(&self) -> BTreeMap<String, String> {
+        let mut defaults = self.execution.placeholder_defaults();
+        defaults.retain(|name, _| {
+            self.parameters
+                .get(name)
+                .is_some_and(|param| !param.required)
+        });
+        defaults
+    }
+
+    /// Maximum number of calls running at the same time, if limited.
+    pub fn concurrency_limit(&self) -> Option<usize> {
+        if self.exclusive {
+            Some(1)
+        } else {
+            self.max_concurrency
+        }
+    }
+
+    /// Returns the description followed by the output format, if set.
+    pub fn description_with_format(&self) -> String {
+        match self.output_format {
+            Some(format) => format!("{} Returns {}.", self.description, format.describe()),
+            None => self.description.clone(),
+        }
+    }
+
+    /// Returns the description and output format followed by the examples, if any.
+    pub fn description_with_examples(&self) -> String {
+        let description = self.description_with_format();
+        let examples = self.examples_json();
+        if examples.is_empty() {
+            return description;
+        }
+
+        format!(
+            "{}\n\nExample arguments:\n{}",
+            description,
+            examples
+                .iter()
+                .map(|example| format!("- {}", example))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    }
+
+    /// Describes where non interactive calls of this function are executed, either over the given
+    /// SSH connection, in its container or on the host.
+    pub fn execution_summary(&self, ssh: Option<&str>) -> String {
+        if let Some(remote) = &self.remote {
+            return format!("runs on remote server {}", remote.url());
+        }
+
+        if let runtime::ExecutionContext::Wasm(wasm) = &self.execution {
+            return format!("runs in wasm module {}", wasm.module);
+        }
+
+        if let runtime::ExecutionContext::Pipeline(steps) = &self.execution {
+            return format!(
+                "runs the functions {} in sequence",
+                steps
+                    .iter()
+                    .map(|step| step.function.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        if let Some(ssh) = ssh {
+            return format!("runs via ssh host {}", ssh);
+        }
+
+        if let Some(container) = &self.container {
+            let needs_container = container.force
+                || self
+                    .execution
+                    .get_command_line()
+                    .map(|cmdline| {
+                        // sudo can't be used without user interaction
+                        cmdline.iter().any(|arg| arg == "sudo")
+                            || cmdline
+                                .iter()
+                                .find(|arg| *arg != "sudo")
+                                .is_none_or(|app| which::which(app).is_err())
+                    })
+                    .unwrap_or(true);
+
+            if needs_container {
+                return format!(
+                    "runs in {} image {}",
+                    get_container_runtime(),
+                    container.source.image()
+                );
+            }
+        }
+
+        "runs on the host".to_string()
+    }
+}
+
+/// Authorship and licensing information of a page.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PageMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+}
+
+impl PageMetadata {
+    /// Returns the set fields as (name, value) pairs.
+    pub fn fields(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("author", &self.author),
+            ("license", &self.license),
+            ("homepage", &self.homepage),
+            ("source_url", &self.source_url),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_deref().map(|value| (name, value)))
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Page {
     #[serde(skip_serializing_if = "String::is_empty")]
     #[serde(default = "String::new")]
@@ -165,9 +901,22 @@ pub struct Page {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(default = "Vec::new")]
     pub categories: Vec<String>,
+    #[serde(flatten)]
+    pub metadata: PageMetadata,
 }
 
+const UNCATEGORIZED_GROUP: &str = "uncategorized";
+
 impl Page {
+    /// Name of the group of tools this page belongs to.
+    pub fn group(&self) -> String {
+        if self.categories.is_empty() {
+            UNCATEGORIZED_GROUP.to_string()
+        } else {
+            self.categories.join("/")
+        }
+    }
+
     fn preprocess(path: &Utf8PathBuf, text: String) -> anyhow::Result<String> {
         let path = path.canonicalize_utf8()?;
         let base_path = path.parent().unwrap();
@@ -191,9 +940,48 @@ pub struct Book {
     pub pages: BTreeMap<Utf8PathBuf, Page>,
 }
 
+/// Names of the functions that differ between two books.
+#[derive(Debug, Default, PartialEq, Serialize, ToSchema)]
+pub struct BookDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
 impl Book {
     pub fn from_path(path: Utf8PathBuf, filter: Option<String>) -> anyhow::Result<Self> {
+        Self::from_path_traced(
+            path,
+            filter,
+            &Discovery::default(),
+            &trust::TrustPolicy::default(),
+            &mut trace::LoadTrace::default(),
+        )
+    }
+
+    /// Load the book, collecting loading diagnostics into the trace.
+    pub fn from_path_traced(
+        path: Utf8PathBuf,
+        filter: Option<String>,
+        discovery: &Discovery,
+        trust: &trust::TrustPolicy,
+        trace: &mut trace::LoadTrace,
+    ) -> anyhow::Result<Self> {
+        let started = std::time::Instant::now();
+        let result = Self::load(path, filter, discovery, trust, trace);
+        trace.elapsed = started.elapsed();
+        result
+    }
+
+    fn load(
+        path: Utf8PathBuf,
+        filter: Option<String>,
+        discovery: &Discovery,
+        trust: &trust::TrustPolicy,
+        trace: &mut trace::LoadTrace,
+    ) -> anyhow::Result<Self> {
         log::debug!("Searching for pages in {:?}", path);
+        let verifier = trust.verifier()?;
         let mut page_paths = Vec::new();
 
         let path = Utf8PathBuf::from(
@@ -207,43 +995,19 @@ impl Book {
         log::debug!("canonicalized path: {:?}", path);
 
         if path.is_file() {
+            if let Some(bundle) = bundle::Bundle::from_path(&path)? {
+                log::debug!("path is a bundle");
+                verifier
+                    .verify(&path)
+                    .map_err(|e| anyhow::anyhow!("refusing to load untrusted bundle: {}", e))?;
+                return Self::from_bundle(&path, bundle, filter);
+            }
+
             log::debug!("path is a file");
             eval_if_in_filter!(path, filter, page_paths.push(path.to_path_buf()));
         } else if path.is_dir() {
             log::debug!("path is a directory, searching for .yml files");
-            let glob_pattern = path.join("**/*.yml").as_str().to_string();
-            log::debug!("using glob pattern: {}", glob_pattern);
-
-            for entry in glob(&glob_pattern)? {
-                match entry {
-                    Ok(entry_path) => {
-                        log::debug!("found file: {:?}", entry_path);
-                        // skip files in hidden directories (starting with .)
-                        // but allow the root .robopages directory
-                        if let Ok(relative_path) = entry_path.strip_prefix(&path) {
-                            if relative_path.components().any(|component| {
-                                let comp_str = component.as_os_str().to_string_lossy();
-                                comp_str.starts_with(".") && comp_str != "." && comp_str != ".."
-                            }) {
-                                log::debug!("skipping hidden file/directory");
-                                continue;
-                            }
-                        }
-
-                        if let Ok(utf8_path) = Utf8PathBuf::from_path_buf(entry_path) {
-                            eval_if_in_filter!(utf8_path, filter, {
-                                log::debug!("Adding path: {:?}", utf8_path);
-                                page_paths.push(utf8_path);
-                            });
-                        } else {
-                            log::error!("failed to convert path to Utf8PathBuf");
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("error in glob: {}", e);
-                    }
-                }
-            }
+            page_paths = discovery.find_pages(&path, filter.as_deref(), trace)?;
         }
 
         log::debug!("found {} page paths", page_paths.len());
@@ -254,12 +1018,38 @@ impl Book {
 
         log::debug!("loading {} pages from {:?}", page_paths.len(), path);
 
+        // functions of namespaced sources are prefixed by their namespace
+        let sources = if path.is_dir() {
+            sources::Sources::load(&path)?
+        } else {
+            sources::Sources::default()
+        };
+
         let mut pages = BTreeMap::new();
         let mut function_names = HashMap::new();
 
         for page_path in page_paths {
+            // categories are derived from the path as discovered, which might go through symlinks
+            let relative_path = page_path
+                .strip_prefix(&path)
+                .map(|relative| relative.to_path_buf())
+                .unwrap_or_default();
             let page_path = page_path.canonicalize_utf8()?;
+            if let Err(e) = verifier.verify(&page_path) {
+                log::error!("refusing to load untrusted page: {}", e);
+                trace.skip(page_path, trace::SkipReason::Untrusted);
+                continue;
+            }
+            let parse_started = std::time::Instant::now();
             let mut page = Page::from_path(&page_path)?;
+            if let Err(e) = verifier.verify_builds(&page) {
+                log::error!("refusing to load page with untrusted build files: {}", e);
+                trace.skip(page_path, trace::SkipReason::Untrusted);
+                continue;
+            }
+            trace
+                .parsed
+                .push((page_path.clone(), parse_started.elapsed()));
 
             // if name is not set, use the file name
             if page.name.is_empty() {
@@ -268,8 +1058,7 @@ impl Book {
 
             // if categories are not set, use the path components
             if page.categories.is_empty() {
-                let path_buf = page_path.strip_prefix(&path)?;
-                let parent = path_buf.parent();
+                let parent = relative_path.parent();
 
                 if let Some(parent_path) = parent {
                     page.categories = parent_path
@@ -282,6 +1071,13 @@ impl Book {
                 }
             }
 
+            if let Some(namespace) = sources.namespace_of(&relative_path) {
+                page.functions = std::mem::take(&mut page.functions)
+                    .into_iter()
+                    .map(|(name, function)| (sources::namespaced(namespace, &name), function))
+                    .collect();
+            }
+
             // make sure function names are unique
             let mut renames = HashMap::new();
             for func_name in page.functions.keys() {
@@ -294,6 +1090,11 @@ impl Book {
                             page_path,
                             new_func_name
                         );
+                        trace.renames.push(trace::Rename {
+                            path: page_path.clone(),
+                            from: func_name.clone(),
+                            to: new_func_name.clone(),
+                        });
                         renames.insert(func_name.clone(), new_func_name.clone());
                     } else {
                         return Err(anyhow::anyhow!(
@@ -317,10 +1118,93 @@ impl Book {
         Ok(Self { pages })
     }
 
+    // pages of a bundle are keyed by their path in the bundle, under the bundle path
+    fn from_bundle(
+        path: &Utf8PathBuf,
+        mut bundle: bundle::Bundle,
+        filter: Option<String>,
+    ) -> anyhow::Result<Self> {
+        if let Some(filter) = &filter {
+            bundle
+                .pages
+                .retain(|relative, _| path.join(relative).as_str().contains(filter.as_str()));
+        }
+        if bundle.pages.is_empty() {
+            return Err(anyhow::anyhow!("no pages found in {:?}", path));
+        }
+
+        let assets_dir = shellexpand::full(bundle::ASSETS_DIR)
+            .map_err(|e| anyhow::anyhow!("failed to expand path: {}", e))?
+            .into_owned();
+        let pages = bundle
+            .into_pages(Utf8PathBuf::from(assets_dir).as_path())?
+            .into_iter()
+            .map(|(relative, page)| (path.join(relative), page))
+            .collect();
+
+        Ok(Self { pages })
+    }
+
     pub fn size(&self) -> usize {
         self.pages.len()
     }
 
+    pub fn num_functions(&self) -> usize {
+        self.pages.values().map(|page| page.functions.len()).sum()
+    }
+
+    /// Returns the functions added, removed and changed by the other book, compared to this one.
+    pub fn diff(&self, other: &Book) -> BookDiff {
+        let functions = |book: &Book| -> BTreeMap<String, String> {
+            book.pages
+                .values()
+                .flat_map(|page| page.functions.iter())
+                .map(|(name, function)| {
+                    (
+                        name.clone(),
+                        serde_json::to_string(function).unwrap_or_default(),
+                    )
+                })
+                .collect()
+        };
+        let (before, after) = (functions(self), functions(other));
+
+        let mut diff = BookDiff::default();
+        for (name, function) in &after {
+            match before.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(previous) if previous != function => diff.changed.push(name.clone()),
+                _ => {}
+            }
+        }
+        diff.removed = before
+            .keys()
+            .filter(|name| !after.contains_key(*name))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    pub fn categories(&self) -> Vec<String> {
+        let mut categories: Vec<String> = self
+            .pages
+            .values()
+            .flat_map(|page| page.categories.iter().cloned())
+            .collect();
+        categories.sort();
+        categories.dedup();
+        categories
+    }
+
+    /// Groups the pages by category path, pages without categories are in the uncategorized group.
+    pub fn groups<'a>(&'a self, filter: Option<&'a str>) -> BTreeMap<String, Vec<&'a Page>> {
+        let mut groups: BTreeMap<String, Vec<&Page>> = BTreeMap::new();
+        for page in self.filtered_pages(filter) {
+            groups.entry(page.group()).or_default().push(page);
+        }
+        groups
+    }
+
     pub fn get_function<'a>(&'a self, name: &str) -> anyhow::Result<runtime::FunctionRef<'a>> {
         for (page_path, page) in &self.pages {
             if let Some(function) = page.functions.get(name) {
@@ -336,6 +1220,104 @@ impl Book {
         Err(anyhow::anyhow!("function {} not found", name))
     }
 
+    /// Returns an error if the function is a pipeline with steps calling unknown functions, or
+    /// itself through other pipelines.
+    pub fn check_pipeline(&self, name: &str) -> anyhow::Result<()> {
+        self.check_steps(name, &mut Vec::new())
+    }
+
+    fn check_steps(&self, name: &str, callers: &mut Vec<String>) -> anyhow::Result<()> {
+        let function = self.get_function(name)?;
+        let runtime::ExecutionContext::Pipeline(steps) = &function.function.execution else {
+            return Ok(());
+        };
+
+        if callers.iter().any(|caller| caller == name) {
+            return Err(anyhow::anyhow!(
+                "pipeline {} calls itself via {}",
+                name,
+                callers.join(" -> ")
+            ));
+        }
+        if steps.is_empty() {
+            return Err(anyhow::anyhow!("pipeline {} has no steps", name));
+        }
+
+        callers.push(name.to_string());
+        for step in steps {
+            if self.get_function(&step.function).is_err() {
+                return Err(anyhow::anyhow!(
+                    "step {} of pipeline {} is not a function",
+                    step.function,
+                    name
+                ));
+            }
+            self.check_steps(&step.function, callers)?;
+        }
+        callers.pop();
+
+        Ok(())
+    }
+
+    /// Returns the pages whose path contains the filter, if set.
+    pub fn filtered_pages<'a>(&'a self, filter: Option<&'a str>) -> impl Iterator<Item = &'a Page> {
+        self.pages
+            .iter()
+            .filter(move |(path, _)| filter.is_none_or(|filter| path.as_str().contains(filter)))
+            .map(|(_, page)| page)
+    }
+
+    /// Add the pages of a remote server, skipping the functions already defined.
+    pub fn merge_remote(&mut self, pages: BTreeMap<Utf8PathBuf, Page>) {
+        for (path, mut page) in pages {
+            page.functions.retain(|name, _| {
+                let exists = self.get_function(name).is_ok();
+                if exists {
+                    log::warn!("function {} of {} is already defined, skipping", name, path);
+                }
+                !exists
+            });
+            if !page.functions.is_empty() {
+                self.pages.insert(path, page);
+            }
+        }
+    }
+
+    /// Returns a copy of the book without the given functions.
+    pub fn without_functions(&self, names: &BTreeSet<String>) -> Self {
+        let mut pages = self.pages.clone();
+        for page in pages.values_mut() {
+            page.functions.retain(|name, _| !names.contains(name));
+        }
+        Self { pages }
+    }
+
+    /// Returns a copy of the book with only the given functions.
+    pub fn only_functions(&self, names: &BTreeSet<String>) -> Self {
+        let mut pages = self.pages.clone();
+        for page in pages.values_mut() {
+            page.functions.retain(|name, _| names.contains(name));
+        }
+        pages.retain(|_, page| !page.functions.is_empty());
+        Self { pages }
+    }
+
+    /// Returns a copy of the book with where each function is executed appended to its
+    /// description.
+    pub fn with_execution_summaries(&self, ssh: Option<&str>) -> Self {
+        let mut pages = self.pages.clone();
+        for page in pages.values_mut() {
+            for function in page.functions.values_mut() {
+                function.description = format!(
+                    "{} ({})",
+                    function.description,
+                    function.execution_summary(ssh)
+                );
+            }
+        }
+        Self { pages }
+    }
+
     pub fn as_tools<'a, T>(&'a self, filter: Option<String>) -> Vec<T>
     where
         Vec<T>: std::convert::From<&'a Page>,
@@ -361,9 +1343,12 @@ mod tests {
     use flavors::openai;
     use std::collections::BTreeMap;
 
+    use crate::runtime::PathArgument;
+
     fn create_test_book() -> Book {
         let mut pages = BTreeMap::new();
         let mut page = Page {
+            metadata: Default::default(),
             name: "Test Page".to_string(),
             description: Some("A test page".to_string()),
             categories: vec!["test".to_string()],
@@ -372,6 +1357,16 @@ mod tests {
         page.functions.insert(
             "test_function".to_string(),
             Function {
+                parser: None,
+                destructive: false,
+                diff_previous: false,
+                client_env: Vec::new(),
+                files: Default::default(),
+                tests: Vec::new(),
+                output_format: None,
+                remote: None,
+                examples: None,
+                priority: None,
                 description: "A test function".to_string(),
                 parameters: BTreeMap::new(),
                 execution: runtime::ExecutionContext::CommandLine(vec![
@@ -379,6 +1374,10 @@ mod tests {
                     "test".to_string(),
                 ]),
                 container: None,
+                executor: None,
+                max_concurrency: None,
+                exclusive: false,
+                cache: None,
             },
         );
         pages.insert(Utf8PathBuf::from("test_page"), page);
@@ -391,6 +1390,13 @@ mod tests {
         assert_eq!(book.size(), 1);
     }
 
+    #[test]
+    fn test_book_functions_and_categories() {
+        let book = create_test_book();
+        assert_eq!(book.num_functions(), 1);
+        assert_eq!(book.categories(), vec!["test".to_string()]);
+    }
+
     #[test]
     fn test_get_existing_function() {
         let book = create_test_book();
@@ -401,6 +1407,36 @@ mod tests {
         assert_eq!(function_ref.path, &Utf8PathBuf::from("test_page"));
     }
 
+    #[test]
+    fn test_book_without_functions() {
+        let book = create_test_book();
+        let disabled = BTreeSet::from(["test_function".to_string()]);
+
+        let filtered = book.without_functions(&disabled);
+        assert_eq!(filtered.num_functions(), 0);
+        assert!(filtered.get_function("test_function").is_err());
+        assert!(book.get_function("test_function").is_ok());
+
+        let allowed = book.only_functions(&disabled);
+        assert_eq!(allowed.num_functions(), 1);
+        assert_eq!(book.only_functions(&BTreeSet::new()).size(), 0);
+    }
+
+    #[test]
+    fn test_book_groups() {
+        let mut book = create_test_book();
+        let mut page = book.pages.values().next().unwrap().clone();
+        page.categories = vec![];
+        book.pages.insert(Utf8PathBuf::from("other.yml"), page);
+
+        let groups = book.groups(None);
+        assert_eq!(
+            groups.keys().collect::<Vec<_>>(),
+            vec!["test", UNCATEGORIZED_GROUP]
+        );
+        assert_eq!(groups["test"].len(), 1);
+    }
+
     #[test]
     fn test_get_non_existing_function() {
         let book = create_test_book();
@@ -429,6 +1465,98 @@ mod tests {
         assert_eq!(tools.len(), 0);
     }
 
+    #[test]
+    fn test_container_hardening_options() {
+        let container: Container = serde_yaml::from_str(
+            r#"
+image: nmap
+read_only: true
+user: nobody
+cap_drop: [ALL]
+cap_add: [NET_RAW]
+security_opt: [no-new-privileges]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            container.hardening_options(),
+            vec![
+                "--read-only",
+                "--tmpfs=/tmp",
+                "--user=nobody",
+                "--cap-drop=ALL",
+                "--cap-add=NET_RAW",
+                "--security-opt=no-new-privileges"
+            ]
+        );
+
+        let container: Container = serde_yaml::from_str("image: nmap").unwrap();
+        assert!(container.hardening_options().is_empty());
+    }
+
+    #[test]
+    fn test_container_mount_paths() {
+        crate::runtime::paths::set(&crate::runtime::paths::PathsConfig {
+            allowed_roots: vec![std::env::temp_dir().display().to_string()],
+        })
+        .unwrap();
+        let container: Container = serde_yaml::from_str("image: nmap").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let targets = dir.path().join("targets.txt");
+        std::fs::write(&targets, "127.0.0.1").unwrap();
+        let targets = targets.display().to_string();
+        let output = dir.path().join("scan.xml").display().to_string();
+
+        let mut cmdline = CommandLine::from_vec(&vec![
+            "nmap".to_string(),
+            "-iL".to_string(),
+            targets.clone(),
+            format!("--output={}", output),
+        ])
+        .unwrap();
+        cmdline.paths = vec![
+            PathArgument {
+                value: targets.clone(),
+                output: false,
+            },
+            PathArgument {
+                value: output,
+                output: true,
+            },
+        ];
+
+        // inputs are mounted read-only
+        let mounts = container.mount_paths(&mut cmdline).unwrap();
+        assert_eq!(
+            mounts,
+            vec![
+                format!(
+                    "{}:/robopages/paths/0/targets.txt:ro",
+                    root.join("targets.txt").display()
+                ),
+                format!("{}:/robopages/paths/1", root.display()),
+            ]
+        );
+        assert_eq!(
+            cmdline.args,
+            vec![
+                "-iL",
+                "/robopages/paths/0/targets.txt",
+                "--output=/robopages/paths/1/scan.xml"
+            ]
+        );
+
+        for path in ["/does/not/exist", "/", "/etc/passwd"] {
+            cmdline.paths = vec![PathArgument {
+                value: path.to_string(),
+                output: true,
+            }];
+            assert!(container.mount_paths(&mut cmdline).is_err());
+        }
+    }
+
     #[test]
     fn test_container_preserve_app() {
         let container = Container {
@@ -438,6 +1566,17 @@ mod tests {
             force: false,
             preserve_app: true,
             platform: None,
+            workdir: None,
+            labels: None,
+            persistent: false,
+            idle_timeout: None,
+            read_only: false,
+            user: None,
+            cap_drop: None,
+            cap_add: None,
+            security_opt: None,
+            verify: false,
+            signature: None,
         };
 
         let original_cmdline = CommandLine {
@@ -446,10 +1585,14 @@ mod tests {
             app_in_path: true,
             args: vec!["arg1".to_string(), "arg2".to_string()],
             env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
 
-        let wrapped_cmdline = container.wrap(original_cmdline).unwrap();
+        let wrapped_cmdline = container.wrap("test_function", original_cmdline).unwrap();
 
         assert!(wrapped_cmdline.args.contains(&"original_app".to_string()));
         assert!(wrapped_cmdline.args.contains(&"arg1".to_string()));
@@ -518,6 +1661,40 @@ functions:
         assert!(result.get_function("page2_duplicate_function").is_ok());
     }
 
+    #[test]
+    fn test_book_diff() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base_path = temp_dir.path();
+        let page = |functions: &str| {
+            std::fs::write(
+                base_path.join("page.yml"),
+                format!(
+                    "description: Page\ncategories: [test]\nfunctions:\n{}",
+                    functions
+                ),
+            )
+            .unwrap();
+            Book::from_path(Utf8PathBuf::from(base_path.to_str().unwrap()), None).unwrap()
+        };
+
+        let before = page(
+            "  kept: {description: Kept, parameters: {}, cmdline: [echo]}\n  edited: {description: Before, parameters: {}, cmdline: [echo]}\n  removed: {description: Removed, parameters: {}, cmdline: [echo]}\n",
+        );
+        let after = page(
+            "  kept: {description: Kept, parameters: {}, cmdline: [echo]}\n  edited: {description: After, parameters: {}, cmdline: [echo]}\n  added: {description: Added, parameters: {}, cmdline: [echo]}\n",
+        );
+
+        assert_eq!(
+            before.diff(&after),
+            BookDiff {
+                added: vec!["added".to_string()],
+                removed: vec!["removed".to_string()],
+                changed: vec!["edited".to_string()],
+            }
+        );
+        assert_eq!(after.diff(&after), BookDiff::default());
+    }
+
     #[test]
     fn test_book_skips_hidden_directories() {
         use std::fs;
@@ -566,6 +1743,88 @@ functions:
         assert!(result.get_function("function2").is_err());
     }
 
+    #[test]
+    fn test_book_namespaces() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::with_prefix("robopage-test-").unwrap();
+        let base_path = temp_dir.path();
+
+        let page = r#"
+description: A page
+functions:
+  scan:
+    description: A function
+    parameters: {}
+    cmdline: [echo, test]
+"#;
+        for namespace in ["offensive", "cloud"] {
+            fs::create_dir(base_path.join(namespace)).unwrap();
+            fs::write(base_path.join(namespace).join("page.yml"), page).unwrap();
+        }
+        fs::write(
+            base_path.join(sources::SOURCES_MANIFEST),
+            "offensive:\n  source: org/repo\ncloud:\n  source: other/repo\n",
+        )
+        .unwrap();
+
+        let book = Book::from_path(Utf8PathBuf::from(base_path.to_str().unwrap()), None).unwrap();
+
+        assert_eq!(book.num_functions(), 2);
+        assert!(book.get_function("offensive__scan").is_ok());
+        assert!(book.get_function("cloud__scan").is_ok());
+        assert!(book.get_function("scan").is_err());
+    }
+
+    #[test]
+    fn test_check_pipeline() {
+        let temp_dir = tempfile::TempDir::with_prefix("robopage-test-").unwrap();
+        let page = r#"
+description: A page
+functions:
+  scan:
+    description: Scan a host.
+    parameters: {}
+    cmdline: [echo, scan]
+  report:
+    description: Scan and report.
+    parameters: {}
+    pipeline:
+      - function: scan
+      - function: summarize
+        arguments:
+          text: ${steps.0.stdout}
+  summarize:
+    description: Summarize a report.
+    parameters: {}
+    cmdline: [echo, summary]
+  broken:
+    description: Call a missing function.
+    parameters: {}
+    pipeline:
+      - function: missing
+  ping:
+    description: Call pong.
+    parameters: {}
+    pipeline:
+      - function: pong
+  pong:
+    description: Call ping.
+    parameters: {}
+    pipeline:
+      - function: ping
+"#;
+        std::fs::write(temp_dir.path().join("page.yml"), page).unwrap();
+        let book =
+            Book::from_path(Utf8PathBuf::from(temp_dir.path().to_str().unwrap()), None).unwrap();
+
+        assert!(book.check_pipeline("scan").is_ok());
+        assert!(book.check_pipeline("report").is_ok());
+        assert!(book.check_pipeline("broken").is_err());
+        assert!(book.check_pipeline("ping").is_err());
+    }
+
     #[test]
     fn test_wrap_with_env() {
         let env: BTreeMap<String, String> = {
@@ -585,9 +1844,20 @@ functions:
             force: false,
             preserve_app: true,
             platform: None,
+            workdir: None,
+            labels: None,
+            persistent: false,
+            idle_timeout: None,
+            read_only: false,
+            user: None,
+            cap_drop: None,
+            cap_add: None,
+            security_opt: None,
+            verify: false,
+            signature: None,
         };
 
-        let wrapped = container.wrap(command_line).unwrap();
+        let wrapped = container.wrap("test_function", command_line).unwrap();
 
         // Find the env-file argument
         let env_file_arg = wrapped
@@ -615,4 +1885,289 @@ functions:
         drop(wrapped);
         assert!(!env_file.exists(), "env file was not deleted");
     }
+
+    #[test]
+    fn test_wrap_with_workdir_and_labels() {
+        let mut labels = BTreeMap::new();
+        labels.insert("team".to_string(), "red".to_string());
+
+        let container = Container {
+            source: ContainerSource::Image("test_image".to_string()),
+            args: None,
+            volumes: None,
+            force: false,
+            preserve_app: false,
+            platform: Some("linux/arm64".to_string()),
+            workdir: Some("/data".to_string()),
+            labels: Some(labels),
+            persistent: false,
+            idle_timeout: None,
+            read_only: false,
+            user: None,
+            cap_drop: None,
+            cap_add: None,
+            security_opt: None,
+            verify: false,
+            signature: None,
+        };
+
+        let command_line = CommandLine::from_vec(&vec!["echo".to_string()]).unwrap();
+        let wrapped = container.wrap("test_function", command_line).unwrap();
+
+        assert!(wrapped
+            .args
+            .contains(&"-lrobopages.function=test_function".to_string()));
+        assert!(wrapped.args.contains(&"-lteam=red".to_string()));
+        assert!(wrapped.args.contains(&"-w/data".to_string()));
+        assert!(wrapped.args.contains(&"--platform=linux/arm64".to_string()));
+
+        // options must come before the image
+        let image_index = wrapped
+            .args
+            .iter()
+            .position(|arg| arg == "test_image")
+            .unwrap();
+        let workdir_index = wrapped
+            .args
+            .iter()
+            .position(|arg| arg == "-w/data")
+            .unwrap();
+        assert!(workdir_index < image_index);
+    }
+
+    #[test]
+    fn test_container_build_deserialize() {
+        let container: Container = serde_yaml::from_str(
+            r#"
+build:
+  name: test
+  path: /tmp/tools/Dockerfile
+  target: runtime
+  build_args:
+    VERSION: "1.0"
+"#,
+        )
+        .unwrap();
+
+        let ContainerSource::Build(build) = container.source else {
+            panic!("expected a build source");
+        };
+        assert_eq!(build.name, "test");
+        assert_eq!(build.target, Some("runtime".to_string()));
+        assert_eq!(
+            build.build_args.unwrap().get("VERSION"),
+            Some(&"1.0".to_string())
+        );
+        assert_eq!(build.context, None);
+    }
+
+    #[test]
+    fn test_wrap_exec() {
+        let container = Container {
+            source: ContainerSource::Image("test_image".to_string()),
+            args: None,
+            volumes: None,
+            force: false,
+            preserve_app: true,
+            platform: None,
+            workdir: None,
+            labels: None,
+            persistent: false,
+            idle_timeout: None,
+            read_only: false,
+            user: None,
+            cap_drop: None,
+            cap_add: None,
+            security_opt: None,
+            verify: false,
+            signature: None,
+        };
+
+        let cmdline = CommandLine {
+            sudo: false,
+            app: "nmap".to_string(),
+            app_in_path: true,
+            args: vec!["-v".to_string()],
+            env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
+            temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
+        };
+
+        let wrapped = container
+            .wrap_exec("warm", &["/entrypoint.sh".to_string()], cmdline)
+            .unwrap();
+
+        assert_eq!(
+            wrapped.args,
+            vec!["exec", "warm", "/entrypoint.sh", "nmap", "-v"]
+        );
+        assert!(wrapped.container.is_none());
+    }
+
+    #[test]
+    fn test_function_examples() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: Scan a host.
+parameters: {}
+cmdline: [echo]
+examples:
+  - target: example.com
+    port: 443
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            function.examples_json(),
+            vec![r#"{"port":443,"target":"example.com"}"#]
+        );
+        assert_eq!(
+            function.description_with_examples(),
+            "Scan a host.\n\nExample arguments:\n- {\"port\":443,\"target\":\"example.com\"}"
+        );
+    }
+
+    #[test]
+    fn test_function_execution_summary() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: Scan a host.
+parameters: {}
+cmdline: [this_app_does_not_exist]
+container:
+  image: example/scanner
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            function.execution_summary(Some("root@10.0.0.1:22")),
+            "runs via ssh host root@10.0.0.1:22"
+        );
+        assert_eq!(
+            function.execution_summary(None),
+            format!("runs in {} image example/scanner", get_container_runtime())
+        );
+
+        let book = create_test_book().with_execution_summaries(None);
+        assert_eq!(
+            book.get_function("test_function")
+                .unwrap()
+                .function
+                .description,
+            "A test function (runs on the host)"
+        );
+    }
+
+    #[test]
+    fn test_function_output_format() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: Scan a host.
+parameters: {}
+cmdline: [echo]
+output_format: json
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(function.output_format, Some(parsers::OutputFormat::Json));
+        assert_eq!(
+            function.description_with_format(),
+            "Scan a host. Returns JSON."
+        );
+        assert_eq!(
+            function.description_with_examples(),
+            "Scan a host. Returns JSON."
+        );
+    }
+
+    #[test]
+    fn test_page_metadata_deserialize() {
+        let page: Page = serde_yaml::from_str(
+            r#"
+description: test
+author: someone
+license: MIT
+functions: {}
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            page.metadata.fields(),
+            vec![("author", "someone"), ("license", "MIT")]
+        );
+    }
+
+    #[test]
+    fn test_function_priority_deserialize() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: test
+parameters: {}
+cmdline: [echo]
+priority: high
+"#,
+        )
+        .unwrap();
+        assert_eq!(function.priority, Some(Priority::High));
+        assert!(Priority::Low < Priority::Normal && Priority::Normal < Priority::High);
+    }
+
+    #[test]
+    fn test_function_concurrency_limit() {
+        let function: Function =
+            serde_yaml::from_str("description: test\nparameters: {}\ncmdline: [echo]\n").unwrap();
+        assert_eq!(function.concurrency_limit(), None);
+
+        let function: Function = serde_yaml::from_str(
+            "description: test\nparameters: {}\ncmdline: [echo]\nmax_concurrency: 4\n",
+        )
+        .unwrap();
+        assert_eq!(function.concurrency_limit(), Some(4));
+
+        let function: Function = serde_yaml::from_str(
+            "description: test\nparameters: {}\ncmdline: [masscan]\nexclusive: true\n",
+        )
+        .unwrap();
+        assert_eq!(function.concurrency_limit(), Some(1));
+    }
+
+    #[test]
+    fn test_target_parameter_schema_type() {
+        let param: Parameter =
+            serde_yaml::from_str("type: target\ndescription: host to scan").unwrap();
+        assert_eq!(param.param_type, "target");
+        assert_eq!(param.schema_type(), "string");
+    }
+
+    #[test]
+    fn test_function_test_check() {
+        let test: FunctionTest = serde_yaml::from_str(
+            "arguments: {target: example.com}\ncontains: [open]\nmatches: \"\\\\d+/tcp\"\n",
+        )
+        .unwrap();
+        assert!(test.check("80/tcp open http").is_ok());
+        assert!(test.check("80/tcp closed http").is_err());
+        assert!(test.check("open").is_err());
+        assert!(test
+            .check(&format!(
+                "{}1\n80/tcp open",
+                crate::runtime::EXIT_CODE_PREFIX
+            ))
+            .is_err());
+
+        let failing = FunctionTest {
+            exit_code: 1,
+            ..Default::default()
+        };
+        assert!(failing
+            .check(&format!("{}1\nerror", crate::runtime::EXIT_CODE_PREFIX))
+            .is_ok());
+    }
 }
@@ -0,0 +1,156 @@
+use std::path::Path;
+
+use camino::Utf8PathBuf;
+use tokio::process::Command;
+
+/// Artifact type of books pushed to OCI registries.
+pub(crate) const ARTIFACT_TYPE: &str = "application/vnd.robopages.book.v1+zip";
+
+/// Reference to a book in an OCI registry, such as oci://ghcr.io/org/book:1.2.0
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Reference {
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl Reference {
+    pub fn parse(reference: &str) -> anyhow::Result<Self> {
+        let reference = reference.strip_prefix("oci://").unwrap_or(reference);
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (reference, None),
+        };
+
+        // a colon after the last slash separates the tag, otherwise it's the port of the registry
+        let (repository, tag) = match name.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag.to_string())),
+            _ => (name, None),
+        };
+
+        // like docker, latest unless pinned
+        let tag = tag.or_else(|| digest.is_none().then(|| "latest".to_string()));
+
+        if repository.is_empty() || !repository.contains('/') {
+            return Err(anyhow::anyhow!("invalid oci reference {}", reference));
+        }
+
+        Ok(Self {
+            repository: repository.to_string(),
+            tag,
+            digest,
+        })
+    }
+
+    /// Returns the same artifact, pinned to its digest.
+    pub fn pinned(&self, digest: &str) -> Self {
+        Self {
+            digest: Some(digest.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+impl std::fmt::Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+// oras reuses the credentials of docker login
+async fn oras(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("oras")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("could not run oras: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "oras {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Returns the digest of the artifact.
+pub(crate) async fn resolve(reference: &Reference) -> anyhow::Result<String> {
+    if let Some(digest) = &reference.digest {
+        return Ok(digest.clone());
+    }
+    oras(Path::new("."), &["resolve", &reference.to_string()]).await
+}
+
+/// Push the files of dir as an artifact and returns its digest.
+pub(crate) async fn push(
+    reference: &Reference,
+    dir: &Path,
+    files: &[String],
+) -> anyhow::Result<String> {
+    let reference = reference.to_string();
+    let mut args = vec!["push", &reference, "--artifact-type", ARTIFACT_TYPE];
+    args.extend(files.iter().map(|file| file.as_str()));
+    oras(dir, &args).await?;
+
+    resolve(&Reference::parse(&reference)?).await
+}
+
+/// Pull the artifact to dir and returns the path of the archive of the book.
+pub(crate) async fn pull(reference: &Reference, dir: &Path) -> anyhow::Result<Utf8PathBuf> {
+    let dir_arg = dir.to_string_lossy();
+    oras(
+        dir,
+        &["pull", &reference.to_string(), "--output", dir_arg.as_ref()],
+    )
+    .await?;
+
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "zip") {
+            return Utf8PathBuf::from_path_buf(path)
+                .map_err(|p| anyhow::anyhow!("invalid path {:?}", p));
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "{} does not contain a book archive",
+        reference
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reference() {
+        let reference = Reference::parse("oci://ghcr.io/org/book:1.2.0").unwrap();
+        assert_eq!(reference.repository, "ghcr.io/org/book");
+        assert_eq!(reference.tag.as_deref(), Some("1.2.0"));
+        assert_eq!(reference.digest, None);
+        assert_eq!(
+            reference.pinned("sha256:abcd").to_string(),
+            "ghcr.io/org/book:1.2.0@sha256:abcd"
+        );
+
+        let reference = Reference::parse("localhost:5000/book@sha256:abcd").unwrap();
+        assert_eq!(reference.repository, "localhost:5000/book");
+        assert_eq!(reference.tag, None);
+        assert_eq!(reference.digest.as_deref(), Some("sha256:abcd"));
+
+        assert_eq!(
+            Reference::parse("ghcr.io/org/book").unwrap().tag.as_deref(),
+            Some("latest")
+        );
+        assert!(Reference::parse("oci://book").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Read, Write},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{Book, ContainerSource};
+
+/// Name of the manifest describing a packaged book.
+pub(crate) const PACKAGE_MANIFEST: &str = "robopages-package.yml";
+/// Name of the lockfile with the hashes of the files of a packaged book.
+pub(crate) const PACKAGE_LOCKFILE: &str = "robopages.lock";
+
+/// Name, version and contents of a packaged book.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    pub name: String,
+    pub version: String,
+    pub robopages_version: String,
+    pub created_at: String,
+    pub functions: Vec<String>,
+}
+
+/// What a packaged book is made of, to verify it once installed.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Lockfile {
+    /// SHA256 of each file, by path relative to the root of the book.
+    pub files: BTreeMap<String, String>,
+    /// Container images referenced by the functions.
+    pub images: BTreeSet<String>,
+}
+
+fn sha256(path: &Utf8Path) -> anyhow::Result<String> {
+    let data =
+        std::fs::read(path).map_err(|e| anyhow::anyhow!("error while reading {}: {}", path, e))?;
+    Ok(format!("{:x}", Sha256::digest(&data)))
+}
+
+// hidden files, such as .git or the install records, are not part of the book
+fn collect_files(
+    root: &Utf8Path,
+    dir: &Utf8Path,
+    files: &mut Vec<Utf8PathBuf>,
+) -> anyhow::Result<()> {
+    let mut entries = dir
+        .read_dir_utf8()
+        .map_err(|e| anyhow::anyhow!("error while reading {}: {}", dir, e))?
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+
+    for entry in entries {
+        if entry.file_name().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(root, path, files)?;
+        } else if entry.file_name() != PACKAGE_MANIFEST && entry.file_name() != PACKAGE_LOCKFILE {
+            files.push(path.strip_prefix(root)?.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+impl Lockfile {
+    pub fn new(root: &Utf8Path, files: &[Utf8PathBuf], book: &Book) -> anyhow::Result<Self> {
+        let files = files
+            .iter()
+            .map(|file| Ok((file.to_string(), sha256(&root.join(file))?)))
+            .collect::<anyhow::Result<_>>()?;
+
+        let images = book
+            .pages
+            .values()
+            .flat_map(|page| page.functions.values())
+            .filter_map(|function| match &function.container.as_ref()?.source {
+                ContainerSource::Image(image) => Some(image.clone()),
+                // built from a Dockerfile that is part of the package
+                ContainerSource::Build(_) => None,
+            })
+            .collect();
+
+        Ok(Self { files, images })
+    }
+
+    /// Check that the files of an installed package match their hashes.
+    pub fn verify(&self, root: &Utf8Path) -> anyhow::Result<()> {
+        for (file, expected) in &self.files {
+            let path = root.join(file);
+            if !path.exists() {
+                return Err(anyhow::anyhow!("{} is missing from the package", file));
+            }
+            if sha256(&path)? != *expected {
+                return Err(anyhow::anyhow!(
+                    "{} does not match the hash of the lockfile",
+                    file
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Package the book in root as a zip archive with its manifest and lockfile.
+pub(crate) fn create(
+    root: &Utf8Path,
+    name: &str,
+    version: &str,
+    archive_path: &Utf8Path,
+) -> anyhow::Result<Manifest> {
+    // refuse to package a book that can't be loaded
+    let book = Book::from_path(root.to_path_buf(), None)?;
+
+    let mut files = Vec::new();
+    collect_files(root, root, &mut files)?;
+    if let Ok(archive) = archive_path.strip_prefix(root) {
+        files.retain(|file| file != archive);
+    }
+
+    let mut functions: Vec<String> = book
+        .pages
+        .values()
+        .flat_map(|page| page.functions.keys().cloned())
+        .collect();
+    functions.sort();
+
+    let manifest = Manifest {
+        name: name.to_string(),
+        version: version.to_string(),
+        robopages_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string(),
+        functions,
+    };
+    let lockfile = Lockfile::new(root, &files, &book)?;
+
+    // fixed timestamps, so that packaging the same files twice produces the same entries
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default())
+        .unix_permissions(0o644);
+
+    let file = std::fs::File::create(archive_path)
+        .map_err(|e| anyhow::anyhow!("could not create {}: {}", archive_path, e))?;
+    let mut zip = zip::ZipWriter::new(file);
+
+    zip.start_file(PACKAGE_MANIFEST, options)?;
+    zip.write_all(serde_yaml::to_string(&manifest)?.as_bytes())?;
+    zip.start_file(PACKAGE_LOCKFILE, options)?;
+    zip.write_all(serde_yaml::to_string(&lockfile)?.as_bytes())?;
+
+    for file in &files {
+        // zip paths always use forward slashes
+        let name = file
+            .components()
+            .map(|c| c.as_str())
+            .collect::<Vec<_>>()
+            .join("/");
+        zip.start_file(name, options)?;
+        zip.write_all(&std::fs::read(root.join(file))?)?;
+    }
+    zip.finish()?;
+
+    Ok(manifest)
+}
+
+/// Read the manifest of a package archive.
+pub(crate) fn read_manifest(archive_path: &Utf8Path) -> anyhow::Result<Manifest> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(archive_path)?)?;
+    let mut entry = archive
+        .by_name(PACKAGE_MANIFEST)
+        .map_err(|_| anyhow::anyhow!("{} is not a robopages package", archive_path))?;
+
+    let mut text = String::new();
+    entry.read_to_string(&mut text)?;
+    serde_yaml::from_str(&text)
+        .map_err(|e| anyhow::anyhow!("invalid manifest in {}: {}", archive_path, e))
+}
+
+/// If the installed path is a package, check its files against the lockfile and returns its
+/// manifest.
+pub(crate) fn verify_installed(root: &Utf8Path) -> anyhow::Result<Option<Manifest>> {
+    let manifest_path = root.join(PACKAGE_MANIFEST);
+    if !manifest_path.exists() {
+        return Ok(None);
+    }
+
+    let manifest: Manifest = serde_yaml::from_str(&std::fs::read_to_string(&manifest_path)?)
+        .map_err(|e| anyhow::anyhow!("error while parsing {}: {}", manifest_path, e))?;
+    let lockfile_path = root.join(PACKAGE_LOCKFILE);
+    let lockfile: Lockfile = serde_yaml::from_str(
+        &std::fs::read_to_string(&lockfile_path)
+            .map_err(|e| anyhow::anyhow!("error while reading {}: {}", lockfile_path, e))?,
+    )
+    .map_err(|e| anyhow::anyhow!("error while parsing {}: {}", lockfile_path, e))?;
+
+    lockfile.verify(root)?;
+
+    Ok(Some(manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"
+functions:
+  httpx_probe:
+    description: Probe a host.
+    parameters:
+      target:
+        type: string
+        description: The target.
+    container:
+      image: projectdiscovery/httpx
+    cmdline: [httpx, -u, "${target}"]
+"#;
+
+    #[test]
+    fn test_create_and_verify() {
+        let src = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(src.path()).unwrap();
+        std::fs::create_dir_all(root.join("recon")).unwrap();
+        std::fs::write(root.join("recon/httpx.yml"), PAGE).unwrap();
+        std::fs::write(root.join(".robopages-install.yml"), "source: org/repo\n").unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let archive = Utf8Path::from_path(out.path())
+            .unwrap()
+            .join("recon-1.0.0.zip");
+        let manifest = create(root, "recon", "1.0.0", &archive).unwrap();
+        assert_eq!(manifest.functions, vec!["httpx_probe"]);
+        assert_eq!(read_manifest(&archive).unwrap().version, "1.0.0");
+
+        let dst = tempfile::tempdir().unwrap();
+        let installed = Utf8Path::from_path(dst.path()).unwrap();
+        zip::ZipArchive::new(std::fs::File::open(&archive).unwrap())
+            .unwrap()
+            .extract(installed)
+            .unwrap();
+        assert!(!installed.join(".robopages-install.yml").exists());
+
+        let lockfile: Lockfile = serde_yaml::from_str(
+            &std::fs::read_to_string(installed.join(PACKAGE_LOCKFILE)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            lockfile.images,
+            BTreeSet::from(["projectdiscovery/httpx".to_string()])
+        );
+        assert_eq!(verify_installed(installed).unwrap().unwrap().name, "recon");
+
+        std::fs::write(installed.join("recon/httpx.yml"), "tampered").unwrap();
+        assert!(verify_installed(installed).is_err());
+    }
+}
@@ -0,0 +1,457 @@
+use jaq_core::{
+    load::{Arena, File, Loader},
+    Ctx, RcIter,
+};
+use jaq_json::Val;
+use lazy_regex::{lazy_regex, Lazy};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+
+static XML_WHITESPACE: Lazy<Regex> = lazy_regex!(r">\s+<");
+
+/// Format of the output of a function, hinted to the model and used to compact the output when
+/// no parser is set.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Json,
+    Xml,
+    Text,
+}
+
+impl OutputFormat {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "JSON",
+            OutputFormat::Xml => "XML",
+            OutputFormat::Text => "plain text",
+        }
+    }
+
+    /// Remove the formatting whitespace, invalid documents are returned as they are.
+    pub fn compact(&self, output: &str) -> String {
+        match self {
+            OutputFormat::Json => serde_json::from_str::<serde_json::Value>(output)
+                .map(|value| value.to_string())
+                .unwrap_or_else(|_| output.to_string()),
+            OutputFormat::Xml => XML_WHITESPACE.replace_all(output.trim(), "><").to_string(),
+            OutputFormat::Text => output.to_string(),
+        }
+    }
+}
+
+/// Converts column oriented output to a JSON array of records.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Columns {
+    /// Column separator, any whitespace if not set. With whitespace the last column keeps the rest
+    /// of the line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+    /// Names of the fields, if not set they're taken from the first line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<Vec<String>>,
+    /// Number of lines to skip before the header or the first record.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub skip: usize,
+    /// Lines starting with this prefix are ignored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl Columns {
+    fn split<'a>(&self, line: &'a str, max: usize) -> Vec<&'a str> {
+        match self.separator.as_deref() {
+            Some(separator) => line.splitn(max, separator).map(str::trim).collect(),
+            None => {
+                let mut fields = Vec::new();
+                let mut rest = line.trim();
+                while !rest.is_empty() {
+                    if fields.len() + 1 == max {
+                        fields.push(rest);
+                        break;
+                    }
+                    let (field, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    fields.push(field);
+                    rest = tail.trim_start();
+                }
+                fields
+            }
+        }
+    }
+
+    fn records(&self, output: &str) -> Vec<serde_json::Map<String, serde_json::Value>> {
+        let mut lines = output
+            .lines()
+            .skip(self.skip)
+            .filter(|line| !line.trim().is_empty())
+            .filter(|line| {
+                self.comment
+                    .as_ref()
+                    .is_none_or(|comment| !line.starts_with(comment.as_str()))
+            });
+
+        let headers: Vec<String> = match &self.headers {
+            Some(headers) => headers.clone(),
+            None => match lines.next() {
+                Some(header) => self
+                    .split(header, usize::MAX)
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+                None => return Vec::new(),
+            },
+        };
+
+        lines
+            .map(|line| {
+                headers
+                    .iter()
+                    .zip(self.split(line, headers.len()))
+                    .map(|(name, value)| (name.clone(), value.into()))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Reduces the output of a function before it's returned to the model, the steps are applied in
+/// the order jq, regex, columns, head and tail.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Parser {
+    /// jq expression applied to JSON output, strings are returned raw and other values as JSON, one per line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jq: Option<String>,
+    /// Only keep the matches, or their capture groups separated by a space, one per line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// Convert the lines to a JSON array of records.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Columns>,
+    /// Only keep the first lines, or records with columns.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head: Option<usize>,
+    /// Only keep the last lines, or records with columns.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tail: Option<usize>,
+}
+
+impl Parser {
+    /// Returns the parser for the given preset: columns, csv or tsv.
+    pub fn preset(name: &str) -> anyhow::Result<Self> {
+        let separator = match name {
+            "columns" => None,
+            "csv" => Some(",".to_string()),
+            "tsv" => Some("\t".to_string()),
+            _ => return Err(anyhow::anyhow!("unknown parser preset: {}", name)),
+        };
+
+        Ok(Self {
+            columns: Some(Columns {
+                separator,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// Make sure the expressions are valid.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(jq) = &self.jq {
+            compile_jq(jq)?;
+        }
+        if let Some(regex) = &self.regex {
+            Regex::new(regex).map_err(|e| anyhow::anyhow!("invalid regex {}: {}", regex, e))?;
+        }
+        if let Some(columns) = &self.columns {
+            if columns.separator.as_ref().is_some_and(|s| s.is_empty()) {
+                return Err(anyhow::anyhow!("columns separator can't be empty"));
+            }
+            if columns.headers.as_ref().is_some_and(|h| h.is_empty()) {
+                return Err(anyhow::anyhow!("columns headers can't be empty"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, output: &str) -> anyhow::Result<String> {
+        let mut output = output.to_string();
+
+        if let Some(jq) = &self.jq {
+            output = apply_jq(jq, &output)?;
+        }
+
+        if let Some(regex) = &self.regex {
+            output = apply_regex(regex, &output)?;
+        }
+
+        if let Some(columns) = &self.columns {
+            let records = self.limit(columns.records(&output));
+            return Ok(serde_json::to_string(&records)?);
+        }
+
+        Ok(self.limit(output.lines().collect()).join("\n"))
+    }
+
+    fn limit<T>(&self, mut items: Vec<T>) -> Vec<T> {
+        if let Some(head) = self.head {
+            items.truncate(head);
+        }
+        if let Some(tail) = self.tail {
+            items.drain(..items.len().saturating_sub(tail));
+        }
+        items
+    }
+}
+
+/// Parsers are either set in full or as the name of a preset.
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<Parser>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Definition {
+        Preset(String),
+        Parser(Parser),
+    }
+
+    match Option::<Definition>::deserialize(deserializer)? {
+        Some(Definition::Preset(name)) => Parser::preset(&name)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        Some(Definition::Parser(parser)) => Ok(Some(parser)),
+        None => Ok(None),
+    }
+}
+
+fn compile_jq(jq: &str) -> anyhow::Result<jaq_core::Filter<jaq_core::Native<Val>>> {
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let arena = Arena::default();
+    let modules = loader
+        .load(&arena, File { code: jq, path: () })
+        .map_err(|errs| anyhow::anyhow!("invalid jq expression {}: {:?}", jq, errs))?;
+
+    jaq_core::Compiler::default()
+        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+        .compile(modules)
+        .map_err(|errs| anyhow::anyhow!("invalid jq expression {}: {:?}", jq, errs))
+}
+
+fn apply_jq(jq: &str, output: &str) -> anyhow::Result<String> {
+    let filter = compile_jq(jq)?;
+    let input: serde_json::Value = serde_json::from_str(output)
+        .map_err(|e| anyhow::anyhow!("output is not valid JSON: {}", e))?;
+
+    let inputs = RcIter::new(core::iter::empty());
+    let mut lines = Vec::new();
+    for value in filter.run((Ctx::new([], &inputs), Val::from(input))) {
+        let value = serde_json::Value::from(
+            value.map_err(|e| anyhow::anyhow!("jq expression {} failed: {}", jq, e))?,
+        );
+        lines.push(match value {
+            serde_json::Value::String(s) => s,
+            value => value.to_string(),
+        });
+    }
+
+    Ok(lines.join("\n"))
+}
+
+fn apply_regex(regex: &str, output: &str) -> anyhow::Result<String> {
+    let regex = Regex::new(&format!("(?m){}", regex))
+        .map_err(|e| anyhow::anyhow!("invalid regex {}: {}", regex, e))?;
+
+    let lines: Vec<String> = regex
+        .captures_iter(output)
+        .map(|caps| {
+            if caps.len() == 1 {
+                caps[0].to_string()
+            } else {
+                caps.iter()
+                    .skip(1)
+                    .flatten()
+                    .map(|m| m.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
+        })
+        .collect();
+
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NMAP_OUTPUT: &str = "Starting Nmap 7.94
+Nmap scan report for scanme.nmap.org (45.33.32.156)
+PORT      STATE    SERVICE
+22/tcp    open     ssh
+25/tcp    filtered smtp
+80/tcp    open     http
+9929/tcp  open     nping-echo
+Nmap done: 1 IP address (1 host up) scanned in 2.10 seconds";
+
+    #[test]
+    fn test_regex_parser() {
+        let parser = Parser {
+            regex: Some(r"^(\d+/tcp)\s+open\s+(\S+)".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            parser.apply(NMAP_OUTPUT).unwrap(),
+            "22/tcp ssh\n80/tcp http\n9929/tcp nping-echo"
+        );
+
+        let parser = Parser {
+            regex: Some(r"\d+/tcp".to_string()),
+            head: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(parser.apply(NMAP_OUTPUT).unwrap(), "22/tcp\n25/tcp");
+    }
+
+    #[test]
+    fn test_head_tail_parser() {
+        let parser = Parser {
+            head: Some(3),
+            tail: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            parser.apply(NMAP_OUTPUT).unwrap(),
+            "PORT      STATE    SERVICE"
+        );
+    }
+
+    #[test]
+    fn test_jq_parser() {
+        let parser = Parser {
+            jq: Some(".hosts[] | select(.up) | .ip".to_string()),
+            ..Default::default()
+        };
+        let output = r#"{"hosts": [{"ip": "10.0.0.1", "up": true}, {"ip": "10.0.0.2", "up": false}, {"ip": "10.0.0.3", "up": true}]}"#;
+        assert_eq!(parser.apply(output).unwrap(), "10.0.0.1\n10.0.0.3");
+
+        let parser = Parser {
+            jq: Some(".hosts | length".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(parser.apply(output).unwrap(), "3");
+
+        assert!(parser.apply("not json").is_err());
+    }
+
+    #[test]
+    fn test_columns_parser() {
+        let ps = "  PID TTY          TIME CMD
+    1 ?        00:00:01 /sbin/init splash
+  412 pts/0    00:00:00 bash";
+
+        let parser = Parser::preset("columns").unwrap();
+        assert_eq!(
+            parser.apply(ps).unwrap(),
+            r#"[{"CMD":"/sbin/init splash","PID":"1","TIME":"00:00:01","TTY":"?"},{"CMD":"bash","PID":"412","TIME":"00:00:00","TTY":"pts/0"}]"#
+        );
+
+        let parser = Parser {
+            columns: Some(Columns {
+                headers: Some(vec!["pid".to_string(), "command".to_string()]),
+                skip: 1,
+                ..Default::default()
+            }),
+            tail: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            parser.apply("header\n1 init\n2 bash -l").unwrap(),
+            r#"[{"command":"bash -l","pid":"2"}]"#
+        );
+
+        let grepable = "# Nmap 7.94 scan
+Host: 10.0.0.1 ()\tStatus: Up
+Host: 10.0.0.2 ()\tStatus: Down
+# Nmap done";
+        let parser = Parser {
+            columns: Some(Columns {
+                separator: Some("\t".to_string()),
+                headers: Some(vec!["host".to_string(), "status".to_string()]),
+                comment: Some("#".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            parser.apply(grepable).unwrap(),
+            r#"[{"host":"Host: 10.0.0.1 ()","status":"Status: Up"},{"host":"Host: 10.0.0.2 ()","status":"Status: Down"}]"#
+        );
+
+        assert!(Parser::preset("xml").is_err());
+    }
+
+    #[test]
+    fn test_parser_deserialize() {
+        #[derive(Deserialize)]
+        struct Function {
+            #[serde(default, deserialize_with = "deserialize")]
+            parser: Option<Parser>,
+        }
+
+        let function: Function = serde_yaml::from_str("parser: csv").unwrap();
+        assert_eq!(
+            function.parser.unwrap().columns.unwrap().separator,
+            Some(",".to_string())
+        );
+
+        let function: Function = serde_yaml::from_str("parser:\n  head: 3").unwrap();
+        assert_eq!(function.parser.unwrap().head, Some(3));
+
+        let function: Function = serde_yaml::from_str("{}").unwrap();
+        assert!(function.parser.is_none());
+
+        assert!(serde_yaml::from_str::<Function>("parser: nope").is_err());
+    }
+
+    #[test]
+    fn test_output_format_compact() {
+        assert_eq!(
+            OutputFormat::Json.compact("{\n  \"hosts\": [\n    \"10.0.0.1\"\n  ]\n}\n"),
+            r#"{"hosts":["10.0.0.1"]}"#
+        );
+        assert_eq!(OutputFormat::Json.compact("not json"), "not json");
+        assert_eq!(
+            OutputFormat::Xml.compact("<hosts>\n  <host addr=\"10.0.0.1\"/>\n</hosts>\n"),
+            r#"<hosts><host addr="10.0.0.1"/></hosts>"#
+        );
+        assert_eq!(OutputFormat::Text.compact("a\n\nb"), "a\n\nb");
+    }
+
+    #[test]
+    fn test_parser_validate() {
+        assert!(Parser {
+            jq: Some(".[".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(Parser {
+            regex: Some("(".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(Parser {
+            jq: Some(".foo".to_string()),
+            regex: Some("\\d+".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_ok());
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use camino::Utf8PathBuf;
+use serde::Deserialize;
+
+use crate::runtime::correlation;
+
+use super::{runtime::ExecutionContext, sources, Function, Page, PageMetadata, Parameter};
+
+/// Another robopages server whose functions are merged into the local book.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Remote {
+    pub url: String,
+    /// Bearer token, if the remote server requires authentication.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// If set, the remote functions are prefixed by this namespace.
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
+/// Where calls to a remote function are forwarded.
+#[derive(Debug, Clone)]
+pub struct RemoteFunction {
+    url: String,
+    token: Option<String>,
+    name: String,
+}
+
+// the subset of the remote listings needed to rebuild the pages
+#[derive(Debug, Deserialize)]
+struct RemotePage {
+    name: String,
+    description: Option<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    functions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteTool {
+    function: RemoteToolFunction,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteToolFunction {
+    name: String,
+    description: String,
+    parameters: RemoteParameters,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteParameters {
+    #[serde(default)]
+    properties: BTreeMap<String, RemoteParameter>,
+    #[serde(default)]
+    required: BTreeSet<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteParameter {
+    #[serde(rename = "type")]
+    param_type: String,
+    #[serde(default)]
+    description: String,
+}
+
+impl RemoteToolFunction {
+    fn into_function(self, remote: &Remote) -> Function {
+        let parameters = self
+            .parameters
+            .properties
+            .into_iter()
+            .map(|(name, param)| {
+                let required = self.parameters.required.contains(&name);
+                (
+                    name,
+                    Parameter {
+                        param_type: param.param_type,
+                        description: param.description,
+                        required,
+                        examples: None,
+                        flag: None,
+                        separator: None,
+                        sensitive: false,
+                        output: false,
+                        constraints: Default::default(),
+                    },
+                )
+            })
+            .collect();
+
+        Function {
+            description: self.description,
+            parameters,
+            container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
+            priority: None,
+            examples: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            parser: None,
+            remote: Some(RemoteFunction {
+                url: remote.url.clone(),
+                token: remote.token.clone(),
+                name: self.name,
+            }),
+            execution: ExecutionContext::CommandLine(Vec::new()),
+        }
+    }
+}
+
+impl Remote {
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}{}", self.url.trim_end_matches('/'), path)
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        let url = self.endpoint(path);
+        let mut request = reqwest::Client::new().get(&url);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| anyhow::anyhow!("could not fetch {}: {}", url, e))?;
+        let text = response.text().await?;
+
+        serde_json::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("invalid response from {}: {}", url, e))
+    }
+
+    /// Fetch the pages of the remote server, keyed by their URL.
+    pub async fn pages(&self) -> anyhow::Result<BTreeMap<Utf8PathBuf, Page>> {
+        let summaries: Vec<RemotePage> = self.get("/pages").await?;
+        let tools: Vec<RemoteTool> = self.get("/tools/openai").await?;
+
+        let mut functions: BTreeMap<String, Function> = tools
+            .into_iter()
+            .map(|tool| {
+                (
+                    tool.function.name.clone(),
+                    tool.function.into_function(self),
+                )
+            })
+            .collect();
+
+        let mut pages = BTreeMap::new();
+        for summary in summaries {
+            let page = Page {
+                functions: summary
+                    .functions
+                    .iter()
+                    .filter_map(|name| {
+                        let function = functions.remove(name)?;
+                        let name = match &self.namespace {
+                            Some(namespace) => sources::namespaced(namespace, name),
+                            None => name.clone(),
+                        };
+                        Some((name, function))
+                    })
+                    .collect(),
+                name: summary.name,
+                description: summary.description,
+                categories: summary.categories,
+                metadata: PageMetadata::default(),
+            };
+
+            pages.insert(
+                Utf8PathBuf::from(self.endpoint(&format!("/pages/{}", page.name))),
+                page,
+            );
+        }
+
+        Ok(pages)
+    }
+}
+
+impl RemoteFunction {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Forward the call to the remote server, returning its output.
+    pub async fn call(
+        &self,
+        arguments: &BTreeMap<String, serde_json::Value>,
+    ) -> anyhow::Result<String> {
+        let url = self.to_string();
+        let mut request = reqwest::Client::new()
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(arguments)?);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        // the logs of both servers can be correlated
+        if let Some(request_id) = correlation::current().request_id {
+            request = request.header(correlation::REQUEST_ID_HEADER, request_id);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("could not reach {}: {}", url, e))?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("{} returned {}: {}", url, status, text));
+        }
+
+        Ok(text)
+    }
+}
+
+impl std::fmt::Display for RemoteFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/functions/{}",
+            self.url.trim_end_matches('/'),
+            self.name
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_function() {
+        let tool: RemoteTool = serde_json::from_str(
+            r#"{"type":"function","function":{"name":"nmap_scan","description":"Scan a target.",
+            "parameters":{"type":"object","properties":{
+                "target":{"type":"string","description":"The target."},
+                "ports":{"type":"integer","description":"The ports."}},
+            "required":["target"]}}}"#,
+        )
+        .unwrap();
+        let remote = Remote {
+            url: "http://hub:8000/".to_string(),
+            token: None,
+            namespace: None,
+        };
+
+        let function = tool.function.into_function(&remote);
+        assert_eq!(function.description, "Scan a target.");
+        assert!(function.parameters["target"].required);
+        assert!(!function.parameters["ports"].required);
+        assert_eq!(function.parameters["ports"].param_type, "integer");
+        assert_eq!(
+            function.remote.unwrap().to_string(),
+            "http://hub:8000/functions/nmap_scan"
+        );
+    }
+}
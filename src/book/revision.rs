@@ -0,0 +1,142 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use sha2::{Digest, Sha256};
+
+use super::Book;
+
+/// Fingerprints of the functions of a book, to tell which ones changed between two revisions.
+#[derive(Debug, Clone)]
+pub(crate) struct Revision {
+    pub id: String,
+    functions: BTreeMap<String, String>,
+}
+
+/// The functions added, changed or removed between two revisions.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Delta {
+    /// Added or changed.
+    pub updated: BTreeSet<String>,
+    pub removed: BTreeSet<String>,
+}
+
+impl Revision {
+    pub fn of(book: &Book) -> Self {
+        let mut functions = BTreeMap::new();
+        for page in book.pages.values() {
+            for (name, function) in &page.functions {
+                // the page is part of the listing of some flavors
+                let mut hasher = Sha256::new();
+                hasher.update(page.name.as_bytes());
+                hasher.update([0]);
+                hasher.update(page.description.as_deref().unwrap_or_default().as_bytes());
+                hasher.update([0]);
+                hasher.update(
+                    serde_json::to_string(function)
+                        .unwrap_or_default()
+                        .as_bytes(),
+                );
+                functions.insert(name.clone(), format!("{:x}", hasher.finalize()));
+            }
+        }
+
+        Self::from_functions(functions)
+    }
+
+    /// Build a revision from the fingerprints of its functions.
+    pub fn from_functions(functions: BTreeMap<String, String>) -> Self {
+        let mut hasher = Sha256::new();
+        for (name, fingerprint) in &functions {
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            hasher.update(fingerprint.as_bytes());
+            hasher.update([0]);
+        }
+        let id = format!("{:x}", hasher.finalize())[..16].to_string();
+
+        Self { id, functions }
+    }
+
+    /// Fingerprint of each function, by name.
+    pub fn functions(&self) -> &BTreeMap<String, String> {
+        &self.functions
+    }
+
+    /// Returns what changed from this revision to the newer one.
+    pub fn delta(&self, newer: &Revision) -> Delta {
+        Delta {
+            updated: newer
+                .functions
+                .iter()
+                .filter(|(name, fingerprint)| self.functions.get(*name) != Some(fingerprint))
+                .map(|(name, _)| name.clone())
+                .collect(),
+            removed: self
+                .functions
+                .keys()
+                .filter(|name| !newer.functions.contains_key(*name))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Page;
+
+    fn book(yaml: &str) -> Book {
+        let page: Page = serde_yaml::from_str(yaml).unwrap();
+        Book {
+            pages: BTreeMap::from([("page.yml".into(), page)]),
+        }
+    }
+
+    #[test]
+    fn test_delta() {
+        let old_yaml = r#"
+functions:
+  kept:
+    description: Kept.
+    parameters: {}
+    cmdline: [echo, kept]
+  changed:
+    description: Before.
+    parameters: {}
+    cmdline: [echo, changed]
+  removed:
+    description: Removed.
+    parameters: {}
+    cmdline: [echo, removed]
+"#;
+        let old = Revision::of(&book(old_yaml));
+        let new = Revision::of(&book(
+            r#"
+functions:
+  kept:
+    description: Kept.
+    parameters: {}
+    cmdline: [echo, kept]
+  changed:
+    description: After.
+    parameters: {}
+    cmdline: [echo, changed]
+  added:
+    description: Added.
+    parameters: {}
+    cmdline: [echo, added]
+"#,
+        ));
+
+        assert_ne!(old.id, new.id);
+        assert_eq!(old.id, Revision::of(&book(old_yaml)).id);
+
+        let delta = old.delta(&new);
+        assert_eq!(
+            delta.updated,
+            BTreeSet::from(["added".to_string(), "changed".to_string()])
+        );
+        assert_eq!(delta.removed, BTreeSet::from(["removed".to_string()]));
+        assert_eq!(new.delta(&new), Delta::default());
+    }
+}
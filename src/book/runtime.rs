@@ -1,13 +1,13 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, path::Path};
 
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use lazy_regex::{lazy_regex, Lazy};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use crate::runtime::CommandLine;
+use crate::runtime::{CommandLine, PathArgument};
 
-use super::{Function, Page};
+use super::{types, Files, Function, Page, Parameter, Transfer};
 
 static ARG_VALUE_PARSER: Lazy<Regex> = lazy_regex!(r"(?m)\$\{\s*([\w\.]+)(\s+or\s+([^}]+))?\}");
 
@@ -19,6 +19,8 @@ pub enum ExecutionFlavor {
     Shell(String),
     Sudo,
     Docker(String),
+    Wasm(String),
+    Pipeline(Vec<String>),
     Error(String),
 }
 
@@ -40,8 +42,15 @@ impl ExecutionFlavor {
     }
 
     fn get_current_shell() -> String {
+        // windows has no $SHELL, the command interpreter is in %COMSPEC%
         let shell_name = std::env::var("SHELL")
-            .map(|s| s.split('/').last().unwrap_or("unknown").to_string())
+            .or_else(|_| std::env::var("COMSPEC"))
+            .map(|s| {
+                s.split(['/', '\\'])
+                    .next_back()
+                    .unwrap_or("unknown")
+                    .to_string()
+            })
             .unwrap_or_else(|_| "unknown".to_string());
 
         if let Ok(shell_path) = which::which(shell_name.clone()) {
@@ -52,6 +61,20 @@ impl ExecutionFlavor {
     }
 
     pub fn for_function(function: &Function) -> anyhow::Result<ExecutionFlavor> {
+        if let ExecutionContext::Pipeline(steps) = &function.execution {
+            return Ok(ExecutionFlavor::Pipeline(
+                steps.iter().map(|step| step.function.clone()).collect(),
+            ));
+        }
+
+        if let ExecutionContext::Wasm(wasm) = &function.execution {
+            return Ok(if which::which("wasmtime").is_ok() {
+                ExecutionFlavor::Wasm(wasm.module.clone())
+            } else {
+                ExecutionFlavor::error("wasmtime not in $PATH".to_string())
+            });
+        }
+
         let mut has_container = false;
         if let Some(container) = function.container.as_ref() {
             has_container = true;
@@ -66,7 +89,7 @@ impl ExecutionFlavor {
             Ok(raw_parts) => {
                 let cmdline = CommandLine::from_vec(&raw_parts)?;
                 if cmdline.sudo {
-                    return Ok(if has_container {
+                    Ok(if has_container {
                         ExecutionFlavor::docker(
                             function
                                 .container
@@ -78,9 +101,9 @@ impl ExecutionFlavor {
                         )
                     } else {
                         ExecutionFlavor::sudo()
-                    });
+                    })
                 } else if !cmdline.app_in_path {
-                    return Ok(if has_container {
+                    Ok(if has_container {
                         ExecutionFlavor::docker(
                             function
                                 .container
@@ -92,9 +115,11 @@ impl ExecutionFlavor {
                         )
                     } else {
                         ExecutionFlavor::error("app not in $PATH".to_string())
-                    });
+                    })
+                } else if let ExecutionContext::PowerShell(_) = &function.execution {
+                    Ok(ExecutionFlavor::shell(cmdline.app))
                 } else {
-                    return Ok(ExecutionFlavor::shell(Self::get_current_shell()));
+                    Ok(ExecutionFlavor::shell(Self::get_current_shell()))
                 }
             }
             Err(e) => Err(e),
@@ -108,23 +133,119 @@ impl std::fmt::Display for ExecutionFlavor {
             Self::Shell(shell) => shell.to_string(),
             Self::Sudo => "sudo".to_string(),
             Self::Docker(image) => format!("docker {}", image),
+            Self::Wasm(module) => format!("wasm {}", module),
+            Self::Pipeline(functions) => format!("pipeline of {}", functions.join(", ")),
             Self::Error(message) => message.to_string(),
         };
         write!(f, "{}", s)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExecutionContext {
     #[serde(rename = "cmdline")]
     CommandLine(Vec<String>),
     #[serde(rename = "platforms")]
     PlatformSpecific(BTreeMap<String, Vec<String>>),
+    /// A script run with sh -c, for pipes, redirections and chains. The interpolated values are
+    /// quoted, so placeholders must not be.
+    #[serde(rename = "shell")]
+    Shell(String),
+    /// A PowerShell script, run with pwsh or, on windows hosts without it, powershell. Like for
+    /// shell, the interpolated values are quoted.
+    #[serde(rename = "powershell")]
+    PowerShell(String),
+    #[serde(rename = "wasm")]
+    Wasm(WasmModule),
+    #[serde(rename = "pipeline")]
+    Pipeline(Vec<Step>),
+}
+
+/// A call of another function in a pipeline, its arguments can reference the ones of the
+/// pipeline and the output of the previous steps as ${steps.N.stdout}.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Step {
+    pub function: String,
+    #[serde(default)]
+    pub arguments: BTreeMap<String, String>,
+}
+
+impl Step {
+    /// Interpolate the arguments of the call, outputs holds the ones of the previous steps.
+    pub fn resolve_arguments(
+        &self,
+        arguments: &BTreeMap<String, String>,
+        outputs: &[String],
+        client_env: &BTreeMap<String, String>,
+    ) -> anyhow::Result<BTreeMap<String, serde_json::Value>> {
+        let mut arguments = arguments.clone();
+        for (index, output) in outputs.iter().enumerate() {
+            arguments.insert(format!("steps.{}.stdout", index), output.clone());
+        }
+
+        let mut env = BTreeMap::new();
+        self.arguments
+            .iter()
+            .map(|(name, value)| {
+                let value = interpolate(
+                    value,
+                    &arguments,
+                    &BTreeMap::new(),
+                    client_env,
+                    &mut env,
+                    Quoting::None,
+                )?;
+                Ok((name.clone(), serde_json::Value::String(value)))
+            })
+            .collect()
+    }
+}
+
+/// A WASI module run with wasmtime, a lightweight sandbox for simple tools that starts much faster
+/// than a container.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmModule {
+    /// Path of the module, relative to the page.
+    pub module: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Written to the standard input of the module.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<String>,
+    /// Host directories the module can access, as host or host:guest, nothing else is.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dirs: Vec<String>,
+}
+
+impl WasmModule {
+    fn command_line(&self, page_dir: Option<&Utf8Path>) -> Vec<String> {
+        let mut cmdline = vec!["wasmtime".to_string(), "run".to_string()];
+        for dir in &self.dirs {
+            // wasmtime separates the guest path with ::
+            let dir = match dir.split_once(':') {
+                Some((host, guest)) if !guest.starts_with(':') => format!("{}::{}", host, guest),
+                _ => dir.to_string(),
+            };
+            cmdline.extend(["--dir".to_string(), dir]);
+        }
+
+        let module = match page_dir {
+            Some(dir) if Utf8Path::new(&self.module).is_relative() => {
+                dir.join(&self.module).to_string()
+            }
+            _ => self.module.clone(),
+        };
+        cmdline.push(module);
+        cmdline.extend(self.args.iter().cloned());
+        cmdline
+    }
 }
 
 impl ExecutionContext {
     pub fn get_command_line(&self) -> anyhow::Result<Vec<String>> {
         match self {
+            Self::Wasm(wasm) => Ok(wasm.command_line(None)),
             Self::CommandLine(cmdline) => Ok(cmdline.clone()),
             Self::PlatformSpecific(platforms) => {
                 if let Some(cmdline) = platforms.get(std::env::consts::OS) {
@@ -136,6 +257,79 @@ impl ExecutionContext {
                     ))
                 }
             }
+            Self::Shell(script) => Ok(vec!["sh".to_string(), "-c".to_string(), script.clone()]),
+            Self::Pipeline(_) => Err(anyhow::anyhow!(
+                "pipelines are executed as a sequence of functions, not a command line"
+            )),
+            Self::PowerShell(script) => Ok(vec![
+                powershell().to_string(),
+                "-NoProfile".to_string(),
+                "-NonInteractive".to_string(),
+                "-Command".to_string(),
+                script.clone(),
+            ]),
+        }
+    }
+
+    /// Values of the ${name or default} placeholders of the command line, by name.
+    pub fn placeholder_defaults(&self) -> BTreeMap<String, String> {
+        let mut defaults = BTreeMap::new();
+        for arg in self.get_command_line().unwrap_or_default() {
+            for caps in ARG_VALUE_PARSER.captures_iter(&arg) {
+                if let Some(default) = caps.get(3) {
+                    defaults
+                        .entry(caps[1].to_string())
+                        .or_insert_with(|| default.as_str().to_string());
+                }
+            }
+        }
+        defaults
+    }
+
+    // how the values interpolated in the command line are quoted
+    fn quoting(&self) -> Quoting {
+        match self {
+            Self::Shell(_) => Quoting::Sh,
+            Self::PowerShell(_) => Quoting::PowerShell,
+            _ => Quoting::None,
+        }
+    }
+}
+
+// PowerShell 7 is cross platform, Windows PowerShell is only used if it's not installed
+fn powershell() -> &'static str {
+    if cfg!(target_os = "windows") && which::which("pwsh").is_err() {
+        "powershell"
+    } else {
+        "pwsh"
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quoting {
+    None,
+    Sh,
+    PowerShell,
+}
+
+impl Quoting {
+    fn quote(self, value: String) -> String {
+        match self {
+            Self::None => value,
+            Self::Sh => shell_escape::unix::escape(value.into()).to_string(),
+            // nothing is expanded in single quoted strings, where a quote is escaped by doubling
+            // it, powershell also treats the typographic ones as quotes
+            Self::PowerShell => {
+                let mut quoted = String::from("'");
+                for c in value.chars() {
+                    if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+                        quoted.push(c);
+                    }
+                    quoted.push(c);
+                }
+                quoted.push('\'');
+                quoted
+            }
         }
     }
 }
@@ -150,6 +344,46 @@ pub struct FunctionRef<'a> {
 }
 
 impl<'a> FunctionRef<'a> {
+    /// Convert the JSON values of the arguments to strings for interpolation, according to the
+    /// type declared for each parameter.
+    pub fn coerce_arguments(
+        &self,
+        arguments: &BTreeMap<String, serde_json::Value>,
+    ) -> anyhow::Result<BTreeMap<String, String>> {
+        let mut coerced = BTreeMap::new();
+        for (arg_name, value) in arguments {
+            let param_type = self
+                .function
+                .parameters
+                .get(arg_name)
+                .map(|param| param.param_type.as_str())
+                .unwrap_or("string");
+
+            let value = coerce_value(param_type, value)
+                .map_err(|e| self.invalid_value_error(arg_name, e))?;
+
+            coerced.insert(arg_name.to_string(), value);
+        }
+        Ok(coerced)
+    }
+
+    // the errors about values of sensitive parameters don't include them
+    fn invalid_value_error(&self, arg_name: &str, error: anyhow::Error) -> anyhow::Error {
+        match self.function.parameters.get(arg_name) {
+            Some(param) if param.sensitive => anyhow::anyhow!(
+                "invalid value for sensitive argument {} of function {}",
+                arg_name,
+                &self.name
+            ),
+            _ => anyhow::anyhow!(
+                "invalid value for argument {} of function {}: {}",
+                arg_name,
+                &self.name,
+                error
+            ),
+        }
+    }
+
     pub fn validate_arguments(
         &self,
         provided_arguments: &BTreeMap<String, String>,
@@ -165,106 +399,518 @@ impl<'a> FunctionRef<'a> {
             }
         }
 
-        // check for extra arguments
-        for arg_name in provided_arguments.keys() {
-            if !self.function.parameters.contains_key(arg_name) {
+        // check for extra arguments and values violating the type or constraints of the parameter
+        for (arg_name, value) in provided_arguments {
+            let Some(param) = self.function.parameters.get(arg_name) else {
                 return Err(anyhow::anyhow!(
                     "unknown argument {} for function {}",
                     arg_name,
                     &self.name
                 ));
+            };
+
+            // empty values are replaced by defaults if any
+            if value.is_empty() {
+                continue;
             }
+            param
+                .constraints
+                .check(&param.param_type, value)
+                .map_err(|e| self.invalid_value_error(arg_name, e))?;
         }
 
         Ok(())
     }
 
+    /// Returns an error if the function does not allow clients to set one of the variables.
+    pub fn check_client_env(&self, env: &BTreeMap<String, String>) -> anyhow::Result<()> {
+        for name in env.keys() {
+            if !self.function.client_env.contains(name) {
+                return Err(anyhow::anyhow!(
+                    "environment variable {} can't be set by clients for function {}",
+                    name,
+                    &self.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolve the command line, the variables set by the client take precedence over the ones of
+    /// this process.
     pub fn resolve_command_line(
         &self,
         arguments: &BTreeMap<String, String>,
+        client_env: &BTreeMap<String, String>,
     ) -> anyhow::Result<CommandLine> {
-        // determine the command line to execute
-        let command_line = self.function.execution.get_command_line()?;
+        // determine the command line to execute, modules are relative to their page
+        let command_line = match &self.function.execution {
+            ExecutionContext::Wasm(wasm) => wasm.command_line(self.path.parent()),
+            execution => execution.get_command_line()?,
+        };
+        // values interpolated in scripts are quoted, so they can't inject commands
+        let quoting = self.function.execution.quoting();
         let mut env = BTreeMap::new();
 
-    // interpolate the arguments
-    let command_line = {
+        // interpolate the arguments
+        let parameters = &self.function.parameters;
         let mut interpolated = Vec::new();
-        for arg in command_line {
-            if ARG_VALUE_PARSER.is_match(&arg) {
-                // Process args with placeholders by replacing only the matched     patterns
-                let mut processed_arg = arg.clone();
-
-                // Find all matches and collect the replacements
-                let mut replacements = Vec::new();
-                for caps in ARG_VALUE_PARSER.captures_iter(&arg) {
-                    let full_match = caps.get(0).unwrap().as_str();
-                    let var_name = caps.get(1).ok_or(ARG_EXPRESSION_ERROR).map_err(|    e| anyhow!(e))?.as_str();
-                    let var_default = caps.get(3).map(|m| m.as_str());
-
-                    let replacement = if var_name.starts_with("env.") || var_name.  starts_with("ENV.") {
-                        let env_var_name = var_name.replace("env.", "").replace ("ENV.", "");
-                        let env_var = std::env::var(&env_var_name);
-                        let env_var_value = if let Ok(value) = env_var {
-                            value
-                        } else if let Some(def) = var_default {
-                            def.to_string()
-                        } else {
-                            return Err(anyhow::anyhow!(
-                                "environment variable {} not set",
-                                env_var_name
-                            ));
-                        };
-
-                        // add the environment variable to the command line for     later use
-                        env.insert(env_var_name, env_var_value.to_owned());
-
-                        env_var_value
-                    } else if let Some(value) = arguments.get(var_name) {
-                        if value.is_empty() {
-                            if let Some(def) = var_default {
-                                def.to_string()
-                            } else {
-                                value.to_string()
-                            }
-                        } else {
-                            value.to_string()
-                        }
-                    } else if let Some(default_value) = var_default {
-                        default_value.to_string()
-                    } else {
-                        return Err(anyhow::anyhow!("argument {} not provided",  var_name));
-                    };
+        for arg in &command_line {
+            match self.expand_flags(arg, arguments, quoting) {
+                Some(expanded) => interpolated.extend(expanded),
+                None => interpolated.push(interpolate(
+                    arg, arguments, parameters, client_env, &mut env, quoting,
+                )?),
+            }
+        }
+        let mut command_line = interpolated;
+        // the variables of the client are passed to the command even if not interpolated
+        env.extend(client_env.clone());
 
-                    replacements.push((full_match, replacement));
-                }
+        // and so are the sensitive arguments, it's the only way they are passed to containers
+        let mut secrets = Vec::new();
+        for (name, _) in parameters.iter().filter(|(_, param)| param.sensitive) {
+            if let Some(value) = arguments.get(name).filter(|value| !value.is_empty()) {
+                env.insert(name.to_uppercase(), value.clone());
+                secrets.push(value.clone());
+            }
+        }
+
+        let stdin = match &self.function.execution {
+            ExecutionContext::Wasm(wasm) => {
+                // modules only see the variables they are given
+                let env_args = env
+                    .keys()
+                    .flat_map(|key| ["--env".to_string(), key.clone()]);
+                command_line.splice(2..2, env_args);
+
+                wasm.stdin
+                    .as_ref()
+                    .map(|stdin| {
+                        interpolate(
+                            stdin,
+                            arguments,
+                            parameters,
+                            client_env,
+                            &mut env,
+                            Quoting::None,
+                        )
+                    })
+                    .transpose()?
+            }
+            _ => None,
+        };
+
+        // mounted if the command runs in a container
+        let paths = parameters
+            .iter()
+            .filter(|(_, param)| param.param_type == types::PATH_TYPE)
+            .filter_map(|(name, param)| {
+                arguments
+                    .get(name)
+                    .filter(|value| !value.is_empty())
+                    .map(|value| PathArgument {
+                        value: value.clone(),
+                        output: param.output,
+                    })
+            })
+            .collect();
 
-                // Apply all replacements to the arg string
-                for (pattern, replacement) in replacements {
-                    processed_arg = processed_arg.replace(pattern, &replacement);
+        // final parsing
+        let mut command_line = CommandLine::from_vec_with_env(&command_line, env)?;
+        command_line.stdin = stdin;
+        command_line.secrets = secrets;
+        command_line.paths = paths;
+        Ok(command_line)
+    }
+
+    // an argument of the command line made only of the placeholder of an array with a flag is
+    // expanded to the flag and each item as separate arguments, or to nothing if it's empty
+    fn expand_flags(
+        &self,
+        arg: &str,
+        arguments: &BTreeMap<String, String>,
+        quoting: Quoting,
+    ) -> Option<Vec<String>> {
+        // scripts are a single argument, the flags are interpolated in them
+        if quoting != Quoting::None {
+            return None;
+        }
+        let caps = ARG_VALUE_PARSER.captures(arg)?;
+        if caps.get(0)?.as_str() != arg {
+            return None;
+        }
+        let param = self.function.parameters.get(&caps[1])?;
+        let flag = param
+            .flag
+            .as_ref()
+            .filter(|_| param.param_type == "array")?;
+
+        let value = match (arguments.get(&caps[1]), caps.get(3)) {
+            (Some(value), _) if !value.is_empty() => value.as_str(),
+            (_, Some(default)) => default.as_str(),
+            (Some(value), None) => value.as_str(),
+            // optional flags are omitted, interpolate reports the missing required ones
+            (None, None) if !param.required => "",
+            (None, None) => return None,
+        };
+        Some(
+            types::array_items(value)
+                .flat_map(|item| [flag.clone(), item.to_string()])
+                .collect(),
+        )
+    }
+
+    /// Resolve the paths of the files to stage, relative local paths of uploads are resolved
+    /// from the directory of the page. Uploads must be in the directory of the page (or be assets
+    /// extracted from a bundle) and downloads in the allowed roots of the configuration.
+    pub fn resolve_files(&self, arguments: &BTreeMap<String, String>) -> anyhow::Result<Files> {
+        let page_dir = self.path.parent().unwrap_or(self.path);
+        let assets_dir = shellexpand::full(super::bundle::ASSETS_DIR)
+            .map_err(|e| anyhow::anyhow!("failed to expand path: {}", e))?;
+        // the directory of the pages of a bundle doesn't exist
+        let upload_dirs: Vec<_> = [page_dir.as_std_path(), Path::new(assets_dir.as_ref())]
+            .iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .collect();
+
+        let upload = |transfer: &Transfer| -> anyhow::Result<Transfer> {
+            let local = interpolate_path(&transfer.local, arguments)?;
+            let joined = page_dir.join(&local);
+            let host = joined
+                .canonicalize()
+                .map_err(|e| anyhow::anyhow!("error while resolving {}: {}", joined, e))?;
+            if !upload_dirs.iter().any(|dir| host.starts_with(dir)) {
+                return Err(anyhow::anyhow!(
+                    "upload {} is not in the directory of the page",
+                    local
+                ));
+            }
+            Ok(Transfer {
+                local: host.display().to_string(),
+                remote: interpolate_path(&transfer.remote, arguments)?,
+            })
+        };
+        let download = |transfer: &Transfer| -> anyhow::Result<Transfer> {
+            let local = interpolate_path(&transfer.local, arguments)?;
+            Ok(Transfer {
+                local: crate::runtime::paths::check(&local, true)?
+                    .display()
+                    .to_string(),
+                remote: interpolate_path(&transfer.remote, arguments)?,
+            })
+        };
+
+        Ok(Files {
+            upload: self
+                .function
+                .files
+                .upload
+                .iter()
+                .map(upload)
+                .collect::<anyhow::Result<_>>()?,
+            download: self
+                .function
+                .files
+                .download
+                .iter()
+                .map(download)
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+}
+
+// replace the ${argument} and ${env.VARIABLE} placeholders, collecting the variables in env
+fn interpolate(
+    arg: &str,
+    arguments: &BTreeMap<String, String>,
+    parameters: &BTreeMap<String, Parameter>,
+    client_env: &BTreeMap<String, String>,
+    env: &mut BTreeMap<String, String>,
+    quoting: Quoting,
+) -> anyhow::Result<String> {
+    // in a single pass, so that the values are never scanned for placeholders again
+    let mut error = None;
+    let interpolated =
+        ARG_VALUE_PARSER.replace_all(arg, |caps: &regex::Captures| {
+            match replacement(caps, arguments, parameters, client_env, env, quoting) {
+                Ok(replacement) => replacement,
+                Err(e) => {
+                    error.get_or_insert(e);
+                    String::new()
                 }
+            }
+        });
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(interpolated.to_string()),
+    }
+}
+
+// the value of a ${argument} or ${env.VARIABLE} placeholder
+fn replacement(
+    caps: &regex::Captures,
+    arguments: &BTreeMap<String, String>,
+    parameters: &BTreeMap<String, Parameter>,
+    client_env: &BTreeMap<String, String>,
+    env: &mut BTreeMap<String, String>,
+    quoting: Quoting,
+) -> anyhow::Result<String> {
+    let var_name = caps
+        .get(1)
+        .ok_or(ARG_EXPRESSION_ERROR)
+        .map_err(|e| anyhow!(e))?
+        .as_str();
+    let var_default = caps.get(3).map(|m| m.as_str());
 
-                interpolated.push(processed_arg);
+    if var_name.starts_with("env.") || var_name.starts_with("ENV.") {
+        let env_var_name = var_name.replace("env.", "").replace("ENV.", "");
+        let env_var = client_env
+            .get(&env_var_name)
+            .cloned()
+            .or_else(|| crate::runtime::env::var(&env_var_name));
+        let env_var_value = if let Some(value) = env_var {
+            value
+        } else if let Some(def) = var_default {
+            def.to_string()
+        } else {
+            return Err(anyhow::anyhow!(
+                "environment variable {} not set",
+                env_var_name
+            ));
+        };
+
+        // add the environment variable to the command line for     later use
+        env.insert(env_var_name, env_var_value.to_owned());
+
+        Ok(quoting.quote(env_var_value))
+    } else if let Some(value) = arguments.get(var_name) {
+        let value = if value.is_empty() {
+            if let Some(def) = var_default {
+                def.to_string()
             } else {
-                // For args without placeholders, use as-is
-                interpolated.push(arg);
+                value.to_string()
             }
-        }
-        interpolated
+        } else {
+            value.to_string()
+        };
+        Ok(render_argument(parameters.get(var_name), value, quoting))
+    } else if let Some(default_value) = var_default {
+        Ok(render_argument(
+            parameters.get(var_name),
+            default_value.to_string(),
+            quoting,
+        ))
+    } else {
+        Err(anyhow::anyhow!("argument {} not provided", var_name))
+    }
+}
+
+// the value of an argument as interpolated, the items of arrays are joined with their separator
+// or each passed after their flag
+fn render_argument(param: Option<&Parameter>, value: String, quoting: Quoting) -> String {
+    let Some(param) = param.filter(|param| param.param_type == "array" && !value.is_empty()) else {
+        return quoting.quote(value);
     };
-            // final parsing
-            CommandLine::from_vec_with_env(&command_line, env)
+
+    let items = types::array_items(&value);
+    match &param.flag {
+        Some(flag) => items
+            .map(|item| {
+                format!(
+                    "{} {}",
+                    quoting.quote(flag.clone()),
+                    quoting.quote(item.to_string())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => quoting.quote(
+            items
+                .collect::<Vec<_>>()
+                .join(param.separator.as_deref().unwrap_or(",")),
+        ),
+    }
+}
+
+// only arguments can be referenced in file paths, not environment variables
+fn interpolate_path(path: &str, arguments: &BTreeMap<String, String>) -> anyhow::Result<String> {
+    let mut error = None;
+    let interpolated = ARG_VALUE_PARSER.replace_all(path, |caps: &regex::Captures| {
+        let name = &caps[1];
+        match arguments.get(name).filter(|value| !value.is_empty()) {
+            // arguments are names, not paths that could escape the directory
+            Some(value) if value.contains(['/', '\\']) || value.contains("..") => {
+                error = Some(anyhow::anyhow!(
+                    "invalid value of argument {} for path {}",
+                    name,
+                    path
+                ));
+                String::new()
+            }
+            Some(value) => value.to_string(),
+            None => match caps.get(3) {
+                Some(default) => default.as_str().to_string(),
+                None => {
+                    error = Some(anyhow::anyhow!(
+                        "argument {} not provided for path {}",
+                        name,
+                        path
+                    ));
+                    String::new()
+                }
+            },
+        }
+    });
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(interpolated.to_string()),
+    }
+}
+
+fn coerce_value(param_type: &str, value: &serde_json::Value) -> anyhow::Result<String> {
+    use serde_json::Value;
+
+    match (param_type, value) {
+        // an explicit null is the same as an empty value, and will use the default if any
+        (_, Value::Null) => Ok(String::new()),
+        ("integer", Value::Number(n)) if !n.is_i64() && !n.is_u64() => {
+            Err(anyhow::anyhow!("expected an integer, got {}", n))
         }
+        ("integer" | "number", Value::String(s)) if s.trim().parse::<f64>().is_err() => {
+            Err(anyhow::anyhow!("expected a number, got '{}'", s))
+        }
+        ("boolean", Value::String(s)) if s != "true" && s != "false" => {
+            Err(anyhow::anyhow!("expected a boolean, got '{}'", s))
+        }
+        (_, Value::String(s)) => Ok(s.to_string()),
+        // the items of arrays are separated by commas
+        ("array", Value::Array(items))
+            if items
+                .iter()
+                .any(|item| item.as_str().is_some_and(|item| item.contains(','))) =>
+        {
+            Err(anyhow::anyhow!("array items can't contain commas"))
+        }
+        (_, Value::Bool(b)) => Ok(b.to_string()),
+        (_, Value::Number(n)) => Ok(n.to_string()),
+        // lists of scalars are joined with commas, which is what most command line tools expect
+        (_, Value::Array(items))
+            if items
+                .iter()
+                .all(|item| !item.is_array() && !item.is_object()) =>
+        {
+            Ok(items
+                .iter()
+                .map(|item| coerce_value("string", item))
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .join(","))
+        }
+        // anything else is passed as JSON
+        (_, value) => Ok(value.to_string()),
     }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::collections::BTreeMap;
 
+    #[test]
+    fn test_coerce_arguments() {
+        let mut parameters = BTreeMap::new();
+        for (name, param_type) in [
+            ("port", "integer"),
+            ("verbose", "boolean"),
+            ("hosts", "array"),
+        ] {
+            parameters.insert(
+                name.to_string(),
+                crate::book::Parameter {
+                    param_type: param_type.to_string(),
+                    description: "".to_string(),
+                    required: false,
+                    examples: None,
+                    flag: None,
+                    separator: None,
+                    sensitive: false,
+                    output: false,
+                    constraints: Default::default(),
+                },
+            );
+        }
+        let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
+            execution: ExecutionContext::CommandLine(vec!["echo".to_string()]),
+            description: "".to_string(),
+            parameters,
+            container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
+        };
+        let page = Page {
+            metadata: Default::default(),
+            name: "test_page".to_string(),
+            description: None,
+            categories: Vec::new(),
+            functions: BTreeMap::new(),
+        };
+        let path = Utf8PathBuf::from("test/path");
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &path,
+            page: &page,
+        };
+
+        let mut arguments = BTreeMap::new();
+        arguments.insert("port".to_string(), serde_json::json!(443));
+        arguments.insert("verbose".to_string(), serde_json::json!(true));
+        arguments.insert("hosts".to_string(), serde_json::json!(["a", "b", 1]));
+        arguments.insert("extra".to_string(), serde_json::json!({"k": "v"}));
+
+        let coerced = resolver.coerce_arguments(&arguments).unwrap();
+        assert_eq!(coerced.get("port").unwrap(), "443");
+        assert_eq!(coerced.get("verbose").unwrap(), "true");
+        assert_eq!(coerced.get("hosts").unwrap(), "a,b,1");
+        assert_eq!(coerced.get("extra").unwrap(), r#"{"k":"v"}"#);
+
+        let mut arguments = BTreeMap::new();
+        arguments.insert("port".to_string(), serde_json::json!(4.5));
+        assert!(resolver.coerce_arguments(&arguments).is_err());
+
+        let mut arguments = BTreeMap::new();
+        arguments.insert("port".to_string(), serde_json::json!("not a number"));
+        assert!(resolver.coerce_arguments(&arguments).is_err());
+    }
+
     #[test]
     fn test_resolve_command_line_with_valid_arguments() {
         let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
             execution: ExecutionContext::CommandLine(vec![
                 "echo".to_string(),
                 "${message}".to_string(),
@@ -272,12 +918,17 @@ mod tests {
             description: "".to_string(),
             parameters: BTreeMap::new(),
             container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
         };
         let resolver = FunctionRef {
             function: &function,
             name: "test_function".to_string(),
             path: &Utf8PathBuf::from("test/path"),
             page: &Page {
+                metadata: Default::default(),
                 name: "test_page".to_string(),
                 description: None,
                 categories: Vec::new(),
@@ -287,7 +938,7 @@ mod tests {
         let mut arguments = BTreeMap::new();
         arguments.insert("message".to_string(), "Hello, World!".to_string());
 
-        let result = resolver.resolve_command_line(&arguments);
+        let result = resolver.resolve_command_line(&arguments, &BTreeMap::new());
         assert!(result.is_ok());
         let command_line = result.unwrap();
         assert!(command_line.app.ends_with("/echo"));
@@ -297,6 +948,16 @@ mod tests {
     #[test]
     fn test_resolve_command_line_with_default_value() {
         let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
             execution: ExecutionContext::CommandLine(vec![
                 "echo".to_string(),
                 "${message or Default message}".to_string(),
@@ -304,12 +965,17 @@ mod tests {
             description: "".to_string(),
             parameters: BTreeMap::new(),
             container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
         };
         let resolver = FunctionRef {
             function: &function,
             name: "test_function".to_string(),
             path: &Utf8PathBuf::from("test/path"),
             page: &Page {
+                metadata: Default::default(),
                 name: "test_page".to_string(),
                 description: None,
                 categories: Vec::new(),
@@ -318,7 +984,7 @@ mod tests {
         };
         let arguments = BTreeMap::new();
 
-        let result = resolver.resolve_command_line(&arguments);
+        let result = resolver.resolve_command_line(&arguments, &BTreeMap::new());
         assert!(result.is_ok());
         let command_line = result.unwrap();
         assert!(command_line.app.ends_with("/echo"));
@@ -328,6 +994,16 @@ mod tests {
     #[test]
     fn test_resolve_command_line_with_empty_value_and_default() {
         let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
             execution: ExecutionContext::CommandLine(vec![
                 "echo".to_string(),
                 "${message or Default message}".to_string(),
@@ -335,12 +1011,17 @@ mod tests {
             description: "".to_string(),
             parameters: BTreeMap::new(),
             container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
         };
         let resolver = FunctionRef {
             function: &function,
             name: "test_function".to_string(),
             path: &Utf8PathBuf::from("test/path"),
             page: &Page {
+                metadata: Default::default(),
                 name: "test_page".to_string(),
                 description: None,
                 categories: Vec::new(),
@@ -350,7 +1031,7 @@ mod tests {
         let mut arguments = BTreeMap::new();
         arguments.insert("message".to_string(), "".to_string());
 
-        let result = resolver.resolve_command_line(&arguments);
+        let result = resolver.resolve_command_line(&arguments, &BTreeMap::new());
         assert!(result.is_ok());
         let command_line = result.unwrap();
         assert!(command_line.app.ends_with("/echo"));
@@ -360,6 +1041,16 @@ mod tests {
     #[test]
     fn test_resolve_command_line_with_missing_required_argument() {
         let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
             execution: ExecutionContext::CommandLine(vec![
                 "echo".to_string(),
                 "${required_arg}".to_string(),
@@ -367,12 +1058,17 @@ mod tests {
             description: "".to_string(),
             parameters: BTreeMap::new(),
             container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
         };
         let resolver = FunctionRef {
             function: &function,
             name: "test_function".to_string(),
             path: &Utf8PathBuf::from("test/path"),
             page: &Page {
+                metadata: Default::default(),
                 name: "test_page".to_string(),
                 description: None,
                 categories: Vec::new(),
@@ -381,7 +1077,7 @@ mod tests {
         };
         let arguments = BTreeMap::new();
 
-        let result = resolver.resolve_command_line(&arguments);
+        let result = resolver.resolve_command_line(&arguments, &BTreeMap::new());
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().to_string(),
@@ -392,6 +1088,16 @@ mod tests {
     #[test]
     fn test_resolve_command_line_with_multiple_arguments() {
         let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
             execution: ExecutionContext::CommandLine(vec![
                 "echo".to_string(),
                 "${arg1}".to_string(),
@@ -401,12 +1107,17 @@ mod tests {
             description: "".to_string(),
             parameters: BTreeMap::new(),
             container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
         };
         let resolver = FunctionRef {
             function: &function,
             name: "test_function".to_string(),
             path: &Utf8PathBuf::from("test/path"),
             page: &Page {
+                metadata: Default::default(),
                 name: "test_page".to_string(),
                 description: None,
                 categories: Vec::new(),
@@ -416,7 +1127,7 @@ mod tests {
         let mut arguments = BTreeMap::new();
         arguments.insert("arg1".to_string(), "value1".to_string());
 
-        let result = resolver.resolve_command_line(&arguments);
+        let result = resolver.resolve_command_line(&arguments, &BTreeMap::new());
         assert!(result.is_ok());
         let command_line = result.unwrap();
         assert!(command_line.app.ends_with("/echo"));
@@ -428,6 +1139,16 @@ mod tests {
         std::env::set_var("TEST_VAR", "test_value");
 
         let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
             execution: ExecutionContext::CommandLine(vec![
                 "echo".to_string(),
                 "${env.TEST_VAR}".to_string(),
@@ -436,12 +1157,17 @@ mod tests {
             description: "".to_string(),
             parameters: BTreeMap::new(),
             container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
         };
         let resolver = FunctionRef {
             function: &function,
             name: "test_function".to_string(),
             path: &Utf8PathBuf::from("test/path"),
             page: &Page {
+                metadata: Default::default(),
                 name: "test_page".to_string(),
                 description: None,
                 categories: Vec::new(),
@@ -450,7 +1176,7 @@ mod tests {
         };
         let arguments = BTreeMap::new();
 
-        let result = resolver.resolve_command_line(&arguments);
+        let result = resolver.resolve_command_line(&arguments, &BTreeMap::new());
         assert!(result.is_ok());
         let command_line = result.unwrap();
         assert!(command_line.app.ends_with("/echo"));
@@ -459,9 +1185,70 @@ mod tests {
         std::env::remove_var("TEST_VAR");
     }
 
+    #[test]
+    fn test_resolve_command_line_with_client_env() {
+        let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: vec!["TOOL_TOKEN".to_string()],
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
+            execution: ExecutionContext::CommandLine(vec![
+                "echo".to_string(),
+                "${env.TOOL_TOKEN}".to_string(),
+            ]),
+            description: "".to_string(),
+            parameters: BTreeMap::new(),
+            container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
+        };
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &Utf8PathBuf::from("test/path"),
+            page: &Page {
+                metadata: Default::default(),
+                name: "test_page".to_string(),
+                description: None,
+                categories: Vec::new(),
+                functions: BTreeMap::new(),
+            },
+        };
+
+        let env = BTreeMap::from([("TOOL_TOKEN".to_string(), "s3cr3t".to_string())]);
+        assert!(resolver.check_client_env(&env).is_ok());
+
+        let command_line = resolver
+            .resolve_command_line(&BTreeMap::new(), &env)
+            .unwrap();
+        assert_eq!(command_line.args, vec!["s3cr3t"]);
+        assert_eq!(command_line.env, env);
+
+        let env = BTreeMap::from([("PATH".to_string(), "/tmp".to_string())]);
+        assert!(resolver.check_client_env(&env).is_err());
+    }
+
     #[test]
     fn test_resolve_command_line_with_undefined_env_variable() {
         let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
             execution: ExecutionContext::CommandLine(vec![
                 "echo".to_string(),
                 "${env.UNDEFINED_VAR}".to_string(),
@@ -469,12 +1256,17 @@ mod tests {
             description: "".to_string(),
             parameters: BTreeMap::new(),
             container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
         };
         let resolver = FunctionRef {
             function: &function,
             name: "test_function".to_string(),
             path: &Utf8PathBuf::from("test/path"),
             page: &Page {
+                metadata: Default::default(),
                 name: "test_page".to_string(),
                 description: None,
                 categories: Vec::new(),
@@ -483,7 +1275,7 @@ mod tests {
         };
         let arguments = BTreeMap::new();
 
-        let result = resolver.resolve_command_line(&arguments);
+        let result = resolver.resolve_command_line(&arguments, &BTreeMap::new());
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().to_string(),
@@ -494,6 +1286,16 @@ mod tests {
     #[test]
     fn test_resolve_command_line_with_undefined_env_variable_with_default() {
         let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
             execution: ExecutionContext::CommandLine(vec![
                 "echo".to_string(),
                 "${env.UNDEFINED_VAR or default_value}".to_string(),
@@ -501,12 +1303,17 @@ mod tests {
             description: "".to_string(),
             parameters: BTreeMap::new(),
             container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
         };
         let resolver = FunctionRef {
             function: &function,
             name: "test_function".to_string(),
             path: &Utf8PathBuf::from("test/path"),
             page: &Page {
+                metadata: Default::default(),
                 name: "test_page".to_string(),
                 description: None,
                 categories: Vec::new(),
@@ -515,10 +1322,518 @@ mod tests {
         };
         let arguments = BTreeMap::new();
 
-        let result = resolver.resolve_command_line(&arguments);
+        let result = resolver.resolve_command_line(&arguments, &BTreeMap::new());
         assert!(result.is_ok());
         let command_line = result.unwrap();
         assert!(command_line.app.ends_with("/echo"));
         assert_eq!(command_line.args, vec!["default_value"]);
     }
+
+    fn files_function(files: Files) -> Function {
+        Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files,
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
+            execution: ExecutionContext::CommandLine(vec!["echo".to_string()]),
+            description: "".to_string(),
+            parameters: BTreeMap::new(),
+            container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
+        }
+    }
+
+    fn files_page() -> Page {
+        Page {
+            metadata: Default::default(),
+            name: "test_page".to_string(),
+            description: None,
+            categories: Vec::new(),
+            functions: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_files() {
+        crate::runtime::paths::set(&crate::runtime::paths::PathsConfig {
+            allowed_roots: vec![std::env::temp_dir().display().to_string()],
+        })
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(dir.path().canonicalize().unwrap()).unwrap();
+        std::fs::create_dir_all(root.join("wordlists")).unwrap();
+        std::fs::write(root.join("wordlists/common.txt"), "admin").unwrap();
+        std::fs::write(root.join("wordlists/words.txt"), "login").unwrap();
+        std::fs::create_dir_all(root.join("out")).unwrap();
+
+        let function = files_function(Files {
+            upload: vec![
+                Transfer {
+                    local: "wordlists/common.txt".to_string(),
+                    remote: "/tmp/common.txt".to_string(),
+                },
+                Transfer {
+                    local: "wordlists/${wordlist}".to_string(),
+                    remote: "/tmp/${name or custom}.txt".to_string(),
+                },
+            ],
+            download: vec![Transfer {
+                local: format!("{}/out/${{target}}.json", root),
+                remote: "/tmp/out.json".to_string(),
+            }],
+        });
+        let page = files_page();
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &root.join("page.yml"),
+            page: &page,
+        };
+
+        let arguments = BTreeMap::from([
+            ("wordlist".to_string(), "words.txt".to_string()),
+            ("target".to_string(), "example.com".to_string()),
+        ]);
+        let files = resolver.resolve_files(&arguments).unwrap();
+        assert_eq!(
+            files.upload,
+            vec![
+                Transfer {
+                    local: root.join("wordlists/common.txt").to_string(),
+                    remote: "/tmp/common.txt".to_string(),
+                },
+                Transfer {
+                    local: root.join("wordlists/words.txt").to_string(),
+                    remote: "/tmp/custom.txt".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            files.download[0].local,
+            root.join("out/example.com.json").as_str()
+        );
+
+        assert!(resolver.resolve_files(&BTreeMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_resolve_files_traversal() {
+        crate::runtime::paths::set(&crate::runtime::paths::PathsConfig {
+            allowed_roots: vec![std::env::temp_dir().display().to_string()],
+        })
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(dir.path().canonicalize().unwrap()).unwrap();
+        std::fs::create_dir_all(root.join("book")).unwrap();
+        std::fs::write(root.join("book/common.txt"), "admin").unwrap();
+        std::fs::write(root.join("secret.txt"), "secret").unwrap();
+        let page = files_page();
+        let path = root.join("book/page.yml");
+
+        let resolve = |files: Files, arguments: &[(&str, &str)]| {
+            let function = files_function(files);
+            let resolver = FunctionRef {
+                function: &function,
+                name: "test_function".to_string(),
+                path: &path,
+                page: &page,
+            };
+            let arguments = arguments
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            resolver.resolve_files(&arguments)
+        };
+        let upload = |local: &str| Files {
+            upload: vec![Transfer {
+                local: local.to_string(),
+                remote: "/tmp/upload.txt".to_string(),
+            }],
+            download: Vec::new(),
+        };
+        let download = |local: String, remote: &str| Files {
+            upload: Vec::new(),
+            download: vec![Transfer {
+                local,
+                remote: remote.to_string(),
+            }],
+        };
+
+        assert!(resolve(upload("${name}"), &[("name", "common.txt")]).is_ok());
+        for value in [
+            "../../../../etc/shadow",
+            "../secret.txt",
+            "..",
+            "a\\b",
+            "/etc/shadow",
+        ] {
+            assert!(resolve(upload("${name}"), &[("name", value)]).is_err());
+            assert!(resolve(
+                download(format!("{}/${{name}}", root), "/tmp/out.json"),
+                &[("name", value)]
+            )
+            .is_err());
+            assert!(resolve(
+                download(root.join("out.json").to_string(), "/tmp/${name}"),
+                &[("name", value)]
+            )
+            .is_err());
+        }
+
+        // the paths of the page itself can't escape its directory or the allowed roots
+        assert!(resolve(upload("../secret.txt"), &[]).is_err());
+        assert!(resolve(upload("/etc/hostname"), &[]).is_err());
+        assert!(resolve(
+            download("/etc/robopages.json".to_string(), "/tmp/out.json"),
+            &[]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_resolve_command_line_with_shell() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: Count the open ports.
+parameters:
+  target:
+    type: string
+    description: The target.
+shell: nmap -oG - ${target} | grep -c open
+"#,
+        )
+        .unwrap();
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &Utf8PathBuf::from("test/path"),
+            page: &Page {
+                metadata: Default::default(),
+                name: "test_page".to_string(),
+                description: None,
+                categories: Vec::new(),
+                functions: BTreeMap::new(),
+            },
+        };
+
+        let arguments = BTreeMap::from([("target".to_string(), "x; rm -rf /".to_string())]);
+        let command_line = resolver
+            .resolve_command_line(&arguments, &BTreeMap::new())
+            .unwrap();
+        assert!(command_line.app.ends_with("/sh"));
+        assert_eq!(
+            command_line.args,
+            vec!["-c", "nmap -oG - 'x; rm -rf /' | grep -c open"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_command_line_with_nested_placeholders() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: Echo two values.
+parameters:
+  a:
+    type: string
+    description: The first value.
+  b:
+    type: string
+    description: The second value.
+shell: echo ${a} ${b}
+"#,
+        )
+        .unwrap();
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &Utf8PathBuf::from("test/path"),
+            page: &Page {
+                metadata: Default::default(),
+                name: "test_page".to_string(),
+                description: None,
+                categories: Vec::new(),
+                functions: BTreeMap::new(),
+            },
+        };
+
+        // placeholders in the values are not interpolated
+        let arguments = BTreeMap::from([
+            ("a".to_string(), "${b}".to_string()),
+            ("b".to_string(), ";id;".to_string()),
+        ]);
+        let command_line = resolver
+            .resolve_command_line(&arguments, &BTreeMap::new())
+            .unwrap();
+        assert_eq!(command_line.args, vec!["-c", "echo '${b}' ';id;'"]);
+    }
+
+    #[test]
+    fn test_resolve_command_line_with_powershell() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: Resolve a host name.
+parameters:
+  name:
+    type: string
+    description: The host name.
+powershell: Resolve-DnsName -Name ${name} | ConvertTo-Json
+"#,
+        )
+        .unwrap();
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &Utf8PathBuf::from("test/path"),
+            page: &Page {
+                metadata: Default::default(),
+                name: "test_page".to_string(),
+                description: None,
+                categories: Vec::new(),
+                functions: BTreeMap::new(),
+            },
+        };
+
+        let arguments = BTreeMap::from([(
+            "name".to_string(),
+            "x'; Remove-Item -Recurse C:\\ #".to_string(),
+        )]);
+        let command_line = resolver
+            .resolve_command_line(&arguments, &BTreeMap::new())
+            .unwrap();
+        assert_eq!(
+            command_line.args,
+            vec![
+                "-NoProfile",
+                "-NonInteractive",
+                "-Command",
+                "Resolve-DnsName -Name 'x''; Remove-Item -Recurse C:\\ #' | ConvertTo-Json"
+            ]
+        );
+        assert_eq!(
+            Quoting::PowerShell.quote("it\u{2019}s $env:PATH".to_string()),
+            "'it\u{2019}\u{2019}s $env:PATH'"
+        );
+    }
+
+    #[test]
+    fn test_resolve_command_line_with_arrays() {
+        let mut function: Function = serde_yaml::from_str(
+            r#"
+description: Scan ports of some hosts.
+parameters:
+  targets:
+    type: array
+    description: The hosts.
+    flag: --target
+  ports:
+    type: array
+    description: The ports.
+    separator: " "
+  exclude:
+    type: array
+    description: The hosts to skip.
+    flag: --exclude
+    required: false
+cmdline: [echo, "${targets}", "-p", "${ports}", "${exclude}"]
+"#,
+        )
+        .unwrap();
+        let page = Page {
+            metadata: Default::default(),
+            name: "test_page".to_string(),
+            description: None,
+            categories: Vec::new(),
+            functions: BTreeMap::new(),
+        };
+        let path = Utf8PathBuf::from("test/path");
+        let arguments = BTreeMap::from([
+            ("targets".to_string(), "a.com, b.com".to_string()),
+            ("ports".to_string(), "22,80".to_string()),
+        ]);
+
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &path,
+            page: &page,
+        };
+        let command_line = resolver
+            .resolve_command_line(&arguments, &BTreeMap::new())
+            .unwrap();
+        assert_eq!(
+            command_line.args,
+            vec!["--target", "a.com", "--target", "b.com", "-p", "22 80"]
+        );
+
+        function.execution = ExecutionContext::Shell("scan ${targets} -p ${ports}".to_string());
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &path,
+            page: &page,
+        };
+        let command_line = resolver
+            .resolve_command_line(&arguments, &BTreeMap::new())
+            .unwrap();
+        assert_eq!(
+            command_line.args,
+            vec!["-c", "scan --target a.com --target b.com -p '22 80'"]
+        );
+
+        let values = BTreeMap::from([(
+            "targets".to_string(),
+            serde_json::json!(["a.com", "b.com,c.com"]),
+        )]);
+        assert!(resolver.coerce_arguments(&values).is_err());
+    }
+
+    #[test]
+    fn test_resolve_command_line_with_sensitive_argument() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: Search a host.
+parameters:
+  api_key:
+    type: string
+    description: The API key.
+    sensitive: true
+    pattern: '[a-z0-9]+'
+  host:
+    type: string
+    description: The host.
+cmdline: [echo, "--key", "${api_key}", "${host}"]
+"#,
+        )
+        .unwrap();
+        let page = Page {
+            metadata: Default::default(),
+            name: "test_page".to_string(),
+            description: None,
+            categories: Vec::new(),
+            functions: BTreeMap::new(),
+        };
+        let path = Utf8PathBuf::from("test/path");
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &path,
+            page: &page,
+        };
+
+        let arguments = BTreeMap::from([
+            ("api_key".to_string(), "s3cr3t".to_string()),
+            ("host".to_string(), "example.com".to_string()),
+        ]);
+        let command_line = resolver
+            .resolve_command_line(&arguments, &BTreeMap::new())
+            .unwrap();
+        assert_eq!(command_line.args, vec!["--key", "s3cr3t", "example.com"]);
+        assert_eq!(command_line.env.get("API_KEY"), Some(&"s3cr3t".to_string()));
+        assert!(command_line.has_secret_args());
+        assert!(command_line
+            .to_string()
+            .ends_with("echo --key ******** example.com"));
+
+        let arguments = BTreeMap::from([
+            ("api_key".to_string(), "S3CR3T".to_string()),
+            ("host".to_string(), "example.com".to_string()),
+        ]);
+        let error = resolver.validate_arguments(&arguments).unwrap_err();
+        assert!(!error.to_string().contains("S3CR3T"));
+    }
+
+    #[test]
+    fn test_step_resolve_arguments() {
+        let step: Step = serde_yaml::from_str(
+            r#"
+function: summarize
+arguments:
+  text: "${steps.1.stdout}"
+  title: "Open ports of ${target}"
+"#,
+        )
+        .unwrap();
+
+        let arguments = BTreeMap::from([("target".to_string(), "example.com".to_string())]);
+        let outputs = vec!["scan".to_string(), "80/tcp open".to_string()];
+        let resolved = step
+            .resolve_arguments(&arguments, &outputs, &BTreeMap::new())
+            .unwrap();
+        assert_eq!(resolved["text"], "80/tcp open");
+        assert_eq!(resolved["title"], "Open ports of example.com");
+
+        assert!(step
+            .resolve_arguments(&arguments, &outputs[..1], &BTreeMap::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_resolve_command_line_with_wasm() {
+        let function: Function = serde_yaml::from_str(
+            r#"
+description: Query a JSON document.
+parameters:
+  filter:
+    type: string
+    description: The jq filter.
+  document:
+    type: string
+    description: The JSON document.
+wasm:
+  module: tools/jq.wasm
+  args: [-r, "${filter}"]
+  stdin: "${document}"
+  dirs: [/tmp/data:/data]
+"#,
+        )
+        .unwrap();
+        let resolver = FunctionRef {
+            function: &function,
+            name: "test_function".to_string(),
+            path: &Utf8PathBuf::from("/book/json/page.yml"),
+            page: &Page {
+                metadata: Default::default(),
+                name: "test_page".to_string(),
+                description: None,
+                categories: Vec::new(),
+                functions: BTreeMap::new(),
+            },
+        };
+
+        let arguments = BTreeMap::from([
+            ("filter".to_string(), ".name".to_string()),
+            (
+                "document".to_string(),
+                r#"{"name": "robopages"}"#.to_string(),
+            ),
+        ]);
+        let env = BTreeMap::from([("TOKEN".to_string(), "s3cr3t".to_string())]);
+        let command_line = resolver.resolve_command_line(&arguments, &env).unwrap();
+        assert_eq!(
+            command_line.args,
+            vec![
+                "run",
+                "--env",
+                "TOKEN",
+                "--dir",
+                "/tmp/data::/data",
+                "/book/json/tools/jq.wasm",
+                "-r",
+                ".name"
+            ]
+        );
+        assert_eq!(
+            command_line.stdin.as_deref(),
+            Some(r#"{"name": "robopages"}"#)
+        );
+    }
 }
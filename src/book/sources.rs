@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    revision::{Delta, Revision},
+    Book,
+};
+
+/// Name of the file recording where the robopages of a path were installed from.
+pub(crate) const INSTALL_RECORD: &str = ".robopages-install.yml";
+/// Name of the manifest tracking the namespaced sources installed in a path.
+pub(crate) const SOURCES_MANIFEST: &str = ".robopages-sources.yml";
+/// Separator between the namespace and the name of its functions.
+pub(crate) const NAMESPACE_SEPARATOR: &str = "__";
+
+/// Where robopages were installed from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Source {
+    pub source: String,
+    #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Resolved commit, or digest of OCI artifacts, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Fingerprint of each function when installed, to tell which ones changed since.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub functions: BTreeMap<String, String>,
+}
+
+impl Source {
+    /// Load the source recorded in the installed path, if any.
+    pub fn load(path: &Utf8Path) -> anyhow::Result<Option<Self>> {
+        let path = path.join(INSTALL_RECORD);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("error while reading {:?}: {}", path, e))?;
+        serde_yaml::from_str(&text)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("error while parsing {:?}: {}", path, e))
+    }
+
+    /// Record the fingerprints of the functions installed in the path.
+    pub fn with_functions(mut self, path: &Utf8Path) -> Self {
+        match Book::from_path(path.to_path_buf(), None) {
+            Ok(book) => self.functions = Revision::of(&book).functions().clone(),
+            Err(e) => log::warn!("could not record the installed functions: {}", e),
+        }
+        self
+    }
+
+    /// Returns the functions changed in the installed path since they were installed.
+    pub fn changes(&self, path: &Utf8Path) -> anyhow::Result<Delta> {
+        if self.functions.is_empty() {
+            return Err(anyhow::anyhow!(
+                "no functions recorded for {} in {}, reinstall it to track changes",
+                self.source,
+                path
+            ));
+        }
+
+        let installed = Revision::from_functions(self.functions.clone());
+        Ok(installed.delta(&Revision::of(&Book::from_path(path.to_path_buf(), None)?)))
+    }
+
+    /// Record the source in the installed path.
+    pub fn save(&self, path: &Utf8Path) -> anyhow::Result<()> {
+        std::fs::write(path.join(INSTALL_RECORD), serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)?;
+        if let Some(git_ref) = &self.git_ref {
+            write!(f, "@{}", git_ref)?;
+        }
+        if let Some(commit) = &self.commit {
+            write!(f, " ({})", commit)?;
+        }
+        Ok(())
+    }
+}
+
+/// The sources installed in a path, by namespace.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Sources {
+    #[serde(flatten)]
+    pub namespaces: BTreeMap<String, Source>,
+}
+
+impl Sources {
+    /// Load the manifest of the path, empty if there is none.
+    pub fn load(root: &Utf8Path) -> anyhow::Result<Self> {
+        let path = root.join(SOURCES_MANIFEST);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("error while reading {:?}: {}", path, e))?;
+        serde_yaml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("error while parsing {:?}: {}", path, e))
+    }
+
+    /// Save the manifest of the path, removing it if there are no sources left.
+    pub fn save(&self, root: &Utf8Path) -> anyhow::Result<()> {
+        let path = root.join(SOURCES_MANIFEST);
+        if self.namespaces.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        } else {
+            std::fs::write(path, serde_yaml::to_string(self)?)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the namespace of a page given its path relative to the root, if any.
+    pub fn namespace_of(&self, relative_path: &Utf8Path) -> Option<&str> {
+        let mut components = relative_path.components();
+        let first = components.next()?.as_str();
+        // a page directly in the root is not in a namespace, even if its name matches one
+        components.next()?;
+        self.namespaces
+            .get_key_value(first)
+            .map(|(namespace, _)| namespace.as_str())
+    }
+}
+
+/// Namespaces become part of function names, so only allow characters valid in tool names.
+pub(crate) fn validate_namespace(namespace: &str) -> anyhow::Result<()> {
+    if namespace.is_empty()
+        || namespace.contains(NAMESPACE_SEPARATOR)
+        || !namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(anyhow::anyhow!("invalid namespace: {}", namespace));
+    }
+    Ok(())
+}
+
+pub(crate) fn namespaced(namespace: &str, function_name: &str) -> String {
+    format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, function_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace_of() {
+        let sources: Sources = serde_yaml::from_str(
+            "offensive:\n  source: org/repo\n  ref: main\ncloud:\n  source: other/repo\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            sources.namespace_of(Utf8Path::new("offensive/nmap/nmap.yml")),
+            Some("offensive")
+        );
+        assert_eq!(
+            sources.namespace_of(Utf8Path::new("cloud/aws.yml")),
+            Some("cloud")
+        );
+        assert_eq!(sources.namespace_of(Utf8Path::new("cloud.yml")), None);
+        assert_eq!(sources.namespace_of(Utf8Path::new("other/page.yml")), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let source = Source {
+            source: "org/repo".to_string(),
+            git_ref: Some("v1.2.0".to_string()),
+            commit: Some("3f2c1e0".to_string()),
+            functions: BTreeMap::new(),
+        };
+        assert_eq!(source.to_string(), "org/repo@v1.2.0 (3f2c1e0)");
+
+        let source = Source {
+            source: "/tmp/pages.zip".to_string(),
+            git_ref: None,
+            commit: None,
+            functions: BTreeMap::new(),
+        };
+        assert_eq!(source.to_string(), "/tmp/pages.zip");
+    }
+
+    #[test]
+    fn test_validate_namespace() {
+        assert!(validate_namespace("offensive").is_ok());
+        assert!(validate_namespace("red-team_1").is_ok());
+        assert!(validate_namespace("").is_err());
+        assert!(validate_namespace("a__b").is_err());
+        assert!(validate_namespace("../etc").is_err());
+    }
+
+    #[test]
+    fn test_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8Path::from_path(dir.path()).unwrap();
+        let page = |description: &str| {
+            format!(
+                "functions:\n  kept:\n    description: Kept.\n    parameters: {{}}\n    cmdline: [echo]\n  changed:\n    description: {}\n    parameters: {{}}\n    cmdline: [echo]\n",
+                description
+            )
+        };
+        std::fs::write(path.join("page.yml"), page("Before.")).unwrap();
+
+        let source = Source {
+            source: "org/repo".to_string(),
+            git_ref: None,
+            commit: None,
+            functions: BTreeMap::new(),
+        };
+        assert!(source.changes(path).is_err());
+
+        let source = source.with_functions(path);
+        assert_eq!(source.functions.len(), 2);
+        assert_eq!(source.changes(path).unwrap(), Delta::default());
+
+        std::fs::write(path.join("page.yml"), page("After.")).unwrap();
+        assert_eq!(
+            source
+                .changes(path)
+                .unwrap()
+                .updated
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["changed"]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use camino::Utf8PathBuf;
+
+use super::Book;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SkipReason {
+    Hidden,
+    Extension,
+    Filter,
+    Symlink,
+    SymlinkLoop,
+    Depth,
+    Untrusted,
+    Manifest,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Hidden => write!(f, "hidden file or directory"),
+            SkipReason::Extension => write!(f, "not a .yml file"),
+            SkipReason::Filter => write!(f, "does not match the filter"),
+            SkipReason::Symlink => write!(f, "symlinked directory"),
+            SkipReason::SymlinkLoop => write!(f, "already visited, symlink loop"),
+            SkipReason::Depth => write!(f, "exceeds the maximum depth"),
+            SkipReason::Untrusted => write!(f, "missing or invalid signature"),
+            SkipReason::Manifest => write!(f, "package manifest"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Rename {
+    pub path: Utf8PathBuf,
+    pub from: String,
+    pub to: String,
+}
+
+/// Diagnostics collected while loading a book.
+#[derive(Debug, Default)]
+pub struct LoadTrace {
+    pub parsed: Vec<(Utf8PathBuf, Duration)>,
+    pub skipped: Vec<(Utf8PathBuf, SkipReason)>,
+    pub renames: Vec<Rename>,
+    pub elapsed: Duration,
+}
+
+impl LoadTrace {
+    pub fn skip(&mut self, path: Utf8PathBuf, reason: SkipReason) {
+        // report a hidden directory once rather than each of its files
+        if reason == SkipReason::Hidden && self.skipped.iter().any(|(p, _)| path.starts_with(p)) {
+            return;
+        }
+        self.skipped.push((path, reason));
+    }
+
+    /// Print the diagnostics, along with the approximate size of the book if it was loaded.
+    pub fn print(&self, book: Option<&Book>) {
+        println!("parsed {} files:", self.parsed.len());
+        for (path, elapsed) in &self.parsed {
+            println!("  {} ({:?})", path, elapsed);
+        }
+
+        println!("skipped {} files:", self.skipped.len());
+        for (path, reason) in &self.skipped {
+            println!("  {} : {}", path, reason);
+        }
+
+        if !self.renames.is_empty() {
+            println!("renamed {} duplicate functions:", self.renames.len());
+            for rename in &self.renames {
+                println!("  {} -> {} in {}", rename.from, rename.to, rename.path);
+            }
+        }
+
+        if let Some(book) = book {
+            let size: usize = book
+                .pages
+                .values()
+                .map(|page| serde_yaml::to_string(page).map(|s| s.len()).unwrap_or(0))
+                .sum();
+            println!(
+                "loaded {} pages with {} functions, ~{} bytes",
+                book.size(),
+                book.num_functions(),
+                size
+            );
+        }
+
+        println!("total loading time: {:?}", self.elapsed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hidden_directories_reported_once() {
+        let mut trace = LoadTrace::default();
+        trace.skip(Utf8PathBuf::from("/pages/.git"), SkipReason::Hidden);
+        trace.skip(Utf8PathBuf::from("/pages/.git/config"), SkipReason::Hidden);
+        trace.skip(Utf8PathBuf::from("/pages/README.md"), SkipReason::Extension);
+
+        assert_eq!(trace.skipped.len(), 2);
+    }
+}
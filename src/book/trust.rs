@@ -0,0 +1,254 @@
+use std::collections::BTreeSet;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+
+use crate::runtime::ContainerSource;
+
+use super::Page;
+
+/// Extension of the detached minisign signature expected next to each page.
+const SIGNATURE_EXTENSION: &str = "minisig";
+
+/// Which pages can be loaded, set in the trust section of the configuration.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct TrustPolicy {
+    /// Refuse pages without a valid signature from one of the trusted keys.
+    pub require_signed: bool,
+    /// Minisign public keys, either base64 encoded or paths to .pub files.
+    pub trusted_keys: Vec<String>,
+    /// Cosign keys and identities the signatures of the container images can be verified with.
+    pub image_signers: Vec<crate::runtime::signing::Signature>,
+}
+
+impl TrustPolicy {
+    fn signature_path(page_path: &Utf8Path) -> Utf8PathBuf {
+        Utf8PathBuf::from(format!("{}.{}", page_path, SIGNATURE_EXTENSION))
+    }
+
+    fn parse_key(key: &str) -> anyhow::Result<PublicKey> {
+        if let Ok(key) = PublicKey::from_base64(key) {
+            return Ok(key);
+        }
+
+        let path = shellexpand::full(key)
+            .map_err(|e| anyhow::anyhow!("failed to expand key path {}: {}", key, e))?
+            .to_string();
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("invalid trusted key {}: {}", key, e))?;
+
+        PublicKey::decode(&data).map_err(|e| anyhow::anyhow!("invalid trusted key {}: {}", key, e))
+    }
+
+    /// Parse the trusted keys, fails if signatures are required but no key is set.
+    pub fn verifier(&self) -> anyhow::Result<Verifier> {
+        if self.require_signed && self.trusted_keys.is_empty() {
+            return Err(anyhow::anyhow!(
+                "trust.require_signed is set but no trusted_keys are configured"
+            ));
+        }
+
+        Ok(Verifier {
+            require_signed: self.require_signed,
+            keys: self
+                .trusted_keys
+                .iter()
+                .map(|key| Self::parse_key(key))
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+}
+
+pub(crate) struct Verifier {
+    require_signed: bool,
+    keys: Vec<PublicKey>,
+}
+
+impl Verifier {
+    /// Check the signature of a page, an invalid signature is never accepted while a
+    /// missing one is only accepted if signatures are not required.
+    pub fn verify(&self, page_path: &Utf8Path) -> anyhow::Result<()> {
+        let sig_path = TrustPolicy::signature_path(page_path);
+        if !sig_path.exists() {
+            if self.require_signed {
+                return Err(anyhow::anyhow!("{} not found", sig_path));
+            }
+            return Ok(());
+        }
+
+        if self.keys.is_empty() {
+            log::debug!("no trusted keys configured, ignoring {}", sig_path);
+            return Ok(());
+        }
+
+        let signature = std::fs::read_to_string(&sig_path)
+            .map_err(|e| anyhow::anyhow!("error while reading {}: {}", sig_path, e))?;
+        let signature = Signature::decode(&signature)
+            .map_err(|e| anyhow::anyhow!("invalid signature {}: {}", sig_path, e))?;
+        let data = std::fs::read(page_path)
+            .map_err(|e| anyhow::anyhow!("error while reading {}: {}", page_path, e))?;
+
+        // legacy signatures are still produced by older minisign versions
+        if self
+            .keys
+            .iter()
+            .any(|key| key.verify(&data, &signature, true).is_ok())
+        {
+            log::debug!("{} signature verified", page_path);
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "{} is not signed by any of the trusted keys",
+                page_path
+            ))
+        }
+    }
+}
+
+impl Verifier {
+    /// Check the Dockerfiles and every file of the build contexts referenced by the page, as
+    /// the commands of its functions can change through a build.
+    pub fn verify_builds(&self, page: &Page) -> anyhow::Result<()> {
+        // without keys only the pages themselves can be checked, and only if signed
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+
+        let mut verified = BTreeSet::new();
+        for container in page.functions.values().filter_map(|f| f.container.as_ref()) {
+            let ContainerSource::Build(build) = &container.source else {
+                continue;
+            };
+
+            let dockerfile = Utf8PathBuf::from(&build.path);
+            if verified.insert(dockerfile.clone()) {
+                self.verify(&dockerfile)?;
+            }
+            let context = Utf8PathBuf::from_path_buf(build.context())
+                .map_err(|p| anyhow::anyhow!("invalid build context {:?}", p))?;
+            if verified.insert(context.clone()) {
+                self.verify_dir(&context)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn verify_dir(&self, dir: &Utf8Path) -> anyhow::Result<()> {
+        let entries = dir
+            .read_dir_utf8()
+            .map_err(|e| anyhow::anyhow!("error while reading {}: {}", dir, e))?;
+
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.verify_dir(entry.path())?;
+            } else if entry.path().extension() != Some(SIGNATURE_EXTENSION) {
+                self.verify(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==";
+
+    fn write_page(dir: &tempfile::TempDir, name: &str, data: &str, signed: bool) -> Utf8PathBuf {
+        let path = Utf8PathBuf::from_path_buf(dir.path().join(name)).unwrap();
+        std::fs::write(&path, data).unwrap();
+        if signed {
+            std::fs::write(TrustPolicy::signature_path(&path), SIGNATURE).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let signed = write_page(&dir, "signed.yml", "test", true);
+        let tampered = write_page(&dir, "tampered.yml", "tested", true);
+        let unsigned = write_page(&dir, "unsigned.yml", "test", false);
+
+        let policy = TrustPolicy {
+            require_signed: false,
+            trusted_keys: vec![PUBLIC_KEY.to_string()],
+            image_signers: vec![],
+        };
+        let verifier = policy.verifier().unwrap();
+        assert!(verifier.verify(&signed).is_ok());
+        assert!(verifier.verify(&tampered).is_err());
+        assert!(verifier.verify(&unsigned).is_ok());
+
+        let policy = TrustPolicy {
+            require_signed: true,
+            ..policy
+        };
+        let verifier = policy.verifier().unwrap();
+        assert!(verifier.verify(&signed).is_ok());
+        assert!(verifier.verify(&unsigned).is_err());
+    }
+
+    #[test]
+    fn test_verify_builds() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = dir.path().join("context");
+        std::fs::create_dir(&context).unwrap();
+        std::fs::write(context.join("Dockerfile"), "test").unwrap();
+        std::fs::write(context.join("Dockerfile.minisig"), SIGNATURE).unwrap();
+
+        let page: Page = serde_yaml::from_str(&format!(
+            r#"
+functions:
+  build_it:
+    description: Runs in a built image.
+    container:
+      build:
+        name: test
+        path: {}/Dockerfile
+    parameters: {{}}
+    cmdline: [id]
+"#,
+            context.display()
+        ))
+        .unwrap();
+
+        let policy = TrustPolicy {
+            require_signed: true,
+            trusted_keys: vec![PUBLIC_KEY.to_string()],
+            image_signers: vec![],
+        };
+        let verifier = policy.verifier().unwrap();
+        assert!(verifier.verify_builds(&page).is_ok());
+
+        // every file of the context is part of the build
+        std::fs::write(context.join("entrypoint.sh"), "id").unwrap();
+        assert!(verifier.verify_builds(&page).is_err());
+    }
+
+    #[test]
+    fn test_verifier_requires_keys() {
+        let policy = TrustPolicy {
+            require_signed: true,
+            trusted_keys: vec![],
+            image_signers: vec![],
+        };
+        assert!(policy.verifier().is_err());
+
+        let policy = TrustPolicy {
+            require_signed: false,
+            trusted_keys: vec!["not a key".to_string()],
+            image_signers: vec![],
+        };
+        assert!(policy.verifier().is_err());
+    }
+}
@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::runtime::scope::TARGET_TYPE;
+
+/// A string naming a file or directory of the host, mounted in the container of the function.
+pub(crate) const PATH_TYPE: &str = "path";
+
+/// Types of parameters, target is a string checked against the engagement scope, enum a string
+/// limited to its choices and path a string mounted in containers.
+pub(crate) const TYPES: &[&str] = &[
+    "string",
+    "integer",
+    "number",
+    "boolean",
+    "array",
+    "enum",
+    TARGET_TYPE,
+    PATH_TYPE,
+];
+
+/// Restrictions on the values of a parameter, enforced before execution and exported in its
+/// JSON schema.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Constraints {
+    /// Allowed values, required for enum parameters.
+    #[serde(default, alias = "enum", skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<Value>,
+    /// Range of numbers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<f64>,
+    /// Number of characters of strings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// Regular expression the whole string must match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Type of the items of arrays, string by default. The other constraints apply to each item.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<String>,
+}
+
+fn is_string_type(param_type: &str) -> bool {
+    matches!(param_type, "string" | "enum" | TARGET_TYPE | PATH_TYPE)
+}
+
+fn is_number_type(param_type: &str) -> bool {
+    matches!(param_type, "integer" | "number")
+}
+
+/// The value of a choice as interpolated in the command line.
+pub(crate) fn choice_to_string(choice: &Value) -> String {
+    match choice {
+        Value::String(s) => s.clone(),
+        choice => choice.to_string(),
+    }
+}
+
+/// Items of an array argument, which is coerced to a list separated by commas.
+pub(crate) fn array_items(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+fn pattern_regex(pattern: &str) -> anyhow::Result<regex::Regex> {
+    regex::Regex::new(&format!("^(?:{})$", pattern))
+        .map_err(|e| anyhow::anyhow!("invalid pattern {}: {}", pattern, e))
+}
+
+impl Constraints {
+    fn item_type(&self) -> &str {
+        self.items.as_deref().unwrap_or("string")
+    }
+
+    /// Returns an error if the type is unknown or the constraints can't apply to it.
+    pub fn check_definition(&self, param_type: &str) -> anyhow::Result<()> {
+        if !TYPES.contains(&param_type) {
+            return Err(anyhow::anyhow!(
+                "unknown type {}, expected one of {}",
+                param_type,
+                TYPES.join(", ")
+            ));
+        }
+
+        let value_type = if param_type == "array" {
+            let item_type = self.item_type();
+            if !TYPES.contains(&item_type) || matches!(item_type, "array" | "enum") {
+                return Err(anyhow::anyhow!("invalid type {} of array items", item_type));
+            }
+            item_type
+        } else if self.items.is_some() {
+            return Err(anyhow::anyhow!("items is only valid for arrays"));
+        } else {
+            param_type
+        };
+
+        if param_type == "enum" && self.choices.is_empty() {
+            return Err(anyhow::anyhow!("enum parameters must have choices"));
+        }
+        if (self.minimum.is_some() || self.maximum.is_some()) && !is_number_type(value_type) {
+            return Err(anyhow::anyhow!(
+                "minimum and maximum are only valid for numbers"
+            ));
+        }
+        if (self.min_length.is_some() || self.max_length.is_some() || self.pattern.is_some())
+            && !is_string_type(value_type)
+        {
+            return Err(anyhow::anyhow!(
+                "min_length, max_length and pattern are only valid for strings"
+            ));
+        }
+        if let (Some(minimum), Some(maximum)) = (self.minimum, self.maximum) {
+            if minimum > maximum {
+                return Err(anyhow::anyhow!("minimum is greater than maximum"));
+            }
+        }
+        if let (Some(min_length), Some(max_length)) = (self.min_length, self.max_length) {
+            if min_length > max_length {
+                return Err(anyhow::anyhow!("min_length is greater than max_length"));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            pattern_regex(pattern)?;
+        }
+
+        // each choice must be a valid value, except for the choices themselves
+        let unrestricted = Self {
+            choices: Vec::new(),
+            ..self.clone()
+        };
+        for choice in &self.choices {
+            unrestricted
+                .check_scalar(value_type, &choice_to_string(choice))
+                .map_err(|e| anyhow::anyhow!("invalid choice {}: {}", choice, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Check a value, as coerced for interpolation, against the type and the constraints.
+    pub fn check(&self, param_type: &str, value: &str) -> anyhow::Result<()> {
+        if param_type == "array" {
+            return array_items(value)
+                .try_for_each(|item| self.check_scalar(self.item_type(), item));
+        }
+        self.check_scalar(param_type, value)
+    }
+
+    fn check_scalar(&self, value_type: &str, value: &str) -> anyhow::Result<()> {
+        match value_type {
+            "integer" if value.trim().parse::<i64>().is_err() => {
+                return Err(anyhow::anyhow!("expected an integer, got '{}'", value));
+            }
+            "number" if value.trim().parse::<f64>().is_err() => {
+                return Err(anyhow::anyhow!("expected a number, got '{}'", value));
+            }
+            "boolean" if value != "true" && value != "false" => {
+                return Err(anyhow::anyhow!("expected a boolean, got '{}'", value));
+            }
+            _ => {}
+        }
+
+        if !self.choices.is_empty()
+            && !self
+                .choices
+                .iter()
+                .any(|choice| choice_to_string(choice) == value)
+        {
+            return Err(anyhow::anyhow!(
+                "expected one of {}, got '{}'",
+                self.choices
+                    .iter()
+                    .map(choice_to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                value
+            ));
+        }
+
+        if is_number_type(value_type) {
+            let number: f64 = value.trim().parse()?;
+            if let Some(minimum) = self.minimum.filter(|minimum| number < *minimum) {
+                return Err(anyhow::anyhow!("{} is less than {}", value, minimum));
+            }
+            if let Some(maximum) = self.maximum.filter(|maximum| number > *maximum) {
+                return Err(anyhow::anyhow!("{} is greater than {}", value, maximum));
+            }
+        }
+
+        if is_string_type(value_type) {
+            let length = value.chars().count();
+            if let Some(min_length) = self.min_length.filter(|min| length < *min) {
+                return Err(anyhow::anyhow!(
+                    "'{}' is shorter than {} characters",
+                    value,
+                    min_length
+                ));
+            }
+            if let Some(max_length) = self.max_length.filter(|max| length > *max) {
+                return Err(anyhow::anyhow!(
+                    "'{}' is longer than {} characters",
+                    value,
+                    max_length
+                ));
+            }
+            if let Some(pattern) = &self.pattern {
+                if !pattern_regex(pattern)?.is_match(value) {
+                    return Err(anyhow::anyhow!(
+                        "'{}' does not match the pattern {}",
+                        value,
+                        pattern
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A value as coerced for interpolation, converted back to JSON for the schema.
+    pub fn schema_value(&self, param_type: &str, value: &str) -> Value {
+        let value_type = match param_type {
+            "array" => {
+                return array_items(value)
+                    .map(|item| self.schema_value(self.item_type(), item))
+                    .collect()
+            }
+            value_type => value_type,
+        };
+
+        let parsed = match value_type {
+            "integer" => value.trim().parse::<i64>().ok().map(Value::from),
+            "number" => value.trim().parse::<f64>().ok().map(Value::from),
+            "boolean" => value.parse::<bool>().ok().map(Value::from),
+            _ => None,
+        };
+        parsed.unwrap_or_else(|| Value::String(value.to_string()))
+    }
+
+    /// JSON schema keywords of the constraints, to add to the schema of the parameter.
+    pub fn schema_keywords(&self, param_type: &str) -> serde_json::Map<String, Value> {
+        let mut keywords = serde_json::Map::new();
+        if !self.choices.is_empty() {
+            let choices = if param_type == "enum" {
+                self.choices
+                    .iter()
+                    .map(|choice| Value::String(choice_to_string(choice)))
+                    .collect()
+            } else {
+                self.choices.clone()
+            };
+            keywords.insert("enum".to_string(), Value::Array(choices));
+        }
+        if let Some(minimum) = self.minimum {
+            keywords.insert("minimum".to_string(), minimum.into());
+        }
+        if let Some(maximum) = self.maximum {
+            keywords.insert("maximum".to_string(), maximum.into());
+        }
+        if let Some(min_length) = self.min_length {
+            keywords.insert("minLength".to_string(), min_length.into());
+        }
+        if let Some(max_length) = self.max_length {
+            keywords.insert("maxLength".to_string(), max_length.into());
+        }
+        if let Some(pattern) = &self.pattern {
+            // json schema patterns are not anchored
+            keywords.insert("pattern".to_string(), format!("^(?:{})$", pattern).into());
+        }
+
+        if param_type != "array" {
+            return keywords;
+        }
+
+        // the constraints of arrays are the ones of their items
+        let mut items = keywords;
+        let item_type = match self.item_type() {
+            TARGET_TYPE => "string",
+            item_type => item_type,
+        };
+        items.insert("type".to_string(), item_type.into());
+        serde_json::Map::from_iter([("items".to_string(), Value::Object(items))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraints(yaml: &str) -> Constraints {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_check_definition() {
+        assert!(Constraints::default().check_definition("string").is_ok());
+        assert!(Constraints::default().check_definition("str").is_err());
+        assert!(Constraints::default().check_definition("enum").is_err());
+        assert!(constraints("choices: [tcp, udp]")
+            .check_definition("enum")
+            .is_ok());
+        assert!(constraints("minimum: 1\nmaximum: 65535")
+            .check_definition("integer")
+            .is_ok());
+        assert!(constraints("minimum: 10\nmaximum: 1")
+            .check_definition("integer")
+            .is_err());
+        assert!(constraints("minimum: 1")
+            .check_definition("string")
+            .is_err());
+        assert!(constraints("pattern: '[a-z'")
+            .check_definition("string")
+            .is_err());
+        assert!(constraints("choices: [1, 2, x]")
+            .check_definition("integer")
+            .is_err());
+        assert!(constraints("items: integer\nmaximum: 10")
+            .check_definition("array")
+            .is_ok());
+        assert!(constraints("items: integer")
+            .check_definition("string")
+            .is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let ports = constraints("minimum: 1\nmaximum: 65535");
+        assert!(ports.check("integer", "443").is_ok());
+        assert!(ports.check("integer", "0").is_err());
+        assert!(ports.check("integer", "70000").is_err());
+        assert!(ports.check("integer", "4.5").is_err());
+
+        let protocol = constraints("choices: [tcp, udp]");
+        assert!(protocol.check("enum", "udp").is_ok());
+        assert!(protocol.check("enum", "icmp").is_err());
+        // as in json schemas
+        assert_eq!(constraints("enum: [tcp, udp]"), protocol);
+
+        let hostname = constraints("min_length: 3\nmax_length: 10\npattern: '[a-z.]+'");
+        assert!(hostname.check("string", "example").is_ok());
+        assert!(hostname.check("string", "ex").is_err());
+        assert!(hostname.check("string", "example.com").is_err());
+        assert!(hostname.check("string", "EXAMPLE").is_err());
+
+        assert!(Constraints::default().check("boolean", "yes").is_err());
+
+        let ports = constraints("items: integer\nmaximum: 1024");
+        assert!(ports.check("array", "22,80, 443").is_ok());
+        assert!(ports.check("array", "22,8080").is_err());
+    }
+
+    #[test]
+    fn test_schema_keywords() {
+        let keywords =
+            constraints("choices: [1, 2]\nminimum: 1\nmaximum: 2").schema_keywords("integer");
+        assert_eq!(
+            Value::Object(keywords),
+            serde_json::json!({"enum": [1, 2], "minimum": 1.0, "maximum": 2.0})
+        );
+
+        let keywords = constraints("pattern: '[a-z]+'\nmax_length: 8").schema_keywords("array");
+        assert_eq!(
+            Value::Object(keywords),
+            serde_json::json!({"items": {"type": "string", "pattern": "^(?:[a-z]+)$", "maxLength": 8}})
+        );
+    }
+}
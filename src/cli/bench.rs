@@ -0,0 +1,209 @@
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use crate::book::{
+    flavors::{openai, BYTES_PER_TOKEN},
+    runtime::ExecutionFlavor,
+};
+use crate::config::Config;
+use crate::runtime::{
+    self, audit::Provenance, backend::ContainerBackend, exit_code, get_container_runtime,
+    workers::Workers,
+};
+
+use super::{load_book, BenchArgs};
+
+// how many containers are started to measure their startup time
+const STARTUP_SAMPLES: usize = 5;
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
+
+// nearest-rank percentile of sorted durations
+fn percentile(sorted: &[Duration], percentile: usize) -> Duration {
+    let rank = (percentile * sorted.len()).div_ceil(100).max(1);
+    sorted[rank.min(sorted.len()) - 1]
+}
+
+// median time to start and remove a container of the image running nothing, or None if the image
+// has no true executable
+async fn container_startup(image: &str, samples: usize) -> Option<Duration> {
+    let runtime = get_container_runtime();
+    let mut elapsed = Vec::new();
+    for _ in 0..samples {
+        let started = Instant::now();
+        let status = Command::new(&runtime)
+            .args(["run", "--rm", "--entrypoint", "true", image])
+            .output()
+            .await
+            .ok()?
+            .status;
+        if !status.success() {
+            return None;
+        }
+        elapsed.push(started.elapsed());
+    }
+    elapsed.sort();
+    Some(percentile(&elapsed, 50))
+}
+
+pub(crate) async fn bench(args: BenchArgs) -> anyhow::Result<()> {
+    if args.iterations == 0 || args.concurrency == 0 {
+        return Err(anyhow::anyhow!(
+            "iterations and concurrency must be greater than zero"
+        ));
+    }
+    if let Some(backend) = args.container_backend {
+        runtime::backend::set(backend);
+    }
+
+    let config = Config::load()?;
+    runtime::paths::set(&config.paths)?;
+    runtime::signing::set_trusted(&config.trust.image_signers)?;
+
+    let book = Arc::new(load_book(args.path, None, &args.discovery, false)?);
+    let function = book.get_function(&args.function)?;
+
+    // the defined arguments, or the first example
+    let mut arguments: BTreeMap<String, serde_json::Value> = function
+        .function
+        .examples
+        .iter()
+        .flatten()
+        .next()
+        .cloned()
+        .unwrap_or_default();
+    let mut provenance: BTreeMap<String, Provenance> = arguments
+        .keys()
+        .map(|name| (name.clone(), Provenance::Example))
+        .collect();
+    for (name, value) in args.defines {
+        provenance.insert(name.clone(), Provenance::Define);
+        arguments.insert(name, value.into());
+    }
+
+    let flavor = ExecutionFlavor::for_function(function.function)?;
+    println!(
+        "benchmarking {} ({}) {} times, {} at a time ...",
+        &args.function, flavor, args.iterations, args.concurrency
+    );
+
+    let call = openai::Call {
+        priority: None,
+        id: None,
+        function: openai::FunctionCall {
+            name: args.function.clone(),
+            arguments,
+        },
+        call_type: "function".to_string(),
+        caller: None,
+        env: BTreeMap::new(),
+        hosts: Vec::new(),
+        provenance,
+    };
+
+    let workers = Workers::new(args.concurrency);
+    let started = Instant::now();
+    let results: Vec<_> = futures::stream::iter(0..args.iterations)
+        .map(|_| {
+            let book = book.clone();
+            let call = call.clone();
+            let workers = workers.clone();
+            async move {
+                let started = Instant::now();
+                let result = runtime::execute_call(
+                    None,
+                    false,
+                    workers,
+                    book,
+                    call,
+                    CancellationToken::new(),
+                )
+                .await;
+                (started.elapsed(), result)
+            }
+        })
+        .buffer_unordered(args.concurrency)
+        .collect()
+        .await;
+    let total = started.elapsed();
+
+    let mut latencies = Vec::new();
+    let mut sizes = Vec::new();
+    let mut failures = 0;
+    for (elapsed, result) in results {
+        latencies.push(elapsed);
+        match result {
+            Ok(result) => {
+                if exit_code(&result.content) != Some(0) {
+                    failures += 1;
+                }
+                sizes.push(result.content.len());
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                failures += 1;
+            }
+        }
+    }
+    latencies.sort();
+
+    println!(
+        "\n{} calls in {} ({:.1} calls/s), {} failed",
+        latencies.len(),
+        millis(total),
+        latencies.len() as f64 / total.as_secs_f64(),
+        failures
+    );
+    println!(
+        "latency: min {}, p50 {}, p90 {}, p99 {}, max {}",
+        millis(latencies[0]),
+        millis(percentile(&latencies, 50)),
+        millis(percentile(&latencies, 90)),
+        millis(percentile(&latencies, 99)),
+        millis(latencies[latencies.len() - 1])
+    );
+
+    if !sizes.is_empty() {
+        let average = sizes.iter().sum::<usize>() / sizes.len();
+        println!(
+            "output: {} B / ~{} tk on average, {} B max",
+            average,
+            average.div_ceil(BYTES_PER_TOKEN),
+            sizes.iter().max().unwrap_or(&0)
+        );
+    }
+
+    if let ExecutionFlavor::Docker(image) = &flavor {
+        if runtime::backend::get() == Some(ContainerBackend::K8s) {
+            return Ok(());
+        }
+
+        let samples = STARTUP_SAMPLES.min(args.iterations);
+        match container_startup(image, samples).await {
+            Some(startup) => {
+                let p50 = percentile(&latencies, 50);
+                println!(
+                    "container startup: {} (median of {}), tool runtime: ~{}",
+                    millis(startup),
+                    samples,
+                    millis(p50.saturating_sub(startup))
+                );
+            }
+            None => println!(
+                "container startup: unknown, {} has no true executable",
+                image
+            ),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use crate::book::bundle::Bundle;
+
+use super::{load_book, BundleArgs};
+
+pub(crate) async fn bundle(args: BundleArgs) -> anyhow::Result<()> {
+    let book = load_book(args.path.clone(), args.filter, &args.discovery, false)?;
+    let bundle = Bundle::new(&args.path, &book)?;
+
+    let data = if args.output.extension() == Some("json") {
+        serde_json::to_string_pretty(&bundle)?
+    } else {
+        serde_yaml::to_string(&bundle)?
+    };
+    std::fs::write(&args.output, data)
+        .map_err(|e| anyhow::anyhow!("error while writing {}: {}", &args.output, e))?;
+
+    log::info!(
+        "bundled {} functions of {} pages and {} assets into {}",
+        book.num_functions(),
+        bundle.pages.len(),
+        bundle.assets.len(),
+        &args.output
+    );
+
+    Ok(())
+}
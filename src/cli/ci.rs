@@ -0,0 +1,327 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use camino::Utf8PathBuf;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use crate::book::{flavors::openai, Book};
+use crate::config::Config;
+use crate::runtime::{
+    self, audit::Provenance, get_container_runtime, image_digest, workers::Workers, ContainerSource,
+};
+
+use super::{load_book, validate_function, validate_page, CiArgs};
+
+struct TestCase {
+    name: String,
+    elapsed: Duration,
+    failure: Option<String>,
+}
+
+impl TestCase {
+    fn new(name: String, elapsed: Duration, result: anyhow::Result<()>) -> Self {
+        let failure = result.err().map(|e| e.to_string());
+        match &failure {
+            Some(failure) => log::error!("  {} - {}", name, failure),
+            None => log::info!("  {} - ok", name),
+        }
+        Self {
+            name,
+            elapsed,
+            failure,
+        }
+    }
+}
+
+struct TestSuite {
+    name: &'static str,
+    cases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    fn new(name: &'static str) -> Self {
+        log::info!("{} ...", name);
+        Self {
+            name,
+            cases: Vec::new(),
+        }
+    }
+
+    fn failures(&self) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| case.failure.is_some())
+            .count()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn junit(suites: &[TestSuite]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+    for suite in suites {
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+            suite.name,
+            suite.cases.len(),
+            suite.failures(),
+            suite
+                .cases
+                .iter()
+                .map(|case| case.elapsed)
+                .sum::<Duration>()
+                .as_secs_f64()
+        ));
+        for case in &suite.cases {
+            xml.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                suite.name,
+                escape(&case.name),
+                case.elapsed.as_secs_f64()
+            ));
+            match &case.failure {
+                Some(failure) => xml.push_str(&format!(
+                    ">\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                    escape(failure)
+                )),
+                None => xml.push_str("/>\n"),
+            }
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+async fn validate(book: &Book) -> TestSuite {
+    let mut suite = TestSuite::new("validate");
+    for (page_path, page) in &book.pages {
+        let started = Instant::now();
+        let result = validate_page(false, page_path, page);
+        suite.cases.push(TestCase::new(
+            page_path.to_string(),
+            started.elapsed(),
+            result,
+        ));
+
+        for (func_name, func) in &page.functions {
+            let started = Instant::now();
+            let result = match validate_function(false, page_path, func_name, func).await {
+                Ok(()) => book.check_pipeline(func_name),
+                Err(e) => Err(e),
+            };
+            suite
+                .cases
+                .push(TestCase::new(func_name.clone(), started.elapsed(), result));
+        }
+    }
+    suite
+}
+
+// images must come from a registry, so that they resolve to the same digest everywhere
+async fn check_images(book: &Book) -> TestSuite {
+    let mut suite = TestSuite::new("images");
+    let images: BTreeSet<&str> = book
+        .pages
+        .values()
+        .flat_map(|page| page.functions.values())
+        .filter_map(|func| match &func.container.as_ref()?.source {
+            ContainerSource::Image(image) => Some(image.as_str()),
+            ContainerSource::Build(_) => None,
+        })
+        .collect();
+
+    for image in images {
+        let started = Instant::now();
+        let result = match image_digest(image).await {
+            Ok(digest) if digest.contains("@sha256:") => {
+                log::debug!("{} resolved to {}", image, digest);
+                Ok(())
+            }
+            Ok(_) => Err(anyhow::anyhow!("{} has no registry digest", image)),
+            Err(e) => Err(e),
+        };
+        suite
+            .cases
+            .push(TestCase::new(image.to_string(), started.elapsed(), result));
+    }
+    suite
+}
+
+async fn run_tests(book: Arc<Book>) -> TestSuite {
+    let mut suite = TestSuite::new("tests");
+    let tests: Vec<_> = book
+        .pages
+        .values()
+        .flat_map(|page| page.functions.iter())
+        .flat_map(|(func_name, func)| {
+            func.tests
+                .iter()
+                .enumerate()
+                .map(move |(index, test)| (func_name.clone(), index, test.clone()))
+        })
+        .collect();
+
+    // one test at a time
+    let workers = Workers::new(1);
+    for (func_name, index, test) in tests {
+        let name = format!(
+            "{}: {}",
+            &func_name,
+            test.name.clone().unwrap_or_else(|| format!("#{}", index))
+        );
+        let call = openai::Call {
+            priority: None,
+            id: None,
+            function: openai::FunctionCall {
+                name: func_name,
+                arguments: test.arguments.clone(),
+            },
+            call_type: "function".to_string(),
+            caller: None,
+            env: BTreeMap::new(),
+            hosts: Vec::new(),
+            provenance: test
+                .arguments
+                .keys()
+                .map(|name| (name.clone(), Provenance::Test))
+                .collect(),
+        };
+
+        let started = Instant::now();
+        let result = runtime::execute_call(
+            None,
+            false,
+            workers.clone(),
+            book.clone(),
+            call,
+            CancellationToken::new(),
+        )
+        .await
+        .and_then(|result| test.check(&result.content));
+        suite
+            .cases
+            .push(TestCase::new(name, started.elapsed(), result));
+    }
+    suite
+}
+
+// run this command again in a disposable robopages container, with access to the docker daemon
+async fn run_in_container(args: &CiArgs, image: &str) -> anyhow::Result<()> {
+    let path = args
+        .path
+        .canonicalize_utf8()
+        .map_err(|e| anyhow::anyhow!("could not find {}: {}", args.path, e))?;
+    let report_dir = match args.junit.parent() {
+        Some(dir) if !dir.as_str().is_empty() => dir.to_path_buf(),
+        _ => Utf8PathBuf::from("."),
+    }
+    .canonicalize_utf8()?;
+    let report = args
+        .junit
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("invalid report path {}", args.junit))?;
+
+    let mut ci_args = vec![
+        "ci".to_string(),
+        "-P".to_string(),
+        "/book".to_string(),
+        "--junit".to_string(),
+        format!("/report/{}", report),
+    ];
+    if let Some(filter) = &args.filter {
+        ci_args.extend(["-F".to_string(), filter.clone()]);
+    }
+    if args.skip_tests {
+        ci_args.push("--skip-tests".to_string());
+    }
+
+    log::info!("running in a disposable {} container ...", image);
+    let status = Command::new(get_container_runtime())
+        .args([
+            "run",
+            "--rm",
+            "-v/var/run/docker.sock:/var/run/docker.sock",
+            &format!("-v{}:/book:ro", path),
+            &format!("-v{}:/report", report_dir),
+            image,
+        ])
+        .args(&ci_args)
+        .status()
+        .await?;
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("ci failed in container with {}", status));
+    }
+    Ok(())
+}
+
+pub(crate) async fn ci(args: CiArgs) -> anyhow::Result<()> {
+    if let Some(image) = &args.container {
+        return run_in_container(&args, image).await;
+    }
+
+    let config = Config::load()?;
+    runtime::paths::set(&config.paths)?;
+    runtime::signing::set_trusted(&config.trust.image_signers)?;
+
+    let mut suites = Vec::new();
+
+    let mut load = TestSuite::new("load");
+    let started = Instant::now();
+    let book = load_book(
+        args.path.clone(),
+        args.filter.clone(),
+        &args.discovery,
+        false,
+    );
+    let elapsed = started.elapsed();
+    let book = match book {
+        Ok(book) => {
+            load.cases
+                .push(TestCase::new(args.path.to_string(), elapsed, Ok(())));
+            suites.push(load);
+            Some(Arc::new(book))
+        }
+        Err(e) => {
+            load.cases
+                .push(TestCase::new(args.path.to_string(), elapsed, Err(e)));
+            suites.push(load);
+            None
+        }
+    };
+
+    if let Some(book) = book {
+        suites.push(validate(&book).await);
+        suites.push(check_images(&book).await);
+        if !args.skip_tests {
+            suites.push(run_tests(book).await);
+        }
+    }
+
+    std::fs::write(&args.junit, junit(&suites))
+        .map_err(|e| anyhow::anyhow!("could not write {}: {}", args.junit, e))?;
+
+    let total: usize = suites.iter().map(|suite| suite.cases.len()).sum();
+    let failures: usize = suites.iter().map(|suite| suite.failures()).sum();
+    println!(
+        "{} checks, {} failures, report written to {}",
+        total, failures, args.junit
+    );
+
+    if failures > 0 {
+        Err(anyhow::anyhow!("ci failed with {} failures", failures))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use crate::runtime;
+
+use super::{ContainersArgs, ContainersCommand};
+
+pub(crate) async fn containers(args: ContainersArgs) -> anyhow::Result<()> {
+    match args.command {
+        ContainersCommand::List => {
+            let containers = runtime::list_containers().await?;
+            if containers.is_empty() {
+                println!("no containers found");
+            }
+
+            for container in containers {
+                println!(
+                    "{} {} : {} ({}){}",
+                    &container.id[..12.min(container.id.len())],
+                    container.function,
+                    container.image,
+                    container.status,
+                    if container.is_orphan() {
+                        " [orphan]"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+        ContainersCommand::Kill { ids, orphans, all } => {
+            if ids.is_empty() && !orphans && !all {
+                return Err(anyhow::anyhow!(
+                    "specify one or more container ids, --orphans or --all"
+                ));
+            }
+
+            for container in runtime::list_containers().await? {
+                let selected = all
+                    || (orphans && container.is_orphan())
+                    || ids
+                        .iter()
+                        .any(|id| container.id.starts_with(id) || &container.name == id);
+
+                if selected {
+                    log::info!(
+                        "removing container {} for function {} ...",
+                        &container.name,
+                        &container.function
+                    );
+                    runtime::kill_container(&container.id).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
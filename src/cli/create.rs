@@ -8,15 +8,11 @@ pub(crate) async fn create(args: CreateArgs) -> anyhow::Result<()> {
     for parts in args.template.get_data()? {
         if let Some(part_name) = parts.name {
             let asset = args.name.parent().unwrap().join(part_name);
-            log::info!("creating asset {:?}", asset.to_string());
+            log::info!("creating asset {:?}", asset);
 
             std::fs::write(asset, parts.data)?;
         } else {
-            log::info!(
-                "creating {:?} from template {}",
-                &args.name,
-                args.template.to_string()
-            );
+            log::info!("creating {:?} from template {}", &args.name, args.template);
 
             std::fs::write(&args.name, parts.data)?;
         }
@@ -0,0 +1,57 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+
+use crate::book::finetune;
+
+use super::{load_book, ExportArgs};
+
+/// What the functions are exported as.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// JSONL samples of requests and expected calls, in the chat format of fine-tuning APIs.
+    Finetune,
+}
+
+pub(crate) async fn export(args: ExportArgs) -> anyhow::Result<()> {
+    let book = load_book(args.path, args.filter, &args.discovery, false)?;
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path)
+                .map_err(|e| anyhow::anyhow!("could not create {}: {}", path, e))?,
+        )),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let mut num_samples = 0;
+    let mut without_samples = Vec::new();
+    match args.format {
+        ExportFormat::Finetune => {
+            for page in book.pages.values() {
+                for (name, function) in &page.functions {
+                    let samples = finetune::samples(name, function);
+                    if samples.is_empty() {
+                        without_samples.push(name.as_str());
+                    }
+                    for sample in samples {
+                        writeln!(out, "{}", serde_json::to_string(&sample)?)?;
+                        num_samples += 1;
+                    }
+                }
+            }
+        }
+    }
+    out.flush()?;
+
+    log::info!(
+        "exported {} samples of {} functions",
+        num_samples,
+        book.num_functions() - without_samples.len()
+    );
+    if !without_samples.is_empty() {
+        log::warn!("no examples to export for {}", without_samples.join(", "));
+    }
+
+    Ok(())
+}
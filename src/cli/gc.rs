@@ -0,0 +1,49 @@
+use crate::runtime::{self, backend};
+
+use super::GcArgs;
+
+pub(crate) async fn gc(args: GcArgs) -> anyhow::Result<()> {
+    if let Some(container_backend) = args.container_backend {
+        backend::set(container_backend);
+    }
+
+    let orphans: Vec<_> = runtime::list_containers()
+        .await?
+        .into_iter()
+        .filter(|container| container.is_orphan())
+        .collect();
+    let images = if args.keep_images {
+        Vec::new()
+    } else {
+        runtime::list_dangling_images().await?
+    };
+
+    if args.dry_run {
+        for container in &orphans {
+            println!(
+                "container {} for function {} ({})",
+                &container.name, &container.function, &container.status
+            );
+        }
+        for id in &images {
+            println!("image {}", id);
+        }
+        println!(
+            "{} containers and {} images would be removed",
+            orphans.len(),
+            images.len()
+        );
+        return Ok(());
+    }
+
+    let containers = runtime::remove_orphans().await?;
+    let images = if images.is_empty() {
+        0
+    } else {
+        runtime::remove_dangling_images().await?
+    };
+
+    println!("removed {} containers and {} images", containers, images);
+
+    Ok(())
+}
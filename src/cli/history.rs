@@ -0,0 +1,54 @@
+use crate::runtime::history::{self, Filter};
+
+use super::HistoryArgs;
+
+pub(crate) async fn history(args: HistoryArgs) -> anyhow::Result<()> {
+    let filter = Filter {
+        function: args.function,
+        functions: None,
+        since: args.since.as_deref().map(history::parse_time).transpose()?,
+        until: args.until.as_deref().map(history::parse_time).transpose()?,
+        limit: Some(args.limit),
+    };
+
+    let records = history::executions(&filter)?;
+    if args.json {
+        for record in &records {
+            println!("{}", serde_json::to_string(record)?);
+        }
+        return Ok(());
+    }
+
+    if records.is_empty() {
+        println!("no executions recorded");
+        return Ok(());
+    }
+
+    for record in &records {
+        let status = match (&record.error, record.exit_code) {
+            (Some(error), _) => format!("error: {}", error),
+            (None, Some(code)) => format!("exit code {}", code),
+            (None, None) => "exit code unknown".to_string(),
+        };
+        println!(
+            "{} {} ({:.2}s, {})",
+            record.timestamp, record.function, record.duration_secs, status
+        );
+        if let Some(caller) = &record.caller {
+            println!("  caller: {}", caller);
+        }
+        println!("  command: {}", record.command);
+        for (name, source) in &record.provenance {
+            println!("  {} from {}", name, source);
+        }
+        if args.output {
+            if let Some(output) = &record.output {
+                for line in output.lines() {
+                    println!("  | {}", line);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,12 +1,68 @@
 use std::{
+    collections::BTreeMap,
     fs::File,
     io::{self, Write},
     path::{Path, PathBuf},
 };
 
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
+use tokio::process::Command;
 
 use super::InstallArgs;
+use crate::book::sources::{self, Source, Sources};
+use crate::book::{oci, package};
+use crate::config::Config;
+use crate::runtime::progress;
+
+// settings and state of older versions, that an installed book must not provide
+const RESERVED: [&str; 2] = [".config.yml", ".state"];
+
+fn is_commit(value: &str) -> bool {
+    value.len() == 40 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn repository_url(source: &str) -> String {
+    if source.contains("://") {
+        source.trim_end_matches('/').to_string()
+    } else {
+        format!("https://github.com/{}", source)
+    }
+}
+
+async fn git(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("could not run git: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+async fn download(url: &str, path: &Path) -> anyhow::Result<()> {
+    let mut response = reqwest::get(url).await?.error_for_status()?;
+    let mut file = std::fs::File::create(path)?;
+
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+    }
+    Ok(())
+}
+
+async fn install_with_git(url: &str, git_ref: &str, path: &Utf8PathBuf) -> anyhow::Result<String> {
+    log::info!("cloning {} to {:?} ...", url, path);
+    progress::emit(progress::Event::Downloading { source: url });
+    git(&["clone", "--quiet", url, path.as_str()]).await?;
+    // checkout after cloning, so that commits work as well as branches and tags
+    git(&["-C", path.as_str(), "checkout", "--quiet", git_ref]).await?;
+    git(&["-C", path.as_str(), "rev-parse", "HEAD"]).await
+}
 
 fn extract_archive_without_intermediate_folder(
     mut archive: zip::ZipArchive<File>,
@@ -39,6 +95,36 @@ fn extract_archive_without_intermediate_folder(
     Ok(())
 }
 
+// remove the reserved entries shipped by the installed book, if any
+fn remove_reserved(path: &Utf8Path) -> anyhow::Result<()> {
+    for name in RESERVED {
+        let reserved = path.join(name);
+        let Ok(metadata) = std::fs::symlink_metadata(&reserved) else {
+            continue;
+        };
+
+        log::warn!(
+            "skipping {:?}, reserved for the settings and state",
+            reserved
+        );
+        if metadata.is_dir() {
+            std::fs::remove_dir_all(&reserved)?;
+        } else {
+            std::fs::remove_file(&reserved)?;
+        }
+    }
+    Ok(())
+}
+
+// github archives store the commit they were created from as the zip comment
+fn archive_commit(archive_path: &Path) -> Option<String> {
+    let archive = zip::ZipArchive::new(File::open(archive_path).ok()?).ok()?;
+    let comment = String::from_utf8_lossy(archive.comment())
+        .trim()
+        .to_string();
+    is_commit(&comment).then_some(comment)
+}
+
 fn extract_archive(archive_path: &Path, target_path: &Path) -> io::Result<()> {
     log::info!("extracting to {:?}", target_path);
 
@@ -71,43 +157,149 @@ fn extract_archive(archive_path: &Path, target_path: &Path) -> io::Result<()> {
     Ok(())
 }
 
+// extract a zip archive and returns its version if it's a package
+fn install_package(
+    archive: &Utf8Path,
+    source: &str,
+    path: &Utf8PathBuf,
+) -> anyhow::Result<Option<String>> {
+    // packages are signed like pages, check them against the trusted keys
+    Config::load()?.trust.verifier()?.verify(archive)?;
+
+    log::info!("extracting archive {} to {:?}", source, path);
+    progress::emit(progress::Event::Extracting {
+        path: path.as_str(),
+    });
+    let mut zip = zip::ZipArchive::new(std::fs::File::open(archive)?)?;
+    zip.extract(path)?;
+
+    match package::verify_installed(path) {
+        Ok(Some(manifest)) => {
+            log::info!("installed {} {}", manifest.name, manifest.version);
+            Ok(Some(manifest.version))
+        }
+        Ok(None) => Ok(None),
+        Err(e) => {
+            std::fs::remove_dir_all(path)?;
+            Err(e)
+        }
+    }
+}
+
 pub(crate) async fn install(args: InstallArgs) -> anyhow::Result<()> {
     let path = Utf8PathBuf::from(
         shellexpand::full(args.path.as_str())
             .map_err(|e| anyhow::anyhow!("failed to expand path: {}", e))?
             .into_owned(),
     );
+    // namespaced sources are installed next to each other in subdirectories of the path
+    let root = path.clone();
+    let path = match &args.namespace {
+        Some(namespace) => {
+            sources::validate_namespace(namespace)?;
+            path.join(namespace)
+        }
+        None => path,
+    };
     if path.exists() {
         return Err(anyhow::anyhow!("{:?} already exists", path));
     }
 
-    if args.source.ends_with(".zip") {
-        // install from zip archive
-        log::info!("extracting archive {} to {:?}", &args.source, &path);
-        let mut zip = zip::ZipArchive::new(std::fs::File::open(&args.source)?)?;
-        zip.extract(path)?;
-    } else {
-        // install from github repository
-        let source = if !args.source.contains("://") {
-            format!(
-                "https://github.com/{}/archive/refs/heads/main.zip",
-                &args.source
-            )
+    let record = if args.source.starts_with("oci://") {
+        let reference = oci::Reference::parse(&args.source)?;
+        let digest = oci::resolve(&reference).await?;
+        let temp_dir = tempfile::tempdir()?;
+
+        // pull by digest, so that what is installed is what was resolved
+        log::info!("pulling {} ({}) ...", reference, &digest);
+        progress::emit(progress::Event::Downloading {
+            source: &args.source,
+        });
+        let archive = oci::pull(&reference.pinned(&digest), temp_dir.path()).await?;
+        let version = install_package(&archive, &args.source, &path)?;
+
+        Source {
+            source: args.source,
+            git_ref: version.or(reference.tag),
+            commit: Some(digest),
+            functions: BTreeMap::new(),
+        }
+    } else if args.source.ends_with(".zip") {
+        // archives from an URL are downloaded with their signature, if any
+        let temp_dir = tempfile::tempdir()?;
+        let archive = if args.source.contains("://") {
+            let archive = Utf8PathBuf::from_path_buf(temp_dir.path().join("package.zip"))
+                .map_err(|p| anyhow::anyhow!("invalid path {:?}", p))?;
+            log::info!("downloading {} ...", &args.source);
+            progress::emit(progress::Event::Downloading {
+                source: &args.source,
+            });
+            download(&args.source, archive.as_std_path()).await?;
+            let signature = format!("{}.minisig", archive);
+            if download(&format!("{}.minisig", &args.source), Path::new(&signature))
+                .await
+                .is_err()
+            {
+                log::debug!("no signature found for {}", &args.source);
+            }
+            archive
         } else {
-            format!("{}/archive/refs/heads/main.zip", &args.source)
+            Utf8PathBuf::from(&args.source)
         };
 
-        log::info!("downloading robopages from {} ...", source);
+        let version = install_package(&archive, &args.source, &path)?;
 
-        let temp_file = tempfile::NamedTempFile::new()?;
-        let mut response = reqwest::get(&source).await?;
-        let mut file = std::fs::File::create(temp_file.path())?;
+        Source {
+            source: args.source,
+            git_ref: version,
+            commit: None,
+            functions: BTreeMap::new(),
+        }
+    } else {
+        let url = repository_url(&args.source);
+        let commit = if args.git {
+            Some(install_with_git(&url, &args.git_ref, &path).await?)
+        } else {
+            // install from the repository archive, which works for branches, tags and commits
+            let source = format!("{}/archive/{}.zip", url, &args.git_ref);
 
-        while let Some(chunk) = response.chunk().await? {
-            file.write_all(&chunk)?;
+            log::info!("downloading robopages from {} ...", source);
+            progress::emit(progress::Event::Downloading { source: &source });
+
+            let temp_file = tempfile::NamedTempFile::new()?;
+            download(&source, temp_file.path()).await?;
+
+            progress::emit(progress::Event::Extracting {
+                path: path.as_str(),
+            });
+            extract_archive(temp_file.path(), path.as_std_path())?;
+            archive_commit(temp_file.path())
+        };
+
+        match &commit {
+            Some(commit) => log::info!("installed {}@{} ({})", url, &args.git_ref, commit),
+            None => log::warn!("could not resolve the commit of {}@{}", url, &args.git_ref),
+        }
+
+        Source {
+            source: args.source,
+            git_ref: Some(args.git_ref),
+            commit,
+            functions: BTreeMap::new(),
         }
+    };
+
+    remove_reserved(&path)?;
+
+    let record = record.with_functions(&path);
+    record.save(&path)?;
+
+    if let Some(namespace) = args.namespace {
+        let mut manifest = Sources::load(&root)?;
+        manifest.namespaces.insert(namespace.clone(), record);
+        manifest.save(&root)?;
 
-        extract_archive(temp_file.path(), path.as_std_path())?;
+        log::info!("installed namespace {} in {:?}", namespace, root);
     }
 
     Ok(())
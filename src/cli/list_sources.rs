@@ -0,0 +1,31 @@
+use camino::Utf8PathBuf;
+
+use crate::book::sources::{Source, Sources};
+
+use super::ListSourcesArgs;
+
+pub(crate) async fn list_sources(args: ListSourcesArgs) -> anyhow::Result<()> {
+    let path = Utf8PathBuf::from(
+        shellexpand::full(args.path.as_str())
+            .map_err(|e| anyhow::anyhow!("failed to expand path: {}", e))?
+            .into_owned(),
+    );
+
+    let source = Source::load(&path)?;
+    let sources = Sources::load(&path)?;
+    if source.is_none() && sources.namespaces.is_empty() {
+        println!("no sources installed in {}", path);
+        return Ok(());
+    }
+
+    match source {
+        Some(source) => println!("{} : {}", path, source),
+        None => println!("{}", path),
+    }
+
+    for (namespace, source) in &sources.namespaces {
+        println!("  {} : {}", namespace, source);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::runtime::correlation;
+
+/// Format of the log lines written on stderr.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with the ids of the request and call if any.
+    Json,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    timestamp: String,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    call_id: Option<String>,
+}
+
+/// Writes the record as a JSON line, for log aggregators.
+pub(crate) fn format_json(
+    buf: &mut env_logger::fmt::Formatter,
+    record: &log::Record,
+) -> std::io::Result<()> {
+    let correlation = correlation::current();
+    let line = Line {
+        timestamp: humantime::format_rfc3339_millis(std::time::SystemTime::now()).to_string(),
+        level: record.level().as_str(),
+        target: record.target(),
+        message: record.args().to_string(),
+        request_id: correlation.request_id,
+        call_id: correlation.call_id,
+    };
+    writeln!(buf, "{}", serde_json::to_string(&line)?)
+}
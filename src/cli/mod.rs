@@ -3,56 +3,229 @@ use std::error::Error;
 use camino::Utf8PathBuf;
 use clap::{Args, Parser, Subcommand};
 
+mod bench;
+mod bundle;
+mod ci;
+mod containers;
 mod create;
+mod export;
+mod gc;
+mod history;
 mod install;
+mod list_sources;
+mod logging;
+mod package;
+mod publish;
+mod pull;
 mod run;
 mod serve;
+mod tls;
+mod uninstall;
 mod validate;
 mod view;
 
+pub(crate) use bench::*;
+pub(crate) use bundle::*;
+pub(crate) use ci::*;
+pub(crate) use containers::*;
 pub(crate) use create::*;
+pub(crate) use export::*;
+pub(crate) use gc::*;
+pub(crate) use history::*;
 pub(crate) use install::*;
+pub(crate) use list_sources::*;
+pub(crate) use logging::*;
+pub(crate) use package::*;
+pub(crate) use publish::*;
+pub(crate) use pull::*;
 pub(crate) use run::*;
 pub(crate) use serve::*;
+pub(crate) use uninstall::*;
 pub(crate) use validate::*;
 pub(crate) use view::*;
 
 use crate::book::templates::Template;
+use crate::book::{trace::LoadTrace, Book, Discovery};
+use crate::config::Config;
+use crate::runtime::backend::ContainerBackend;
+use crate::runtime::progress::{self, ProgressFormat};
 
 const DEFAULT_REPO: &str = "dreadnode/robopages";
 const DEFAULT_PATH: &str = "~/.robopages/";
+// images pulled or built at the same time
+const DEFAULT_PULL_CONCURRENCY: usize = 4;
 
 #[derive(Debug, Parser)]
 #[clap(name = "robopages", about, version)]
 pub(crate) struct Arguments {
     #[clap(subcommand)]
     pub command: Command,
+    /// Format of the log lines.
+    #[clap(long, value_enum, global = true, default_value = "text")]
+    pub log_format: LogFormat,
 }
 
 #[derive(Debug, Subcommand)]
 pub(crate) enum Command {
     /// Install robopages from a given repository or ZIP archive.
     Install(InstallArgs),
+    /// Remove installed robopages, or a single namespace.
+    Uninstall(UninstallArgs),
+    /// List the sources robopages were installed from.
+    ListSources(ListSourcesArgs),
+    /// Package a book as a versioned archive that can be installed and published.
+    Package(PackageArgs),
+    /// Publish a packaged book as a GitHub release asset or an OCI artifact.
+    Publish(PublishArgs),
     /// Create a new robopage file.
     Create(CreateArgs),
     /// View currently installed robopages.
     View(ViewArgs),
     /// Serve the robopages as a local API.
-    Serve(ServeArgs),
+    Serve(Box<ServeArgs>),
     /// Execute a function from the robopages.
-    Run(RunArgs),
+    Run(Box<RunArgs>),
     /// Validate a robopage YML file.
     Validate(ValidateArgs),
+    /// Manage containers started by robopages.
+    Containers(ContainersArgs),
+    /// Remove the leftover containers and dangling images created by robopages.
+    Gc(GcArgs),
+    /// Pull or build the images of all the containers ahead of time.
+    Pull(PullArgs),
+    /// Load, validate and test the robopages, and write a JUnit XML report.
+    Ci(CiArgs),
+    /// Execute a function repeatedly and report its latency and output size.
+    Bench(BenchArgs),
+    /// Export the functions as a dataset.
+    Export(ExportArgs),
+    /// Compile the robopages to a single YAML or JSON file that serve and run can load.
+    Bundle(BundleArgs),
+    /// Show the executions recorded by run and serve.
+    History(HistoryArgs),
+}
+
+impl Command {
+    /// Log filter of the command, unless set with RUST_LOG.
+    pub(crate) fn log_filter(&self) -> &'static str {
+        match self {
+            Self::Run(args) => args.log_filter(),
+            // NOTE: actix_server is waaaay too verbose at the info level -.-
+            _ => "info,actix_server=warn",
+        }
+    }
+
+    /// Returns true if the logs must not be colored.
+    pub(crate) fn plain(&self) -> bool {
+        matches!(self, Self::Run(args) if args.plain)
+    }
+
+    /// Format of the progress events, if reported.
+    pub(crate) fn progress(&self) -> Option<ProgressFormat> {
+        match self {
+            Self::Run(args) => args.progress,
+            Self::Install(args) => args.progress,
+            Self::Validate(args) => args.progress,
+            Self::Pull(args) => args.progress,
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Args)]
 pub(crate) struct InstallArgs {
-    /// Repository user/name, URL or ZIP archive path.
+    /// Repository user/name, URL, ZIP archive path or URL, or oci:// reference.
     #[clap(long, short = 'S', default_value = DEFAULT_REPO)]
     source: String,
     /// Destination path.
     #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
     path: Utf8PathBuf,
+    /// Branch, tag or commit of the repository to install.
+    #[clap(long = "ref", default_value = "main")]
+    git_ref: String,
+    /// Install with git clone instead of downloading the repository archive.
+    #[clap(long)]
+    git: bool,
+    /// Install in a subdirectory of the path, prefixing the function names with the namespace.
+    #[clap(long, short = 'N')]
+    namespace: Option<String>,
+    /// Report the progress on stderr in this format, for the programs wrapping this one.
+    #[clap(long, value_enum)]
+    progress: Option<ProgressFormat>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct UninstallArgs {
+    /// Path robopages were installed to.
+    #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
+    path: Utf8PathBuf,
+    /// Only remove this namespace.
+    #[clap(long, short = 'N')]
+    namespace: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct PackageArgs {
+    /// Path of the book to package.
+    #[clap(long, short = 'P', default_value = ".")]
+    path: Utf8PathBuf,
+    /// Name of the package, defaults to the name of the directory.
+    #[clap(long)]
+    name: Option<String>,
+    /// Version of the package.
+    #[clap(long)]
+    version: String,
+    /// Directory to write the archive to.
+    #[clap(long, short = 'o', default_value = ".")]
+    output: Utf8PathBuf,
+    /// Sign the archive with this minisign secret key.
+    #[clap(long)]
+    sign: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct PublishArgs {
+    /// Archive created by the package command, its .minisig signature is published as well.
+    #[clap(long, short = 'A')]
+    archive: Utf8PathBuf,
+    /// Attach the archive to a release of this GitHub repository, authenticated with GITHUB_TOKEN.
+    #[clap(long, conflicts_with = "oci", required_unless_present = "oci")]
+    github: Option<String>,
+    /// Release tag, defaults to v followed by the version of the package.
+    #[clap(long)]
+    tag: Option<String>,
+    /// Push the archive with oras to this OCI reference, for instance oci://ghcr.io/org/book:1.0.0.
+    #[clap(long)]
+    oci: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct HistoryArgs {
+    /// Only show the executions of this function.
+    #[clap(long)]
+    function: Option<String>,
+    /// Only show the executions started after this date, time in UTC or duration ago (2h, 3days).
+    #[clap(long)]
+    since: Option<String>,
+    /// Only show the executions started before this date, time in UTC or duration ago.
+    #[clap(long)]
+    until: Option<String>,
+    /// Maximum number of executions to show, the most recent ones.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+    /// Also print the output of each execution.
+    #[clap(long)]
+    output: bool,
+    /// Print the records as JSON lines.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ListSourcesArgs {
+    /// Path robopages were installed to.
+    #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
+    path: Utf8PathBuf,
 }
 
 #[derive(Debug, Args)]
@@ -65,14 +238,44 @@ pub(crate) struct CreateArgs {
     name: Utf8PathBuf,
 }
 
+#[derive(Debug, Args)]
+pub(crate) struct DiscoveryArgs {
+    /// Do not descend into symlinked directories.
+    #[clap(long)]
+    no_follow_symlinks: bool,
+    /// Maximum number of directory levels to descend below the base path.
+    #[clap(long)]
+    max_depth: Option<usize>,
+}
+
+impl From<&DiscoveryArgs> for Discovery {
+    fn from(args: &DiscoveryArgs) -> Self {
+        Self {
+            follow_symlinks: !args.no_follow_symlinks,
+            max_depth: args.max_depth,
+        }
+    }
+}
+
 #[derive(Debug, Args)]
 pub(crate) struct ViewArgs {
     /// Base path to search for robopages.
     #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
     path: Utf8PathBuf,
+    #[clap(flatten)]
+    discovery: DiscoveryArgs,
     /// Filter results by this string.
     #[clap(long, short = 'F')]
     filter: Option<String>,
+    /// Report parse times, skipped files and renamed functions while loading the pages.
+    #[clap(long)]
+    trace_loading: bool,
+    /// Report the size in bytes and approximate tokens of the tool listing of each page, for each flavor.
+    #[clap(long)]
+    sizes: bool,
+    /// Only show the functions added or modified since they were installed, and the removed ones.
+    #[clap(long)]
+    changed_since_install: bool,
 }
 
 #[derive(Debug, Args)]
@@ -80,9 +283,14 @@ pub(crate) struct ServeArgs {
     /// Base path to search for robopages.
     #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
     path: Utf8PathBuf,
+    #[clap(flatten)]
+    discovery: DiscoveryArgs,
     /// Filter results by this string.
     #[clap(long, short = 'F')]
     filter: Option<String>,
+    /// Report parse times, skipped files and renamed functions while loading the pages.
+    #[clap(long)]
+    trace_loading: bool,
     /// Address to bind to.
     #[clap(long, short = 'A', default_value = "127.0.0.1:8000")]
     address: String,
@@ -92,15 +300,109 @@ pub(crate) struct ServeArgs {
     /// Maximum number of parallel calls to execute. Leave to 0 to use all available cores.
     #[clap(long, default_value = "0")]
     workers: usize,
+    /// Maximum number of calls waiting for a worker, requests exceeding it are rejected with a 503.
+    #[clap(long, default_value = "100")]
+    queue_size: usize,
+    /// Maximum size in bytes of request bodies, larger ones are rejected with a 413, 0 to disable.
+    #[clap(long, default_value = "2097152")]
+    max_body_size: usize,
+    /// Maximum number of calls in a single request, 0 to disable.
+    #[clap(long, default_value = "256")]
+    max_calls: usize,
+    /// Maximum length in bytes of each argument value, 0 to disable.
+    #[clap(long, default_value = "65536")]
+    max_argument_length: usize,
+    /// Accept environment variables in calls, limited to the ones listed in the client_env of
+    /// each function.
+    #[clap(long)]
+    allow_client_env: bool,
     /// Optional SSH connection string, if set commands will be executed over SSH on the given host.
     #[clap(long)]
     ssh: Option<String>,
-    /// SSH key to use for authentication if --ssh is set.
-    #[clap(long, default_value = "~/.ssh/id_ed25519")]
-    ssh_key: String,
+    /// SSH key to use for authentication if --ssh is set, a .pub file to use the matching ssh-agent
+    /// identity. By default ~/.ssh/id_ed25519, id_ecdsa or id_rsa, via the ssh-agent if running.
+    #[clap(long)]
+    ssh_key: Option<String>,
     /// SSH passphrase to unlock the key.
     #[clap(long)]
     ssh_key_passphrase: Option<String>,
+    /// Authenticate with a password instead of a key, prompted for if no value is given.
+    #[clap(long, num_args = 0..=1, default_missing_value = "")]
+    ssh_password: Option<String>,
+    /// How to verify the key of the SSH server: yes (must be in the known hosts file), accept-new
+    /// (unknown hosts are added to it), no, or a pinned SHA256:... fingerprint.
+    #[clap(long, default_value = "accept-new")]
+    ssh_strict_host_key: String,
+    /// Known hosts file used to verify the key of the SSH server.
+    #[clap(long, default_value = "~/.ssh/known_hosts")]
+    ssh_known_hosts: String,
+    /// Inventory file mapping group names to lists of SSH targets, calls can be executed on the
+    /// hosts and groups listed in their hosts field.
+    #[clap(long)]
+    inventory: Option<String>,
+    /// Set one or more environment variables as KEY=VALUE pairs, used for ${env.X} and passed to the commands.
+    #[clap(long = "env", short = 'E', value_parser = parse_key_val::<String, String>, number_of_values = 1)]
+    env: Vec<(String, String)>,
+    /// Engagement scope file, calls with target parameters outside of it are rejected.
+    #[clap(long)]
+    scope: Option<String>,
+    /// Policy file with the rules allowing, denying or requiring approval for the calls.
+    #[clap(long)]
+    policy: Option<String>,
+    /// Queue every call until it's approved or denied with the /approvals endpoints or page, requires an admin token.
+    #[clap(long)]
+    interactive: bool,
+    /// Sandbox profile with the network, resources, environment and approval defaults: strict, standard, yolo or one from the configuration.
+    #[clap(long)]
+    sandbox_profile: Option<String>,
+    /// Backend executing the functions that require a container, instead of ROBOPAGES_CONTAINER_RUNTIME (docker by default).
+    #[clap(long, value_enum)]
+    container_backend: Option<ContainerBackend>,
+    /// Number of idle containers to keep ready for each container used, calls are executed in them to avoid the startup time. 0 to disable.
+    #[clap(long, default_value = "0")]
+    warm_pool: usize,
+    /// Refuse the images of the containers without a signature, which are verified with cosign.
+    #[clap(long)]
+    require_signed_images: bool,
+    /// Maximum number of images pulled or built at the same time when not --lazy.
+    #[clap(long, default_value_t = DEFAULT_PULL_CONCURRENCY)]
+    pull_concurrency: usize,
+    /// Append an entry for every execution to this JSONL file.
+    #[clap(long)]
+    audit_log: Option<String>,
+    /// Serve HTTPS with this PEM certificate chain, reloaded when it changes. Requires --tls-key.
+    #[clap(long, requires = "tls_key")]
+    tls_cert: Option<String>,
+    /// PEM private key of the --tls-cert certificate.
+    #[clap(long, requires = "tls_cert")]
+    tls_key: Option<String>,
+    /// Require this bearer token for all requests, can be repeated. Can also be set with ROBOPAGES_AUTH_TOKEN.
+    #[clap(long, number_of_values = 1)]
+    auth_token: Vec<String>,
+    /// Append where each function is executed (container image, SSH host or host) to its description.
+    #[clap(long)]
+    describe_execution: bool,
+    /// Mount all routes under this path prefix, for instance /robopages when behind a shared ingress.
+    #[clap(long, default_value = "")]
+    base_path: String,
+    /// Also serve the functions of this robopages server, forwarding their calls to it. Can be repeated.
+    #[clap(long, number_of_values = 1)]
+    remote: Vec<String>,
+    /// Token required to access the admin API, which is disabled if not set. Can also be set with ROBOPAGES_ADMIN_TOKEN.
+    #[clap(long)]
+    admin_token: Option<String>,
+    /// Interval in seconds between orphaned containers cleanups, 0 to disable.
+    #[clap(long, default_value = "300")]
+    gc_interval: u64,
+    /// Inject the failures described in this file (timeouts, exit codes, garbled output) in the calls of the selected functions, for testing.
+    #[clap(long)]
+    fault_injection: Option<String>,
+    /// Hide and refuse to execute the destructive functions and the ones matching the deny patterns of the configuration, also enabled by safe_mode.enabled in the configuration.
+    #[clap(long)]
+    safe_mode: bool,
+    /// Allow the functions blocked by the safe mode in this category and its subcategories, for instance offensive/network.
+    #[clap(long, number_of_values = 1)]
+    unsafe_allow: Vec<String>,
 }
 
 #[derive(Debug, Args)]
@@ -108,6 +410,8 @@ pub(crate) struct RunArgs {
     /// Base path to search for robopages.
     #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
     path: Utf8PathBuf,
+    #[clap(flatten)]
+    discovery: DiscoveryArgs,
     /// Function name.
     #[clap(long, short = 'F')]
     function: String,
@@ -117,15 +421,68 @@ pub(crate) struct RunArgs {
     /// Execute the function without user interaction.
     #[clap(long, short = 'A')]
     auto: bool,
+    /// Set one or more environment variables as KEY=VALUE pairs, used for ${env.X} and passed to the commands.
+    #[clap(long = "env", short = 'E', value_parser = parse_key_val::<String, String>, number_of_values = 1)]
+    env: Vec<(String, String)>,
+    /// Engagement scope file, target parameters outside of it are rejected.
+    #[clap(long)]
+    scope: Option<String>,
+    /// Policy file with the rules allowing, denying or requiring approval for the calls.
+    #[clap(long)]
+    policy: Option<String>,
+    /// Sandbox profile with the network, resources, environment and approval defaults: strict, standard, yolo or one from the configuration.
+    #[clap(long)]
+    sandbox_profile: Option<String>,
+    /// Backend executing the functions that require a container, instead of ROBOPAGES_CONTAINER_RUNTIME (docker by default).
+    #[clap(long, value_enum)]
+    container_backend: Option<ContainerBackend>,
     /// Optional SSH connection string, if set commands will be executed over SSH on the given host.
     #[clap(long)]
     ssh: Option<String>,
-    /// SSH key to use for authentication if --ssh is set.
-    #[clap(long, default_value = "~/.ssh/id_ed25519")]
-    ssh_key: String,
+    /// SSH key to use for authentication if --ssh is set, a .pub file to use the matching ssh-agent
+    /// identity. By default ~/.ssh/id_ed25519, id_ecdsa or id_rsa, via the ssh-agent if running.
+    #[clap(long)]
+    ssh_key: Option<String>,
     /// SSH passphrase to unlock the key.
     #[clap(long)]
     ssh_key_passphrase: Option<String>,
+    /// Authenticate with a password instead of a key, prompted for if no value is given.
+    #[clap(long, num_args = 0..=1, default_missing_value = "")]
+    ssh_password: Option<String>,
+    /// How to verify the key of the SSH server: yes (must be in the known hosts file), accept-new
+    /// (unknown hosts are added to it), no, or a pinned SHA256:... fingerprint.
+    #[clap(long, default_value = "accept-new")]
+    ssh_strict_host_key: String,
+    /// Known hosts file used to verify the key of the SSH server.
+    #[clap(long, default_value = "~/.ssh/known_hosts")]
+    ssh_known_hosts: String,
+    /// Inventory file mapping group names to lists of SSH targets, for --hosts.
+    #[clap(long)]
+    inventory: Option<String>,
+    /// Execute the function on each of these SSH targets or inventory groups concurrently.
+    #[clap(long, value_delimiter = ',')]
+    hosts: Vec<String>,
+    /// Show more of what happens, can be repeated up to -vvv.
+    #[clap(long, short = 'v', action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    /// Only print the output of the function, and errors.
+    #[clap(long, short = 'q')]
+    quiet: bool,
+    /// Do not use colors and decorations, for logs and terminals that don't support them.
+    #[clap(long)]
+    plain: bool,
+    /// Inject the failures described in this file (timeouts, exit codes, garbled output) in the calls of the selected functions, for testing.
+    #[clap(long)]
+    fault_injection: Option<String>,
+    /// Hide and refuse to execute the destructive functions and the ones matching the deny patterns of the configuration, also enabled by safe_mode.enabled in the configuration.
+    #[clap(long)]
+    safe_mode: bool,
+    /// Allow the functions blocked by the safe mode in this category and its subcategories, for instance offensive/network.
+    #[clap(long, number_of_values = 1)]
+    unsafe_allow: Vec<String>,
+    /// Report the progress on stderr in this format, for the programs wrapping this one.
+    #[clap(long, value_enum)]
+    progress: Option<ProgressFormat>,
 }
 
 #[derive(Debug, Args)]
@@ -133,9 +490,195 @@ pub(crate) struct ValidateArgs {
     /// Path to the robopage YML file or files to validate.
     #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
     path: Utf8PathBuf,
+    #[clap(flatten)]
+    discovery: DiscoveryArgs,
+    /// Only validate the pages whose path contains this string.
+    #[clap(long, short = 'F')]
+    filter: Option<String>,
     /// Do not attempt to pull or build containers.
     #[clap(long)]
     skip_docker: bool,
+    /// Backend executing the functions that require a container, instead of ROBOPAGES_CONTAINER_RUNTIME (docker by default).
+    #[clap(long, value_enum)]
+    container_backend: Option<ContainerBackend>,
+    /// Require the metadata expected from pages intended for public sharing (author and license).
+    #[clap(long)]
+    public: bool,
+    /// Report the functions whose container platform will run emulated on this machine.
+    #[clap(long)]
+    platform: bool,
+    /// Print a report of the descriptions and parameters that could be improved for the models, and how.
+    #[clap(long)]
+    fix_suggestions: bool,
+    /// Print how this function would be executed, without executing it.
+    #[clap(long)]
+    explain: Option<String>,
+    /// Arguments of the function to explain as key=value pairs, its first example is used otherwise.
+    #[clap(long = "define", short = 'D', value_parser = parse_key_val::<String, String>, number_of_values = 1, requires = "explain")]
+    defines: Vec<(String, String)>,
+    /// Report the progress on stderr in this format, for the programs wrapping this one.
+    #[clap(long, value_enum)]
+    progress: Option<ProgressFormat>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct BenchArgs {
+    /// Base path to search for robopages.
+    #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
+    path: Utf8PathBuf,
+    #[clap(flatten)]
+    discovery: DiscoveryArgs,
+    /// Function name.
+    #[clap(long, short = 'F')]
+    function: String,
+    /// Arguments as key=value pairs, the first example of the function is used otherwise.
+    #[clap(long = "define", short = 'D', value_parser = parse_key_val::<String, String>, number_of_values = 1)]
+    defines: Vec<(String, String)>,
+    /// Number of executions.
+    #[clap(long = "iterations", short = 'n', default_value_t = 10)]
+    iterations: usize,
+    /// Number of executions running at the same time.
+    #[clap(long, short = 'c', default_value_t = 1)]
+    concurrency: usize,
+    /// Backend executing the functions that require a container, instead of ROBOPAGES_CONTAINER_RUNTIME (docker by default).
+    #[clap(long, value_enum)]
+    container_backend: Option<ContainerBackend>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ExportArgs {
+    /// Base path to search for robopages.
+    #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
+    path: Utf8PathBuf,
+    #[clap(flatten)]
+    discovery: DiscoveryArgs,
+    /// Filter results by this string.
+    #[clap(long, short = 'F')]
+    filter: Option<String>,
+    /// Format of the export.
+    #[clap(long, value_enum)]
+    format: ExportFormat,
+    /// Write to this file instead of the standard output.
+    #[clap(long, short = 'O')]
+    output: Option<Utf8PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct BundleArgs {
+    /// Base path to search for robopages.
+    #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
+    path: Utf8PathBuf,
+    #[clap(flatten)]
+    discovery: DiscoveryArgs,
+    /// Filter results by this string.
+    #[clap(long, short = 'F')]
+    filter: Option<String>,
+    /// Bundle file to write, as JSON if its extension is .json and YAML otherwise.
+    #[clap(long, short = 'O')]
+    output: Utf8PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct CiArgs {
+    /// Base path to search for robopages.
+    #[clap(long, short = 'P', default_value = ".")]
+    path: Utf8PathBuf,
+    #[clap(flatten)]
+    discovery: DiscoveryArgs,
+    /// Only check the pages whose path contains this string.
+    #[clap(long, short = 'F')]
+    filter: Option<String>,
+    /// Path of the JUnit XML report.
+    #[clap(long, default_value = "robopages-junit.xml")]
+    junit: Utf8PathBuf,
+    /// Do not execute the tests declared by the functions.
+    #[clap(long)]
+    skip_tests: bool,
+    /// Run in a disposable container of this robopages image, with access to the docker socket.
+    #[clap(long, num_args = 0..=1, default_missing_value = "dreadnode/robopages:latest")]
+    container: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct PullArgs {
+    /// Path to the robopages.
+    #[clap(long, short = 'P', default_value = DEFAULT_PATH)]
+    path: Utf8PathBuf,
+    #[clap(flatten)]
+    discovery: DiscoveryArgs,
+    /// Only pull the containers of the pages whose path contains this string.
+    #[clap(long, short = 'F')]
+    filter: Option<String>,
+    /// Maximum number of images pulled or built at the same time.
+    #[clap(long, default_value_t = DEFAULT_PULL_CONCURRENCY)]
+    concurrency: usize,
+    /// Backend executing the functions that require a container, instead of ROBOPAGES_CONTAINER_RUNTIME (docker by default).
+    #[clap(long, value_enum)]
+    container_backend: Option<ContainerBackend>,
+    /// Report the progress on stderr in this format, for the programs wrapping this one.
+    #[clap(long, value_enum)]
+    progress: Option<ProgressFormat>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct GcArgs {
+    /// Only print what would be removed.
+    #[clap(long)]
+    dry_run: bool,
+    /// Keep the dangling images built by robopages.
+    #[clap(long)]
+    keep_images: bool,
+    /// Backend the containers were started with, instead of ROBOPAGES_CONTAINER_RUNTIME (docker by default).
+    #[clap(long, value_enum)]
+    container_backend: Option<ContainerBackend>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ContainersArgs {
+    #[clap(subcommand)]
+    command: ContainersCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum ContainersCommand {
+    /// List containers started by robopages.
+    List,
+    /// Remove containers started by robopages.
+    Kill {
+        /// Container IDs or names to remove.
+        ids: Vec<String>,
+        /// Remove only containers not tracked by a running robopages process.
+        #[clap(long)]
+        orphans: bool,
+        /// Remove all containers started by robopages.
+        #[clap(long)]
+        all: bool,
+    },
+}
+
+/// Load the book according to the configured trust policy, printing the loading diagnostics if requested.
+fn load_book(
+    path: Utf8PathBuf,
+    filter: Option<String>,
+    discovery: &DiscoveryArgs,
+    trace_loading: bool,
+) -> anyhow::Result<Book> {
+    progress::emit(progress::Event::LoadingBook {
+        path: path.as_str(),
+    });
+    let config = Config::load()?;
+    let mut trace = LoadTrace::default();
+    let result = Book::from_path_traced(path, filter, &discovery.into(), &config.trust, &mut trace);
+    if trace_loading {
+        trace.print(result.as_ref().ok());
+    }
+    if let Ok(book) = &result {
+        progress::emit(progress::Event::BookLoaded {
+            pages: book.size(),
+            functions: book.num_functions(),
+        });
+    }
+    result
 }
 
 /// Parse a single key-value pair
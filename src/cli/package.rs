@@ -0,0 +1,57 @@
+use camino::Utf8PathBuf;
+use tokio::process::Command;
+
+use crate::book::package;
+
+use super::PackageArgs;
+
+pub(crate) async fn package(args: PackageArgs) -> anyhow::Result<()> {
+    let path = Utf8PathBuf::from(
+        shellexpand::full(args.path.as_str())
+            .map_err(|e| anyhow::anyhow!("failed to expand path: {}", e))?
+            .into_owned(),
+    )
+    .canonicalize_utf8()?;
+    let name = match args.name {
+        Some(name) => name,
+        None => path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("can't get a name from {}, set --name", path))?
+            .to_string(),
+    };
+
+    std::fs::create_dir_all(&args.output)?;
+    let archive = args
+        .output
+        .canonicalize_utf8()?
+        .join(format!("{}-{}.zip", name, args.version));
+
+    let manifest = package::create(&path, &name, &args.version, &archive)?;
+    log::info!(
+        "packaged {} {} with {} functions to {}",
+        manifest.name,
+        manifest.version,
+        manifest.functions.len(),
+        archive
+    );
+
+    if let Some(key) = args.sign {
+        let key = shellexpand::full(&key)?.to_string();
+        let signature = format!("{}.minisig", archive);
+        // minisign prompts for the password of the key, if any
+        let status = Command::new("minisign")
+            .args(["-S", "-s", &key, "-m", archive.as_str(), "-x", &signature])
+            .arg("-t")
+            .arg(format!("{} {}", manifest.name, manifest.version))
+            .status()
+            .await
+            .map_err(|e| anyhow::anyhow!("could not run minisign: {}", e))?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("minisign failed with {}", status));
+        }
+
+        log::info!("signed to {}", signature);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use camino::Utf8Path;
+use reqwest::{header, StatusCode};
+use serde::Deserialize;
+
+use crate::book::{oci, package};
+
+use super::PublishArgs;
+
+const GITHUB_API: &str = "https://api.github.com";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    upload_url: String,
+    html_url: String,
+}
+
+struct GitHub {
+    client: reqwest::Client,
+    token: String,
+}
+
+impl GitHub {
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, url)
+            .bearer_auth(&self.token)
+            .header(header::ACCEPT, "application/vnd.github+json")
+            .header(header::USER_AGENT, "robopages")
+    }
+
+    async fn release(&self, repo: &str, tag: &str, title: &str) -> anyhow::Result<Release> {
+        let response = self
+            .request(
+                reqwest::Method::GET,
+                &format!("{}/repos/{}/releases/tags/{}", GITHUB_API, repo, tag),
+            )
+            .send()
+            .await?;
+
+        let response = if response.status() == StatusCode::NOT_FOUND {
+            log::info!("creating release {} of {}", tag, repo);
+            self.request(
+                reqwest::Method::POST,
+                &format!("{}/repos/{}/releases", GITHUB_API, repo),
+            )
+            .body(serde_json::json!({ "tag_name": tag, "name": title }).to_string())
+            .send()
+            .await?
+        } else {
+            response
+        };
+
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "could not get release {} of {}: {} {}",
+                tag,
+                repo,
+                status,
+                text
+            ));
+        }
+
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    async fn upload(&self, release: &Release, path: &Utf8Path) -> anyhow::Result<()> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("invalid file {}", path))?;
+        // the upload url is a template ending with {?name,label}
+        let url = release
+            .upload_url
+            .split('{')
+            .next()
+            .unwrap_or(&release.upload_url);
+
+        log::info!("uploading {} ...", name);
+        let response = self
+            .request(reqwest::Method::POST, url)
+            .query(&[("name", name)])
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(std::fs::read(path)?)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "could not upload {}: {} {}",
+                name,
+                status,
+                response.text().await?
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) async fn publish(args: PublishArgs) -> anyhow::Result<()> {
+    let manifest = package::read_manifest(&args.archive)?;
+    let signature = Utf8Path::new(&format!("{}.minisig", args.archive)).to_path_buf();
+    let signature = signature.exists().then_some(signature);
+    if signature.is_none() {
+        log::warn!("{} is not signed", args.archive);
+    }
+
+    if let Some(repo) = args.github {
+        let token = std::env::var("GITHUB_TOKEN")
+            .map_err(|_| anyhow::anyhow!("GITHUB_TOKEN must be set to publish on GitHub"))?;
+        let github = GitHub {
+            client: reqwest::Client::new(),
+            token,
+        };
+
+        let tag = args.tag.unwrap_or_else(|| format!("v{}", manifest.version));
+        let release = github
+            .release(
+                &repo,
+                &tag,
+                &format!("{} {}", manifest.name, manifest.version),
+            )
+            .await?;
+
+        github.upload(&release, &args.archive).await?;
+        if let Some(signature) = &signature {
+            github.upload(&release, signature).await?;
+        }
+
+        log::info!("published to {}", release.html_url);
+    } else if let Some(reference) = args.oci {
+        let reference = oci::Reference::parse(&reference)?;
+        // oras pushes files relative to the working directory with their name as title
+        let dir = args
+            .archive
+            .parent()
+            .filter(|dir| !dir.as_str().is_empty())
+            .unwrap_or(Utf8Path::new("."));
+        let mut files = vec![format!(
+            "{}:application/zip",
+            args.archive.file_name().unwrap_or_default()
+        )];
+        if let Some(signature) = &signature {
+            files.push(format!(
+                "{}:application/vnd.minisign.signature",
+                signature.file_name().unwrap_or_default()
+            ));
+        }
+
+        let digest = oci::push(&reference, dir.as_std_path(), &files).await?;
+
+        log::info!("published to {} ({})", reference, digest);
+    }
+
+    Ok(())
+}
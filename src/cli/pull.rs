@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use futures::StreamExt;
+
+use crate::book::{Book, Container};
+use crate::runtime::{backend, progress};
+
+use super::{load_book, PullArgs};
+
+// the functions using an image, with their container configuration
+type Using<'a> = Vec<(&'a str, &'a Container)>;
+
+// same image, pinning and signature
+fn same_configuration(container: &Container, other: &Container) -> bool {
+    serde_yaml::to_string(container).ok() == serde_yaml::to_string(other).ok()
+}
+
+/// Pull or build the images of all the containers of the book at most concurrency at a time,
+/// returns how many were resolved.
+pub(super) async fn pull_containers(book: &Book, concurrency: usize) -> anyhow::Result<usize> {
+    // each image is resolved once, along with the configurations of the functions using it
+    let mut images: BTreeMap<(&str, Option<&str>), Using> = BTreeMap::new();
+    for page in book.pages.values() {
+        for (func_name, func) in page.functions.iter() {
+            if let Some(container) = &func.container {
+                let using = images
+                    .entry((container.source.image(), container.platform.as_deref()))
+                    .or_default();
+                // only the first function of each configuration is needed
+                if !using
+                    .iter()
+                    .any(|(_, other)| same_configuration(container, other))
+                {
+                    using.push((func_name, container));
+                }
+            }
+        }
+    }
+
+    let total = images.len();
+    let mut resolved = futures::stream::iter(images)
+        .map(|((image, _), using)| async move {
+            let started = std::time::Instant::now();
+            let mut result = Ok(());
+            for (func_name, container) in using {
+                result = container.resolve().await.map_err(|e| {
+                    anyhow::anyhow!(
+                        "failed to resolve container {} of function {}: {}",
+                        image,
+                        func_name,
+                        e
+                    )
+                });
+                if result.is_err() {
+                    break;
+                }
+            }
+            (image, result, started.elapsed())
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    let mut current = 0;
+    let mut errors = 0;
+    while let Some((image, result, elapsed)) = resolved.next().await {
+        current += 1;
+        progress::emit(progress::Event::ContainerResolved {
+            image,
+            current,
+            total,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+
+        match result {
+            Ok(_) => log::info!("[{}/{}] {} ready in {:?}", current, total, image, elapsed),
+            Err(e) => {
+                log::error!("[{}/{}] {}", current, total, e);
+                errors += 1;
+            }
+        }
+    }
+
+    if errors > 0 {
+        Err(anyhow::anyhow!(
+            "{} of {} images could not be resolved",
+            errors,
+            total
+        ))
+    } else {
+        Ok(total)
+    }
+}
+
+pub(crate) async fn pull(args: PullArgs) -> anyhow::Result<()> {
+    if let Some(container_backend) = args.container_backend {
+        backend::set(container_backend);
+    }
+
+    let book = load_book(args.path, args.filter, &args.discovery, false)?;
+    let resolved = pull_containers(&book, args.concurrency).await?;
+
+    println!("{} images ready", resolved);
+
+    Ok(())
+}
@@ -1,17 +1,158 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, io::Write, sync::Arc};
+
+use anstream::{AutoStream, ColorChoice};
+use anstyle::{AnsiColor, Style};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    book::{flavors::openai, Book},
-    runtime::{self, prompt, ssh::SSHConnection},
+    book::flavors::openai,
+    config::Config,
+    runtime::{
+        self,
+        audit::Provenance,
+        host_key::HostKeyCheck,
+        inventory::Inventory,
+        prompt,
+        ssh::{SSHConnection, SSHCredentials},
+        workers::Workers,
+        Execution,
+    },
 };
 
-use super::RunArgs;
+use super::{load_book, RunArgs};
+
+// time given to the cancelled call to clean up before exiting
+const INTERRUPT_GRACE: std::time::Duration = std::time::Duration::from_secs(2);
+
+const SECTION: Style = Style::new().bold().dimmed();
+const COMMAND: Style = AnsiColor::Cyan.on_default().bold();
+const STDERR: Style = AnsiColor::Yellow.on_default();
+const SUCCESS: Style = AnsiColor::Green.on_default().bold();
+const FAILURE: Style = AnsiColor::Red.on_default().bold();
+
+impl RunArgs {
+    pub(super) fn log_filter(&self) -> &'static str {
+        if self.quiet {
+            return "error";
+        }
+        match self.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }
+    }
+}
+
+// colors are also disabled if stdout is not a terminal or NO_COLOR is set
+struct Printer {
+    out: AutoStream<std::io::Stdout>,
+    plain: bool,
+}
+
+impl Printer {
+    fn new(plain: bool) -> Self {
+        let choice = if plain {
+            ColorChoice::Never
+        } else {
+            ColorChoice::Auto
+        };
+        Self {
+            out: AutoStream::new(std::io::stdout(), choice),
+            plain,
+        }
+    }
+
+    fn styled(&mut self, style: Style, text: &str) -> std::io::Result<()> {
+        write!(
+            self.out,
+            "{}{}{}",
+            style.render(),
+            text,
+            style.render_reset()
+        )
+    }
+
+    fn section(&mut self, name: &str, text: &str, style: Style) -> std::io::Result<()> {
+        if self.plain {
+            writeln!(self.out, "{}:", name)?;
+        } else {
+            self.styled(SECTION, &format!("── {} ", name))?;
+            writeln!(self.out)?;
+        }
+        self.styled(style, text.trim_end())?;
+        writeln!(self.out)
+    }
+
+    fn execution(
+        &mut self,
+        execution: &Execution,
+        content: &str,
+        verbose: bool,
+    ) -> anyhow::Result<()> {
+        self.section("command", &execution.command, COMMAND)?;
+
+        match &execution.stdout {
+            Some(stdout) => {
+                if !stdout.is_empty() {
+                    self.section("stdout", stdout, Style::new())?;
+                }
+                if let Some(stderr) = execution.stderr.as_ref().filter(|s| !s.is_empty()) {
+                    self.section("stderr", stderr, STDERR)?;
+                }
+                // after the parser and the other transformations
+                if verbose {
+                    self.section("returned", content, Style::new())?;
+                }
+            }
+            // both streams are merged by ssh and the engine api
+            None => self.section("output", content, Style::new())?,
+        }
+
+        if verbose && !execution.provenance.is_empty() {
+            let provenance = execution
+                .provenance
+                .iter()
+                .map(|(name, source)| format!("{}: {}", name, source))
+                .collect::<Vec<_>>()
+                .join("\n");
+            self.section("provenance", &provenance, Style::new())?;
+        }
+
+        let (status, style) = match execution.exit_code {
+            Some(0) => ("exit code 0".to_string(), SUCCESS),
+            Some(code) => (format!("exit code {}", code), FAILURE),
+            None => ("killed".to_string(), FAILURE),
+        };
+        let status = format!("{} in {:.2?}", status, execution.elapsed);
+        if self.plain {
+            writeln!(self.out, "status:\n{}", status)?;
+        } else {
+            self.styled(SECTION, "── ")?;
+            self.styled(style, &status)?;
+            writeln!(self.out)?;
+        }
+
+        Ok(())
+    }
+}
 
 pub(crate) async fn run(args: RunArgs) -> anyhow::Result<()> {
+    let mut credentials = SSHCredentials {
+        key: args.ssh_key,
+        passphrase: args.ssh_key_passphrase,
+        password: args.ssh_password,
+    };
+    let host_key_check = HostKeyCheck::parse(&args.ssh_strict_host_key, &args.ssh_known_hosts)?;
+    if !args.hosts.is_empty() {
+        credentials.prompt_password()?;
+    }
+
     // parse and validate SSH connection string if provided
     let ssh = if let Some(ssh_str) = args.ssh {
         // parse
-        let conn = SSHConnection::from_str(&ssh_str, &args.ssh_key, args.ssh_key_passphrase)?;
+        let conn = SSHConnection::from_str(&ssh_str, &credentials)?
+            .with_host_key_check(host_key_check.clone());
         // make sure we can connect
         conn.test_connection().await?;
 
@@ -20,40 +161,126 @@ pub(crate) async fn run(args: RunArgs) -> anyhow::Result<()> {
         None
     };
 
-    let book = Arc::new(Book::from_path(args.path, None)?);
+    if !args.hosts.is_empty() {
+        let inventory = match &args.inventory {
+            Some(path) => Inventory::load(path, &credentials, &host_key_check)?,
+            // without an inventory the hosts are the targets
+            None => Inventory::from_targets(&args.hosts, &credentials, &host_key_check)?,
+        };
+        runtime::inventory::set(inventory);
+    }
+
+    let config = Config::load()?;
+    runtime::notifications::set(config.notifications);
+    runtime::executor::register_commands(&config.executors);
+    runtime::history::set(config.history);
+    runtime::paths::set(&config.paths)?;
+    runtime::signing::set_trusted(&config.trust.image_signers)?;
+
+    if let Some(name) = &args.sandbox_profile {
+        let profile = runtime::sandbox::SandboxProfile::named(name, &config.sandbox_profiles)?;
+        runtime::sandbox::set(name, profile);
+    }
+    if let Some(backend) = args.container_backend {
+        runtime::backend::set(backend);
+    }
+    runtime::env::set(args.env);
+
+    if let Some(scope) = &args.scope {
+        runtime::scope::set(runtime::scope::Scope::from_path(scope)?);
+    }
+
+    if let Some(policy) = &args.policy {
+        runtime::policy::set(runtime::policy::Policy::from_path(policy)?);
+    }
+
+    if let Some(faults) = &args.fault_injection {
+        runtime::faults::set(runtime::faults::Faults::from_path(faults)?);
+    }
+
+    if args.safe_mode || config.safe_mode.enabled {
+        runtime::safe_mode::set(runtime::safe_mode::SafeMode::new(
+            &config.safe_mode,
+            args.unsafe_allow,
+        )?);
+    }
+
+    if !config.auto_approve.is_empty() {
+        runtime::approval::set(runtime::approval::AutoApprove::new(&config.auto_approve)?);
+    }
+
+    let book = Arc::new(load_book(args.path, None, &args.discovery, false)?);
     let function = book.get_function(&args.function)?;
 
     let mut arguments = BTreeMap::new();
+    let mut provenance = BTreeMap::new();
 
     // convert defines to BTreeMap
     let defines: BTreeMap<String, String> = args.defines.into_iter().collect();
 
     for arg_name in function.function.parameters.keys() {
         if let Some(value) = defines.get(arg_name) {
-            arguments.insert(arg_name.to_string(), value.to_string());
+            arguments.insert(arg_name.to_string(), value.to_string().into());
+            provenance.insert(arg_name.to_string(), Provenance::Define);
         } else {
+            provenance.insert(arg_name.to_string(), Provenance::Prompt);
             arguments.insert(
                 arg_name.to_string(),
                 prompt::ask(
                     &format!(">> enter value for argument '{}': ", arg_name),
                     &[],
-                )?,
+                )?
+                .into(),
             );
         }
     }
 
     let call = openai::Call {
+        priority: None,
         id: None,
         function: openai::FunctionCall {
             name: args.function,
             arguments,
         },
         call_type: "function".to_string(),
+        caller: None,
+        env: BTreeMap::new(),
+        hosts: args.hosts,
+        provenance,
     };
 
-    let result = runtime::execute_call(ssh, !args.auto, 10, book, call).await?;
+    // commands run in their own process group, so they are killed here when interrupted
+    let cancel = CancellationToken::new();
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
+                // in case it was waiting for the user to answer a prompt
+                tokio::time::sleep(INTERRUPT_GRACE).await;
+                std::process::exit(130);
+            }
+        }
+    });
+
+    let result =
+        runtime::execute_call(ssh, !args.auto, Workers::new(10), book, call, cancel).await?;
+
+    if let Some(receipt) = &result.receipt {
+        log::info!("receipt: {}", serde_json::to_string(receipt)?);
+    }
 
-    println!("\n{}", result.content);
+    if args.quiet {
+        println!("{}", result.content);
+        return Ok(());
+    }
+
+    let mut printer = Printer::new(args.plain);
+    match &result.execution {
+        Some(execution) => printer.execution(execution, &result.content, args.verbose > 0)?,
+        // cancelled, or executed on several hosts
+        None => printer.section("output", &result.content, Style::new())?,
+    }
 
     Ok(())
 }
@@ -1,27 +1,458 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
 
 use actix_cors::Cors;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::JsonPayloadError;
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::middleware::{from_fn, Next};
 use actix_web::web;
 use actix_web::App;
+use actix_web::HttpMessage;
+use actix_web::HttpRequest;
 use actix_web::HttpResponse;
 use actix_web::HttpServer;
+use camino::Utf8PathBuf;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use utoipa::{OpenApi, ToSchema};
 
 use crate::book::flavors::rigging;
-use crate::book::flavors::Flavor;
+use crate::book::flavors::{Flavor, ListingSize};
 use crate::book::{
-    flavors::{nerve, openai},
-    Book,
+    flavors::{anthropic, gemini, nerve, openai},
+    remote::Remote,
+    revision::{Delta, Revision},
+    Book, BookDiff, PageMetadata,
 };
+use crate::config::{AuthToken, Config};
 use crate::runtime;
-use crate::runtime::ssh::SSHConnection;
+use crate::runtime::host_key::HostKeyCheck;
+use crate::runtime::inventory::Inventory;
+use crate::runtime::ssh::{SSHConnection, SSHCredentials};
 
-use super::ServeArgs;
+use super::tls;
+use super::{load_book, DiscoveryArgs, ServeArgs};
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct ServerInfo {
+    version: &'static str,
+    pages: usize,
+    functions: usize,
+    categories: Vec<String>,
+    flavors: Vec<&'static str>,
+    auth: &'static str,
+    backends: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct AuthRequirements {
+    required: bool,
+    scheme: Option<&'static str>,
+}
+
+/// Describes what this server supports, the shape of this structure is stable
+/// and new fields are only ever added.
+#[derive(Debug, Serialize, ToSchema)]
+struct Capabilities {
+    version: u32,
+    flavors: Vec<&'static str>,
+    default_flavor: &'static str,
+    streaming: bool,
+    max_output_size: Option<usize>,
+    default_timeout: Option<u64>,
+    auth: AuthRequirements,
+    limits: Limits,
+    /// Whether calls can set the environment variables allowed by their function.
+    client_env: bool,
+}
+
+/// Limits of the requests, 0 if disabled.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+struct Limits {
+    max_body_size: usize,
+    max_calls: usize,
+    max_argument_length: usize,
+}
+
+/// Reported when a request is rejected because of its size or content.
+#[derive(Debug, Serialize, ToSchema)]
+struct RequestError {
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+}
+
+fn request_error(status: StatusCode, error: String, limit: Option<usize>) -> actix_web::Error {
+    actix_web::error::InternalError::from_response(
+        error.clone(),
+        HttpResponse::build(status).json(RequestError { error, limit }),
+    )
+    .into()
+}
+
+/// Reported in place of a result when streaming and a call fails.
+#[derive(Debug, Serialize, ToSchema)]
+struct CallError {
+    call_id: Option<String>,
+    error: String,
+}
+
+// suggested delay for clients to retry when the queue is full
+const RETRY_AFTER_SECS: u64 = 5;
+
+/// Where the book is loaded from, to reload it at runtime.
+struct BookSource {
+    path: Utf8PathBuf,
+    filter: Option<String>,
+    discovery: DiscoveryArgs,
+    // if set, the descriptions report where functions are executed
+    describe_execution: Option<ExecutionTarget>,
+    remotes: Vec<Remote>,
+}
+
+/// Where the functions are executed, for --describe-execution.
+struct ExecutionTarget {
+    ssh: Option<String>,
+}
+
+impl BookSource {
+    async fn load(&self) -> anyhow::Result<Book> {
+        let book = load_book(
+            self.path.clone(),
+            self.filter.clone(),
+            &self.discovery,
+            false,
+        )?;
+        Ok(self.prepare(book).await)
+    }
+
+    async fn prepare(&self, mut book: Book) -> Book {
+        // an unreachable remote server should not prevent serving the other functions
+        for remote in &self.remotes {
+            match remote.pages().await {
+                Ok(pages) => book.merge_remote(pages),
+                Err(e) => log::error!("could not load the functions of {}: {}", remote.url, e),
+            }
+        }
+
+        // functions blocked by the safe mode are not listed
+        let hidden = runtime::safe_mode::hidden(&book);
+        if !hidden.is_empty() {
+            log::info!("safe mode hides {} functions", hidden.len());
+            book = book.without_functions(&hidden);
+        }
+
+        match &self.describe_execution {
+            Some(target) => book.with_execution_summaries(target.ssh.as_deref()),
+            None => book,
+        }
+    }
+}
+
+// revisions of the served book kept to answer ?since= listings, older ones get a full listing
+const MAX_REVISIONS: usize = 32;
+
+/// The loaded book and the one served, without the functions disabled by an operator.
+struct Books {
+    loaded: Arc<Book>,
+    disabled: BTreeSet<String>,
+    active: Arc<Book>,
+    // most recent last
+    revisions: VecDeque<Revision>,
+}
+
+impl Books {
+    fn new(book: Book) -> Self {
+        let loaded = Arc::new(book);
+        Self {
+            revisions: VecDeque::from([Revision::of(&loaded)]),
+            active: loaded.clone(),
+            loaded,
+            disabled: BTreeSet::new(),
+        }
+    }
+
+    fn update(&mut self) {
+        self.active = if self.disabled.is_empty() {
+            self.loaded.clone()
+        } else {
+            Arc::new(self.loaded.without_functions(&self.disabled))
+        };
+
+        let revision = Revision::of(&self.active);
+        if self.revision().id != revision.id {
+            if self.revisions.len() == MAX_REVISIONS {
+                self.revisions.pop_front();
+            }
+            self.revisions.push_back(revision);
+        }
+    }
+
+    fn revision(&self) -> &Revision {
+        self.revisions
+            .back()
+            .expect("the initial revision is always kept")
+    }
+}
+
+/// What the token of an authenticated request grants access to.
+#[derive(Debug, Clone)]
+struct Grant {
+    functions: Option<BTreeSet<String>>,
+    // identifies the token without keeping a copy of it, for the resources it owns
+    owner: String,
+}
 
 struct AppState {
-    max_running_tasks: usize,
-    book: Arc<Book>,
+    workers: Arc<runtime::workers::Workers>,
+    max_queued_tasks: usize,
+    books: RwLock<Books>,
+    source: BookSource,
+    // if empty, authentication is disabled
+    auth_tokens: Vec<AuthToken>,
+    // prefix of all routes, empty if served at the root
+    base_path: String,
+    admin_token: Option<String>,
     ssh: Option<SSHConnection>,
+    default_flavor: Flavor,
+    info: ServerInfo,
+    capabilities: Capabilities,
+}
+
+impl AppState {
+    fn book(&self) -> Arc<Book> {
+        self.books.read().unwrap().active.clone()
+    }
+
+    /// The served book and its revision id, consistent with each other.
+    fn book_with_revision(&self) -> (Arc<Book>, String) {
+        let books = self.books.read().unwrap();
+        (books.active.clone(), books.revision().id.clone())
+    }
+
+    /// The changes since the given revision, or None if it is unknown.
+    fn delta_since(&self, since: &str) -> Option<Delta> {
+        let books = self.books.read().unwrap();
+        let old = books
+            .revisions
+            .iter()
+            .find(|revision| revision.id == since)?;
+        Some(old.delta(books.revision()))
+    }
+
+    /// Restrict the book to the functions allowed by the token of the request, if any.
+    fn restrict_for(&self, req: &HttpRequest, book: Arc<Book>) -> Arc<Book> {
+        match Self::granted_functions(req) {
+            Some(functions) => Arc::new(book.only_functions(&functions)),
+            None => book,
+        }
+    }
+
+    fn granted_functions(req: &HttpRequest) -> Option<BTreeSet<String>> {
+        req.extensions()
+            .get::<Grant>()
+            .and_then(|grant| grant.functions.clone())
+    }
+
+    /// Who owns the resources created by the request, None if authentication is disabled.
+    fn owner(req: &HttpRequest) -> Option<String> {
+        req.extensions()
+            .get::<Grant>()
+            .map(|grant| grant.owner.clone())
+    }
+
+    /// The book served for the request, restricted to the functions allowed by its token.
+    fn book_for(&self, req: &HttpRequest) -> Arc<Book> {
+        self.restrict_for(req, self.book())
+    }
+}
+
+/// Summary of the pages, flavors and backends of the server.
+#[utoipa::path(
+    get,
+    path = "/info",
+    tag = "server",
+    responses((status = 200, body = ServerInfo))
+)]
+async fn serve_info(state: web::Data<Arc<AppState>>) -> actix_web::Result<HttpResponse> {
+    // pages and functions can change at runtime
+    let book = state.book();
+    Ok(HttpResponse::Ok().json(ServerInfo {
+        pages: book.size(),
+        functions: book.num_functions(),
+        categories: book.categories(),
+        ..state.info.clone()
+    }))
+}
+
+/// What the server supports, to be checked by the clients.
+#[utoipa::path(
+    get,
+    path = "/capabilities",
+    tag = "server",
+    responses((status = 200, body = Capabilities))
+)]
+async fn serve_capabilities(state: web::Data<Arc<AppState>>) -> actix_web::Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(&state.capabilities))
+}
+
+/// Scheduler metrics in the Prometheus text format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "server",
+    responses((status = 200, body = String, content_type = "text/plain"))
+)]
+async fn serve_metrics(state: web::Data<Arc<AppState>>) -> actix_web::Result<HttpResponse> {
+    let stats = state.workers.stats();
+    let metrics = [
+        ("robopages_active_tasks", "gauge", stats.active),
+        ("robopages_queued_tasks", "gauge", stats.queued),
+        ("robopages_rejected_tasks_total", "counter", stats.rejected),
+        ("robopages_max_running_tasks", "gauge", state.workers.size()),
+        (
+            "robopages_max_queued_tasks",
+            "gauge",
+            state.max_queued_tasks,
+        ),
+    ];
+
+    let mut body = String::new();
+    for (name, kind, value) in metrics {
+        body.push_str(&format!("# TYPE {} {}\n{} {}\n", name, kind, name, value));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
+/// Reports oversized and invalid JSON bodies as structured errors.
+fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Overflow { limit }
+        | JsonPayloadError::OverflowKnownLength { limit, .. } => request_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "request body too large".to_string(),
+            Some(limit),
+        ),
+        JsonPayloadError::Deserialize(e) => {
+            request_error(StatusCode::UNPROCESSABLE_ENTITY, e.to_string(), None)
+        }
+        err => err.into(),
+    }
+}
+
+fn check_arguments(
+    limits: &Limits,
+    arguments: &BTreeMap<String, serde_json::Value>,
+) -> actix_web::Result<()> {
+    if limits.max_argument_length == 0 {
+        return Ok(());
+    }
+
+    for (name, value) in arguments {
+        let length = match value {
+            serde_json::Value::String(value) => value.len(),
+            value => value.to_string().len(),
+        };
+        if length > limits.max_argument_length {
+            return Err(request_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("argument {} is too long ({} bytes)", name, length),
+                Some(limits.max_argument_length),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Reject batches with too many calls or arguments too long.
+fn check_limits(limits: &Limits, calls: &[openai::Call]) -> actix_web::Result<()> {
+    if limits.max_calls > 0 && calls.len() > limits.max_calls {
+        return Err(request_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("too many calls ({})", calls.len()),
+            Some(limits.max_calls),
+        ));
+    }
+
+    for call in calls {
+        check_arguments(limits, &call.function.arguments)?;
+    }
+
+    Ok(())
+}
+
+/// Reject the calls setting environment variables, unless the server allows it.
+fn check_client_env(state: &AppState, calls: &[openai::Call]) -> actix_web::Result<()> {
+    if !state.capabilities.client_env && calls.iter().any(|call| !call.env.is_empty()) {
+        return Err(request_error(
+            StatusCode::FORBIDDEN,
+            "environment variables in calls are not allowed, start the server with --allow-client-env"
+                .to_string(),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+/// Reject the request if there's no room to execute or queue the calls.
+fn check_capacity(state: &AppState, calls: usize) -> actix_web::Result<()> {
+    if state.workers.can_accept(calls, state.max_queued_tasks) {
+        return Ok(());
+    }
+
+    log::warn!("queue full, rejecting {} calls", calls);
+
+    Err(actix_web::error::InternalError::from_response(
+        "queue full",
+        HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS.to_string()))
+            .body("queue full, retry later"),
+    )
+    .into())
+}
+
+#[derive(Debug, Serialize)]
+struct PageSummary<'a> {
+    name: &'a str,
+    description: Option<&'a str>,
+    categories: &'a [String],
+    functions: Vec<&'a str>,
+    #[serde(flatten)]
+    metadata: &'a PageMetadata,
+}
+
+/// The pages with their functions, restricted to the functions allowed by the token.
+#[utoipa::path(
+    get,
+    path = "/pages",
+    tag = "tools",
+    responses((status = 200, description = "Name, description, categories, functions and metadata of each page", body = [Object]))
+)]
+async fn serve_page_list(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+) -> actix_web::Result<HttpResponse> {
+    let book = state.book_for(&req);
+    let pages: Vec<PageSummary> = book
+        .pages
+        .values()
+        .map(|page| PageSummary {
+            name: &page.name,
+            description: page.description.as_deref(),
+            categories: &page.categories,
+            functions: page.functions.keys().map(|name| name.as_str()).collect(),
+            metadata: &page.metadata,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(pages))
 }
 
 async fn not_found() -> actix_web::Result<HttpResponse> {
@@ -29,67 +460,1186 @@ async fn not_found() -> actix_web::Result<HttpResponse> {
 }
 
 async fn serve_pages_impl(
+    req: HttpRequest,
     state: web::Data<Arc<AppState>>,
     query: web::Query<HashMap<String, String>>,
     filter: Option<String>,
 ) -> actix_web::Result<HttpResponse> {
-    let flavor = Flavor::from_map_or_default(&query)
+    if query.get("groups").is_some_and(|groups| groups == "true") {
+        return Ok(serve_groups_index(
+            &state.book_for(&req),
+            filter.as_deref(),
+            &state.base_path,
+        ));
+    }
+
+    let flavor = Flavor::from_map_or(&query, state.default_flavor)
         .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
 
-    match flavor {
-        Flavor::Nerve => {
-            Ok(HttpResponse::Ok().json(state.book.as_tools::<nerve::FunctionGroup>(filter)))
+    serve_listing(&req, &state, &query, flavor, filter)
+}
+
+/// Changes of the tool listing since the revision requested with ?since=.
+#[derive(Debug, Serialize, ToSchema)]
+struct ToolsDelta {
+    revision: String,
+    /// Set if the requested revision is unknown, tools then lists all the functions.
+    full: bool,
+    /// Added or changed.
+    tools: serde_json::Value,
+    removed: Vec<String>,
+}
+
+/// Serve the tools, or only their changes since ?since=, tagged with the revision of the book.
+fn serve_listing(
+    req: &HttpRequest,
+    state: &AppState,
+    query: &HashMap<String, String>,
+    flavor: Flavor,
+    filter: Option<String>,
+) -> actix_web::Result<HttpResponse> {
+    let (book, revision) = state.book_with_revision();
+    let etag = header::ETag(header::EntityTag::new_strong(revision.clone()));
+
+    let Some(since) = query.get("since") else {
+        let tools = flavor_tools(&state.restrict_for(req, book), flavor, filter)?;
+        return Ok(HttpResponse::Ok().insert_header(etag).json(tools));
+    };
+
+    let since = since.trim_matches('"');
+    let delta = match state.delta_since(since) {
+        Some(delta) => {
+            let granted = AppState::granted_functions(req);
+            let granted = |name: &String| granted.as_ref().is_none_or(|g| g.contains(name));
+            let updated = delta.updated.into_iter().filter(granted).collect();
+            ToolsDelta {
+                revision,
+                full: false,
+                tools: flavor_tools(&book.only_functions(&updated), flavor, filter)?,
+                // removed functions have no page left to apply the filter to
+                removed: delta.removed.into_iter().filter(granted).collect(),
+            }
         }
-        Flavor::Rigging => {
-            Ok(HttpResponse::Ok().json(state.book.as_tools::<rigging::Tool>(filter)))
+        None => ToolsDelta {
+            revision,
+            full: true,
+            tools: flavor_tools(&state.restrict_for(req, book), flavor, filter)?,
+            removed: Vec::new(),
+        },
+    };
+
+    Ok(HttpResponse::Ok().insert_header(etag).json(delta))
+}
+
+/// Compact entry of the tool groups index.
+#[derive(Debug, Serialize)]
+struct ToolGroup<'a> {
+    name: &'a str,
+    functions: Vec<&'a str>,
+    url: String,
+}
+
+fn serve_groups_index(book: &Book, filter: Option<&str>, base_path: &str) -> HttpResponse {
+    let groups = book.groups(filter);
+    let index: Vec<ToolGroup> = groups
+        .iter()
+        .map(|(name, pages)| ToolGroup {
+            name,
+            functions: pages
+                .iter()
+                .flat_map(|page| page.functions.keys().map(|name| name.as_str()))
+                .collect(),
+            url: format!("{}/groups/{}", base_path, name),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(index)
+}
+
+/// The tools of a group, in the requested flavor.
+#[utoipa::path(
+    get,
+    path = "/groups/{group}",
+    tag = "tools",
+    params(
+        ("group" = String, Path, description = "Name of the group, as listed with ?groups=true"),
+        ("flavor" = Option<String>, Query, description = "Tool format, the server default if not set"),
+    ),
+    responses(
+        (status = 200, description = "The tools of the group", body = Object),
+        (status = 400, description = "Unknown flavor"),
+        (status = 404, description = "Unknown group"),
+    )
+)]
+async fn serve_group(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>,
+    actix_web_lab::extract::Path((group,)): actix_web_lab::extract::Path<(String,)>,
+) -> actix_web::Result<HttpResponse> {
+    let flavor = Flavor::from_map_or(&query, state.default_flavor)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+
+    let book = state.book_for(&req);
+    let groups = book.groups(None);
+    let pages = groups
+        .get(&group)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("group {} not found", group)))?;
+
+    let tools = flavor
+        .tools(pages.iter().copied())
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(tools))
+}
+
+fn flavor_tools(
+    book: &Book,
+    flavor: Flavor,
+    filter: Option<String>,
+) -> actix_web::Result<serde_json::Value> {
+    match flavor {
+        Flavor::Anthropic => serde_json::to_value(book.as_tools::<anthropic::Tool>(filter)),
+        Flavor::Gemini => {
+            serde_json::to_value(book.as_tools::<gemini::FunctionDeclaration>(filter))
         }
+        Flavor::Nerve => serde_json::to_value(book.as_tools::<nerve::FunctionGroup>(filter)),
+        Flavor::Rigging => serde_json::to_value(book.as_tools::<rigging::Tool>(filter)),
         // default to openai
-        _ => Ok(HttpResponse::Ok().json(state.book.as_tools::<openai::Tool>(filter))),
+        _ => serde_json::to_value(book.as_tools::<openai::Tool>(filter)),
     }
+    .map_err(actix_web::error::ErrorInternalServerError)
 }
 
+/// The tools of the pages matching the filter, in the requested flavor.
+#[utoipa::path(
+    get,
+    path = "/{filter}",
+    tag = "tools",
+    params(
+        ("filter" = String, Path, description = "Page, category or function to list"),
+        ("flavor" = Option<String>, Query, description = "Tool format, the server default if not set"),
+        ("since" = Option<String>, Query, description = "Only list the changes since this revision"),
+        ("groups" = Option<bool>, Query, description = "List the tool groups instead"),
+    ),
+    responses(
+        (status = 200, description = "The tools, or their changes if since is set", body = Object),
+        (status = 400, description = "Unknown flavor"),
+    )
+)]
 async fn serve_pages_with_filter(
+    req: HttpRequest,
     state: web::Data<Arc<AppState>>,
     query: web::Query<HashMap<String, String>>,
     actix_web_lab::extract::Path((filter,)): actix_web_lab::extract::Path<(String,)>,
 ) -> actix_web::Result<HttpResponse> {
-    serve_pages_impl(state, query, Some(filter)).await
+    serve_pages_impl(req, state, query, Some(filter)).await
 }
 
+/// The tools of all the pages, in the requested flavor.
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "tools",
+    params(
+        ("flavor" = Option<String>, Query, description = "Tool format, the server default if not set"),
+        ("since" = Option<String>, Query, description = "Only list the changes since this revision"),
+        ("groups" = Option<bool>, Query, description = "List the tool groups instead"),
+    ),
+    responses(
+        (status = 200, description = "The tools, or their changes if since is set", body = Object),
+        (status = 400, description = "Unknown flavor"),
+    )
+)]
 async fn serve_pages(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>,
+) -> actix_web::Result<HttpResponse> {
+    serve_pages_impl(req, state, query, None).await
+}
+
+/// The tools in the given flavor.
+#[utoipa::path(
+    get,
+    path = "/tools/{flavor}",
+    tag = "tools",
+    params(
+        ("flavor" = String, Path, description = "Tool format"),
+        ("filter" = Option<String>, Query, description = "Page, category or function to list"),
+        ("since" = Option<String>, Query, description = "Only list the changes since this revision"),
+    ),
+    responses(
+        (status = 200, description = "The tools, or their changes if since is set", body = Object),
+        (status = 404, description = "Unknown flavor"),
+    )
+)]
+async fn serve_flavor_tools(
+    req: HttpRequest,
     state: web::Data<Arc<AppState>>,
     query: web::Query<HashMap<String, String>>,
+    actix_web_lab::extract::Path((flavor,)): actix_web_lab::extract::Path<(String,)>,
 ) -> actix_web::Result<HttpResponse> {
-    serve_pages_impl(state, query, None).await
+    let flavor =
+        Flavor::from_string(&flavor).map_err(|e| actix_web::error::ErrorNotFound(e.to_string()))?;
+
+    serve_listing(&req, &state, &query, flavor, query.get("filter").cloned())
+}
+
+#[derive(Debug, Serialize)]
+struct FlavorListingSize {
+    flavor: &'static str,
+    #[serde(flatten)]
+    size: ListingSize,
+}
+
+/// Size of the tool listing in each flavor, to choose one fitting the context of the model.
+#[utoipa::path(
+    get,
+    path = "/tools/sizes",
+    tag = "tools",
+    params(("filter" = Option<String>, Query, description = "Page, category or function to list")),
+    responses((status = 200, description = "Size of the listing of each flavor", body = [Object]))
+)]
+async fn serve_tools_sizes(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>,
+) -> actix_web::Result<HttpResponse> {
+    let book = state.book_for(&req);
+    let filter = query.get("filter").map(|filter| filter.as_str());
+
+    let sizes = Flavor::all()
+        .into_iter()
+        .map(|flavor| {
+            Ok(FlavorListingSize {
+                flavor: flavor.name(),
+                size: flavor.listing_size(book.filtered_pages(filter))?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(sizes))
+}
+
+/// Address of the client, for the audit log.
+fn caller_of(req: &HttpRequest) -> Option<String> {
+    req.peer_addr().map(|peer| peer.ip().to_string())
+}
+
+fn wants_ndjson(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/x-ndjson"))
 }
 
+fn stream_calls(state: &AppState, book: Arc<Book>, calls: Vec<openai::Call>) -> HttpResponse {
+    let results = runtime::execute_stream(state.ssh.clone(), book, calls, state.workers.clone())
+        .map(|(call_id, result)| {
+            let mut line = match result {
+                Ok(message) => serde_json::to_vec(&message),
+                Err(e) => serde_json::to_vec(&CallError {
+                    call_id,
+                    error: e.to_string(),
+                }),
+            }?;
+            line.push(b'\n');
+            Ok::<_, serde_json::Error>(web::Bytes::from(line))
+        });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(results)
+}
+
+/// Execute the calls, in our format or as sent by the OpenAI, Anthropic and Gemini APIs.
+#[utoipa::path(
+    post,
+    path = "/process",
+    tag = "calls",
+    request_body = Vec<openai::Call>,
+    responses(
+        (status = 200, description = "The results, as JSON lines if application/x-ndjson is accepted", body = Vec<openai::CallResultMessage>),
+        (status = 400, description = "Invalid calls or failed execution"),
+        (status = 403, description = "Environment variables not allowed", body = RequestError),
+        (status = 413, description = "Request body too large", body = RequestError),
+        (status = 422, description = "Too many calls or argument too long", body = RequestError),
+        (status = 503, description = "Queue full, retry later"),
+    )
+)]
 async fn process_calls(
+    req: HttpRequest,
     state: web::Data<Arc<AppState>>,
-    calls: web::Json<Vec<openai::Call>>,
+    payload: web::Json<serde_json::Value>,
 ) -> actix_web::Result<HttpResponse> {
-    match runtime::execute(
+    let mut calls = openai::parse_calls(payload.0)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+    for call in &mut calls {
+        call.caller = caller_of(&req);
+    }
+
+    check_limits(&state.capabilities.limits, &calls)?;
+    check_client_env(&state, &calls)?;
+    check_capacity(&state, calls.len())?;
+
+    let book = state.book_for(&req);
+    if wants_ndjson(&req) {
+        return Ok(stream_calls(&state, book, calls));
+    }
+
+    match runtime::execute(state.ssh.clone(), false, book, calls, state.workers.clone()).await {
+        Ok(resp) => Ok(HttpResponse::Ok().json(resp)),
+        Err(e) => Err(actix_web::error::ErrorBadRequest(e)),
+    }
+}
+
+/// Execute a function with the arguments, returning its output as plain text.
+#[utoipa::path(
+    post,
+    path = "/functions/{name}",
+    tag = "calls",
+    params(("name" = String, Path, description = "Name of the function")),
+    request_body = Object,
+    responses(
+        (status = 200, description = "Output of the function", body = String, content_type = "text/plain"),
+        (status = 400, description = "Failed execution"),
+        (status = 404, description = "Unknown function"),
+        (status = 422, description = "Argument too long", body = RequestError),
+        (status = 503, description = "Queue full, retry later"),
+    )
+)]
+async fn process_function(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    actix_web_lab::extract::Path((name,)): actix_web_lab::extract::Path<(String,)>,
+    arguments: web::Json<BTreeMap<String, serde_json::Value>>,
+) -> actix_web::Result<HttpResponse> {
+    let book = state.book_for(&req);
+    book.get_function(&name)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    check_arguments(&state.capabilities.limits, &arguments)?;
+    check_capacity(&state, 1)?;
+
+    let call = openai::Call {
+        priority: None,
+        id: None,
+        call_type: "function".to_string(),
+        caller: caller_of(&req),
+        env: BTreeMap::new(),
+        hosts: Vec::new(),
+        provenance: BTreeMap::new(),
+        function: openai::FunctionCall {
+            name,
+            arguments: arguments.0,
+        },
+    };
+
+    // the call is dropped, and so cancelled, if the client disconnects
+    match runtime::execute_call(
         state.ssh.clone(),
         false,
-        state.book.clone(),
-        calls.0,
-        state.max_running_tasks,
+        state.workers.clone(),
+        book,
+        call,
+        CancellationToken::new(),
     )
     .await
     {
-        Ok(resp) => Ok(HttpResponse::Ok().json(resp)),
+        Ok(resp) => Ok(HttpResponse::Ok()
+            .content_type("text/plain")
+            .body(resp.content)),
         Err(e) => Err(actix_web::error::ErrorBadRequest(e)),
     }
 }
 
+/// Execute the calls in the background, to be polled with the returned id.
+#[utoipa::path(
+    post,
+    path = "/jobs",
+    tag = "jobs",
+    request_body = Vec<openai::Call>,
+    responses(
+        (status = 202, body = runtime::jobs::JobInfo),
+        (status = 400, description = "Invalid calls"),
+        (status = 404, description = "Unknown function"),
+        (status = 422, description = "Too many calls or argument too long", body = RequestError),
+        (status = 503, description = "Queue full, retry later"),
+    )
+)]
+async fn submit_job(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    payload: web::Json<serde_json::Value>,
+) -> actix_web::Result<HttpResponse> {
+    let mut calls = openai::parse_calls(payload.0)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+    for call in &mut calls {
+        call.caller = caller_of(&req);
+    }
+
+    check_limits(&state.capabilities.limits, &calls)?;
+    check_client_env(&state, &calls)?;
+
+    // fail early instead of reporting unknown functions when polling
+    let book = state.book_for(&req);
+    for call in &calls {
+        book.get_function(&call.function.name)
+            .map_err(actix_web::error::ErrorNotFound)?;
+    }
+
+    check_capacity(&state, calls.len())?;
+
+    let owner = AppState::owner(&req);
+    let id = runtime::jobs::submit(
+        state.ssh.clone(),
+        book,
+        calls,
+        state.workers.clone(),
+        owner.clone(),
+    );
+
+    Ok(HttpResponse::Accepted()
+        .insert_header((header::LOCATION, format!("{}/jobs/{}", state.base_path, id)))
+        .json(runtime::jobs::get(&id, owner.as_deref())))
+}
+
+/// Status and results of a job.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path)),
+    responses(
+        (status = 200, body = runtime::jobs::JobInfo),
+        (status = 404, description = "Unknown job, or submitted with another token"),
+    )
+)]
+async fn get_job(
+    req: HttpRequest,
+    actix_web_lab::extract::Path((id,)): actix_web_lab::extract::Path<(String,)>,
+) -> actix_web::Result<HttpResponse> {
+    // jobs of other tokens are reported as unknown
+    match runtime::jobs::get(&id, AppState::owner(&req).as_deref()) {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Err(actix_web::error::ErrorNotFound(format!(
+            "job {} not found",
+            id
+        ))),
+    }
+}
+
+/// Cancel a job, killing its processes and containers.
+#[utoipa::path(
+    delete,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path)),
+    responses(
+        (status = 200, body = runtime::jobs::JobInfo),
+        (status = 404, description = "Unknown job, or submitted with another token"),
+    )
+)]
+async fn cancel_job(
+    req: HttpRequest,
+    actix_web_lab::extract::Path((id,)): actix_web_lab::extract::Path<(String,)>,
+) -> actix_web::Result<HttpResponse> {
+    // jobs of other tokens are reported as unknown
+    match runtime::jobs::cancel(&id, AppState::owner(&req).as_deref()) {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Err(actix_web::error::ErrorNotFound(format!(
+            "job {} not found",
+            id
+        ))),
+    }
+}
+
+fn owner_of(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+// compare in constant time to not leak the token through timing
+fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(|auth| auth.strip_prefix("Bearer "))
+}
+
+/// Runs the request with its id, so that its logs can be correlated, and returns the id in the
+/// response.
+async fn correlate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> actix_web::Result<ServiceResponse<impl MessageBody>> {
+    let request_id = runtime::correlation::request_id(
+        req.headers()
+            .get(runtime::correlation::REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+
+    let mut response =
+        runtime::correlation::with_request(request_id.clone(), next.call(req)).await?;
+    if let Ok(value) = header::HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(
+            header::HeaderName::from_static(runtime::correlation::REQUEST_ID_HEADER),
+            value,
+        );
+    }
+    Ok(response)
+}
+
+/// Rejects the requests without a valid bearer token if authentication is enabled. Admin
+/// endpoints are authenticated by the admin token instead.
+async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> actix_web::Result<ServiceResponse<impl MessageBody>> {
+    if let Some(state) = req.app_data::<web::Data<Arc<AppState>>>() {
+        let path = req
+            .path()
+            .strip_prefix(&state.base_path)
+            .unwrap_or_default();
+        // admin endpoints check the admin token instead
+        if !state.auth_tokens.is_empty()
+            && !path.starts_with("/admin/")
+            && path != "/reload"
+            && !path.starts_with("/approvals")
+        {
+            let grant = bearer_token(req.request()).and_then(|provided| {
+                state
+                    .auth_tokens
+                    .iter()
+                    .find(|token| tokens_match(provided, &token.token))
+                    .map(|token| Grant {
+                        functions: token.functions.clone(),
+                        owner: owner_of(&token.token),
+                    })
+            });
+
+            match grant {
+                Some(grant) => {
+                    req.extensions_mut().insert(grant);
+                }
+                None => {
+                    return Err(actix_web::error::InternalError::from_response(
+                        "unauthorized",
+                        HttpResponse::Unauthorized()
+                            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                            .body("missing or invalid token"),
+                    )
+                    .into())
+                }
+            }
+        }
+    }
+
+    next.call(req).await
+}
+
+/// Admin endpoints are only available if an admin token is set, and require it as bearer token.
+fn check_admin(req: &HttpRequest, state: &AppState) -> actix_web::Result<()> {
+    let Some(expected) = &state.admin_token else {
+        return Err(actix_web::error::ErrorNotFound("nope"));
+    };
+
+    let provided = bearer_token(req);
+
+    if provided.is_some_and(|provided| tokens_match(provided, expected)) {
+        Ok(())
+    } else {
+        Err(actix_web::error::ErrorUnauthorized("invalid admin token"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CacheFilter {
+    function: Option<String>,
+}
+
+/// Remove the cached results, of all the functions allowed by the token of the request or of one.
+#[utoipa::path(
+    post,
+    path = "/cache/clear",
+    tag = "server",
+    params(("function" = Option<String>, Query)),
+    responses(
+        (status = 200, description = "Number of results removed", body = Object),
+        (status = 404, description = "Function not allowed by the token"),
+    )
+)]
+async fn clear_cache(
+    req: HttpRequest,
+    filter: web::Query<CacheFilter>,
+) -> actix_web::Result<HttpResponse> {
+    let removed = match (
+        AppState::granted_functions(&req),
+        filter.function.as_deref(),
+    ) {
+        (None, function) => runtime::cache::clear(function),
+        (Some(granted), Some(function)) if granted.contains(function) => {
+            runtime::cache::clear(Some(function))
+        }
+        (Some(_), Some(function)) => {
+            return Err(actix_web::error::ErrorNotFound(format!(
+                "function {} not found",
+                function
+            )))
+        }
+        // the results of the functions of other tokens are left alone
+        (Some(granted), None) => runtime::cache::clear_functions(|name| granted.contains(name)),
+    };
+    log::info!("{} cached results removed", removed);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "removed": removed })))
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    function: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    limit: Option<usize>,
+}
+
+/// The recorded executions, restricted to the functions allowed by the token of the request.
+#[utoipa::path(
+    get,
+    path = "/history",
+    tag = "server",
+    params(
+        ("function" = Option<String>, Query),
+        ("since" = Option<String>, Query, description = "Date, UTC time or duration ago such as 2h"),
+        ("until" = Option<String>, Query, description = "Date, UTC time or duration ago such as 2h"),
+        ("limit" = Option<usize>, Query, description = "Only the most recent records"),
+    ),
+    responses(
+        (status = 200, body = Vec<runtime::history::Record>),
+        (status = 400, description = "Invalid time"),
+    )
+)]
+async fn serve_history(
+    req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let query = query.into_inner();
+    let parse = |value: Option<String>| {
+        value
+            .as_deref()
+            .map(runtime::history::parse_time)
+            .transpose()
+            .map_err(actix_web::error::ErrorBadRequest)
+    };
+    let filter = runtime::history::Filter {
+        function: query.function,
+        functions: AppState::granted_functions(&req),
+        since: parse(query.since)?,
+        until: parse(query.until)?,
+        limit: query.limit,
+    };
+
+    // reading the history blocks
+    let records = web::block(move || runtime::history::executions(&filter))
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(records))
+}
+
+// a page polling the approvals, the token is only needed by its requests
+const APPROVALS_PAGE: &str = include_str!("approvals.html");
+
+/// The calls waiting for approval, or a page to decide on them if HTML is accepted.
+#[utoipa::path(
+    get,
+    path = "/approvals",
+    tag = "approvals",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, body = Vec<runtime::approval::PendingApproval>),
+        (status = 401, description = "Invalid admin token"),
+    )
+)]
+async fn list_approvals(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+) -> actix_web::Result<HttpResponse> {
+    let wants_html = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if wants_html {
+        return Ok(HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(APPROVALS_PAGE));
+    }
+
+    // the admin token is required, so that clients can't approve their own calls
+    check_admin(&req, &state)?;
+    Ok(HttpResponse::Ok().json(runtime::approval::pending()))
+}
+
+/// Approve or deny a queued call.
+#[utoipa::path(
+    post,
+    path = "/approvals/{id}/{decision}",
+    tag = "approvals",
+    security(("admin_token" = [])),
+    params(
+        ("id" = String, Path),
+        ("decision" = String, Path, description = "approve or deny"),
+    ),
+    responses(
+        (status = 204, description = "Decided"),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Unknown approval or decision"),
+    )
+)]
+async fn decide_approval(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    actix_web_lab::extract::Path((id, decision)): actix_web_lab::extract::Path<(String, String)>,
+) -> actix_web::Result<HttpResponse> {
+    check_admin(&req, &state)?;
+    let approved = match decision.as_str() {
+        "approve" => true,
+        "deny" => false,
+        _ => return Err(actix_web::error::ErrorNotFound("nope")),
+    };
+
+    if runtime::approval::decide(&id, approved) {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(actix_web::error::ErrorNotFound(format!(
+            "approval {} not found",
+            id
+        )))
+    }
+}
+
+/// The calls being executed.
+#[utoipa::path(
+    get,
+    path = "/admin/calls",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, body = Vec<runtime::ActiveCall>),
+        (status = 401, description = "Invalid admin token"),
+    )
+)]
+async fn admin_calls(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+) -> actix_web::Result<HttpResponse> {
+    check_admin(&req, &state)?;
+    Ok(HttpResponse::Ok().json(runtime::active_calls()))
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+struct Workers {
+    max_running_tasks: usize,
+}
+
+/// Change the maximum number of calls running at the same time.
+#[utoipa::path(
+    put,
+    path = "/admin/workers",
+    tag = "admin",
+    security(("admin_token" = [])),
+    request_body = Workers,
+    responses(
+        (status = 200, body = Workers),
+        (status = 400, description = "No workers"),
+        (status = 401, description = "Invalid admin token"),
+    )
+)]
+async fn admin_set_workers(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    workers: web::Json<Workers>,
+) -> actix_web::Result<HttpResponse> {
+    check_admin(&req, &state)?;
+    if workers.max_running_tasks == 0 {
+        return Err(actix_web::error::ErrorBadRequest(
+            "max_running_tasks must be greater than 0",
+        ));
+    }
+
+    log::info!("max running tasks set to {}", workers.max_running_tasks);
+    state.workers.set_size(workers.max_running_tasks);
+
+    Ok(HttpResponse::Ok().json(workers.0))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct FunctionToggle {
+    enabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DisabledFunctions<'a> {
+    disabled: &'a BTreeSet<String>,
+}
+
+/// Enable or disable a function, returning the disabled ones.
+#[utoipa::path(
+    put,
+    path = "/admin/functions/{name}",
+    tag = "admin",
+    security(("admin_token" = [])),
+    params(("name" = String, Path, description = "Name of the function")),
+    request_body = FunctionToggle,
+    responses(
+        (status = 200, body = Object),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Unknown function"),
+    )
+)]
+async fn admin_toggle_function(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    actix_web_lab::extract::Path((name,)): actix_web_lab::extract::Path<(String,)>,
+    toggle: web::Json<FunctionToggle>,
+) -> actix_web::Result<HttpResponse> {
+    check_admin(&req, &state)?;
+
+    let mut books = state.books.write().unwrap();
+    books
+        .loaded
+        .get_function(&name)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    if toggle.enabled {
+        log::info!("enabling function {}", name);
+        books.disabled.remove(&name);
+    } else {
+        log::info!("disabling function {}", name);
+        books.disabled.insert(name);
+    }
+    books.update();
+
+    Ok(HttpResponse::Ok().json(DisabledFunctions {
+        disabled: &books.disabled,
+    }))
+}
+
+/// Flush the caches and remove the warm containers.
+#[utoipa::path(
+    post,
+    path = "/admin/cache/flush",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Number of containers removed", body = Object),
+        (status = 401, description = "Invalid admin token"),
+    )
+)]
+async fn admin_flush_caches(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+) -> actix_web::Result<HttpResponse> {
+    check_admin(&req, &state)?;
+
+    let removed = runtime::flush_caches();
+    log::info!("caches flushed, {} warm containers removed", removed);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "removed_containers": removed })))
+}
+
+/// The book after a reload, and the functions that changed.
+#[derive(Debug, Serialize, ToSchema)]
+struct Reloaded {
+    pages: usize,
+    functions: usize,
+    #[serde(flatten)]
+    diff: BookDiff,
+}
+
+/// Reload the pages, keeping the current ones if loading fails.
+#[utoipa::path(
+    post,
+    path = "/admin/reload",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, body = Reloaded),
+        (status = 401, description = "Invalid admin token"),
+        (status = 500, description = "Loading failed"),
+    )
+)]
+async fn admin_reload(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+) -> actix_web::Result<HttpResponse> {
+    check_admin(&req, &state)?;
+
+    // the current book is kept if the new one fails to load
+    let book = state
+        .source
+        .load()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut books = state.books.write().unwrap();
+    let diff = books.loaded.diff(&book);
+    books.loaded = Arc::new(book);
+    books.update();
+
+    log::info!(
+        "book reloaded, {} pages, {} functions: {} added, {} removed, {} changed",
+        books.active.size(),
+        books.active.num_functions(),
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len()
+    );
+
+    Ok(HttpResponse::Ok().json(Reloaded {
+        pages: books.active.size(),
+        functions: books.active.num_functions(),
+        diff,
+    }))
+}
+
+/// Same as /admin/reload, for deployments where the pages are not watched.
+#[utoipa::path(
+    post,
+    path = "/reload",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, body = Reloaded),
+        (status = 401, description = "Invalid admin token"),
+        (status = 500, description = "Loading failed"),
+    )
+)]
+async fn reload(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+) -> actix_web::Result<HttpResponse> {
+    admin_reload(req, state).await
+}
+
+/// The routes of the API, recorded to be checked against its specification.
+struct Routes {
+    scope: actix_web::Scope,
+    paths: Vec<(Method, &'static str)>,
+}
+
+impl Routes {
+    fn new(base_path: &str) -> Self {
+        Self {
+            scope: web::scope(base_path),
+            paths: Vec::new(),
+        }
+    }
+
+    fn route<F, Args>(mut self, method: Method, path: &'static str, handler: F) -> Self
+    where
+        F: actix_web::Handler<Args>,
+        Args: actix_web::FromRequest + 'static,
+        F::Output: actix_web::Responder + 'static,
+    {
+        self.scope = self
+            .scope
+            .route(path, web::method(method.clone()).to(handler));
+        self.paths.push((method, path));
+        self
+    }
+}
+
+fn routes(base_path: &str) -> Routes {
+    Routes::new(base_path)
+        .route(Method::POST, "/process", process_calls)
+        .route(Method::POST, "/functions/{name}", process_function)
+        .route(Method::POST, "/jobs", submit_job)
+        .route(Method::GET, "/jobs/{id}", get_job)
+        .route(Method::DELETE, "/jobs/{id}", cancel_job)
+        .route(Method::GET, "/info", serve_info)
+        .route(Method::GET, "/capabilities", serve_capabilities)
+        .route(Method::GET, "/metrics", serve_metrics)
+        .route(Method::GET, "/pages", serve_page_list)
+        .route(Method::GET, "/tools/sizes", serve_tools_sizes)
+        .route(Method::GET, "/groups/{group:.*}", serve_group)
+        .route(Method::GET, "/tools/{flavor}", serve_flavor_tools)
+        .route(Method::POST, "/cache/clear", clear_cache)
+        .route(Method::GET, "/history", serve_history)
+        .route(Method::GET, "/approvals", list_approvals)
+        .route(Method::POST, "/approvals/{id}/{decision}", decide_approval)
+        .route(Method::GET, "/admin/calls", admin_calls)
+        .route(Method::PUT, "/admin/workers", admin_set_workers)
+        .route(
+            Method::PUT,
+            "/admin/functions/{name}",
+            admin_toggle_function,
+        )
+        .route(Method::POST, "/admin/cache/flush", admin_flush_caches)
+        .route(Method::POST, "/admin/reload", admin_reload)
+        .route(Method::POST, "/reload", reload)
+        .route(Method::GET, "/openapi.json", serve_openapi)
+        // TODO: is this is the best way to do this? can't find a clean way to have an optional path parameter
+        .route(Method::GET, "/{filter}", serve_pages_with_filter)
+        .route(Method::GET, "", serve_pages)
+        .route(Method::GET, "/", serve_pages)
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "robopages"),
+    paths(
+        serve_pages,
+        serve_pages_with_filter,
+        serve_page_list,
+        serve_tools_sizes,
+        serve_group,
+        serve_flavor_tools,
+        process_calls,
+        process_function,
+        submit_job,
+        get_job,
+        cancel_job,
+        serve_info,
+        serve_capabilities,
+        serve_metrics,
+        clear_cache,
+        serve_history,
+        list_approvals,
+        decide_approval,
+        serve_openapi,
+        admin_calls,
+        admin_set_workers,
+        admin_toggle_function,
+        admin_flush_caches,
+        admin_reload,
+        reload,
+    ),
+    components(schemas(CallError)),
+    modifiers(&SecuritySchemes)
+)]
+struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl utoipa::Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for name in ["token", "admin_token"] {
+            components.add_security_scheme(
+                name,
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+/// The OpenAPI specification of the endpoints available on this server.
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "server",
+    responses((status = 200, description = "This specification", body = Object))
+)]
+async fn serve_openapi(state: web::Data<Arc<AppState>>) -> actix_web::Result<HttpResponse> {
+    let mut doc = ApiDoc::openapi();
+    if !state.base_path.is_empty() {
+        doc.servers = Some(vec![utoipa::openapi::Server::new(&state.base_path)]);
+    }
+    if state.capabilities.auth.required {
+        doc.security = Some(vec![utoipa::openapi::security::SecurityRequirement::new(
+            "token",
+            Vec::<String>::new(),
+        )]);
+    }
+    if state.admin_token.is_none() {
+        doc.paths.paths.retain(|_, item| !is_admin(item));
+    }
+
+    Ok(HttpResponse::Ok().json(doc))
+}
+
+// the admin endpoints are tagged as such in the specification
+fn is_admin(item: &utoipa::openapi::PathItem) -> bool {
+    [&item.get, &item.post, &item.put, &item.delete]
+        .into_iter()
+        .flatten()
+        .all(|operation| {
+            operation
+                .tags
+                .as_ref()
+                .is_some_and(|tags| tags.iter().any(|tag| tag == "admin"))
+        })
+}
+
+/// Returns the base path with a leading slash and without a trailing one, empty for the root.
+fn normalize_base_path(base_path: &str) -> String {
+    let base_path = base_path.trim_matches('/');
+    if base_path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", base_path)
+    }
+}
+
 pub(crate) async fn serve(args: ServeArgs) -> anyhow::Result<()> {
-    if !args.address.contains("127.0.0.1:") && !args.address.contains("localhost:") {
-        log::warn!("external address specified, this is an unsafe configuration as no authentication is provided");
+    let config = Config::load()?;
+
+    // before looking for orphaned containers, which depends on it
+    if let Some(backend) = args.container_backend {
+        runtime::backend::set(backend);
+    }
+
+    // tokens from the command line and the environment are not restricted
+    let mut auth_tokens: Vec<AuthToken> = args
+        .auth_token
+        .into_iter()
+        .chain(std::env::var("ROBOPAGES_AUTH_TOKEN").ok())
+        .filter(|token| !token.is_empty())
+        .map(|token| AuthToken {
+            token,
+            functions: None,
+        })
+        .collect();
+    auth_tokens.extend(config.serve.auth_tokens.clone());
+
+    if auth_tokens.is_empty()
+        && !args.address.contains("127.0.0.1:")
+        && !args.address.contains("localhost:")
+    {
+        log::warn!("external address specified, this is an unsafe configuration as no authentication is provided, set --auth-token to require one");
+    }
+
+    let mut credentials = SSHCredentials {
+        key: args.ssh_key,
+        passphrase: args.ssh_key_passphrase,
+        password: args.ssh_password,
+    };
+    let host_key_check = HostKeyCheck::parse(&args.ssh_strict_host_key, &args.ssh_known_hosts)?;
+    if args.inventory.is_some() {
+        credentials.prompt_password()?;
     }
 
     // parse and validate SSH connection string if provided
     let ssh = if let Some(ssh_str) = args.ssh {
         // parse
-        let conn = SSHConnection::from_str(&ssh_str, &args.ssh_key, args.ssh_key_passphrase)?;
+        let conn = SSHConnection::from_str(&ssh_str, &credentials)?
+            .with_host_key_check(host_key_check.clone());
         // make sure we can connect
         conn.test_connection().await?;
 
@@ -98,17 +1648,123 @@ pub(crate) async fn serve(args: ServeArgs) -> anyhow::Result<()> {
         None
     };
 
-    let book = Arc::new(Book::from_path(args.path, args.filter)?);
-    if !args.lazy {
-        for page in book.pages.values() {
-            for (func_name, func) in page.functions.iter() {
-                if let Some(container) = &func.container {
-                    log::info!("pre building container for function {} ...", func_name);
-                    container.resolve().await?;
+    if let Some(path) = &args.inventory {
+        runtime::inventory::set(Inventory::load(path, &credentials, &host_key_check)?);
+    }
+
+    // remove containers left behind by previous instances
+    match runtime::remove_orphans().await {
+        Ok(0) => {}
+        Ok(removed) => log::info!("removed {} orphaned containers", removed),
+        Err(e) => log::warn!("could not check for orphaned containers: {}", e),
+    }
+
+    if args.gc_interval > 0 {
+        let interval = std::time::Duration::from_secs(args.gc_interval);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = runtime::remove_orphans().await {
+                    log::debug!("orphaned containers cleanup failed: {}", e);
                 }
             }
+        });
+    }
+
+    if let Some(audit_log) = &args.audit_log {
+        runtime::audit::set(audit_log)?;
+    }
+
+    if let Some(name) = &args.sandbox_profile {
+        let profile = runtime::sandbox::SandboxProfile::named(name, &config.sandbox_profiles)?;
+        runtime::sandbox::set(name, profile);
+    }
+
+    runtime::pool::set_size(args.warm_pool);
+    runtime::signing::set_required(args.require_signed_images);
+    runtime::env::set(args.env);
+
+    if let Some(scope) = &args.scope {
+        runtime::scope::set(runtime::scope::Scope::from_path(scope)?);
+    }
+
+    if let Some(policy) = &args.policy {
+        runtime::policy::set(runtime::policy::Policy::from_path(policy)?);
+    }
+
+    if let Some(faults) = &args.fault_injection {
+        runtime::faults::set(runtime::faults::Faults::from_path(faults)?);
+    }
+
+    if args.safe_mode || config.safe_mode.enabled {
+        runtime::safe_mode::set(runtime::safe_mode::SafeMode::new(
+            &config.safe_mode,
+            args.unsafe_allow,
+        )?);
+    }
+
+    runtime::notifications::set(config.notifications.clone());
+    runtime::executor::register_commands(&config.executors);
+    runtime::history::set(config.history.clone());
+    runtime::paths::set(&config.paths)?;
+    runtime::signing::set_trusted(&config.trust.image_signers)?;
+    let admin_token = args
+        .admin_token
+        .or_else(|| std::env::var("ROBOPAGES_ADMIN_TOKEN").ok())
+        .filter(|token| !token.is_empty());
+    if admin_token.is_some() {
+        log::info!("admin api enabled");
+    }
+
+    if args.interactive {
+        if admin_token.is_none() {
+            return Err(anyhow!(
+                "an admin token is required to approve the calls with --interactive"
+            ));
+        }
+        runtime::approval::enable_queue();
+        if !config.auto_approve.is_empty() {
+            runtime::approval::set(runtime::approval::AutoApprove::new(&config.auto_approve)?);
         }
     }
+    config.serve.hooks.validate()?;
+    runtime::hooks::set(config.serve.hooks.clone());
+    let default_flavor = match &config.serve.flavor {
+        Some(flavor) => Flavor::from_string(flavor)?,
+        None => Flavor::default(),
+    };
+
+    let source = BookSource {
+        path: args.path,
+        filter: args.filter,
+        discovery: args.discovery,
+        describe_execution: args.describe_execution.then(|| ExecutionTarget {
+            ssh: ssh.as_ref().map(|ssh| ssh.to_string()),
+        }),
+        remotes: args
+            .remote
+            .into_iter()
+            .map(|url| Remote {
+                url,
+                token: None,
+                namespace: None,
+            })
+            .chain(config.serve.remotes.clone())
+            .collect(),
+    };
+
+    let book = source
+        .prepare(load_book(
+            source.path.clone(),
+            source.filter.clone(),
+            &source.discovery,
+            args.trace_loading,
+        )?)
+        .await;
+    if !args.lazy {
+        log::info!("pre building containers ...");
+        super::pull::pull_containers(&book, args.pull_concurrency).await?;
+    }
 
     let max_running_tasks = if args.workers == 0 {
         std::thread::available_parallelism()?.into()
@@ -116,34 +1772,238 @@ pub(crate) async fn serve(args: ServeArgs) -> anyhow::Result<()> {
         args.workers
     };
 
+    let info = ServerInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        pages: book.size(),
+        functions: book.num_functions(),
+        categories: book.categories(),
+        flavors: Flavor::all().iter().map(|f| f.name()).collect(),
+        auth: if auth_tokens.is_empty() {
+            "none"
+        } else {
+            "bearer"
+        },
+        backends: runtime::available_backends(ssh.as_ref()).await,
+    };
+
+    log::info!("robopages v{}", info.version);
+    log::info!("  {} pages, {} functions", info.pages, info.functions);
+    log::info!("  categories: {}", info.categories.join(", "));
+    log::info!("  flavors: {}", info.flavors.join(", "));
+    log::info!("  auth: {}", info.auth);
+    log::info!("  backends: {}", info.backends.join(", "));
+
     log::info!(
-        "serving {} pages on http://{} with {max_running_tasks} max running tasks",
+        "serving {} pages on {}://{} with {max_running_tasks} max running tasks and {} max queued",
         book.size(),
+        if args.tls_cert.is_some() {
+            "https"
+        } else {
+            "http"
+        },
         &args.address,
+        args.queue_size,
     );
 
+    let capabilities = Capabilities {
+        version: 1,
+        flavors: info.flavors.clone(),
+        default_flavor: default_flavor.name(),
+        streaming: true,
+        max_output_size: None,
+        default_timeout: None,
+        auth: AuthRequirements {
+            required: !auth_tokens.is_empty(),
+            scheme: (!auth_tokens.is_empty()).then_some("bearer"),
+        },
+        limits: Limits {
+            max_body_size: args.max_body_size,
+            max_calls: args.max_calls,
+            max_argument_length: args.max_argument_length,
+        },
+        client_env: args.allow_client_env,
+    };
+
     let app_state = Arc::new(AppState {
-        max_running_tasks,
-        book,
+        workers: runtime::workers::Workers::new(max_running_tasks),
+        max_queued_tasks: args.queue_size,
+        books: RwLock::new(Books::new(book)),
+        source,
+        auth_tokens,
+        base_path: normalize_base_path(&args.base_path),
+        admin_token,
         ssh,
+        default_flavor,
+        info,
+        capabilities,
     });
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
+        let base_path = app_state.base_path.clone();
         let cors = Cors::default().max_age(3600);
 
         App::new()
+            .wrap(from_fn(authenticate))
             .wrap(cors)
             .app_data(web::Data::new(app_state.clone()))
-            .route("/process", web::post().to(process_calls))
-            // TODO: is this is the best way to do this? can't find a clean way to have an optional path parameter
-            .service(web::resource("/{filter}").route(web::get().to(serve_pages_with_filter)))
-            .service(web::resource("/").route(web::get().to(serve_pages)))
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(match app_state.capabilities.limits.max_body_size {
+                        0 => usize::MAX,
+                        limit => limit,
+                    })
+                    .error_handler(json_error),
+            )
+            // all routes are mounted under the base path, if set
+            .service(routes(&base_path).scope)
             .default_service(web::route().to(not_found))
             .wrap(actix_web::middleware::Logger::default())
-    })
-    .bind(&args.address)
-    .map_err(|e| anyhow!(e))?
-    .run()
-    .await
-    .map_err(|e| anyhow!(e))
+            .wrap(from_fn(correlate))
+    });
+
+    let server = match (args.tls_cert, args.tls_key) {
+        (Some(cert), Some(key)) => {
+            let config = tls::server_config(
+                shellexpand::full(&cert)?.to_string().into(),
+                shellexpand::full(&key)?.to_string().into(),
+            )?;
+            server
+                .bind_rustls_0_23(&args.address, config)
+                .map_err(|e| anyhow!(e))?
+        }
+        _ => server.bind(&args.address).map_err(|e| anyhow!(e))?,
+    };
+
+    server.run().await.map_err(|e| anyhow!(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_are_documented() {
+        let doc = ApiDoc::openapi();
+        let documented: BTreeSet<(String, String)> = doc
+            .paths
+            .paths
+            .iter()
+            .flat_map(|(path, item)| {
+                [
+                    (Method::GET, &item.get),
+                    (Method::POST, &item.post),
+                    (Method::PUT, &item.put),
+                    (Method::DELETE, &item.delete),
+                ]
+                .into_iter()
+                .filter(|(_, operation)| operation.is_some())
+                .map(move |(method, _)| (method.to_string(), path.clone()))
+            })
+            .collect();
+
+        let registered: BTreeSet<(String, String)> = routes("")
+            .paths
+            .iter()
+            .map(|(method, path)| {
+                // the specification has no empty path nor patterns of the parameters
+                let path = if path.is_empty() { "/" } else { path };
+                let path = regex::Regex::new(r"\{(\w+):[^}]*\}")
+                    .unwrap()
+                    .replace_all(path, "{$1}");
+                (method.to_string(), path.to_string())
+            })
+            .collect();
+
+        assert_eq!(registered, documented);
+    }
+
+    #[actix_web::test]
+    async fn test_reload_with_auth_and_admin_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("echo.yml"),
+            "description: echo\nfunctions:\n  say:\n    description: say something\n    parameters: {}\n    cmdline: [echo, hello]\n",
+        )
+        .unwrap();
+        let source = BookSource {
+            path: Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            filter: None,
+            discovery: DiscoveryArgs {
+                no_follow_symlinks: false,
+                max_depth: None,
+            },
+            describe_execution: None,
+            remotes: Vec::new(),
+        };
+        let book = source.load().await.unwrap();
+        let state = Arc::new(AppState {
+            workers: runtime::workers::Workers::new(1),
+            max_queued_tasks: 1,
+            info: ServerInfo {
+                version: env!("CARGO_PKG_VERSION"),
+                pages: book.size(),
+                functions: book.num_functions(),
+                categories: book.categories(),
+                flavors: Vec::new(),
+                auth: "bearer",
+                backends: Vec::new(),
+            },
+            books: RwLock::new(Books::new(book)),
+            source,
+            auth_tokens: vec![AuthToken {
+                token: "client".to_string(),
+                functions: None,
+            }],
+            base_path: String::new(),
+            admin_token: Some("admin".to_string()),
+            ssh: None,
+            default_flavor: Flavor::default(),
+            capabilities: Capabilities {
+                version: 1,
+                flavors: Vec::new(),
+                default_flavor: Flavor::default().name(),
+                streaming: true,
+                max_output_size: None,
+                default_timeout: None,
+                auth: AuthRequirements {
+                    required: true,
+                    scheme: Some("bearer"),
+                },
+                limits: Limits {
+                    max_body_size: 0,
+                    max_calls: 0,
+                    max_argument_length: 0,
+                },
+                client_env: false,
+            },
+        });
+        let app = actix_web::test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate))
+                .app_data(web::Data::new(state))
+                .service(routes("").scope),
+        )
+        .await;
+
+        let status = |path: &'static str, token: Option<&'static str>| {
+            let mut req = actix_web::test::TestRequest::post().uri(path);
+            if let Some(token) = token {
+                req = req.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)));
+            }
+            let req = req.to_request();
+            let app = &app;
+            async move {
+                match actix_web::test::try_call_service(app, req).await {
+                    Ok(res) => res.status(),
+                    Err(e) => e.as_response_error().status_code(),
+                }
+            }
+        };
+
+        for path in ["/reload", "/admin/reload"] {
+            assert_eq!(status(path, Some("admin")).await, StatusCode::OK);
+            assert_eq!(status(path, Some("client")).await, StatusCode::UNAUTHORIZED);
+            assert_eq!(status(path, None).await, StatusCode::UNAUTHORIZED);
+        }
+    }
 }
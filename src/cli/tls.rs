@@ -0,0 +1,103 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+
+// how often the certificate and key files are checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+fn load_certified_key(cert_path: &PathBuf, key_path: &PathBuf) -> anyhow::Result<CertifiedKey> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("could not read {}: {}", cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no certificates found in {}",
+            cert_path.display()
+        ));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| anyhow::anyhow!("could not read {}: {}", key_path.display(), e))?;
+    let key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .map_err(|e| anyhow::anyhow!("unsupported key {}: {}", key_path.display(), e))?;
+
+    Ok(CertifiedKey::new(certs, key))
+}
+
+fn last_modified(paths: &[&PathBuf]) -> Option<SystemTime> {
+    paths
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+/// Serves the certificate loaded from disk, reloaded when its files change.
+#[derive(Debug)]
+struct ReloadingCert {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadingCert {
+    fn new(cert_path: PathBuf, key_path: PathBuf) -> anyhow::Result<Self> {
+        let current = RwLock::new(Arc::new(load_certified_key(&cert_path, &key_path)?));
+        Ok(Self {
+            cert_path,
+            key_path,
+            current,
+        })
+    }
+
+    fn watch(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut modified = last_modified(&[&self.cert_path, &self.key_path]);
+            loop {
+                tokio::time::sleep(RELOAD_INTERVAL).await;
+
+                let now = last_modified(&[&self.cert_path, &self.key_path]);
+                if now == modified {
+                    continue;
+                }
+                modified = now;
+
+                // keep serving the current certificate if the new one is invalid or incomplete
+                match load_certified_key(&self.cert_path, &self.key_path) {
+                    Ok(key) => {
+                        log::info!("reloaded tls certificate {}", self.cert_path.display());
+                        *self.current.write().unwrap() = Arc::new(key);
+                    }
+                    Err(e) => log::error!("could not reload tls certificate: {}", e),
+                }
+            }
+        });
+    }
+}
+
+impl ResolvesServerCert for ReloadingCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+/// Configuration of the HTTPS server, serving the certificate reloaded when its files change.
+pub(crate) fn server_config(cert_path: PathBuf, key_path: PathBuf) -> anyhow::Result<ServerConfig> {
+    let certs = Arc::new(ReloadingCert::new(cert_path, key_path)?);
+    certs.clone().watch();
+
+    Ok(
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(certs),
+    )
+}
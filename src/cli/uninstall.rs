@@ -0,0 +1,49 @@
+use camino::Utf8PathBuf;
+
+use crate::book::sources::{Source, Sources};
+
+use super::UninstallArgs;
+
+pub(crate) async fn uninstall(args: UninstallArgs) -> anyhow::Result<()> {
+    let path = Utf8PathBuf::from(
+        shellexpand::full(args.path.as_str())
+            .map_err(|e| anyhow::anyhow!("failed to expand path: {}", e))?
+            .into_owned(),
+    );
+
+    match args.namespace {
+        Some(namespace) => {
+            let mut sources = Sources::load(&path)?;
+            if sources.namespaces.remove(&namespace).is_none() {
+                return Err(anyhow::anyhow!(
+                    "namespace {} is not installed in {:?}",
+                    namespace,
+                    path
+                ));
+            }
+
+            let namespace_path = path.join(&namespace);
+            if namespace_path.exists() {
+                std::fs::remove_dir_all(&namespace_path)?;
+            }
+            sources.save(&path)?;
+
+            log::info!("removed namespace {} from {:?}", namespace, path);
+        }
+        None => {
+            // only remove what was installed, never an arbitrary directory
+            if Source::load(&path)?.is_none() && Sources::load(&path)?.namespaces.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "{:?} was not installed by robopages, remove it manually",
+                    path
+                ));
+            }
+
+            std::fs::remove_dir_all(&path)?;
+
+            log::info!("removed {:?}", path);
+        }
+    }
+
+    Ok(())
+}
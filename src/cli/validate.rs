@@ -1,148 +1,493 @@
-use crate::{book::Book, runtime::CommandLine};
+use std::collections::BTreeMap;
 
-use super::ValidateArgs;
+use camino::Utf8PathBuf;
 
-pub(crate) async fn validate(args: ValidateArgs) -> anyhow::Result<()> {
-    let book = Book::from_path(args.path.clone(), None)?;
+use crate::book::{lint, runtime::ExecutionContext, Book, Function, Page};
+use crate::runtime::{backend, host_arch, is_emulated_platform, progress, CommandLine, Volume};
 
-    // we need at least one page
-    if book.pages.is_empty() {
-        return Err(anyhow::anyhow!("no pages found in {:?}", &args.path));
-    }
+use super::{load_book, ValidateArgs};
 
-    for (page_path, page) in book.pages {
-        log::info!("validating {:?} ...", page_path);
+pub(super) fn validate_page(
+    public: bool,
+    page_path: &Utf8PathBuf,
+    page: &Page,
+) -> anyhow::Result<()> {
+    // and at least one function per page, at least what's the point of the page?
+    if page.functions.is_empty() {
+        return Err(anyhow::anyhow!("no functions found in {:?}", page_path));
+    } else if page.name.is_empty() {
+        // set by Book::from_path if not specified
+        return Err(anyhow::anyhow!("page name is empty in {:?}", page_path));
+    } else if page.categories.is_empty() {
+        // set by Book::from_path if not specified
+        return Err(anyhow::anyhow!(
+            "page categories are empty in {:?}",
+            page_path
+        ));
+    }
 
-        // and at least one function per page, at least what's the point of the page?
-        if page.functions.is_empty() {
-            return Err(anyhow::anyhow!("no functions found in {:?}", page_path));
-        } else if page.name.is_empty() {
-            // set by Book::from_path if not specified
-            return Err(anyhow::anyhow!("page name is empty in {:?}", page_path));
-        } else if page.categories.is_empty() {
-            // set by Book::from_path if not specified
+    // pages meant to be shared must say who wrote them and under which terms
+    if public {
+        if page.metadata.author.is_none() {
+            return Err(anyhow::anyhow!("page author is not set in {:?}", page_path));
+        } else if page.metadata.license.is_none() {
             return Err(anyhow::anyhow!(
-                "page categories are empty in {:?}",
+                "page license is not set in {:?}",
                 page_path
             ));
+        } else if page.metadata.homepage.is_none() && page.metadata.source_url.is_none() {
+            log::warn!("neither homepage nor source_url are set in {:?}", page_path);
         }
+    }
 
-        for (func_name, func) in page.functions {
-            // the model needs at least a name and a description
-            if func_name.is_empty() {
-                return Err(anyhow::anyhow!("function name is empty in {:?}", page_path));
-            } else if func.description.is_empty() {
-                return Err(anyhow::anyhow!(
-                    "function description is empty in {:?}",
-                    page_path
-                ));
-            }
+    Ok(())
+}
 
-            if func.parameters.is_empty() {
-                return Err(anyhow::anyhow!(
-                    "function {} parameters are empty in {:?}",
-                    func_name,
-                    page_path
-                ));
-            }
+pub(super) async fn validate_function(
+    skip_docker: bool,
+    page_path: &Utf8PathBuf,
+    func_name: &str,
+    func: &Function,
+) -> anyhow::Result<()> {
+    // the model needs at least a name and a description
+    if func_name.is_empty() {
+        return Err(anyhow::anyhow!("function name is empty in {:?}", page_path));
+    } else if func.description.is_empty() {
+        return Err(anyhow::anyhow!(
+            "function description is empty in {:?}",
+            page_path
+        ));
+    }
+
+    if let Some(parser) = &func.parser {
+        parser.validate().map_err(|e| {
+            anyhow::anyhow!(
+                "invalid parser for function {} in {:?}: {}",
+                func_name,
+                page_path,
+                e
+            )
+        })?;
+    }
+
+    match func.max_concurrency {
+        Some(0) => {
+            return Err(anyhow::anyhow!(
+                "max_concurrency of function {} in {:?} must be greater than 0",
+                func_name,
+                page_path
+            ))
+        }
+        Some(max) if func.exclusive && max > 1 => {
+            return Err(anyhow::anyhow!(
+                "function {} in {:?} is exclusive but allows {} concurrent calls",
+                func_name,
+                page_path,
+                max
+            ))
+        }
+        _ => {}
+    }
+
+    if func.parameters.is_empty() {
+        return Err(anyhow::anyhow!(
+            "function {} parameters are empty in {:?}",
+            func_name,
+            page_path
+        ));
+    }
+
+    for (param_name, param) in &func.parameters {
+        param.check_definition().map_err(|e| {
+            anyhow::anyhow!(
+                "invalid parameter {} of function {} in {:?}: {}",
+                param_name,
+                func_name,
+                page_path,
+                e
+            )
+        })?;
+    }
+
+    // the steps of pipelines are validated as functions of their own
+    if let ExecutionContext::Pipeline(_) = &func.execution {
+        return Ok(());
+    }
+
+    // make sure the function resolves to a valid command line
+    let cmdline = func.execution.get_command_line().map_err(|e| {
+        anyhow::anyhow!(
+            "error while getting command line for function {}: {}",
+            func_name,
+            e
+        )
+    })?;
 
-            // make sure the function resolves to a valid command line
-            let cmdline = func.execution.get_command_line().map_err(|e| {
+    if cmdline.is_empty() {
+        return Err(anyhow::anyhow!(
+            "command line is empty for function {} in {:?}",
+            func_name,
+            page_path
+        ));
+    }
+
+    let cmdline = CommandLine::from_vec(&cmdline).map_err(|e| {
+        anyhow::anyhow!(
+            "error while parsing command line for function {}: {}",
+            func_name,
+            e
+        )
+    })?;
+
+    // validate container requirements - a container is required if:
+    let container = if !cmdline.app_in_path {
+        // the binary is not in $PATH
+        if let Some(container) = &func.container {
+            Some(container)
+        } else {
+            return Err(anyhow::anyhow!(
+                "binary for function {} in {:?} not in $PATH and container not specified",
+                func_name,
+                page_path
+            ));
+        }
+    } else if func.container.is_some() && func.container.as_ref().unwrap().force {
+        // it's set and forced
+        Some(func.container.as_ref().unwrap())
+    } else {
+        None
+    };
+
+    // validate the container if any
+    if let Some(container) = container {
+        // even without resolving it, make sure the backend can execute it
+        backend::check(container).map_err(|e| {
+            anyhow::anyhow!(
+                "function {} in {} can't be executed: {}",
+                func_name,
+                page_path,
+                e
+            )
+        })?;
+
+        if let Some(signature) = &container.signature {
+            signature.check().map_err(|e| {
                 anyhow::anyhow!(
-                    "error while getting command line for function {}: {}",
+                    "function {} in {} has an invalid signature: {}",
                     func_name,
+                    page_path,
                     e
                 )
             })?;
+        }
 
-            if cmdline.is_empty() {
-                return Err(anyhow::anyhow!(
-                    "command line is empty for function {} in {:?}",
+        container.check_pinning().map_err(|e| {
+            anyhow::anyhow!(
+                "function {} in {} can't be verified: {}",
+                func_name,
+                page_path,
+                e
+            )
+        })?;
+
+        if container.persistent {
+            container.idle_timeout().map_err(|e| {
+                anyhow::anyhow!(
+                    "function {} in {} has an invalid persistent container: {}",
                     func_name,
-                    page_path
-                ));
-            }
+                    page_path,
+                    e
+                )
+            })?;
+        }
 
-            let cmdline = CommandLine::from_vec(&cmdline).map_err(|e| {
+        if skip_docker {
+            // or not :P
+            log::warn!("skipping container resolution for function {}", func_name);
+        } else {
+            // this will pull or build the image
+            container.resolve().await.map_err(|e| {
                 anyhow::anyhow!(
-                    "error while parsing command line for function {}: {}",
+                    "error while resolving container for function {} in {}: {}",
                     func_name,
+                    page_path,
                     e
                 )
             })?;
 
-            // validate container requirements - a container is required if:
-            let container = if !cmdline.app_in_path {
-                // the binary is not in $PATH
-                if let Some(container) = &func.container {
-                    Some(container)
-                } else {
-                    return Err(anyhow::anyhow!(
-                        "binary for function {} in {:?} not in $PATH and container not specified",
-                        func_name,
-                        page_path
-                    ));
-                }
-            } else if func.container.is_some() && func.container.as_ref().unwrap().force {
-                // it's set and forced
-                Some(func.container.as_ref().unwrap())
-            } else {
-                None
-            };
+            // if volumes are defined make sure they exist
+            if let Some(volumes) = &container.volumes {
+                for volume in volumes {
+                    let volume = Volume::parse(volume);
+                    let (on_host, on_guest) = (volume.host, volume.guest_or_host());
 
-            // validate the container if any
-            if let Some(container) = container {
-                if args.skip_docker {
-                    // or not :P
-                    log::warn!("skipping container resolution for function {}", func_name);
-                } else {
-                    // this will pull or build the image
-                    container.resolve().await.map_err(|e| {
-                        anyhow::anyhow!(
-                            "error while resolving container for function {} in {}: {}",
-                            func_name,
+                    let on_host = shellexpand::full(on_host)
+                        .map_err(|e| {
+                            anyhow::anyhow!(
+                                "error while expanding volume path for function {}: {}",
+                                func_name,
+                                e
+                            )
+                        })?
+                        .to_string();
+
+                    if !std::path::Path::new(&on_host).exists() {
+                        return Err(anyhow::anyhow!(
+                            "page {}, function {}, path {} for volume '{}' does not exist",
                             page_path,
-                            e
-                        )
-                    })?;
-
-                    // if volumes are defined make sure they exist
-                    if let Some(volumes) = &container.volumes {
-                        for volume in volumes {
-                            let (on_host, on_guest) =
-                                volume.split_once(':').unwrap_or((volume, volume));
-
-                            let on_host = shellexpand::full(on_host)
-                                .map_err(|e| {
-                                    anyhow::anyhow!(
-                                        "error while expanding volume path for function {}: {}",
-                                        func_name,
-                                        e
-                                    )
-                                })?
-                                .to_string();
-
-                            if !std::path::Path::new(&on_host).exists() {
-                                return Err(anyhow::anyhow!(
-                                    "page {}, function {}, path {} for volume '{}' does not exist",
-                                    page_path,
-                                    func_name,
-                                    on_host,
-                                    on_guest
-                                ));
-                            }
-                        }
+                            func_name,
+                            on_host,
+                            on_guest
+                        ));
                     }
                 }
             }
+        }
+    }
+
+    log::info!("  {} - ok", func_name);
+    log::debug!("    cmdline = {:?}", cmdline);
+    if let Some(container) = container {
+        log::debug!("    container = {:?}", container);
+    }
+
+    Ok(())
+}
 
-            log::info!("  {} - ok", func_name);
-            log::debug!("    cmdline = {:?}", cmdline);
-            if let Some(container) = container {
-                log::debug!("    container = {:?}", container);
+// sample arguments: the defined ones, then the first example, then placeholders for the required
+// ones so that the optional ones get their default
+fn sample_arguments(
+    function: &Function,
+    defines: &[(String, String)],
+) -> BTreeMap<String, serde_json::Value> {
+    let mut arguments: BTreeMap<String, serde_json::Value> = function
+        .examples
+        .iter()
+        .flatten()
+        .next()
+        .cloned()
+        .unwrap_or_default();
+
+    for (name, param) in &function.parameters {
+        if param.required && !arguments.contains_key(name) {
+            // placeholders would not be valid choices
+            let sample = match param.constraints.choices.first() {
+                Some(choice) => choice.clone(),
+                None => format!("<{}>", name).into(),
+            };
+            arguments.insert(name.clone(), sample);
+        }
+    }
+    for (name, value) in defines {
+        arguments.insert(name.clone(), value.clone().into());
+    }
+    arguments
+}
+
+/// Print how the function would be executed with sample arguments, without executing it.
+fn explain(book: &Book, name: &str, defines: &[(String, String)]) -> anyhow::Result<()> {
+    let function = book.get_function(name)?;
+    let arguments = sample_arguments(function.function, defines);
+
+    println!("function: {} ({})", name, function.path);
+    println!("arguments: {}", serde_json::to_string(&arguments)?);
+
+    let arguments = function.coerce_arguments(&arguments)?;
+    function.validate_arguments(&arguments)?;
+
+    if let ExecutionContext::Pipeline(steps) = &function.function.execution {
+        book.check_pipeline(name)?;
+        let mut outputs = Vec::new();
+        for (index, step) in steps.iter().enumerate() {
+            let step_arguments = step.resolve_arguments(&arguments, &outputs, &BTreeMap::new())?;
+            println!(
+                "step {}: {} {}",
+                index,
+                &step.function,
+                serde_json::to_string(&step_arguments)?
+            );
+            outputs.push(format!("<output of step {}>", index));
+        }
+        return Ok(());
+    }
+
+    let command_line = function.resolve_command_line(&arguments, &BTreeMap::new())?;
+    println!("command line: {}", &command_line);
+
+    if !command_line.env.is_empty() {
+        println!("env:");
+        for (key, value) in &command_line.env {
+            println!("  {}={}", key, value);
+        }
+    }
+
+    let files = function.resolve_files(&arguments)?;
+    for transfer in &files.upload {
+        println!("upload: {} -> {}", transfer.local, transfer.remote);
+    }
+    for transfer in &files.download {
+        println!("download: {} -> {}", transfer.remote, transfer.local);
+    }
+
+    // the same decision as when executing, ssh hosts take precedence if the app is in their $PATH
+    let container = function.function.container.as_ref();
+    let container = if !command_line.app_in_path {
+        match container {
+            Some(container) => {
+                println!(
+                    "execution: container, {} is not in $PATH",
+                    &command_line.app
+                );
+                container
+            }
+            None => {
+                return Err(anyhow::anyhow!(
+                    "{} is not in $PATH and no container is set",
+                    &command_line.app
+                ))
             }
         }
+    } else if let Some(container) = container.filter(|container| container.force) {
+        println!("execution: container, forced");
+        container
+    } else if command_line.sudo {
+        match container {
+            Some(container) => {
+                println!("execution: sudo on this host when interactive, container otherwise");
+                container
+            }
+            None => {
+                println!("execution: sudo on this host, fails when not interactive");
+                return Ok(());
+            }
+        }
+    } else {
+        println!("execution: this host");
+        return Ok(());
+    };
+
+    println!("image: {}", container.source.image());
+    if let Some(volumes) = &container.volumes {
+        println!("volumes:");
+        for volume in volumes {
+            let volume = Volume::parse(volume);
+            println!("  {} -> {}", volume.host, volume.guest_or_host());
+        }
+    }
+
+    match container.wrap(name, command_line) {
+        Ok(wrapped) => println!("container command line: {}", wrapped),
+        Err(e) => println!("container command line: {}", e),
     }
 
     Ok(())
 }
+
+pub(crate) async fn validate(args: ValidateArgs) -> anyhow::Result<()> {
+    if let Some(container_backend) = args.container_backend {
+        backend::set(container_backend);
+    }
+
+    let book = load_book(
+        args.path.clone(),
+        args.filter.clone(),
+        &args.discovery,
+        false,
+    )?;
+
+    // we need at least one page
+    if book.pages.is_empty() {
+        return Err(anyhow::anyhow!("no pages found in {:?}", &args.path));
+    }
+
+    if let Some(function) = &args.explain {
+        // the local paths of the downloads are checked against the allowed roots
+        crate::runtime::paths::set(&crate::config::Config::load()?.paths)?;
+        return explain(&book, function, &args.defines);
+    }
+
+    // keep going to report all the errors at once
+    let mut errors = Vec::new();
+    let mut num_functions = 0;
+    let mut emulated = Vec::new();
+    let total = book.num_functions();
+
+    for (page_path, page) in &book.pages {
+        log::info!("validating {:?} ...", page_path);
+
+        if let Err(e) = validate_page(args.public, page_path, page) {
+            log::error!("{}", e);
+            errors.push(e);
+        }
+
+        for (func_name, func) in &page.functions {
+            num_functions += 1;
+            progress::emit(progress::Event::Validating {
+                function: func_name,
+                current: num_functions,
+                total,
+            });
+
+            if let Some(platform) = func
+                .container
+                .as_ref()
+                .and_then(|container| container.platform.as_deref())
+                .filter(|platform| is_emulated_platform(platform))
+            {
+                emulated.push(format!("{} ({})", func_name, platform));
+            }
+
+            if let Err(e) = validate_function(args.skip_docker, page_path, func_name, func).await {
+                log::error!("  {} - {}", func_name, e);
+                errors.push(e);
+            } else if let Err(e) = book.check_pipeline(func_name) {
+                log::error!("  {} - {}", func_name, e);
+                errors.push(e);
+            }
+        }
+    }
+
+    // model-friendliness issues are reported as warnings
+    let findings = lint::lint(&book);
+    for finding in &findings {
+        log::warn!("{}", finding);
+    }
+
+    if args.fix_suggestions && !findings.is_empty() {
+        println!("suggestions:");
+        for finding in &findings {
+            println!("  - {}\n      {}", finding, finding.suggestion);
+        }
+    }
+
+    if args.platform {
+        println!(
+            "{} functions will run emulated on this {} machine:",
+            emulated.len(),
+            host_arch()
+        );
+        for function in &emulated {
+            println!("  - {}", function);
+        }
+    }
+
+    println!(
+        "validated {} pages and {} functions: {} errors, {} warnings",
+        book.size(),
+        num_functions,
+        errors.len(),
+        findings.len()
+    );
+    for error in &errors {
+        println!("  - {}", error);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "validation failed with {} errors",
+            errors.len()
+        ))
+    }
+}
@@ -1,15 +1,116 @@
-use crate::book::{runtime::ExecutionFlavor, Book};
+use std::collections::BTreeSet;
 
-use super::ViewArgs;
+use camino::Utf8PathBuf;
+
+use crate::book::{
+    flavors::Flavor,
+    revision::Delta,
+    runtime::ExecutionFlavor,
+    sources::{self, Source, Sources},
+    Book,
+};
+
+use super::{load_book, ViewArgs};
+
+fn print_sizes(book: &Book) -> anyhow::Result<()> {
+    let flavors = Flavor::all();
+
+    print!("{:<40}", "page");
+    for flavor in &flavors {
+        print!(" {:>20}", flavor.name());
+    }
+    println!();
+
+    let rows = book
+        .pages
+        .values()
+        .map(|page| (page.name.as_str(), vec![page]))
+        .chain(std::iter::once(("total", book.pages.values().collect())));
+
+    for (name, pages) in rows {
+        print!("{:<40}", name);
+        for flavor in &flavors {
+            let size = flavor.listing_size(pages.iter().copied())?;
+            print!(" {:>20}", format!("{} B / ~{} tk", size.bytes, size.tokens));
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+// changes of the sources installed in the path, with the function names as they are loaded
+fn changes_since_install(path: &Utf8PathBuf) -> anyhow::Result<Delta> {
+    let root = Utf8PathBuf::from(shellexpand::full(path.as_str())?.into_owned());
+    let mut changes = Delta::default();
+    let mut found = false;
+
+    if let Some(source) = Source::load(&root)? {
+        found = true;
+        changes = source.changes(&root)?;
+    }
+
+    for (namespace, source) in Sources::load(&root)?.namespaces {
+        found = true;
+        let delta = source.changes(&root.join(&namespace))?;
+        changes.updated.extend(
+            delta
+                .updated
+                .iter()
+                .map(|name| sources::namespaced(&namespace, name)),
+        );
+        changes.removed.extend(
+            delta
+                .removed
+                .iter()
+                .map(|name| sources::namespaced(&namespace, name)),
+        );
+    }
+
+    if !found {
+        return Err(anyhow::anyhow!("no installed sources found in {}", root));
+    }
+    Ok(changes)
+}
 
 pub(crate) async fn view(args: ViewArgs) -> anyhow::Result<()> {
-    let book = Book::from_path(args.path, args.filter)?;
+    let changes = if args.changed_since_install {
+        Some(changes_since_install(&args.path)?)
+    } else {
+        None
+    };
+
+    let book = load_book(args.path, args.filter, &args.discovery, args.trace_loading)?;
+
+    if args.sizes {
+        return print_sizes(&book);
+    }
+
+    let changed: Option<&BTreeSet<String>> = changes.as_ref().map(|changes| &changes.updated);
 
     for (_, page) in book.pages {
+        let mut functions = page.functions;
+        if let Some(changed) = changed {
+            functions.retain(|name, _| changed.contains(name));
+            if functions.is_empty() {
+                continue;
+            }
+        }
+
         println!("{} > [{}]", page.categories.join(" > "), page.name);
+        for (name, value) in page.metadata.fields() {
+            println!("  {} : {}", name, value);
+        }
 
-        for (function_name, function) in page.functions {
-            println!("    * {} : {}", function_name, function.description);
+        for (function_name, function) in functions {
+            if changed.is_some() {
+                println!(
+                    "    * {} (changed since install) : {}",
+                    function_name, function.description
+                );
+            } else {
+                println!("    * {} : {}", function_name, function.description);
+            }
             println!(
                 "         running with: {}",
                 ExecutionFlavor::for_function(&function)?
@@ -19,9 +120,23 @@ pub(crate) async fn view(args: ViewArgs) -> anyhow::Result<()> {
                 println!("            {} : {}", parameter_name, parameter.description);
             }
 
+            let examples = function.examples_json();
+            if !examples.is_empty() {
+                println!("         examples:");
+                for example in examples {
+                    println!("            {}", example);
+                }
+            }
+
             println!();
         }
     }
 
+    if let Some(changes) = changes {
+        for name in &changes.removed {
+            println!("    * {} (removed since install)", name);
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,140 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::book::remote::Remote;
+use crate::book::trust::TrustPolicy;
+use crate::runtime::approval::AutoApproveConfig;
+use crate::runtime::executor::CommandExecutor;
+use crate::runtime::history::HistoryConfig;
+use crate::runtime::hooks::HooksConfig;
+use crate::runtime::notifications::NotificationsConfig;
+use crate::runtime::paths::PathsConfig;
+use crate::runtime::safe_mode::SafeModeConfig;
+use crate::runtime::sandbox::SandboxProfile;
+
+const DEFAULT_CONFIG_PATH: &str = "~/.config/robopages/config.yml";
+// settings used to be kept with the pages, where an installed book could override them
+const LEGACY_CONFIG_PATH: &str = "~/.robopages/.config.yml";
+
+/// Optional settings loaded from ~/.config/robopages/config.yml (or the path set in ROBOPAGES_CONFIG).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    pub serve: ServeConfig,
+    pub trust: TrustPolicy,
+    pub notifications: NotificationsConfig,
+    /// Custom sandbox profiles, overriding the built in ones with the same name.
+    pub sandbox_profiles: BTreeMap<String, SandboxProfile>,
+    pub safe_mode: SafeModeConfig,
+    /// Calls run without confirmation in interactive mode.
+    pub auto_approve: AutoApproveConfig,
+    /// Executors running the command lines through a command, selected by the functions by name.
+    pub executors: BTreeMap<String, CommandExecutor>,
+    /// Recording of the executions, read with the history command.
+    pub history: HistoryConfig,
+    /// Host paths that can be passed as arguments and mounted in containers.
+    pub paths: PathsConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ServeConfig {
+    /// Flavor used when a client does not specify one.
+    pub flavor: Option<String>,
+    /// Bearer tokens accepted by the API, authentication is required if any is set.
+    pub auth_tokens: Vec<AuthToken>,
+    /// Other robopages servers whose functions are served by this one.
+    pub remotes: Vec<Remote>,
+    /// Commands run before and after each call, that can change or reject it.
+    pub hooks: HooksConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct AuthToken {
+    pub token: String,
+    /// If set, only these functions are listed and can be called with this token.
+    #[serde(default)]
+    pub functions: Option<BTreeSet<String>>,
+}
+
+impl Config {
+    fn path() -> anyhow::Result<PathBuf> {
+        let path = std::env::var("ROBOPAGES_CONFIG").unwrap_or(DEFAULT_CONFIG_PATH.to_string());
+        Ok(PathBuf::from(shellexpand::full(&path)?.to_string()))
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        let path = Self::path()?;
+        let legacy = PathBuf::from(shellexpand::full(LEGACY_CONFIG_PATH)?.to_string());
+        if legacy.exists() {
+            log::warn!(
+                "{} is ignored, move the settings to {}",
+                legacy.display(),
+                path.display()
+            );
+        }
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        log::debug!("loading configuration from {}", path.display());
+
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("error while reading {}: {}", path.display(), e))?;
+
+        Self::from_str(&data)
+            .map_err(|e| anyhow::anyhow!("error while parsing {}: {}", path.display(), e))
+    }
+
+    fn from_str(data: &str) -> anyhow::Result<Self> {
+        // an empty file is a valid, empty configuration
+        if data.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(serde_yaml::from_str(data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_from_str() {
+        let config = Config::from_str("serve:\n  flavor: nerve\n").unwrap();
+        assert_eq!(config.serve.flavor, Some("nerve".to_string()));
+        assert!(!config.trust.require_signed);
+
+        let config =
+            Config::from_str("trust:\n  require_signed: true\n  trusted_keys: [\"RWQ...\"]\n")
+                .unwrap();
+        assert!(config.trust.require_signed);
+        assert_eq!(config.trust.trusted_keys.len(), 1);
+
+        let config = Config::from_str(
+            "serve:\n  auth_tokens:\n    - token: abc\n    - token: def\n      functions: [nmap_scan]\n",
+        )
+        .unwrap();
+        assert_eq!(config.serve.auth_tokens.len(), 2);
+        assert!(config.serve.auth_tokens[0].functions.is_none());
+        assert!(config.serve.auth_tokens[1]
+            .functions
+            .as_ref()
+            .is_some_and(|functions| functions.contains("nmap_scan")));
+
+        let config = Config::from_str(
+            "serve:\n  remotes:\n    - url: http://team:8000\n      namespace: team\n",
+        )
+        .unwrap();
+        assert_eq!(config.serve.remotes[0].url, "http://team:8000");
+        assert_eq!(config.serve.remotes[0].namespace.as_deref(), Some("team"));
+
+        let config = Config::from_str("").unwrap();
+        assert_eq!(config.serve.flavor, None);
+
+        assert!(Config::from_str("serve: [").is_err());
+    }
+}
@@ -3,6 +3,7 @@ extern crate anyhow;
 
 mod book;
 mod cli;
+mod config;
 mod runtime;
 
 use clap::Parser;
@@ -14,24 +15,50 @@ async fn main() -> anyhow::Result<()> {
 
     if std::env::var_os("RUST_LOG").is_none() {
         // set `RUST_LOG=debug` to see debug logs
-        // NOTE: actix_server is waaaay too verbose at the info level -.-
-        std::env::set_var("RUST_LOG", "info,actix_server=warn");
+        std::env::set_var("RUST_LOG", args.command.log_filter());
     }
 
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .format_module_path(false)
-        .format_target(false)
-        .init();
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    logger.format_module_path(false).format_target(false);
+    if args.command.plain() {
+        logger.write_style(env_logger::WriteStyle::Never);
+    }
+    if args.log_format == cli::LogFormat::Json {
+        logger.format(cli::format_json);
+    }
+    logger.init();
+
+    if let Some(format) = args.command.progress() {
+        runtime::progress::set(format);
+    }
 
     let result = match args.command {
         cli::Command::Install(args) => cli::install(args).await,
+        cli::Command::Uninstall(args) => cli::uninstall(args).await,
+        cli::Command::ListSources(args) => cli::list_sources(args).await,
+        cli::Command::Package(args) => cli::package(args).await,
+        cli::Command::Publish(args) => cli::publish(args).await,
         cli::Command::Create(args) => cli::create(args).await,
         cli::Command::View(args) => cli::view(args).await,
-        cli::Command::Serve(args) => cli::serve(args).await,
-        cli::Command::Run(args) => cli::run(args).await,
+        cli::Command::Serve(args) => cli::serve(*args).await,
+        cli::Command::Run(args) => cli::run(*args).await,
         cli::Command::Validate(args) => cli::validate(args).await,
+        cli::Command::Containers(args) => cli::containers(args).await,
+        cli::Command::Gc(args) => cli::gc(args).await,
+        cli::Command::Pull(args) => cli::pull(args).await,
+        cli::Command::Ci(args) => cli::ci(args).await,
+        cli::Command::Bench(args) => cli::bench(args).await,
+        cli::Command::Export(args) => cli::export(args).await,
+        cli::Command::Bundle(args) => cli::bundle(args).await,
+        cli::Command::History(args) => cli::history(args).await,
     };
 
+    runtime::progress::emit(runtime::progress::Event::Completed {
+        success: result.is_ok(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    });
+
     if let Err(e) = result {
         log::error!("{:?}", e);
         std::process::exit(1);
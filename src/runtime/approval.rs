@@ -0,0 +1,320 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use utoipa::ToSchema;
+
+use crate::book::runtime::FunctionRef;
+
+use super::{prompt, safe_mode::glob_regex, session, CommandLine};
+
+// calls still waiting for a decision after this long are denied
+const APPROVAL_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+static AUTO_APPROVE: OnceLock<AutoApprove> = OnceLock::new();
+static QUEUE: OnceLock<Queue> = OnceLock::new();
+static NEXT_APPROVAL_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Calls executed without asking for a confirmation in interactive mode.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct AutoApproveConfig {
+    /// Names of the functions, a * matches any characters.
+    pub functions: Vec<String>,
+    /// Command lines starting with the name or the path of the binary, a * matches any characters.
+    pub commands: Vec<String>,
+}
+
+impl AutoApproveConfig {
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.commands.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct AutoApprove {
+    functions: Vec<(String, regex::Regex)>,
+    commands: Vec<(String, regex::Regex)>,
+}
+
+fn compile(patterns: &[String]) -> anyhow::Result<Vec<(String, regex::Regex)>> {
+    patterns
+        .iter()
+        .map(|pattern| Ok((pattern.clone(), glob_regex(pattern)?)))
+        .collect()
+}
+
+impl AutoApprove {
+    pub fn new(config: &AutoApproveConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            functions: compile(&config.functions)?,
+            commands: compile(&config.commands)?,
+        })
+    }
+
+    /// Returns the pattern approving the call, if any.
+    fn approving(&self, function: &str, command_line: &CommandLine) -> Option<&str> {
+        if let Some((pattern, _)) = self
+            .functions
+            .iter()
+            .find(|(_, regex)| regex.is_match(function))
+        {
+            return Some(pattern);
+        }
+
+        // the binary is usually resolved to its full path
+        let name = std::path::Path::new(&command_line.app)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let args = command_line.redact(&command_line.args.join(" "));
+        let commands = [
+            format!("{} {}", &command_line.app, &args),
+            format!("{} {}", name, &args),
+        ];
+        self.commands
+            .iter()
+            .find(|(_, regex)| {
+                commands
+                    .iter()
+                    .any(|command| regex.is_match(command.trim_end()))
+            })
+            .map(|(pattern, _)| pattern.as_str())
+    }
+}
+
+/// Skip the confirmation of the calls matching the configuration in interactive mode.
+pub(crate) fn set(auto_approve: AutoApprove) {
+    log::info!(
+        "auto-approving {} function and {} command patterns",
+        auto_approve.functions.len(),
+        auto_approve.commands.len()
+    );
+    if AUTO_APPROVE.set(auto_approve).is_err() {
+        log::warn!("auto-approval already set");
+    }
+}
+
+/// Returns true if the call can be executed without asking, destructive functions and commands
+/// run with sudo are always confirmed.
+pub(crate) fn is_approved(function: &FunctionRef, command_line: &CommandLine) -> bool {
+    let Some(auto_approve) = AUTO_APPROVE.get() else {
+        return false;
+    };
+    if function.function.destructive || command_line.sudo {
+        return false;
+    }
+
+    match auto_approve.approving(&function.name, command_line) {
+        Some(pattern) => {
+            log::info!("{} auto-approved by {}", &function.name, pattern);
+            true
+        }
+        None => false,
+    }
+}
+
+/// A call waiting in the queue for a user to approve or deny it.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct PendingApproval {
+    pub id: String,
+    pub function: String,
+    pub command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caller: Option<String>,
+    pub waiting_secs: f64,
+}
+
+struct Pending {
+    function: String,
+    command: String,
+    caller: Option<String>,
+    queued: Instant,
+    decision: oneshot::Sender<bool>,
+}
+
+/// Calls waiting for a user to decide from the API.
+#[derive(Default)]
+pub(crate) struct Queue {
+    pending: Mutex<BTreeMap<String, Pending>>,
+}
+
+// the call leaves the queue once decided, timed out or cancelled
+struct Dequeue<'a>(&'a Queue, &'a str);
+
+impl Drop for Dequeue<'_> {
+    fn drop(&mut self) {
+        self.0.pending.lock().unwrap().remove(self.1);
+    }
+}
+
+impl Queue {
+    fn pending(&self) -> Vec<PendingApproval> {
+        let mut pending: Vec<_> = self
+            .pending
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, pending)| PendingApproval {
+                id: id.clone(),
+                function: pending.function.clone(),
+                command: pending.command.clone(),
+                caller: pending.caller.clone(),
+                waiting_secs: pending.queued.elapsed().as_secs_f64(),
+            })
+            .collect();
+        pending.sort_by(|a, b| b.waiting_secs.total_cmp(&a.waiting_secs));
+        pending
+    }
+
+    fn decide(&self, id: &str, approved: bool) -> bool {
+        let Some(pending) = self.pending.lock().unwrap().remove(id) else {
+            return false;
+        };
+        log::warn!(
+            "call of {} {} ({})",
+            &pending.function,
+            if approved { "approved" } else { "denied" },
+            id
+        );
+        // the call might have been cancelled meanwhile
+        let _ = pending.decision.send(approved);
+        true
+    }
+
+    async fn confirm(&self, function: &str, command: &str, caller: Option<&str>) -> bool {
+        let id = format!(
+            "{}-{}",
+            session(),
+            NEXT_APPROVAL_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let (decision, decided) = oneshot::channel();
+        self.pending.lock().unwrap().insert(
+            id.clone(),
+            Pending {
+                function: function.to_string(),
+                command: command.to_string(),
+                caller: caller.map(str::to_string),
+                queued: Instant::now(),
+                decision,
+            },
+        );
+        let _dequeue = Dequeue(self, &id);
+        log::warn!("call of {} waiting for approval ({})", function, &id);
+
+        match tokio::time::timeout(APPROVAL_TIMEOUT, decided).await {
+            Ok(decision) => decision.unwrap_or(false),
+            Err(_) => {
+                log::warn!(
+                    "call of {} denied, not approved in time ({})",
+                    function,
+                    &id
+                );
+                false
+            }
+        }
+    }
+}
+
+/// Queue the calls to confirm for a user to decide from the API, instead of asking on the
+/// terminal.
+pub(crate) fn enable_queue() {
+    if QUEUE.set(Queue::default()).is_err() {
+        log::warn!("approval queue already enabled");
+    }
+}
+
+/// Returns true if the calls to confirm are queued.
+pub(crate) fn queue_enabled() -> bool {
+    QUEUE.get().is_some()
+}
+
+/// The calls waiting for a decision, oldest first.
+pub(crate) fn pending() -> Vec<PendingApproval> {
+    QUEUE.get().map(Queue::pending).unwrap_or_default()
+}
+
+/// Approve or deny the queued call, returns false if there's no such call.
+pub(crate) fn decide(id: &str, approved: bool) -> bool {
+    QUEUE.get().is_some_and(|queue| queue.decide(id, approved))
+}
+
+/// Ask a user to confirm the command of the call, from the queue if enabled or on the terminal.
+pub(crate) async fn confirm(
+    function: &str,
+    command: &str,
+    caller: Option<&str>,
+) -> anyhow::Result<bool> {
+    match QUEUE.get() {
+        Some(queue) => Ok(queue.confirm(function, command, caller).await),
+        None => Ok(prompt::ask(
+            ">> enter 'y' to proceed or any other key to cancel: ",
+            &["y", "n"],
+        )? == "y"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_approving() {
+        let auto_approve = AutoApprove::new(
+            &serde_yaml::from_str("functions: [dns_*]\ncommands: ['whois *', /usr/bin/dig]")
+                .unwrap(),
+        )
+        .unwrap();
+
+        let whois = CommandLine::from_vec(&vec![
+            "/usr/bin/whois".to_string(),
+            "example.com".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(
+            auto_approve.approving("domain_whois", &whois),
+            Some("whois *")
+        );
+        assert_eq!(auto_approve.approving("dns_lookup", &whois), Some("dns_*"));
+
+        let dig = CommandLine::from_vec(&vec!["/usr/bin/dig".to_string()]).unwrap();
+        assert_eq!(
+            auto_approve.approving("dig_lookup", &dig),
+            Some("/usr/bin/dig")
+        );
+
+        let nmap =
+            CommandLine::from_vec(&vec!["/usr/bin/nmap".to_string(), "10.0.0.1".to_string()])
+                .unwrap();
+        assert_eq!(auto_approve.approving("nmap_scan", &nmap), None);
+    }
+
+    #[tokio::test]
+    async fn test_queue() {
+        let queue = std::sync::Arc::new(Queue::default());
+        assert!(!queue.decide("unknown", true));
+
+        for approved in [true, false] {
+            let confirmed = tokio::spawn({
+                let queue = queue.clone();
+                async move { queue.confirm("nmap_scan", "nmap 10.0.0.1", None).await }
+            });
+            let id = loop {
+                if let Some(pending) = queue.pending().pop() {
+                    break pending.id;
+                }
+                tokio::task::yield_now().await;
+            };
+            assert!(queue.decide(&id, approved));
+            assert_eq!(confirmed.await.unwrap(), approved);
+            assert!(queue.pending().is_empty());
+        }
+    }
+}
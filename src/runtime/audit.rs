@@ -0,0 +1,193 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Write,
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::book::{flavors::openai, runtime::FunctionRef};
+
+static AUDIT_LOG: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Where the value of an argument or interpolated variable of a call comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Provenance {
+    /// The arguments of calls received by the server.
+    Model,
+    /// A -D define of the command line.
+    Define,
+    /// Entered by the user when prompted.
+    Prompt,
+    /// An example of the function.
+    Example,
+    /// A test of the function.
+    Test,
+    /// Interpolated by a previous step of a pipeline.
+    Step,
+    /// Not provided, the default of the placeholder is used if any.
+    Default,
+    /// A variable sent by the client with the call.
+    ClientEnv,
+    /// A variable of this process or set with -E.
+    HostEnv,
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Model => "model",
+            Self::Define => "define",
+            Self::Prompt => "prompt",
+            Self::Example => "example",
+            Self::Test => "test",
+            Self::Step => "step",
+            Self::Default => "default",
+            Self::ClientEnv => "client_env",
+            Self::HostEnv => "host_env",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where each argument of the call, and each variable interpolated in its command, comes from.
+/// The variables are prefixed with env.
+pub(crate) fn provenance(
+    function: &FunctionRef,
+    call: &openai::Call,
+    arguments: &BTreeMap<String, String>,
+    env: &BTreeMap<String, String>,
+) -> BTreeMap<String, Provenance> {
+    let mut provenance = BTreeMap::new();
+    for name in function.function.parameters.keys() {
+        let source = match arguments.get(name) {
+            Some(value) if !value.is_empty() => call
+                .provenance
+                .get(name)
+                .copied()
+                .unwrap_or(Provenance::Model),
+            _ => Provenance::Default,
+        };
+        provenance.insert(name.clone(), source);
+    }
+    for name in env.keys() {
+        // sensitive arguments are also passed as variables
+        let parameter = function.function.parameters.get(&name.to_lowercase());
+        if parameter.is_some_and(|param| param.sensitive) {
+            continue;
+        }
+        let source = if call.env.contains_key(name) {
+            Provenance::ClientEnv
+        } else {
+            Provenance::HostEnv
+        };
+        provenance.insert(format!("env.{}", name), source);
+    }
+    provenance
+}
+
+/// An executed command, as recorded in the audit log.
+#[derive(Debug, Serialize)]
+pub(crate) struct Entry<'a> {
+    pub timestamp: String,
+    pub caller: Option<&'a str>,
+    pub function: &'a str,
+    pub call_id: Option<&'a str>,
+    pub command_line: &'a str,
+    pub exit_code: Option<i64>,
+    pub duration_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<&'a BTreeMap<String, Provenance>>,
+}
+
+impl<'a> Entry<'a> {
+    pub fn new(function: &'a str, command_line: &'a str, duration: Duration) -> Self {
+        Self {
+            timestamp: humantime::format_rfc3339_millis(SystemTime::now() - duration).to_string(),
+            caller: None,
+            function,
+            call_id: None,
+            command_line,
+            exit_code: None,
+            duration_secs: duration.as_secs_f64(),
+            error: None,
+            provenance: None,
+        }
+    }
+}
+
+/// Append every execution to the given JSONL file.
+pub(crate) fn set(path: &str) -> anyhow::Result<()> {
+    let path = shellexpand::full(path)?.to_string();
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| anyhow::anyhow!("could not open audit log {}: {}", path, e))?;
+
+    AUDIT_LOG
+        .set(Mutex::new(file))
+        .map_err(|_| anyhow::anyhow!("audit log already set"))?;
+
+    log::info!("audit log: {}", path);
+    Ok(())
+}
+
+pub(crate) fn record(entry: &Entry) {
+    let Some(file) = AUDIT_LOG.get() else {
+        return;
+    };
+
+    let line = match serde_json::to_string(entry) {
+        Ok(line) => line,
+        Err(e) => {
+            log::error!("could not serialize audit log entry: {}", e);
+            return;
+        }
+    };
+
+    // one write per line, so that concurrent entries are never interleaved
+    if let Err(e) = file
+        .lock()
+        .unwrap()
+        .write_all(format!("{}\n", line).as_bytes())
+    {
+        log::error!("could not write to the audit log: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_serialize() {
+        let provenance = BTreeMap::from([
+            ("target".to_string(), Provenance::Model),
+            ("env.SHODAN_API_KEY".to_string(), Provenance::HostEnv),
+        ]);
+        let mut entry = Entry::new("nmap_scan", "nmap -sS 10.0.0.1", Duration::from_secs(2));
+        entry.caller = Some("10.0.0.2");
+        entry.exit_code = Some(0);
+        entry.provenance = Some(&provenance);
+
+        let value: serde_json::Value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(value["caller"], "10.0.0.2");
+        assert_eq!(value["function"], "nmap_scan");
+        assert_eq!(value["command_line"], "nmap -sS 10.0.0.1");
+        assert_eq!(value["exit_code"], 0);
+        assert_eq!(value["duration_secs"], 2.0);
+        assert!(value["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert!(value.get("error").is_none());
+        assert_eq!(
+            value["provenance"],
+            serde_json::json!({"target": "model", "env.SHODAN_API_KEY": "host_env"})
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+
+use crate::book::Container;
+
+use super::ContainerSource;
+
+static BACKEND: OnceLock<ContainerBackend> = OnceLock::new();
+
+/// Executes the functions requiring a container.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ContainerBackend {
+    Docker,
+    Podman,
+    Nerdctl,
+    /// Pods started with kubectl in the current context.
+    K8s,
+    /// Functions requiring a container can't be executed.
+    None,
+}
+
+impl ContainerBackend {
+    /// Command line client of the backend.
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+            Self::Nerdctl => "nerdctl",
+            Self::K8s => "kubectl",
+            Self::None => "",
+        }
+    }
+
+    /// Returns true if the client accepts the same commands as docker.
+    pub fn is_docker_compatible(&self) -> bool {
+        matches!(self, Self::Docker | Self::Podman | Self::Nerdctl)
+    }
+
+    /// Returns an error explaining why the container can't be executed by this backend, if so.
+    pub fn check(&self, container: &Container) -> anyhow::Result<()> {
+        match self {
+            Self::None => Err(anyhow::anyhow!(
+                "a container is required but the container backend is none, install the binary on the host or use another --container-backend"
+            )),
+            Self::K8s => {
+                if let ContainerSource::Build(build) = &container.source {
+                    return Err(anyhow::anyhow!(
+                        "images built from a Dockerfile are not supported by the k8s backend, push {} to a registry and use it as image",
+                        build.name
+                    ));
+                }
+                if container.volumes.as_ref().is_some_and(|v| !v.is_empty()) {
+                    return Err(anyhow::anyhow!(
+                        "volumes are not supported by the k8s backend"
+                    ));
+                }
+                if !super::sandbox::container_options().is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "the limits of the sandbox profile can't be enforced by the k8s backend"
+                    ));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Set the container backend for this process, instead of the ROBOPAGES_CONTAINER_RUNTIME client.
+pub(crate) fn set(backend: ContainerBackend) {
+    log::info!("container backend: {:?}", backend);
+    if BACKEND.set(backend).is_err() {
+        log::warn!("container backend already set");
+    }
+}
+
+pub(crate) fn get() -> Option<ContainerBackend> {
+    BACKEND.get().copied()
+}
+
+/// Returns true if containers are executed with a docker compatible client, the default.
+pub(crate) fn is_docker_compatible() -> bool {
+    get().is_none_or(|backend| backend.is_docker_compatible())
+}
+
+/// Returns an error if the container can't be executed by the backend set for this process.
+pub(crate) fn check(container: &Container) -> anyhow::Result<()> {
+    match get() {
+        Some(backend) => backend.check(container),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(yaml: &str) -> Container {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_check() {
+        let image = container("image: projectdiscovery/httpx");
+        let build = container("build:\n  name: tool\n  path: Dockerfile");
+        let volumes = container("image: alpine\nvolumes: [\"/tmp:/data\"]");
+
+        assert!(ContainerBackend::Docker.check(&build).is_ok());
+        assert!(ContainerBackend::Nerdctl.check(&volumes).is_ok());
+
+        assert!(ContainerBackend::K8s.check(&image).is_ok());
+        assert!(ContainerBackend::K8s.check(&build).is_err());
+        assert!(ContainerBackend::K8s.check(&volumes).is_err());
+
+        assert!(ContainerBackend::None.check(&image).is_err());
+    }
+}
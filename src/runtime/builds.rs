@@ -0,0 +1,124 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::docker::ImageBuild;
+
+const STATE_FILE: &str = "~/.config/robopages/state/builds.yml";
+
+/// Fingerprints of the last successful build of each image.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct BuildState {
+    builds: BTreeMap<String, String>,
+}
+
+impl BuildState {
+    fn path() -> anyhow::Result<PathBuf> {
+        Ok(PathBuf::from(shellexpand::full(STATE_FILE)?.to_string()))
+    }
+
+    pub fn load() -> Self {
+        let path = match Self::path() {
+            Ok(path) => path,
+            Err(_) => return Self::default(),
+        };
+
+        std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_yaml::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn is_up_to_date(&self, image: &str, fingerprint: &str) -> bool {
+        self.builds.get(image).map(|f| f.as_str()) == Some(fingerprint)
+    }
+
+    pub fn record(image: &str, fingerprint: &str) -> anyhow::Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // reload to minimize races with other instances
+        let mut state = Self::load();
+        state
+            .builds
+            .insert(image.to_string(), fingerprint.to_string());
+
+        std::fs::write(&path, serde_yaml::to_string(&state)?)?;
+        Ok(())
+    }
+}
+
+fn hash_dir(hasher: &mut Sha256, root: &Path, dir: &Path) -> anyhow::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            hash_dir(hasher, root, &path)?;
+        } else if file_type.is_file() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update(std::fs::read(&path)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Compute a fingerprint of everything that affects the build output.
+pub(crate) fn fingerprint(build: &ImageBuild) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+
+    hasher.update(std::fs::read(build.dockerfile()?)?);
+    hasher.update(build.target.as_deref().unwrap_or_default().as_bytes());
+    for (key, value) in build.build_args.iter().flatten() {
+        hasher.update(format!("{}={}", key, value).as_bytes());
+    }
+
+    let context = build.context();
+    hash_dir(&mut hasher, &context, &context)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_changes_with_context() {
+        let temp_dir = tempfile::TempDir::with_prefix("robopage-test-").unwrap();
+        let dockerfile = temp_dir.path().join("Dockerfile");
+        std::fs::write(&dockerfile, "FROM alpine").unwrap();
+
+        let build = ImageBuild {
+            name: "test".to_string(),
+            path: dockerfile.to_string_lossy().to_string(),
+            context: None,
+            target: None,
+            build_args: None,
+            no_cache: false,
+            pull: false,
+        };
+
+        let first = fingerprint(&build).unwrap();
+        assert_eq!(first, fingerprint(&build).unwrap());
+
+        std::fs::write(temp_dir.path().join("script.sh"), "echo hi").unwrap();
+        let second = fingerprint(&build).unwrap();
+        assert_ne!(first, second);
+
+        let mut state = BuildState::default();
+        state.builds.insert("test".to_string(), second.to_string());
+        assert!(state.is_up_to_date("test", &second));
+        assert!(!state.is_up_to_date("test", &first));
+    }
+}
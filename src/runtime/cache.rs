@@ -0,0 +1,251 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use super::receipt::Receipt;
+
+const CACHE_DIR: &str = "~/.config/robopages/state/cache";
+
+static ENTRIES: LazyLock<Mutex<HashMap<String, Entry>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// How long the results of a function are reused for identical calls.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachePolicy {
+    /// Such as 10m or 1h.
+    #[serde(serialize_with = "serialize_ttl", deserialize_with = "deserialize_ttl")]
+    pub ttl: Duration,
+    /// Also keep the results on disk, so they survive restarts.
+    #[serde(default)]
+    pub persist: bool,
+}
+
+fn serialize_ttl<S: Serializer>(ttl: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&humantime::format_duration(*ttl).to_string())
+}
+
+fn deserialize_ttl<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let ttl = String::deserialize(deserializer)?;
+    humantime::parse_duration(&ttl)
+        .map_err(|e| serde::de::Error::custom(format!("invalid ttl {}: {}", ttl, e)))
+}
+
+/// A cached result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    pub function: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Receipt>,
+    expires_at: SystemTime,
+}
+
+impl Entry {
+    fn is_expired(&self) -> bool {
+        self.expires_at <= SystemTime::now()
+    }
+}
+
+fn dir() -> anyhow::Result<PathBuf> {
+    Ok(PathBuf::from(shellexpand::full(CACHE_DIR)?.to_string()))
+}
+
+/// Calls of the same function with the same arguments and environment, on the same host, share
+/// the key.
+pub(crate) fn key(
+    function: &str,
+    arguments: &BTreeMap<String, String>,
+    env: &BTreeMap<String, String>,
+    host: Option<&str>,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(function.as_bytes());
+    for (prefix, values) in [("arg", arguments), ("env", env)] {
+        for (name, value) in values {
+            hasher.update([0]);
+            hasher.update(prefix.as_bytes());
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            hasher.update(value.as_bytes());
+        }
+    }
+    if let Some(host) = host {
+        hasher.update([1]);
+        hasher.update(host.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn read_persisted(key: &str) -> Option<Entry> {
+    let path = dir().ok()?.join(format!("{}.json", key));
+    let data = std::fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&data) {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            log::warn!("ignoring invalid cache entry {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn remove_persisted(key: &str) {
+    if let Ok(dir) = dir() {
+        let _ = std::fs::remove_file(dir.join(format!("{}.json", key)));
+    }
+}
+
+/// Returns the result cached with the key, if not expired.
+pub(crate) fn get(key: &str, policy: &CachePolicy) -> Option<Entry> {
+    let mut entries = ENTRIES.lock().unwrap();
+    let entry = match entries.get(key) {
+        Some(entry) => Some(entry.clone()),
+        None if policy.persist => read_persisted(key),
+        None => None,
+    }?;
+
+    if entry.is_expired() {
+        entries.remove(key);
+        if policy.persist {
+            remove_persisted(key);
+        }
+        return None;
+    }
+
+    entries.insert(key.to_string(), entry.clone());
+    Some(entry)
+}
+
+/// Cache the result of the function with the key for the ttl of the policy.
+pub(crate) fn put(
+    key: &str,
+    function: &str,
+    policy: &CachePolicy,
+    content: &str,
+    receipt: Option<&Receipt>,
+) {
+    let entry = Entry {
+        function: function.to_string(),
+        content: content.to_string(),
+        receipt: receipt.cloned(),
+        expires_at: SystemTime::now() + policy.ttl,
+    };
+
+    if policy.persist {
+        let persisted = dir().and_then(|dir| {
+            std::fs::create_dir_all(&dir)?;
+            std::fs::write(
+                dir.join(format!("{}.json", key)),
+                serde_json::to_string(&entry)?,
+            )?;
+            Ok(())
+        });
+        if let Err(e) = persisted {
+            log::warn!("could not persist the result of {}: {}", function, e);
+        }
+    }
+
+    ENTRIES.lock().unwrap().insert(key.to_string(), entry);
+}
+
+/// Remove the cached results of the function, or all of them, returns how many were removed.
+pub(crate) fn clear(function: Option<&str>) -> usize {
+    clear_functions(|name| function.is_none_or(|function| name == function))
+}
+
+/// Remove the cached results of the functions matching the predicate, returns how many.
+pub(crate) fn clear_functions(matches: impl Fn(&str) -> bool) -> usize {
+    let matches = |entry: &Entry| matches(&entry.function);
+
+    let mut removed = 0;
+    ENTRIES.lock().unwrap().retain(|_, entry| {
+        let remove = matches(entry);
+        if remove {
+            removed += 1;
+        }
+        !remove
+    });
+
+    // the persisted ones might not have been loaded in memory
+    let Ok(entries) = dir().and_then(|dir| Ok(std::fs::read_dir(dir)?)) else {
+        return removed;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let persisted = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<Entry>(&data).ok());
+        if persisted.is_some_and(|entry| matches(&entry)) && std::fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        let arguments = BTreeMap::from([("target".to_string(), "10.0.0.1".to_string())]);
+        let other = BTreeMap::from([("target".to_string(), "10.0.0.2".to_string())]);
+        let env = BTreeMap::from([("HTTP_PROXY".to_string(), "http://proxy:8080".to_string())]);
+        let none = BTreeMap::new();
+
+        assert_eq!(
+            key("nmap", &arguments, &none, None),
+            key("nmap", &arguments, &none, None)
+        );
+        assert_ne!(
+            key("nmap", &arguments, &none, None),
+            key("nmap", &other, &none, None)
+        );
+        assert_ne!(
+            key("nmap", &arguments, &none, None),
+            key("masscan", &arguments, &none, None)
+        );
+        assert_ne!(
+            key("nmap", &arguments, &none, None),
+            key("nmap", &arguments, &env, None)
+        );
+        assert_ne!(
+            key("nmap", &arguments, &none, None),
+            key("nmap", &arguments, &none, Some("user@host:22"))
+        );
+    }
+
+    #[test]
+    fn test_cache() {
+        let policy: CachePolicy = serde_yaml::from_str("ttl: 1h").unwrap();
+        assert_eq!(policy.ttl, Duration::from_secs(3600));
+        assert!(serde_yaml::from_str::<CachePolicy>("ttl: soon").is_err());
+
+        let arguments = BTreeMap::from([("target".to_string(), "10.0.0.1".to_string())]);
+        let key = key("test_cache_scan", &arguments, &BTreeMap::new(), None);
+        assert_ne!(
+            key,
+            super::key("test_cache_scan", &arguments, &BTreeMap::new(), Some("db1"))
+        );
+
+        assert!(get(&key, &policy).is_none());
+        put(&key, "test_cache_scan", &policy, "22/tcp open", None);
+        assert_eq!(get(&key, &policy).unwrap().content, "22/tcp open");
+
+        assert_eq!(clear(Some("test_cache_other")), 0);
+        assert_eq!(clear(Some("test_cache_scan")), 1);
+        assert!(get(&key, &policy).is_none());
+
+        let expired = CachePolicy {
+            ttl: Duration::ZERO,
+            persist: false,
+        };
+        put(&key, "test_cache_scan", &expired, "22/tcp open", None);
+        assert!(get(&key, &expired).is_none());
+    }
+}
@@ -1,21 +1,138 @@
 use std::{collections::BTreeMap, fmt};
 
+use super::docker::TrackedContainer;
+
+const REDACTED: &str = "********";
+
+/// Mask the secrets in the text.
+pub(crate) fn redact(text: &str, secrets: &[String]) -> String {
+    secrets
+        .iter()
+        .filter(|secret| !secret.is_empty())
+        .fold(text.to_string(), |text, secret| {
+            text.replace(secret.as_str(), REDACTED)
+        })
+}
+
+/// What a command printed on each stream, and how it exited.
 #[derive(Debug)]
+pub struct Output {
+    pub status: std::process::ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Output {
+    /// Merge the exit code and both streams in the text returned for the call.
+    pub fn content(&self) -> String {
+        let mut parts = vec![];
+
+        if !self.status.success() {
+            log::warn!("command failed with exit code: {}", self.status);
+            // killed by a signal if there's no exit code
+            parts.push(format!(
+                "{}{}",
+                super::EXIT_CODE_PREFIX,
+                self.status
+                    .code()
+                    .map_or(self.status.to_string(), |code| code.to_string())
+            ));
+        }
+
+        if !self.stdout.is_empty() {
+            log::trace!("command stdout: {}", self.stdout);
+            parts.push(self.stdout.clone());
+        }
+
+        if !self.stderr.is_empty() {
+            if self.status.success() {
+                log::debug!("command stderr (success): {}", self.stderr);
+                parts.push(self.stderr.clone());
+            } else {
+                log::error!("command stderr (failure): {}", self.stderr);
+                parts.push(format!("ERROR: {}", self.stderr));
+            }
+        }
+
+        let result = parts.join("\n");
+        log::debug!(
+            "command execution completed, output length: {}",
+            result.len()
+        );
+        log::trace!("command output: {}", result);
+
+        result
+    }
+}
+
+/// Kills the process group of a command if dropped before being disarmed, since killing the
+/// process alone leaves the processes it started, such as a docker run, behind.
+struct ProcessGroup {
+    #[cfg(unix)]
+    pgid: Option<nix::unistd::Pid>,
+}
+
+impl ProcessGroup {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn of(child: &tokio::process::Child, own_group: bool) -> Self {
+        Self {
+            #[cfg(unix)]
+            pgid: child
+                .id()
+                .filter(|_| own_group)
+                .map(|pid| nix::unistd::Pid::from_raw(pid as i32)),
+        }
+    }
+
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    fn disarm(mut self) {
+        #[cfg(unix)]
+        {
+            self.pgid = None;
+        }
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.pgid.take() {
+            log::debug!("killing process group {} of cancelled command", pgid);
+            let _ = nix::sys::signal::killpg(pgid, nix::sys::signal::Signal::SIGKILL);
+        }
+    }
+}
+
+/// A host path passed as argument, mounted when the command runs in a container.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathArgument {
+    pub value: String,
+    /// Written by the command, so mounted read-write.
+    pub output: bool,
+}
+
 pub struct CommandLine {
     pub sudo: bool,
     pub app: String,
     pub app_in_path: bool,
     pub args: Vec<String>,
     pub env: BTreeMap<String, String>,
+    /// Written to the standard input of the process.
+    pub stdin: Option<String>,
+
+    /// Values of the sensitive arguments, masked when the command line is displayed.
+    pub secrets: Vec<String>,
 
     // used to keep a valid reference to this while the command is running
     pub temp_env_file: Option<tempfile::NamedTempFile>,
+    // keeps the container tracked as running while the command is alive
+    pub container: Option<TrackedContainer>,
+    /// Host paths passed as arguments, mounted when the command runs in a container.
+    pub paths: Vec<PathArgument>,
 }
 
 impl CommandLine {
     pub fn from_vec(vec: &Vec<String>) -> anyhow::Result<Self> {
-        log::debug!("Creating CommandLine from vector: {:?}", vec);
-
         if vec.is_empty() {
             log::error!("Empty command line vector provided");
             return Err(anyhow::anyhow!("empty command line"));
@@ -25,8 +142,8 @@ impl CommandLine {
         let mut app = String::new();
         let mut args = Vec::new();
 
+        // the arguments hold the values of the call, they are not logged
         for arg in vec {
-            log::trace!("Processing argument: {}", arg);
             if arg == "sudo" {
                 log::debug!("Sudo flag detected");
                 sudo = true;
@@ -34,13 +151,11 @@ impl CommandLine {
                 log::debug!("Setting application name: {}", arg);
                 app = arg.to_string();
             } else {
-                log::trace!("Adding argument: {}", arg);
                 args.push(arg.to_string());
             }
         }
 
         if app.is_empty() {
-            log::error!("Could not determine application name from: {:?}", vec);
             return Err(anyhow::anyhow!(
                 "could not determine application name from command line: {:?}",
                 vec
@@ -57,11 +172,11 @@ impl CommandLine {
         };
 
         log::debug!(
-            "Created CommandLine: sudo={}, app={}, app_in_path={}, args={:?}",
+            "Created CommandLine: sudo={}, app={}, app_in_path={}, args={}",
             sudo,
             app,
             app_in_path,
-            args
+            args.len()
         );
 
         Ok(Self {
@@ -70,7 +185,11 @@ impl CommandLine {
             args,
             app_in_path,
             env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         })
     }
 
@@ -79,87 +198,123 @@ impl CommandLine {
         env: BTreeMap<String, String>,
     ) -> anyhow::Result<Self> {
         log::debug!("creating CommandLine with environment variables");
-        log::trace!("environment variables: {:?}", env);
+        log::trace!("environment variables: {:?}", env.keys());
         let mut cmd = Self::from_vec(vec)?;
         cmd.env = env;
         Ok(cmd)
     }
 
-    fn get_env_interpolated_args(&self) -> Vec<String> {
-        log::debug!("interpolating variables from environment: {:?}", self.env);
+    /// Mask the values of the sensitive arguments in the text.
+    pub fn redact(&self, text: &str) -> String {
+        redact(text, &self.secrets)
+    }
 
-        let args = self
-            .args
+    /// Returns true if the value of a sensitive argument is interpolated in the arguments.
+    pub fn has_secret_args(&self) -> bool {
+        self.secrets
             .iter()
-            .map(|arg| {
-                let mut result = arg.clone();
-                for (key, value) in &self.env {
-                    let pattern = format!("${{{}}}", key);
-                    if result.contains(&pattern) {
-                        log::debug!("replacing {} with {}", pattern, value);
-                        result = result.replace(&pattern, value);
-                    }
-                }
-                result
-            })
-            .collect();
+            .filter(|secret| !secret.is_empty())
+            .any(|secret| self.args.iter().any(|arg| arg.contains(secret.as_str())))
+    }
 
-        log::debug!("after interpolation: {:?}", &args);
+    async fn spawn_and_wait(
+        mut command: tokio::process::Command,
+        stdin: Option<&str>,
+        process_group: bool,
+    ) -> std::io::Result<std::process::Output> {
+        use tokio::io::AsyncWriteExt;
+
+        command
+            .stdin(if stdin.is_some() {
+                std::process::Stdio::piped()
+            } else {
+                std::process::Stdio::null()
+            })
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+        #[cfg(unix)]
+        if process_group {
+            command.process_group(0);
+        }
 
-        args
+        let mut child = command.spawn()?;
+        let group = ProcessGroup::of(&child, process_group);
+        let pipe = child.stdin.take();
+        // written while the output is read, the process could block on a full stdout pipe before
+        // reading all of its input
+        let write = async move {
+            match (stdin, pipe) {
+                (Some(stdin), Some(mut pipe)) => pipe.write_all(stdin.as_bytes()).await,
+                // closing it lets the process know there's nothing more to read
+                _ => Ok(()),
+            }
+        };
+        let (written, output) = tokio::join!(write, child.wait_with_output());
+        group.disarm();
+        match written {
+            // a process exiting without reading its input is not an error
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e),
+            _ => output,
+        }
     }
 
     pub async fn execute(&self) -> anyhow::Result<String> {
+        Ok(self.output().await?.content())
+    }
+
+    pub async fn output(&self) -> anyhow::Result<Output> {
         log::debug!("executing command: {}", self);
         log::debug!("full command details: {:?}", self);
 
-        let args = self.get_env_interpolated_args();
-
+        // the arguments already hold the values of the call, they are never expanded again
         let mut command = tokio::process::Command::new(&self.app);
-        command.args(&args);
+        command.args(&self.args);
+        if let Some(env) = super::sandbox::allowed_env() {
+            command.env_clear();
+            command.envs(env);
+        }
+        command.envs(super::env::overrides());
 
         // log environment variables if present
         if !self.env.is_empty() {
-            log::debug!("setting environment variables: {:?}", self.env);
+            log::debug!("setting environment variables: {:?}", self.env.keys());
             command.envs(&self.env);
         }
 
-        let output = command.output().await?;
-        log::debug!("command completed with status: {:?}", output.status);
+        // cancelled calls don't leave the process, its children or its container behind
+        command.kill_on_drop(true);
+        let guard = self.container.as_ref().map(|c| c.cancel_guard());
 
-        let mut parts = vec![];
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-
-        if !output.status.success() {
-            log::warn!("command failed with exit code: {}", output.status);
-            parts.push(format!("EXIT CODE: {}", &output.status));
-        }
-
-        if !stdout.is_empty() {
-            log::trace!("command stdout: {}", stdout);
-            parts.push(stdout.to_string());
-        }
-
-        if !stderr.is_empty() {
-            if output.status.success() {
-                log::debug!("command stderr (success): {}", stderr);
-                parts.push(stderr.to_string());
-            } else {
-                log::error!("command stderr (failure): {}", stderr);
-                parts.push(format!("ERROR: {}", stderr));
-            }
+        // sudo needs the terminal of this process to ask for the password
+        let output = Self::spawn_and_wait(command, self.stdin.as_deref(), !self.sudo).await;
+        if let Some(guard) = guard {
+            guard.disarm();
         }
+        let output = output?;
+        log::debug!("command completed with status: {:?}", output.status);
 
-        let result = parts.join("\n");
-        log::debug!(
-            "command execution completed, output length: {}",
-            result.len()
-        );
-        log::trace!("command output: {}", result);
+        Ok(Output {
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        })
+    }
+}
 
-        Ok(result)
+// the values of the call can be sensitive, they are masked or left out
+impl fmt::Debug for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let args: Vec<String> = self.args.iter().map(|arg| self.redact(arg)).collect();
+        f.debug_struct("CommandLine")
+            .field("sudo", &self.sudo)
+            .field("app", &self.app)
+            .field("app_in_path", &self.app_in_path)
+            .field("args", &args)
+            .field("env", &self.env.keys())
+            .field("stdin", &self.stdin.as_ref().map(|_| REDACTED))
+            .field("container", &self.container)
+            .field("paths", &self.paths)
+            .finish_non_exhaustive()
     }
 }
 
@@ -178,7 +333,7 @@ impl fmt::Display for CommandLine {
             command.push_str(arg);
         }
 
-        write!(f, "{}", command)
+        write!(f, "{}", self.redact(&command))
     }
 }
 
@@ -194,7 +349,11 @@ mod tests {
             args: vec!["-l".to_string(), "-a".to_string()],
             app_in_path: true,
             env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
         assert_eq!(format!("{}", cmd), "ls -l -a");
 
@@ -204,11 +363,37 @@ mod tests {
             args: vec!["install".to_string(), "package".to_string()],
             app_in_path: true,
             env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
         assert_eq!(format!("{}", cmd_with_sudo), "sudo apt install package");
     }
 
+    #[test]
+    fn test_command_line_debug() {
+        let cmd = CommandLine {
+            sudo: false,
+            app: "curl".to_string(),
+            args: vec!["-H".to_string(), "Authorization: hunter2".to_string()],
+            app_in_path: true,
+            env: BTreeMap::from([("API_KEY".to_string(), "s3cr3t".to_string())]),
+            stdin: Some("password".to_string()),
+            secrets: vec!["hunter2".to_string()],
+            temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
+        };
+        let debug = format!("{:?}", cmd);
+        assert!(debug.contains("API_KEY"));
+        assert!(debug.contains("Authorization: ********"));
+        for secret in ["hunter2", "s3cr3t", "password"] {
+            assert!(!debug.contains(secret), "{} in {}", secret, debug);
+        }
+    }
+
     #[tokio::test]
     async fn test_command_line_execute_success() {
         let cmd = CommandLine {
@@ -217,7 +402,11 @@ mod tests {
             args: vec!["-n".to_string(), "Hello, World!".to_string()],
             app_in_path: true,
             env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
         let result = cmd.execute().await.unwrap();
         assert_eq!(result, "Hello, World!");
@@ -231,7 +420,11 @@ mod tests {
             args: vec!["nonexistent_file".to_string()],
             app_in_path: true,
             env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
         let result = cmd.execute().await.unwrap();
         assert!(result.contains("EXIT CODE:"));
@@ -249,7 +442,11 @@ mod tests {
             ],
             app_in_path: true,
             env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
         let result = cmd.execute().await.unwrap();
         assert!(result.contains("Hello"));
@@ -257,73 +454,104 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_command_line_empty_app() {
+    async fn test_command_line_execute_with_stdin() {
         let cmd = CommandLine {
             sudo: false,
-            app: "".to_string(),
-            args: vec!["arg1".to_string(), "arg2".to_string()],
+            app: "cat".to_string(),
+            args: vec![],
             app_in_path: true,
             env: BTreeMap::new(),
+            stdin: Some("from stdin".to_string()),
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
-        let result = cmd.execute().await;
-        assert!(result.is_err());
+        let result = cmd.execute().await.unwrap();
+        assert_eq!(result, "from stdin");
     }
 
-    #[test]
-    fn test_get_env_interpolated_args_with_env_vars() {
-        let mut env = BTreeMap::new();
-        env.insert("TEST_VAR".to_string(), "test_value".to_string());
-        env.insert("OTHER_VAR".to_string(), "other_value".to_string());
-
+    #[tokio::test]
+    async fn test_command_line_large_stdin() {
+        // larger than the pipe buffers, cat writes it back while it's being written
+        let input = "x".repeat(4 * 1024 * 1024);
         let cmd = CommandLine {
             sudo: false,
-            app: "echo".to_string(),
-            args: vec!["${TEST_VAR}".to_string(), "${OTHER_VAR}".to_string()],
+            app: "cat".to_string(),
+            args: vec![],
             app_in_path: true,
-            env,
+            env: BTreeMap::new(),
+            stdin: Some(input.clone()),
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
-
-        let result = cmd.get_env_interpolated_args();
-        assert_eq!(result, vec!["test_value", "other_value"]);
+        let result = tokio::time::timeout(std::time::Duration::from_secs(30), cmd.execute())
+            .await
+            .expect("writing the input deadlocked")
+            .unwrap();
+        assert_eq!(result.len(), input.len());
     }
 
-    #[test]
-    fn test_get_env_interpolated_args_with_missing_vars() {
-        let env = BTreeMap::new();
+    #[tokio::test]
+    async fn test_command_line_empty_app() {
         let cmd = CommandLine {
             sudo: false,
-            app: "echo".to_string(),
-            args: vec!["${MISSING_VAR}".to_string()],
+            app: "".to_string(),
+            args: vec!["arg1".to_string(), "arg2".to_string()],
             app_in_path: true,
-            env,
+            env: BTreeMap::new(),
+            stdin: None,
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
-
-        let result = cmd.get_env_interpolated_args();
-        assert_eq!(result, vec!["${MISSING_VAR}"]);
+        let result = cmd.execute().await;
+        assert!(result.is_err());
     }
 
-    #[test]
-    fn test_get_env_interpolated_args_with_mixed_content() {
+    #[tokio::test]
+    async fn test_env_not_expanded_in_args() {
         let mut env = BTreeMap::new();
-        env.insert("VAR".to_string(), "value".to_string());
+        env.insert("TOKEN".to_string(), "secret".to_string());
 
         let cmd = CommandLine {
             sudo: false,
             app: "echo".to_string(),
-            args: vec![
-                "prefix_${VAR}".to_string(),
-                "normal_arg".to_string(),
-                "${VAR}_suffix".to_string(),
-            ],
+            args: vec!["${TOKEN}".to_string()],
             app_in_path: true,
             env,
+            stdin: None,
+            secrets: Vec::new(),
             temp_env_file: None,
+            container: None,
+            paths: Vec::new(),
         };
 
-        let result = cmd.get_env_interpolated_args();
-        assert_eq!(result, vec!["prefix_value", "normal_arg", "value_suffix"]);
+        assert_eq!(cmd.execute().await.unwrap().trim(), "${TOKEN}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_cancelled_command_kills_its_children() {
+        let pid_file = tempfile::NamedTempFile::new().unwrap();
+        let cmd = CommandLine::from_vec(&vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("sleep 30 & echo $! > {}; wait", pid_file.path().display()),
+        ])
+        .unwrap();
+
+        let cancelled =
+            tokio::time::timeout(std::time::Duration::from_millis(500), cmd.output()).await;
+        assert!(cancelled.is_err());
+
+        let pid = std::fs::read_to_string(pid_file.path()).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        // either reaped or a zombie
+        let status = std::fs::read_to_string(format!("/proc/{}/status", pid.trim()));
+        assert!(status.is_err() || status.unwrap().contains("State:\tZ"));
     }
 }
@@ -0,0 +1,96 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// the limit each semaphore was created for, the pages might have been reloaded since
+type Limited = (usize, Arc<Semaphore>);
+
+static SEMAPHORES: LazyLock<Mutex<HashMap<String, Limited>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn semaphore(function: &str, limit: usize) -> Arc<Semaphore> {
+    let mut semaphores = SEMAPHORES.lock().unwrap();
+    match semaphores.get(function) {
+        Some((current, semaphore)) if *current == limit => semaphore.clone(),
+        _ => {
+            // the calls running with the previous limit keep their permits until they complete
+            let semaphore = Arc::new(Semaphore::new(limit));
+            semaphores.insert(function.to_string(), (limit, semaphore.clone()));
+            semaphore
+        }
+    }
+}
+
+/// Waits until fewer than limit calls of the function are running, the returned permit must be
+/// kept until the call completes. Calls of functions without a limit don't wait.
+pub(crate) async fn acquire(
+    function: &str,
+    limit: Option<usize>,
+) -> anyhow::Result<Option<OwnedSemaphorePermit>> {
+    let Some(limit) = limit else {
+        return Ok(None);
+    };
+    if limit == 0 {
+        return Err(anyhow::anyhow!(
+            "max_concurrency of function {} must be greater than 0",
+            function
+        ));
+    }
+
+    let semaphore = semaphore(function, limit);
+    if semaphore.available_permits() == 0 {
+        log::info!(
+            "waiting for one of the {} running calls of {} to complete",
+            limit,
+            function
+        );
+    }
+
+    let permit = semaphore
+        .acquire_owned()
+        .await
+        .map_err(|e| anyhow::anyhow!("could not acquire a permit for {}: {}", function, e))?;
+    Ok(Some(permit))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acquire() {
+        assert!(acquire("test_acquire_unlimited", None)
+            .await
+            .unwrap()
+            .is_none());
+
+        let first = acquire("test_acquire_masscan", Some(1)).await.unwrap();
+        assert!(first.is_some());
+
+        // the second call waits for the first one
+        let second = tokio::time::timeout(
+            Duration::from_millis(50),
+            acquire("test_acquire_masscan", Some(1)),
+        )
+        .await;
+        assert!(second.is_err());
+
+        drop(first);
+        let second = tokio::time::timeout(
+            Duration::from_millis(50),
+            acquire("test_acquire_masscan", Some(1)),
+        )
+        .await;
+        assert!(second.is_ok());
+
+        // other functions are not limited by it
+        assert!(acquire("test_acquire_whois", Some(1)).await.is_ok());
+
+        assert!(acquire("test_acquire_never", Some(0)).await.is_err());
+    }
+}
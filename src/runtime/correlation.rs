@@ -0,0 +1,91 @@
+use std::future::Future;
+
+tokio::task_local! {
+    static CONTEXT: Correlation;
+}
+
+/// Header carrying the id of a request, taken from the client if set and returned in the response.
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Identifies the request and the call a log line was written for.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Correlation {
+    pub request_id: Option<String>,
+    pub call_id: Option<String>,
+}
+
+/// Returns the ids of the request and call being executed by this task, if any.
+pub(crate) fn current() -> Correlation {
+    CONTEXT.try_with(Correlation::clone).unwrap_or_default()
+}
+
+/// Returns the id sent by the client if valid, a new one otherwise.
+pub(crate) fn request_id(provided: Option<&str>) -> String {
+    match provided {
+        Some(id)
+            if !id.is_empty()
+                && id.len() <= 128
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+        {
+            id.to_string()
+        }
+        _ => format!("{:016x}", fastrand::u64(..)),
+    }
+}
+
+/// Runs the future as part of the request.
+pub(crate) async fn with_request<F: Future>(request_id: String, future: F) -> F::Output {
+    CONTEXT
+        .scope(
+            Correlation {
+                request_id: Some(request_id),
+                call_id: None,
+            },
+            future,
+        )
+        .await
+}
+
+/// Runs the future as part of the call, within the request of the current task.
+pub(crate) async fn with_call<F: Future>(call_id: Option<String>, future: F) -> F::Output {
+    let mut correlation = current();
+    correlation.call_id = call_id;
+    CONTEXT.scope(correlation, future).await
+}
+
+/// Keeps the ids of the current task for the future, to be spawned as a task of its own.
+pub(crate) fn propagate<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    CONTEXT.scope(current(), future)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_propagate() {
+        assert!(current().request_id.is_none());
+
+        let request_id = request_id(Some("req-1"));
+        let correlation = with_request(request_id, async {
+            tokio::spawn(propagate(with_call(Some("call-1".to_string()), async {
+                current()
+            })))
+            .await
+            .unwrap()
+        })
+        .await;
+
+        assert_eq!(correlation.request_id.as_deref(), Some("req-1"));
+        assert_eq!(correlation.call_id.as_deref(), Some("call-1"));
+    }
+
+    #[test]
+    fn test_request_id() {
+        assert_eq!(request_id(Some("abc-123_x")), "abc-123_x");
+        assert_eq!(request_id(Some("no spaces")).len(), 16);
+        assert_eq!(request_id(None).len(), 16);
+    }
+}
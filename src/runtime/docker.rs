@@ -1,41 +1,654 @@
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     env,
     path::{Path, PathBuf},
     process::Stdio,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
 };
 
+use super::backend::ContainerBackend;
+use super::builds::{self, BuildState};
 use serde::{Deserialize, Serialize};
+
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     process::Command,
     task,
 };
 
-/// Get the container runtime command from environment or default to "docker"
+/// Label attached to every container started by robopages, set to the originating function name.
+pub const FUNCTION_LABEL: &str = "robopages.function";
+
+/// Label attached to every container started by robopages, set to the session of the owning process.
+pub const SESSION_LABEL: &str = "robopages.session";
+
+/// Label attached to every image built by robopages, set to the name of the image.
+pub const BUILD_LABEL: &str = "robopages.build";
+
+// unique identifier of this process, used to tell our containers apart from other instances
+static SESSION: LazyLock<String> = LazyLock::new(|| {
+    let started = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    match PID_NAMESPACE.as_deref() {
+        Some(namespace) => format!("{}-{:x}-{}", std::process::id(), started, namespace),
+        None => format!("{}-{:x}", std::process::id(), started),
+    }
+});
+
+// identifies where the pids of the sessions can be checked by this process, as instances in
+// other containers or on other hosts can share the same container runtime
+static PID_NAMESPACE: LazyLock<Option<String>> = LazyLock::new(pid_namespace);
+
+// names of the containers currently being executed by this process
+static TRACKED: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+static NEXT_CONTAINER_ID: AtomicU64 = AtomicU64::new(0);
+
+// images resolved by this process, so that each one is pulled or built only once
+type ResolvedImages = HashMap<String, Arc<tokio::sync::OnceCell<()>>>;
+static RESOLVED: LazyLock<Mutex<ResolvedImages>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Resolve the images again on their next use.
+pub(crate) fn forget_resolved_images() {
+    RESOLVED.lock().unwrap().clear();
+}
+
+pub fn session() -> &'static str {
+    &SESSION
+}
+
+/// A container name tracked as running by this process until dropped.
+#[derive(Debug)]
+pub struct TrackedContainer {
+    pub name: String,
+    // for containers not started with --rm
+    remove_on_drop: bool,
+}
+
+impl TrackedContainer {
+    pub fn new() -> Self {
+        let name = format!(
+            "robopages-{}-{}",
+            session(),
+            NEXT_CONTAINER_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        TRACKED.lock().unwrap().insert(name.clone());
+        Self {
+            name,
+            remove_on_drop: false,
+        }
+    }
+
+    /// Track a container that is removed in the background once dropped.
+    pub fn disposable() -> Self {
+        let mut tracked = Self::new();
+        tracked.remove_on_drop = true;
+        tracked
+    }
+
+    /// Returns a guard removing the container if dropped before being disarmed.
+    pub fn cancel_guard(&self) -> CancelGuard {
+        CancelGuard {
+            name: Some(self.name.clone()),
+        }
+    }
+}
+
+/// Removes a container whose command was cancelled while running, since killing the client
+/// process doesn't stop it.
+#[derive(Debug)]
+pub struct CancelGuard {
+    name: Option<String>,
+}
+
+impl CancelGuard {
+    pub fn disarm(mut self) {
+        self.name = None;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        let Some(name) = self.name.take() else {
+            return;
+        };
+
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            log::info!("removing container {} of cancelled call", name);
+            handle.spawn(async move {
+                if let Err(e) = kill_container(&name).await {
+                    log::debug!("{}", e);
+                }
+            });
+        }
+    }
+}
+
+impl Drop for TrackedContainer {
+    fn drop(&mut self) {
+        TRACKED.lock().unwrap().remove(&self.name);
+
+        if self.remove_on_drop {
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                let name = std::mem::take(&mut self.name);
+                handle.spawn(async move {
+                    if let Err(e) = kill_container(&name).await {
+                        log::debug!("{}", e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// A container created by robopages, as reported by the container runtime.
+#[derive(Debug)]
+pub struct ContainerInfo {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub status: String,
+    pub function: String,
+    pub session: String,
+}
+
+impl ContainerInfo {
+    /// Returns true if the container is not tracked by any live robopages process. Containers
+    /// of processes that can't be checked from here are never orphans.
+    pub fn is_orphan(&self) -> bool {
+        if self.session == session() {
+            // ours, but not running anymore from our point of view
+            return !TRACKED.lock().unwrap().contains(&self.name);
+        }
+
+        let mut parts = self.session.split('-');
+        let pid = parts.next().and_then(|pid| pid.parse::<u32>().ok());
+        let namespace = parts.nth(1);
+        match (pid, namespace) {
+            (Some(pid), Some(namespace)) if PID_NAMESPACE.as_deref() == Some(namespace) => {
+                // same pid but different session, we've been restarted (pid 1 in containers)
+                pid == std::process::id() || !process_alive(pid)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pid_namespace() -> Option<String> {
+    // pids are only meaningful for the same boot and pid namespace
+    let boot_id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+    let namespace = std::fs::read_link("/proc/self/ns/pid").ok()?;
+    Some(short_hash(&[
+        boot_id.trim().as_bytes(),
+        namespace.as_os_str().as_encoded_bytes(),
+    ]))
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn pid_namespace() -> Option<String> {
+    let host = nix::unistd::gethostname().ok()?;
+    Some(short_hash(&[host.as_encoded_bytes()]))
+}
+
+#[cfg(not(unix))]
+fn pid_namespace() -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn short_hash(parts: &[&[u8]]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())[..12].to_string()
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> bool {
+    Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process_alive(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(true)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    // no cheap way to check, assume it is so we never kill someone else's containers
+    true
+}
+
+// pods are listed by name, which is also what kubectl expects to delete them
+async fn list_pods() -> anyhow::Result<std::process::Output> {
+    Command::new("kubectl")
+        .args([
+            "get",
+            "pods",
+            "-l",
+            SESSION_LABEL,
+            "-o",
+            &format!(
+                "jsonpath={{range .items[*]}}{{.metadata.name}}{{\"\\t\"}}{{.metadata.name}}{{\"\\t\"}}{{.spec.containers[0].image}}{{\"\\t\"}}{{.status.phase}}{{\"\\t\"}}{{.metadata.labels.{}}}{{\"\\t\"}}{{.metadata.labels.{}}}{{\"\\n\"}}{{end}}",
+                FUNCTION_LABEL.replace('.', "\\."),
+                SESSION_LABEL.replace('.', "\\.")
+            ),
+        ])
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run kubectl: {}", e))
+}
+
+/// List all containers (running or not) created by robopages.
+pub async fn list_containers() -> anyhow::Result<Vec<ContainerInfo>> {
+    let runtime = get_container_runtime();
+    let output = match super::backend::get() {
+        Some(ContainerBackend::None) => return Ok(Vec::new()),
+        Some(ContainerBackend::K8s) => list_pods().await?,
+        _ => Command::new(&runtime)
+        .args([
+            "ps",
+            "-a",
+            "--no-trunc",
+            "--filter",
+            &format!("label={}", SESSION_LABEL),
+            "--format",
+            &format!(
+                "{{{{.ID}}}}\t{{{{.Names}}}}\t{{{{.Image}}}}\t{{{{.Status}}}}\t{{{{.Label \"{}\"}}}}\t{{{{.Label \"{}\"}}}}",
+                FUNCTION_LABEL, SESSION_LABEL
+            ),
+        ])
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", runtime, e))?,
+    };
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "failed to list containers: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split('\t').collect();
+            if parts.len() != 6 {
+                log::warn!("unexpected container listing line: {}", line);
+                return None;
+            }
+            Some(ContainerInfo {
+                id: parts[0].to_string(),
+                name: parts[1].to_string(),
+                image: parts[2].to_string(),
+                status: parts[3].to_string(),
+                function: parts[4].to_string(),
+                session: parts[5].to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Forcefully remove a container.
+pub async fn kill_container(id: &str) -> anyhow::Result<()> {
+    let runtime = get_container_runtime();
+    let args = match super::backend::get() {
+        Some(ContainerBackend::K8s) => vec!["delete", "pod", "--wait=false", id],
+        _ => vec!["rm", "-f", id],
+    };
+    let output = Command::new(&runtime)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", runtime, e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "failed to remove container {}: {}",
+            id,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Remove every robopages container that is no longer tracked by a live process, returns how many were removed.
+pub async fn remove_orphans() -> anyhow::Result<usize> {
+    let mut removed = 0;
+    for container in list_containers().await? {
+        if container.is_orphan() {
+            log::info!(
+                "removing orphaned container {} ({}) for function {}",
+                &container.name,
+                &container.status,
+                &container.function
+            );
+            match kill_container(&container.id).await {
+                Ok(_) => removed += 1,
+                Err(e) => log::error!("{}", e),
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// List the images built by robopages that are no longer tagged, because they were rebuilt.
+pub async fn list_dangling_images() -> anyhow::Result<Vec<String>> {
+    if matches!(
+        super::backend::get(),
+        Some(ContainerBackend::None) | Some(ContainerBackend::K8s)
+    ) {
+        // images are built for the local engine only
+        return Ok(Vec::new());
+    }
+
+    let runtime = get_container_runtime();
+    let output = Command::new(&runtime)
+        .args([
+            "images",
+            "-q",
+            "--no-trunc",
+            "--filter",
+            "dangling=true",
+            "--filter",
+            &format!("label={}", BUILD_LABEL),
+        ])
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", runtime, e))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "failed to list images: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect())
+}
+
+/// Remove the dangling images built by robopages, returns how many were removed.
+pub async fn remove_dangling_images() -> anyhow::Result<usize> {
+    let runtime = get_container_runtime();
+    let mut removed = 0;
+    for id in list_dangling_images().await? {
+        log::info!("removing dangling image {}", &id);
+        let output = Command::new(&runtime)
+            .args(["rmi", &id])
+            .output()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to run {}: {}", runtime, e))?;
+
+        if output.status.success() {
+            removed += 1;
+        } else {
+            // still used by a container or by another image
+            log::error!(
+                "failed to remove image {}: {}",
+                &id,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+    Ok(removed)
+}
+
+/// Get the container runtime command from the container backend, environment or default to "docker"
 pub fn get_container_runtime() -> String {
-    env::var("ROBOPAGES_CONTAINER_RUNTIME").unwrap_or_else(|_| "docker".to_string())
+    match super::backend::get() {
+        Some(backend) if backend != ContainerBackend::None => backend.command().to_string(),
+        _ => env::var("ROBOPAGES_CONTAINER_RUNTIME").unwrap_or_else(|_| "docker".to_string()),
+    }
+}
+
+/// Get the OS of the containers when not explicitly set by the platform, from environment or default to "linux"
+pub fn get_container_os() -> String {
+    env::var("ROBOPAGES_CONTAINER_OS").unwrap_or_else(|_| "linux".to_string())
+}
+
+/// Returns true if containers for the given platform run Windows.
+pub fn is_windows_platform(platform: Option<&str>) -> bool {
+    match platform {
+        Some(platform) => platform.to_lowercase().starts_with("windows"),
+        None => cfg!(windows) && get_container_os().eq_ignore_ascii_case("windows"),
+    }
+}
+
+// normalize the architecture names used by rust and docker
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" | "x86-64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" | "i386" | "i686" => "386",
+        arch => arch,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Returns the architecture of this host as named in container platforms.
+pub fn host_arch() -> &'static str {
+    normalize_arch(std::env::consts::ARCH)
+}
+
+/// Returns true if containers for the given platform (os/arch[/variant]) run emulated on this host.
+pub fn is_emulated_platform(platform: &str) -> bool {
+    platform
+        .split('/')
+        .nth(1)
+        .is_some_and(|arch| normalize_arch(&arch.to_lowercase()) != host_arch())
+}
+
+// split a path from the rest of a volume specification, taking care of drive letters
+fn split_path(s: &str) -> (&str, Option<&str>) {
+    let bytes = s.as_bytes();
+    let skip = if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        2
+    } else {
+        0
+    };
+
+    match s[skip..].find(':') {
+        Some(idx) => (&s[..skip + idx], Some(&s[skip + idx + 1..])),
+        None => (s, None),
+    }
+}
+
+/// A parsed host:guest[:options] volume specification.
+#[derive(Debug, PartialEq)]
+pub struct Volume<'a> {
+    pub host: &'a str,
+    pub guest: Option<&'a str>,
+    pub options: Option<&'a str>,
+}
+
+impl<'a> Volume<'a> {
+    pub fn parse(volume: &'a str) -> Self {
+        let (host, rest) = split_path(volume);
+        let (guest, options) = match rest {
+            Some(rest) => {
+                let (guest, options) = split_path(rest);
+                (Some(guest), options)
+            }
+            None => (None, None),
+        };
+
+        Self {
+            host,
+            guest,
+            options,
+        }
+    }
+
+    /// Returns the guest path, or the host path if not specified.
+    pub fn guest_or_host(&self) -> &'a str {
+        self.guest.unwrap_or(self.host)
+    }
+
+    /// Translate the volume for the host we're running on and the containers OS.
+    pub fn translate(&self, windows_guest: bool) -> String {
+        let host = if cfg!(windows) {
+            self.host.replace('/', "\\")
+        } else {
+            self.host.to_string()
+        };
+
+        let mut translated = host;
+        if let Some(guest) = self.guest {
+            translated.push(':');
+            translated.push_str(&translate_guest_path(guest, windows_guest));
+        }
+        if let Some(options) = self.options {
+            translated.push(':');
+            translated.push_str(options);
+        }
+
+        translated
+    }
+}
+
+/// Translate an absolute unix style path to a Windows one for Windows containers.
+pub fn translate_guest_path(path: &str, windows_guest: bool) -> String {
+    if windows_guest && path.starts_with('/') {
+        format!("C:{}", path.replace('/', "\\"))
+    } else {
+        path.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ContainerSource {
     #[serde(rename = "image")]
     Image(String),
     #[serde(rename = "build")]
-    Build { name: String, path: String },
+    Build(ImageBuild),
+}
+
+/// Describes how to build an image from a Dockerfile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageBuild {
+    pub name: String,
+    /// Path of the Dockerfile.
+    pub path: String,
+    /// Build context directory, defaults to the Dockerfile parent directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// Stage to build for multi-stage Dockerfiles.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_args: Option<BTreeMap<String, String>>,
+    /// Do not use the build cache and always rebuild.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub no_cache: bool,
+    /// Always attempt to pull newer versions of the base images.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub pull: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !(*b)
+}
+
+impl ImageBuild {
+    pub fn dockerfile(&self) -> anyhow::Result<PathBuf> {
+        let dockerfile = PathBuf::from(&self.path);
+        if !dockerfile.exists() {
+            return Err(anyhow::anyhow!(
+                "dockerfile '{}' does not exist",
+                &self.path
+            ));
+        } else if !dockerfile.is_file() {
+            return Err(anyhow::anyhow!("path '{}' is not a dockerfile", &self.path));
+        }
+        Ok(dockerfile)
+    }
+
+    pub fn context(&self) -> PathBuf {
+        match &self.context {
+            Some(context) => PathBuf::from(context),
+            None => Path::new(&self.path)
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+                .to_path_buf(),
+        }
+    }
 }
 
 impl ContainerSource {
     pub async fn resolve(&self, platform: Option<String>) -> anyhow::Result<()> {
+        // images are pulled by the cluster
+        if super::backend::get() == Some(ContainerBackend::K8s) {
+            return Ok(());
+        }
+
+        super::progress::emit(super::progress::Event::ResolvingContainer {
+            image: self.image(),
+        });
+
+        let key = format!(
+            "{}@{}",
+            self.image(),
+            platform.as_deref().unwrap_or_default()
+        );
+        let resolved = RESOLVED.lock().unwrap().entry(key).or_default().clone();
+
+        // concurrent callers wait for the first one, failures are retried by the next caller
+        resolved
+            .get_or_try_init(|| self.resolve_uncached(platform))
+            .await
+            .map(|_| ())
+    }
+
+    async fn resolve_uncached(&self, platform: Option<String>) -> anyhow::Result<()> {
+        if let Some(platform) = platform.as_deref().filter(|p| is_emulated_platform(p)) {
+            log::warn!(
+                "{} will run emulated as {} on this {} host, expect it to be slower",
+                self.image(),
+                platform,
+                host_arch()
+            );
+        }
+
         match self {
-            Self::Image(image) => pull_image(image, platform).await,
-            Self::Build { name, path } => build_image(name, path).await,
+            Self::Image(image) => {
+                #[cfg(feature = "bollard")]
+                if let Some(docker) = super::engine::get().await {
+                    return super::engine::pull_image(docker, image, platform).await;
+                }
+
+                pull_image(image, platform).await
+            }
+            Self::Build(build) => resolve_build(build).await,
         }
     }
 
     pub fn image(&self) -> &str {
         match self {
             Self::Image(image) => image,
-            Self::Build { name, .. } => name,
+            Self::Build(build) => &build.name,
         }
     }
 }
@@ -45,7 +658,8 @@ async fn run_command(command: &str, args: &[&str]) -> anyhow::Result<()> {
         .args(args)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn()?;
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", command, e))?;
 
     let stdout = child.stdout.take().expect("failed to capture stdout");
     let stderr = child.stderr.take().expect("failed to capture stderr");
@@ -74,50 +688,415 @@ async fn run_command(command: &str, args: &[&str]) -> anyhow::Result<()> {
     if status.success() {
         Ok(())
     } else {
-        Err(anyhow::anyhow!("command failed with status: {:?}", status))
+        Err(anyhow::anyhow!(
+            "'{} {}' failed with status: {:?}",
+            command,
+            args.join(" "),
+            status
+        ))
+    }
+}
+
+/// Returns true if the image is available locally.
+pub(crate) async fn image_exists(image: &str) -> anyhow::Result<bool> {
+    let runtime = get_container_runtime();
+    let status = Command::new(&runtime)
+        .args(["image", "inspect", image])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", runtime, e))?;
+
+    Ok(status.success())
+}
+
+/// Returns the repository digest of a local image, or its id if it was not pulled from a registry.
+pub(crate) async fn image_digest(image: &str) -> anyhow::Result<String> {
+    let runtime = get_container_runtime();
+    let output = Command::new(&runtime)
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{if .RepoDigests}}{{index .RepoDigests 0}}{{else}}{{.Id}}{{end}}",
+            image,
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", runtime, e))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("failed to inspect image {}", image));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Returns the digest the image reference is pinned to, as in name@sha256:...
+pub(crate) fn pinned_digest(image: &str) -> anyhow::Result<&str> {
+    let digest = image
+        .rsplit_once('@')
+        .map(|(_, digest)| digest)
+        .ok_or_else(|| anyhow::anyhow!("image {} is not pinned to a digest", image))?;
+
+    match digest.strip_prefix("sha256:") {
+        Some(hash) if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) => Ok(digest),
+        _ => Err(anyhow::anyhow!(
+            "image {} is pinned to an invalid digest {}",
+            image,
+            digest
+        )),
     }
 }
 
+/// Returns the repository of the image reference, without its tag or digest.
+fn repository(image: &str) -> &str {
+    let image = image.split_once('@').map_or(image, |(name, _)| name);
+    // a colon before the last slash is the port of the registry
+    match image.rfind(':') {
+        Some(colon) if !image[colon..].contains('/') => &image[..colon],
+        _ => image,
+    }
+}
+
+// docker.io/library/nmap, library/nmap and nmap are the same repository
+fn normalized_repository(repository: &str) -> &str {
+    let repository = repository.strip_prefix("docker.io/").unwrap_or(repository);
+    repository.strip_prefix("library/").unwrap_or(repository)
+}
+
+/// Returns the reference of the local image pinned to the digest it was pulled at, as in
+/// name@sha256:...
+pub(crate) async fn repo_digest(image: &str) -> anyhow::Result<String> {
+    if pinned_digest(image).is_ok() {
+        return Ok(image.to_string());
+    }
+
+    // pulled by the cluster, the digest can't be known in advance
+    if super::backend::get() == Some(ContainerBackend::K8s) {
+        return Err(anyhow::anyhow!(
+            "image {} must be pinned to a digest to be verified with the k8s backend",
+            image
+        ));
+    }
+
+    let runtime = get_container_runtime();
+    let output = Command::new(&runtime)
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{json .RepoDigests}}",
+            image,
+        ])
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", runtime, e))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "failed to inspect image {}: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let repo_digests: Option<Vec<String>> =
+        serde_json::from_slice(&output.stdout).unwrap_or_default();
+    let repository = repository(image);
+    repo_digests
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|repo_digest| repo_digest.split_once('@'))
+        .find(|(name, _)| normalized_repository(name) == normalized_repository(repository))
+        .map(|(_, digest)| format!("{}@{}", repository, digest))
+        .ok_or_else(|| anyhow::anyhow!("image {} was not pulled from {}", image, repository))
+}
+
 pub(crate) async fn pull_image(image: &str, platform: Option<String>) -> anyhow::Result<()> {
+    if image_exists(image).await? {
+        log::debug!("image '{}' already present", image);
+        return Ok(());
+    }
+
     let runtime = get_container_runtime();
-    run_command(
-        "sh",
-        &[
-            "-c",
-            &format!(
-                "{runtime} images -q '{image}' | grep -q . || {runtime} pull {}'{image}'",
-                if let Some(platform) = platform {
-                    format!("--platform '{}' ", platform)
-                } else {
-                    "".to_string()
-                }
-            ),
-        ],
-    )
-    .await
+    let mut args = vec!["pull"];
+    if let Some(platform) = platform.as_ref() {
+        args.extend(["--platform", platform]);
+    }
+    args.push(image);
+
+    log::info!("pulling image '{}' ...", image);
+    super::progress::emit(super::progress::Event::PullingImage { image });
+
+    run_command(&runtime, &args).await
+}
+
+async fn resolve_build(build: &ImageBuild) -> anyhow::Result<()> {
+    let fingerprint = builds::fingerprint(build)?;
+
+    // skip the build if nothing changed since the last successful one and the image is still there
+    if !build.no_cache
+        && !build.pull
+        && BuildState::load().is_up_to_date(&build.name, &fingerprint)
+        && image_exists(&build.name).await.unwrap_or(false)
+    {
+        log::debug!("image '{}' is up to date", &build.name);
+        return Ok(());
+    }
+
+    let started = std::time::Instant::now();
+    super::progress::emit(super::progress::Event::BuildingImage { image: &build.name });
+
+    #[cfg(feature = "bollard")]
+    let result = if let Some(docker) = super::engine::get().await {
+        super::engine::build_image(docker, build).await
+    } else {
+        build_image(build).await
+    };
+    #[cfg(not(feature = "bollard"))]
+    let result = build_image(build).await;
+
+    match &result {
+        Ok(_) => {
+            log::info!("image '{}' built in {:?}", &build.name, started.elapsed());
+            if let Err(e) = BuildState::record(&build.name, &fingerprint) {
+                log::warn!("could not save build state: {}", e);
+            }
+        }
+        Err(e) => log::error!(
+            "building image '{}' failed after {:?}: {}",
+            &build.name,
+            started.elapsed(),
+            e
+        ),
+    }
+
+    result
 }
 
-pub(crate) async fn build_image(name: &str, path: &str) -> anyhow::Result<()> {
+pub(crate) async fn build_image(build: &ImageBuild) -> anyhow::Result<()> {
     let runtime = get_container_runtime();
-    let dockerfile = PathBuf::from(path);
-    if !dockerfile.exists() {
-        return Err(anyhow::anyhow!("dockerfile '{}' does not exist", path));
-    } else if !dockerfile.is_file() {
-        return Err(anyhow::anyhow!("path '{}' is not a dockerfile", path));
+    let dockerfile = build.dockerfile()?;
+    let context = build.context();
+    if !context.is_dir() {
+        return Err(anyhow::anyhow!(
+            "build context '{}' is not a directory",
+            context.display()
+        ));
     }
 
-    log::info!("building image '{}' from '{}'", name, dockerfile.display());
+    log::info!(
+        "building image '{}' from '{}'",
+        &build.name,
+        dockerfile.display()
+    );
+
+    let dockerfile_path = dockerfile.display().to_string();
+    let context = context.display().to_string();
+
+    let mut args = vec![
+        "build".to_string(),
+        "-f".to_string(),
+        dockerfile_path,
+        "-t".to_string(),
+        build.name.clone(),
+        "--quiet".to_string(),
+        "--label".to_string(),
+        format!("{}={}", BUILD_LABEL, &build.name),
+    ];
+    if build.no_cache {
+        args.push("--no-cache".to_string());
+    }
+    if build.pull {
+        args.push("--pull".to_string());
+    }
+    if let Some(target) = &build.target {
+        args.push("--target".to_string());
+        args.push(target.clone());
+    }
+    if let Some(build_args) = &build.build_args {
+        for (key, value) in build_args {
+            args.push("--build-arg".to_string());
+            args.push(format!("{}={}", key, value));
+        }
+    }
+    args.push(context);
 
     run_command(
-        "sh",
-        &[
-            "-c",
-            &format!(
-                "{runtime} build -f '{}' -t '{name}' --quiet '{}'",
-                dockerfile.display(),
-                dockerfile.parent().unwrap_or(Path::new(".")).display(),
-            ),
-        ],
+        &runtime,
+        &args.iter().map(|s| s.as_str()).collect::<Vec<_>>(),
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container_info(name: &str, session: &str) -> ContainerInfo {
+        ContainerInfo {
+            id: "id".to_string(),
+            name: name.to_string(),
+            image: "image".to_string(),
+            status: "Up".to_string(),
+            function: "function".to_string(),
+            session: session.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_pinned_digest() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        assert_eq!(pinned_digest(&format!("nmap@{}", digest)).unwrap(), digest);
+        assert_eq!(
+            pinned_digest(&format!("ghcr.io/org/nmap:7.95@{}", digest)).unwrap(),
+            digest
+        );
+        assert!(pinned_digest("nmap:7.95").is_err());
+        assert!(pinned_digest("nmap@sha256:1234").is_err());
+        assert!(pinned_digest(&format!("nmap@md5:{}", "a".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn test_tracked_container_is_not_orphan() {
+        let tracked = TrackedContainer::new();
+        assert!(!container_info(&tracked.name, session()).is_orphan());
+
+        let name = tracked.name.clone();
+        drop(tracked);
+        assert!(container_info(&name, session()).is_orphan());
+    }
+
+    #[test]
+    fn test_volume_parse() {
+        assert_eq!(
+            Volume::parse("/host:/guest:ro"),
+            Volume {
+                host: "/host",
+                guest: Some("/guest"),
+                options: Some("ro")
+            }
+        );
+        assert_eq!(
+            Volume::parse("C:\\data:/data"),
+            Volume {
+                host: "C:\\data",
+                guest: Some("/data"),
+                options: None
+            }
+        );
+        assert_eq!(
+            Volume::parse("C:\\data:C:\\data:ro"),
+            Volume {
+                host: "C:\\data",
+                guest: Some("C:\\data"),
+                options: Some("ro")
+            }
+        );
+        assert_eq!(Volume::parse("/data").guest_or_host(), "/data");
+    }
+
+    #[test]
+    fn test_volume_translate_for_windows_guest() {
+        let volume = Volume::parse("/host:/guest/dir:ro");
+        assert_eq!(volume.translate(false), "/host:/guest/dir:ro");
+        assert!(volume.translate(true).ends_with(":C:\\guest\\dir:ro"));
+    }
+
+    #[test]
+    fn test_build_context() {
+        let mut build = ImageBuild {
+            name: "test".to_string(),
+            path: "/tmp/tools/Dockerfile".to_string(),
+            context: None,
+            target: None,
+            build_args: None,
+            no_cache: false,
+            pull: false,
+        };
+        assert_eq!(build.context(), PathBuf::from("/tmp/tools"));
+
+        build.path = "Dockerfile".to_string();
+        assert_eq!(build.context(), PathBuf::from("."));
+
+        build.context = Some("/src".to_string());
+        assert_eq!(build.context(), PathBuf::from("/src"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_failure_is_retried() {
+        let source = ContainerSource::Build(ImageBuild {
+            name: "robopages-test-missing".to_string(),
+            path: "/nonexistent/Dockerfile".to_string(),
+            context: None,
+            target: None,
+            build_args: None,
+            no_cache: false,
+            pull: false,
+        });
+
+        assert!(source.resolve(None).await.is_err());
+        // not cached as resolved
+        assert!(source.resolve(None).await.is_err());
+    }
+
+    #[test]
+    fn test_is_emulated_platform() {
+        let native = format!("linux/{}", host_arch());
+        assert!(!is_emulated_platform(&native));
+        assert!(!is_emulated_platform("linux"));
+
+        let foreign = if host_arch() == "amd64" {
+            "linux/arm64/v8"
+        } else {
+            "linux/amd64"
+        };
+        assert!(is_emulated_platform(foreign));
+        assert_eq!(normalize_arch("aarch64"), "arm64");
+        assert_eq!(normalize_arch("x86_64"), "amd64");
+    }
+
+    #[test]
+    fn test_is_windows_platform() {
+        assert!(is_windows_platform(Some("windows/amd64")));
+        assert!(!is_windows_platform(Some("linux/arm64")));
+    }
+
+    #[test]
+    fn test_repository() {
+        assert_eq!(repository("nmap"), "nmap");
+        assert_eq!(repository("nmap:7.95"), "nmap");
+        assert_eq!(repository("ghcr.io/org/tool:1.0"), "ghcr.io/org/tool");
+        assert_eq!(
+            repository("registry:5000/tool@sha256:0123"),
+            "registry:5000/tool"
+        );
+        assert_eq!(repository("registry:5000/tool"), "registry:5000/tool");
+        assert_eq!(
+            normalized_repository("docker.io/library/nmap"),
+            normalized_repository("nmap")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restarted_session_is_orphan() {
+        let namespace = PID_NAMESPACE.as_deref().unwrap();
+        let old_session = format!("{}-0-{}", std::process::id(), namespace);
+        assert!(container_info("robopages-old", &old_session).is_orphan());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unknown_session_is_not_orphan() {
+        // instances in other pid namespaces, or of older versions, can't be checked
+        let other = format!("{}-0-000000000000", std::process::id());
+        assert!(!container_info("robopages-other", &other).is_orphan());
+        let legacy = format!("{}-0", std::process::id());
+        assert!(!container_info("robopages-legacy", &legacy).is_orphan());
+        assert!(!container_info("robopages-invalid", "invalid").is_orphan());
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+
+use bollard::{
+    container::{
+        Config, CreateContainerOptions, LogOutput, LogsOptions, RemoveContainerOptions,
+        WaitContainerOptions,
+    },
+    errors::Error,
+    image::{BuildImageOptions, CreateImageOptions},
+    secret::HostConfig,
+    Docker,
+};
+use futures::StreamExt;
+use tokio::sync::OnceCell;
+
+use crate::book::Container;
+
+use super::{
+    docker::{get_container_runtime, session, ImageBuild, TrackedContainer, Volume, BUILD_LABEL},
+    CommandLine, FUNCTION_LABEL, SESSION_LABEL,
+};
+
+static ENGINE: OnceCell<Option<Docker>> = OnceCell::const_new();
+
+/// Returns a connection to the Docker Engine API if available, or None to fall back to the CLI.
+pub(crate) async fn get() -> Option<&'static Docker> {
+    ENGINE
+        .get_or_init(|| async {
+            // other runtimes are driven via their CLI
+            if get_container_runtime() != "docker" {
+                return None;
+            }
+
+            let docker = match Docker::connect_with_local_defaults() {
+                Ok(docker) => docker,
+                Err(e) => {
+                    log::debug!("docker engine api not available: {}", e);
+                    return None;
+                }
+            };
+
+            match docker.ping().await {
+                Ok(_) => {
+                    log::debug!("using docker engine api");
+                    Some(docker)
+                }
+                Err(e) => {
+                    log::debug!("docker engine api not reachable: {}", e);
+                    None
+                }
+            }
+        })
+        .await
+        .as_ref()
+}
+
+/// Returns true if the container can be executed through the Docker Engine API.
+pub(crate) async fn can_run(container: &Container) -> bool {
+    // raw docker cli arguments and sandbox limits can't be mapped to the api
+    container.args.is_none()
+        && super::sandbox::container_options().is_empty()
+        && get().await.is_some()
+}
+
+pub(crate) async fn pull_image(
+    docker: &Docker,
+    image: &str,
+    platform: Option<String>,
+) -> anyhow::Result<()> {
+    if docker.inspect_image(image).await.is_ok() {
+        log::debug!("image '{}' already present", image);
+        return Ok(());
+    }
+
+    log::info!("pulling image '{}' ...", image);
+    super::progress::emit(super::progress::Event::PullingImage { image });
+
+    let options = CreateImageOptions {
+        from_image: image.to_string(),
+        platform: platform.unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let mut stream = docker.create_image(Some(options), None, None);
+    while let Some(info) = stream.next().await {
+        let info = info.map_err(|e| anyhow::anyhow!("failed to pull image '{}': {}", image, e))?;
+        log::info!(
+            "{}{}{}",
+            info.id.map(|id| format!("{}: ", id)).unwrap_or_default(),
+            info.status.unwrap_or_default(),
+            info.progress.map(|p| format!(" {}", p)).unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn build_image(docker: &Docker, build: &ImageBuild) -> anyhow::Result<()> {
+    let dockerfile = build.dockerfile()?;
+    let context = build.context();
+
+    log::info!(
+        "building image '{}' from '{}'",
+        &build.name,
+        dockerfile.display()
+    );
+
+    // the api expects the build context as a tar archive
+    let mut archive = tar::Builder::new(Vec::new());
+    archive.append_dir_all(".", &context)?;
+
+    // the dockerfile must be part of the archive, add it if it lives outside of the context
+    let dockerfile_name = match dockerfile
+        .canonicalize()?
+        .strip_prefix(context.canonicalize()?)
+    {
+        Ok(relative) => relative.to_string_lossy().to_string(),
+        Err(_) => {
+            let name = ".robopages.Dockerfile".to_string();
+            archive.append_path_with_name(&dockerfile, &name)?;
+            name
+        }
+    };
+    let archive = archive.into_inner()?;
+
+    let options = BuildImageOptions {
+        dockerfile: dockerfile_name,
+        t: build.name.clone(),
+        target: build.target.clone().unwrap_or_default(),
+        buildargs: build
+            .build_args
+            .clone()
+            .map(|args| args.into_iter().collect())
+            .unwrap_or_default(),
+        nocache: build.no_cache,
+        pull: build.pull,
+        rm: true,
+        labels: HashMap::from([(BUILD_LABEL.to_string(), build.name.clone())]),
+        ..Default::default()
+    };
+
+    let name = &build.name;
+    let mut stream = docker.build_image(options, None, Some(archive.into()));
+    while let Some(info) = stream.next().await {
+        let info = info.map_err(|e| anyhow::anyhow!("failed to build image '{}': {}", name, e))?;
+        if let Some(error) = info.error {
+            return Err(anyhow::anyhow!(
+                "failed to build image '{}': {}",
+                name,
+                error
+            ));
+        }
+        if let Some(line) = info.stream {
+            let line = line.trim();
+            if !line.is_empty() {
+                log::info!("{}", line);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the command line inside the container and return its output.
+pub(crate) async fn run(
+    container: &Container,
+    function_name: &str,
+    cmdline: CommandLine,
+) -> anyhow::Result<String> {
+    let docker = get()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("docker engine api not available"))?;
+
+    let windows = container.is_windows();
+    let mut cmdline = cmdline;
+    let mounts = container.mount_paths(&mut cmdline)?;
+    let tracked = TrackedContainer::new();
+
+    let mut labels = HashMap::new();
+    labels.insert(FUNCTION_LABEL.to_string(), function_name.to_string());
+    labels.insert(SESSION_LABEL.to_string(), session().to_string());
+    if let Some(extra) = &container.labels {
+        labels.extend(extra.clone());
+    }
+
+    let mut cmd = vec![];
+    if container.preserve_app {
+        cmd.push(cmdline.app.clone());
+    }
+    cmd.extend(cmdline.args.clone());
+
+    let config = Config {
+        image: Some(container.run_image()?),
+        cmd: Some(cmd),
+        env: Some(
+            cmdline
+                .env
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect(),
+        ),
+        labels: Some(labels),
+        working_dir: container
+            .workdir
+            .as_ref()
+            .map(|workdir| super::translate_guest_path(workdir, windows)),
+        user: container.user.clone(),
+        host_config: Some(HostConfig {
+            binds: Some(
+                container
+                    .volumes
+                    .iter()
+                    .flatten()
+                    .map(|volume| Volume::parse(volume).translate(windows))
+                    .chain(mounts)
+                    .collect(),
+            ),
+            readonly_rootfs: Some(container.read_only),
+            tmpfs: container
+                .read_only
+                .then(|| HashMap::from([("/tmp".to_string(), String::new())])),
+            cap_drop: container.cap_drop.clone(),
+            cap_add: container.cap_add.clone(),
+            security_opt: container.security_opt.clone(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let options = CreateContainerOptions {
+        name: tracked.name.clone(),
+        platform: container.platform.clone(),
+    };
+
+    docker
+        .create_container(Some(options), config)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to create container: {}", e))?;
+
+    let guard = tracked.cancel_guard();
+    let result = run_created(docker, &tracked.name).await;
+    guard.disarm();
+
+    // always cleanup, even if something went wrong
+    if let Err(e) = docker
+        .remove_container(
+            &tracked.name,
+            Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            }),
+        )
+        .await
+    {
+        log::error!("failed to remove container {}: {}", &tracked.name, e);
+    }
+
+    result
+}
+
+async fn run_created(docker: &Docker, name: &str) -> anyhow::Result<String> {
+    docker
+        .start_container::<String>(name, None)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to start container: {}", e))?;
+
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+
+    // stream the logs as they are produced, until the container exits
+    let mut logs = docker.logs(
+        name,
+        Some(LogsOptions::<String> {
+            follow: true,
+            stdout: true,
+            stderr: true,
+            ..Default::default()
+        }),
+    );
+    while let Some(output) = logs.next().await {
+        match output? {
+            LogOutput::StdErr { message } => {
+                let message = String::from_utf8_lossy(&message);
+                log::debug!("[{}] {}", name, message.trim_end());
+                stderr.push_str(&message);
+            }
+            LogOutput::StdOut { message } | LogOutput::Console { message } => {
+                let message = String::from_utf8_lossy(&message);
+                log::debug!("[{}] {}", name, message.trim_end());
+                stdout.push_str(&message);
+            }
+            LogOutput::StdIn { .. } => {}
+        }
+    }
+
+    let mut exit_code = 0;
+    let mut wait = docker.wait_container(name, None::<WaitContainerOptions<String>>);
+    while let Some(result) = wait.next().await {
+        match result {
+            Ok(response) => exit_code = response.status_code,
+            Err(Error::DockerContainerWaitError { code, .. }) => exit_code = code,
+            Err(e) => return Err(anyhow::anyhow!("failed to wait for container: {}", e)),
+        }
+    }
+
+    let mut parts = vec![];
+
+    if exit_code != 0 {
+        log::warn!("command failed with exit code: {}", exit_code);
+        parts.push(format!("{}{}", super::EXIT_CODE_PREFIX, exit_code));
+    }
+
+    if !stdout.is_empty() {
+        parts.push(stdout);
+    }
+
+    if !stderr.is_empty() {
+        if exit_code == 0 {
+            parts.push(stderr);
+        } else {
+            parts.push(format!("ERROR: {}", stderr));
+        }
+    }
+
+    Ok(parts.join("\n"))
+}
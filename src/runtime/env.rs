@@ -0,0 +1,30 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+// variables set with -E, taking precedence over the ones of this process
+static OVERRIDES: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+/// Set the variables used to resolve ${env.X} and passed to the commands executed on this host.
+pub(crate) fn set(vars: Vec<(String, String)>) {
+    if !vars.is_empty() {
+        log::debug!(
+            "{} environment variables set from the command line",
+            vars.len()
+        );
+    }
+    if OVERRIDES.set(vars.into_iter().collect()).is_err() {
+        log::warn!("environment variables already set");
+    }
+}
+
+pub(crate) fn overrides() -> &'static BTreeMap<String, String> {
+    static EMPTY: BTreeMap<String, String> = BTreeMap::new();
+    OVERRIDES.get().unwrap_or(&EMPTY)
+}
+
+/// Returns the value of a variable, from the ones set with -E or the environment of this process.
+pub(crate) fn var(name: &str) -> Option<String> {
+    overrides()
+        .get(name)
+        .cloned()
+        .or_else(|| std::env::var(name).ok())
+}
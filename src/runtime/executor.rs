@@ -0,0 +1,206 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use crate::book::{Container, Files};
+
+use super::{cmd, ssh::SSHConnection, CommandLine};
+
+static REGISTRY: OnceLock<RwLock<BTreeMap<String, Arc<dyn Executor>>>> = OnceLock::new();
+
+/// Name of the executor running the command lines on this host.
+pub(crate) const HOST: &str = "host";
+
+/// A command line resolved for the arguments of a call.
+pub(crate) struct Job<'a> {
+    pub function: &'a str,
+    pub command_line: CommandLine,
+    /// Container of the function, if it runs in one.
+    pub container: Option<&'a Container>,
+    /// Files to stage around the execution, for the executors supporting it.
+    pub files: &'a Files,
+}
+
+/// The content returned to the model and, if the process ran on this host, its streams.
+pub(crate) struct Executed {
+    pub content: String,
+    pub output: Option<cmd::Output>,
+}
+
+/// Runs the command lines of the calls. Functions select a registered executor by name with
+/// `executor`, otherwise the host, container or ssh one is picked for each call.
+pub(crate) trait Executor: Send + Sync {
+    fn execute<'a>(&'a self, job: Job<'a>) -> BoxFuture<'a, anyhow::Result<Executed>>;
+}
+
+/// Runs the command line as a process of this host.
+pub(crate) struct Host;
+
+impl Executor for Host {
+    fn execute<'a>(&'a self, job: Job<'a>) -> BoxFuture<'a, anyhow::Result<Executed>> {
+        Box::pin(async move {
+            let output = job.command_line.output().await?;
+            Ok(Executed {
+                content: output.content(),
+                output: Some(output),
+            })
+        })
+    }
+}
+
+/// Runs the command line in a container created with the docker engine api.
+pub(crate) struct Engine;
+
+impl Executor for Engine {
+    fn execute<'a>(&'a self, job: Job<'a>) -> BoxFuture<'a, anyhow::Result<Executed>> {
+        Box::pin(async move {
+            let container = job.container.ok_or_else(|| {
+                anyhow::anyhow!("container required for function {}", job.function)
+            })?;
+            Ok(Executed {
+                content: super::execute_with_engine(container, job.function, job.command_line)
+                    .await?,
+                output: None,
+            })
+        })
+    }
+}
+
+/// Runs the command line on an ssh host, staging the files around it.
+pub(crate) struct Ssh(pub SSHConnection);
+
+impl Executor for Ssh {
+    fn execute<'a>(&'a self, job: Job<'a>) -> BoxFuture<'a, anyhow::Result<Executed>> {
+        Box::pin(async move {
+            let command_line = job.command_line;
+            Ok(Executed {
+                content: self
+                    .0
+                    .execute(
+                        command_line.sudo,
+                        &command_line.app,
+                        &command_line.args,
+                        job.files,
+                    )
+                    .await?,
+                output: None,
+            })
+        })
+    }
+}
+
+/// Runs the command line through a command, such as the agent of a VM, configured in the
+/// executors of the settings.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct CommandExecutor {
+    /// The command and its arguments, followed by the command line of the call.
+    pub command: Vec<String>,
+}
+
+impl CommandExecutor {
+    fn wrap(&self, command_line: CommandLine) -> anyhow::Result<CommandLine> {
+        if command_line.sudo {
+            return Err(anyhow::anyhow!(
+                "commands run with sudo can't be wrapped by {}",
+                self.command.join(" ")
+            ));
+        }
+
+        let mut command = self.command.clone();
+        command.push(command_line.app);
+        command.extend(command_line.args);
+
+        let mut wrapped = CommandLine::from_vec(&command)?;
+        wrapped.env = command_line.env;
+        wrapped.stdin = command_line.stdin;
+        wrapped.secrets = command_line.secrets;
+        wrapped.temp_env_file = command_line.temp_env_file;
+        Ok(wrapped)
+    }
+}
+
+impl Executor for CommandExecutor {
+    fn execute<'a>(&'a self, job: Job<'a>) -> BoxFuture<'a, anyhow::Result<Executed>> {
+        Box::pin(async move {
+            let output = self.wrap(job.command_line)?.output().await?;
+            Ok(Executed {
+                content: output.content(),
+                output: Some(output),
+            })
+        })
+    }
+}
+
+fn registry() -> &'static RwLock<BTreeMap<String, Arc<dyn Executor>>> {
+    REGISTRY.get_or_init(|| {
+        let host: Arc<dyn Executor> = Arc::new(Host);
+        RwLock::new(BTreeMap::from([(HOST.to_string(), host)]))
+    })
+}
+
+/// Make the executor selectable by the functions with its name.
+pub(crate) fn register(name: &str, executor: Arc<dyn Executor>) {
+    log::debug!("registering executor {}", name);
+    if registry()
+        .write()
+        .unwrap()
+        .insert(name.to_string(), executor)
+        .is_some()
+    {
+        log::warn!("executor {} replaced", name);
+    }
+}
+
+/// Register the command executors of the settings.
+pub(crate) fn register_commands(executors: &BTreeMap<String, CommandExecutor>) {
+    for (name, executor) in executors {
+        register(name, Arc::new(executor.clone()));
+    }
+}
+
+/// Returns the executor registered with the name.
+pub(crate) fn get(name: &str) -> anyhow::Result<Arc<dyn Executor>> {
+    registry()
+        .read()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("executor {} is not registered", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_command_executor() {
+        let executor: CommandExecutor =
+            serde_yaml::from_str("command: [env, GREETING=hello]").unwrap();
+        register("test_env", Arc::new(executor));
+
+        let command_line = CommandLine::from_vec(&vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "echo $GREETING".to_string(),
+        ])
+        .unwrap();
+        let executed = get("test_env")
+            .unwrap()
+            .execute(Job {
+                function: "greet",
+                command_line,
+                container: None,
+                files: &Files::default(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(executed.content.trim(), "hello");
+
+        assert!(get(HOST).is_ok());
+        assert!(get("vm").is_err());
+    }
+}
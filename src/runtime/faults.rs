@@ -0,0 +1,275 @@
+use std::{
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+static FAULTS: OnceLock<Faults> = OnceLock::new();
+
+// appended to garbled output, a mix of broken encoding, truncated structures and control codes
+const JUNK: &[char] = &[
+    '\u{fffd}', '\u{0}', '\u{1b}', '{', '}', '[', '"', '<', '/', '\\', '\n', '\t', '#', '~',
+];
+
+/// A failure injected in the calls of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Fault {
+    /// The call hangs for the delay of the rule, then fails without executing the command.
+    Timeout,
+    /// The command is not executed and fails with the exit code and message of the rule.
+    Exit,
+    /// The command is executed, but its output is truncated and corrupted.
+    Garbled,
+}
+
+/// Which functions fail, how and how often.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Rule {
+    /// Names of the functions, a trailing * matches any suffix.
+    pub functions: Vec<String>,
+    /// One of them is picked at random each time a call fails.
+    pub faults: Vec<Fault>,
+    /// Probability for each call to fail, from 0 to 1.
+    #[serde(default = "default_probability")]
+    pub probability: f64,
+    /// Seconds before a timeout is reported.
+    #[serde(default = "default_delay")]
+    pub delay: u64,
+    #[serde(default = "default_exit_code")]
+    pub exit_code: i32,
+    /// Error output of the failed command.
+    #[serde(default = "default_message")]
+    pub message: String,
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+fn default_delay() -> u64 {
+    30
+}
+
+fn default_exit_code() -> i32 {
+    1
+}
+
+fn default_message() -> String {
+    "fault injected by robopages".to_string()
+}
+
+impl Rule {
+    fn matches(&self, function: &str) -> bool {
+        self.functions
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => function.starts_with(prefix),
+                None => function == pattern,
+            })
+    }
+}
+
+/// Failures injected in the calls, to test how agents recover from them.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Faults {
+    /// Seed of the random generator, to inject the same failures in the same calls on every run.
+    #[serde(default)]
+    seed: Option<u64>,
+    rules: Vec<Rule>,
+    #[serde(skip)]
+    rng: Mutex<fastrand::Rng>,
+}
+
+/// The failure drawn for a call.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Injected {
+    Timeout(Duration),
+    Exit(i32, String),
+    Garbled,
+}
+
+impl Faults {
+    pub fn from_path(path: &str) -> anyhow::Result<Self> {
+        let path = shellexpand::full(path)
+            .map_err(|e| anyhow::anyhow!("failed to expand path {}: {}", path, e))?
+            .to_string();
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("error while reading {}: {}", path, e))?;
+
+        Self::from_str(&data).map_err(|e| anyhow::anyhow!("error while parsing {}: {}", path, e))
+    }
+
+    fn from_str(data: &str) -> anyhow::Result<Self> {
+        let faults: Self = serde_yaml::from_str(data)?;
+        for rule in &faults.rules {
+            if rule.functions.is_empty() || rule.faults.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "fault rules must have at least one function and one fault"
+                ));
+            }
+            if !(0.0..=1.0).contains(&rule.probability) {
+                return Err(anyhow::anyhow!(
+                    "probability {} of the faults of {} is not between 0 and 1",
+                    rule.probability,
+                    rule.functions.join(", ")
+                ));
+            }
+        }
+        if let Some(seed) = faults.seed {
+            faults.rng.lock().unwrap().seed(seed);
+        }
+        Ok(faults)
+    }
+
+    /// Returns the failure to inject in a call of the function, if any. The first rule matching
+    /// the function applies.
+    pub fn draw(&self, function: &str) -> Option<Injected> {
+        let rule = self.rules.iter().find(|rule| rule.matches(function))?;
+        let mut rng = self.rng.lock().unwrap();
+        if rng.f64() >= rule.probability {
+            return None;
+        }
+
+        Some(match rule.faults[rng.usize(..rule.faults.len())] {
+            Fault::Timeout => Injected::Timeout(Duration::from_secs(rule.delay)),
+            Fault::Exit => Injected::Exit(rule.exit_code, rule.message.clone()),
+            Fault::Garbled => Injected::Garbled,
+        })
+    }
+
+    fn garble(&self, output: String) -> String {
+        let mut rng = self.rng.lock().unwrap();
+        let kept = rng.usize(..=output.len());
+        let kept = (0..=kept)
+            .rev()
+            .find(|&index| output.is_char_boundary(index))
+            .unwrap_or(0);
+
+        let mut garbled = output[..kept].to_string();
+        for _ in 0..rng.usize(16..64) {
+            garbled.push(JUNK[rng.usize(..JUNK.len())]);
+        }
+        garbled
+    }
+}
+
+impl Injected {
+    /// Fail the call instead of executing it, or returns None if it must be executed.
+    pub async fn replace_execution(&self, function: &str) -> Option<anyhow::Result<String>> {
+        match self {
+            Self::Timeout(delay) => {
+                tokio::time::sleep(*delay).await;
+                Some(Err(anyhow::anyhow!(
+                    "function {} timed out after {}s",
+                    function,
+                    delay.as_secs()
+                )))
+            }
+            Self::Exit(code, message) => Some(Ok(format!(
+                "{}{}\nERROR: {}",
+                super::EXIT_CODE_PREFIX,
+                code,
+                message
+            ))),
+            Self::Garbled => None,
+        }
+    }
+
+    /// Corrupt the output of the executed command if needed.
+    pub fn corrupt(&self, output: String) -> String {
+        match (self, FAULTS.get()) {
+            (Self::Garbled, Some(faults)) => faults.garble(output),
+            _ => output,
+        }
+    }
+}
+
+/// Inject failures in the calls of this process.
+pub(crate) fn set(faults: Faults) {
+    log::warn!(
+        "fault injection enabled with {} rules, calls will fail on purpose",
+        faults.rules.len()
+    );
+    if FAULTS.set(faults).is_err() {
+        log::warn!("fault injection already set");
+    }
+}
+
+/// Returns the failure to inject in a call of the function, if fault injection is enabled.
+pub(crate) fn draw(function: &str) -> Option<Injected> {
+    let injected = FAULTS.get()?.draw(function);
+    if let Some(injected) = &injected {
+        log::warn!("injecting {:?} in the call of {}", injected, function);
+    }
+    injected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+seed: 42
+rules:
+  - functions: [nmap_*]
+    faults: [exit]
+    exit_code: 2
+    message: "Failed to resolve target"
+  - functions: [httpx_probe]
+    faults: [timeout, garbled]
+    probability: 0.5
+    delay: 5
+  - functions: [whois_lookup]
+    faults: [garbled]
+    probability: 0
+"#;
+
+    #[test]
+    fn test_draw() {
+        let faults = Faults::from_str(RULES).unwrap();
+
+        assert_eq!(
+            faults.draw("nmap_tcp_ports"),
+            Some(Injected::Exit(2, "Failed to resolve target".to_string()))
+        );
+        assert_eq!(faults.draw("whois_lookup"), None);
+        assert_eq!(faults.draw("dig_lookup"), None);
+
+        let drawn: Vec<_> = (0..100).map(|_| faults.draw("httpx_probe")).collect();
+        let failed = drawn.iter().filter(|injected| injected.is_some()).count();
+        assert!(failed > 20 && failed < 80);
+        assert!(drawn.contains(&Some(Injected::Timeout(Duration::from_secs(5)))));
+        assert!(drawn.contains(&Some(Injected::Garbled)));
+
+        // the same seed draws the same failures
+        let again = Faults::from_str(RULES).unwrap();
+        again.draw("nmap_tcp_ports");
+        again.draw("whois_lookup");
+        let redrawn: Vec<_> = (0..100).map(|_| again.draw("httpx_probe")).collect();
+        assert_eq!(drawn, redrawn);
+    }
+
+    #[test]
+    fn test_garble() {
+        let faults = Faults::from_str(RULES).unwrap();
+        let output = r#"{"host": "example.com", "status": 200, "title": "Exämple"}"#;
+        for _ in 0..20 {
+            let garbled = faults.garble(output.to_string());
+            assert_ne!(garbled, output);
+            let kept = garbled.trim_end_matches(JUNK);
+            assert!(garbled.len() > kept.len());
+            assert!(output.starts_with(kept));
+        }
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(
+            Faults::from_str("rules: [{functions: [a], faults: [exit], probability: 2}]").is_err()
+        );
+        assert!(Faults::from_str("rules: [{functions: [], faults: [exit]}]").is_err());
+        assert!(Faults::from_str("rules: [{functions: [a], faults: [crash]}]").is_err());
+    }
+}
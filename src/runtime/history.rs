@@ -0,0 +1,382 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
+    io::{BufRead, Write},
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::audit::Provenance;
+
+const HISTORY_DIR: &str = "~/.config/robopages/state/history";
+const EXECUTIONS_PATH: &str = "~/.config/robopages/state/executions.jsonl";
+
+static SETTINGS: OnceLock<HistoryConfig> = OnceLock::new();
+// appends of concurrent executions are never interleaved
+static WRITER: Mutex<()> = Mutex::new(());
+
+/// Settings of the history of the executions.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct HistoryConfig {
+    /// Record the executions of run and serve.
+    pub enabled: bool,
+    /// Outputs are truncated to this many bytes.
+    pub max_output: usize,
+    /// The history is rotated once it reaches this many bytes, only the previous one is kept.
+    pub max_size: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_output: 64 * 1024,
+            max_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// An execution, as recorded in the history.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Record {
+    pub timestamp: String,
+    pub function: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caller: Option<String>,
+    /// The sensitive ones are redacted.
+    pub arguments: BTreeMap<String, String>,
+    pub command: String,
+    pub exit_code: Option<i64>,
+    pub duration_secs: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub provenance: BTreeMap<String, Provenance>,
+}
+
+impl Record {
+    pub fn new(function: &str, command: &str, duration: Duration) -> Self {
+        Self {
+            timestamp: humantime::format_rfc3339_millis(SystemTime::now() - duration).to_string(),
+            function: function.to_string(),
+            call_id: None,
+            caller: None,
+            arguments: BTreeMap::new(),
+            command: command.to_string(),
+            exit_code: None,
+            duration_secs: duration.as_secs_f64(),
+            output: None,
+            error: None,
+            provenance: BTreeMap::new(),
+        }
+    }
+
+    fn started_at(&self) -> Option<SystemTime> {
+        humantime::parse_rfc3339(&self.timestamp).ok()
+    }
+}
+
+/// Which records of the history are returned.
+#[derive(Debug, Default)]
+pub(crate) struct Filter {
+    pub function: Option<String>,
+    /// Only the executions of these functions, such as the ones allowed by a token.
+    pub functions: Option<BTreeSet<String>>,
+    pub since: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+    /// Only the most recent ones.
+    pub limit: Option<usize>,
+}
+
+impl Filter {
+    fn matches(&self, record: &Record) -> bool {
+        if self
+            .function
+            .as_ref()
+            .is_some_and(|function| &record.function != function)
+        {
+            return false;
+        }
+        if self
+            .functions
+            .as_ref()
+            .is_some_and(|functions| !functions.contains(&record.function))
+        {
+            return false;
+        }
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        let Some(started_at) = record.started_at() else {
+            return false;
+        };
+        self.since.is_none_or(|since| started_at >= since)
+            && self.until.is_none_or(|until| started_at <= until)
+    }
+}
+
+/// Parses a time of a filter, either as a date, a date and time in UTC, or a duration ago such as
+/// 2h or 3days.
+pub(crate) fn parse_time(value: &str) -> anyhow::Result<SystemTime> {
+    if let Ok(ago) = humantime::parse_duration(value) {
+        return Ok(SystemTime::now() - ago);
+    }
+    let value = if value.len() == 10 {
+        format!("{} 00:00:00", value)
+    } else {
+        value.to_string()
+    };
+    humantime::parse_rfc3339_weak(&value)
+        .map_err(|e| anyhow::anyhow!("invalid time {}: {}", value, e))
+}
+
+fn executions_path() -> anyhow::Result<PathBuf> {
+    Ok(PathBuf::from(
+        shellexpand::full(EXECUTIONS_PATH)?.to_string(),
+    ))
+}
+
+// the previous history, replaced at each rotation
+fn rotated_path(path: &std::path::Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Record the executions with these settings.
+pub(crate) fn set(config: HistoryConfig) {
+    if SETTINGS.set(config).is_err() {
+        log::warn!("history settings already set");
+    }
+}
+
+/// Append the execution to the history, if enabled.
+pub(crate) fn record(mut record: Record) {
+    let Some(settings) = SETTINGS.get().filter(|settings| settings.enabled) else {
+        return;
+    };
+
+    if let Some(output) = &mut record.output {
+        truncate(output, settings.max_output);
+    }
+
+    if let Err(e) = executions_path().and_then(|path| append(&path, &record, settings.max_size)) {
+        log::warn!(
+            "could not record the execution of {}: {}",
+            record.function,
+            e
+        );
+    }
+}
+
+fn truncate(output: &mut String, max: usize) {
+    if output.len() <= max {
+        return;
+    }
+    let mut end = max;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    output.truncate(end);
+    output.push_str("\n<truncated>");
+}
+
+fn append(path: &std::path::Path, record: &Record, max_size: u64) -> anyhow::Result<()> {
+    let line = serde_json::to_string(record)?;
+    let _guard = WRITER.lock().unwrap();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let size = std::fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+    if size > 0 && size + line.len() as u64 >= max_size {
+        std::fs::rename(path, rotated_path(path))?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(format!("{}\n", line).as_bytes())?;
+    Ok(())
+}
+
+/// Returns the recorded executions matching the filter, from the oldest.
+pub(crate) fn executions(filter: &Filter) -> anyhow::Result<Vec<Record>> {
+    read(&executions_path()?, filter)
+}
+
+fn read(path: &std::path::Path, filter: &Filter) -> anyhow::Result<Vec<Record>> {
+    // only the most recent ones are kept while reading, from the previous history first
+    let mut records = VecDeque::new();
+    for path in [rotated_path(path), path.to_path_buf()] {
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(anyhow::anyhow!("could not read {}: {}", path.display(), e)),
+        };
+
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Record>(&line) {
+                Ok(record) if filter.matches(&record) => {
+                    if filter.limit.is_some_and(|limit| records.len() >= limit) {
+                        records.pop_front();
+                    }
+                    records.push_back(record);
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("ignoring invalid history record: {}", e),
+            }
+        }
+    }
+
+    if filter.limit == Some(0) {
+        records.clear();
+    }
+
+    Ok(records.into())
+}
+
+fn dir() -> anyhow::Result<PathBuf> {
+    Ok(PathBuf::from(shellexpand::full(HISTORY_DIR)?.to_string()))
+}
+
+/// Returns the output of the last run with the same key (see cache::key), if any.
+pub(crate) fn previous_output(key: &str) -> Option<String> {
+    let path = dir().ok()?.join(key);
+    std::fs::read_to_string(path).ok()
+}
+
+pub(crate) fn record_output(key: &str, output: &str) -> anyhow::Result<()> {
+    let dir = dir()?;
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join(key), output)?;
+    Ok(())
+}
+
+/// Returns the lines added (prefixed by +) and removed (prefixed by -) since the previous output,
+/// regardless of their order.
+pub(crate) fn diff(previous: &str, current: &str) -> String {
+    let before: HashSet<&str> = previous.lines().collect();
+    let after: HashSet<&str> = current.lines().collect();
+
+    let added = current
+        .lines()
+        .filter(|line| !before.contains(line))
+        .map(|line| format!("+ {}", line));
+    let removed = previous
+        .lines()
+        .filter(|line| !after.contains(line))
+        .map(|line| format!("- {}", line));
+
+    let changes: Vec<String> = added.chain(removed).collect();
+    if changes.is_empty() {
+        "<no changes since the previous run>".to_string()
+    } else {
+        changes.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let previous = "22/tcp open ssh\n80/tcp open http";
+        let current = "80/tcp open http\n443/tcp open https\n22/tcp open ssh";
+        assert_eq!(diff(previous, current), "+ 443/tcp open https");
+
+        assert_eq!(
+            diff(current, "22/tcp open ssh"),
+            "- 80/tcp open http\n- 443/tcp open https"
+        );
+        assert_eq!(
+            diff(current, current),
+            "<no changes since the previous run>"
+        );
+    }
+
+    #[test]
+    fn test_executions() {
+        let path = std::env::temp_dir().join(format!(
+            "robopages-test-executions-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let mut old = Record::new("nmap_scan", "nmap 10.0.0.1", Duration::from_secs(3 * 3600));
+        old.exit_code = Some(0);
+        let mut scan = Record::new("nmap_scan", "nmap 10.0.0.2", Duration::from_secs(1));
+        scan.provenance = BTreeMap::from([("target".to_string(), Provenance::Model)]);
+        let mut output = "é".repeat(10);
+        truncate(&mut output, 5);
+        scan.output = Some(output);
+        let whois = Record::new("whois", "whois example.com", Duration::from_secs(1));
+        for record in [&old, &scan, &whois] {
+            append(&path, record, u64::MAX).unwrap();
+        }
+
+        assert_eq!(read(&path, &Filter::default()).unwrap().len(), 3);
+
+        let filter = Filter {
+            function: Some("nmap_scan".to_string()),
+            since: Some(parse_time("1h").unwrap()),
+            ..Default::default()
+        };
+        let records = read(&path, &filter).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].command, "nmap 10.0.0.2");
+        assert_eq!(records[0].output.as_deref(), Some("éé\n<truncated>"));
+        assert_eq!(records[0].provenance["target"], Provenance::Model);
+
+        let filter = Filter {
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(read(&path, &filter).unwrap()[0].function, "whois");
+
+        let filter = Filter {
+            functions: Some(BTreeSet::from(["nmap_scan".to_string()])),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let records = read(&path, &filter).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].command, "nmap 10.0.0.2");
+
+        let _ = std::fs::remove_file(&path);
+        assert!(read(&path, &Filter::default()).unwrap().is_empty());
+
+        assert!(parse_time("2024-01-31").is_ok());
+        assert!(parse_time("2024-01-31T10:00:00Z").is_ok());
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("executions.jsonl");
+
+        let record = Record::new("whois", "whois example.com", Duration::from_secs(1));
+        let max_size = serde_json::to_string(&record).unwrap().len() as u64 * 3;
+        for _ in 0..5 {
+            append(&path, &record, max_size).unwrap();
+        }
+
+        // two records fit in each file, the previous history is read as well and the oldest ones
+        // were removed
+        assert!(rotated_path(&path).exists());
+        assert_eq!(read(&path, &Filter::default()).unwrap().len(), 3);
+    }
+}
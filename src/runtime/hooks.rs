@@ -0,0 +1,216 @@
+use std::{sync::OnceLock, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::book::flavors::openai;
+
+use super::CommandLine;
+
+static HOOKS: OnceLock<HooksConfig> = OnceLock::new();
+
+// a hook that doesn't return in time rejects the call
+const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Commands run by the server around each call, configured in the hooks of the serve settings.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct HooksConfig {
+    /// Run before the execution with the call as JSON on stdin, can print a modified call.
+    pub pre_execution: Vec<Hook>,
+    /// Run after the execution with the call and its result as JSON on stdin, can print a
+    /// modified result.
+    pub post_execution: Vec<Hook>,
+}
+
+/// A command or a WASI module, failing rejects the call with its stderr as the reason and
+/// printing nothing keeps the JSON unchanged.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Hook {
+    /// The command and its arguments.
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// A WASI module run with wasmtime instead of a command.
+    #[serde(default)]
+    pub wasm: Option<String>,
+    /// Only run for these functions, or all of them if empty.
+    #[serde(default)]
+    pub functions: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct PostExecution<'a> {
+    call: &'a openai::Call,
+    result: &'a openai::CallResultMessage,
+}
+
+// only the content of the result can be changed, not where it was produced
+#[derive(Deserialize)]
+struct HookResult {
+    content: String,
+}
+
+impl Hook {
+    fn command_line(&self) -> anyhow::Result<Vec<String>> {
+        match (&self.wasm, self.command.is_empty()) {
+            (Some(module), true) => Ok(vec![
+                "wasmtime".to_string(),
+                "run".to_string(),
+                module.clone(),
+            ]),
+            (None, false) => Ok(self.command.clone()),
+            _ => Err(anyhow::anyhow!(
+                "hooks must have either a command or a wasm module"
+            )),
+        }
+    }
+
+    fn applies_to(&self, function: &str) -> bool {
+        self.functions.is_empty() || self.functions.iter().any(|name| name == function)
+    }
+
+    /// Run the hook with the JSON on stdin, returns what it printed if anything.
+    async fn run(&self, function: &str, input: String) -> anyhow::Result<Option<String>> {
+        let command = self.command_line()?;
+        let mut command_line = CommandLine::from_vec(&command)?;
+        command_line.stdin = Some(input);
+
+        let output = tokio::time::timeout(HOOK_TIMEOUT, command_line.output())
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "hook {} timed out for call of {}",
+                    command.join(" "),
+                    function
+                )
+            })??;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "call of {} rejected by hook {}: {}",
+                function,
+                command.join(" "),
+                output.stderr.trim()
+            ));
+        }
+
+        let stdout = output.stdout.trim();
+        Ok((!stdout.is_empty()).then(|| stdout.to_string()))
+    }
+}
+
+impl HooksConfig {
+    /// Returns an error if a hook has neither or both a command and a module.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for hook in self.pre_execution.iter().chain(&self.post_execution) {
+            hook.command_line()?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the hooks of this process around the calls.
+pub(crate) fn set(hooks: HooksConfig) {
+    log::info!(
+        "{} pre-execution and {} post-execution hooks",
+        hooks.pre_execution.len(),
+        hooks.post_execution.len()
+    );
+    if HOOKS.set(hooks).is_err() {
+        log::warn!("hooks already set");
+    }
+}
+
+/// Pass the call through the pre-execution hooks, which can change or reject it.
+pub(crate) async fn before(mut call: openai::Call) -> anyhow::Result<openai::Call> {
+    let Some(hooks) = HOOKS.get() else {
+        return Ok(call);
+    };
+
+    for hook in &hooks.pre_execution {
+        if !hook.applies_to(&call.function.name) {
+            continue;
+        }
+
+        let input = serde_json::to_string(&call)?;
+        if let Some(output) = hook.run(&call.function.name, input).await? {
+            let changed: openai::Call = serde_json::from_str(&output)
+                .map_err(|e| anyhow::anyhow!("invalid call returned by hook: {}", e))?;
+            log::debug!(
+                "call of {} changed by hook: {:?}",
+                &call.function.name,
+                &changed
+            );
+            // what the server knows about the call is kept
+            call = openai::Call {
+                caller: call.caller,
+                provenance: call.provenance,
+                ..changed
+            };
+        }
+    }
+
+    Ok(call)
+}
+
+/// Pass the result through the post-execution hooks, which can change its content or reject it.
+pub(crate) async fn after(
+    call: &openai::Call,
+    mut result: openai::CallResultMessage,
+) -> anyhow::Result<openai::CallResultMessage> {
+    let Some(hooks) = HOOKS.get() else {
+        return Ok(result);
+    };
+
+    for hook in &hooks.post_execution {
+        if !hook.applies_to(&call.function.name) {
+            continue;
+        }
+
+        let input = serde_json::to_string(&PostExecution {
+            call,
+            result: &result,
+        })?;
+        if let Some(output) = hook.run(&call.function.name, input).await? {
+            let changed: HookResult = serde_json::from_str(&output)
+                .map_err(|e| anyhow::anyhow!("invalid result returned by hook: {}", e))?;
+            result.content = changed.content;
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_command_line() {
+        let hook: Hook = serde_yaml::from_str("command: [jq, .]").unwrap();
+        assert_eq!(hook.command_line().unwrap(), vec!["jq", "."]);
+        assert!(hook.applies_to("nmap_scan"));
+
+        let hook: Hook = serde_yaml::from_str("wasm: filter.wasm\nfunctions: [curl]").unwrap();
+        assert_eq!(
+            hook.command_line().unwrap(),
+            vec!["wasmtime", "run", "filter.wasm"]
+        );
+        assert!(!hook.applies_to("nmap_scan"));
+
+        let hook: Hook = serde_yaml::from_str("functions: [curl]").unwrap();
+        assert!(hook.command_line().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hook_run() {
+        let hook: Hook =
+            serde_yaml::from_str(r#"command: [sh, -c, "sed s/secret/redacted/"]"#).unwrap();
+        let output = hook.run("curl", "a secret".to_string()).await.unwrap();
+        assert_eq!(output, Some("a redacted".to_string()));
+
+        let hook: Hook =
+            serde_yaml::from_str(r#"command: [sh, -c, "echo denied >&2; exit 1"]"#).unwrap();
+        let error = hook.run("curl", "{}".to_string()).await.unwrap_err();
+        assert!(error.to_string().ends_with("denied"));
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use russh::keys::{known_hosts, HashAlg, PublicKey, PublicKeyBase64};
+
+/// How the key of the SSH server is verified.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum HostKeyCheck {
+    /// The key must be in the known hosts file.
+    KnownHosts(PathBuf),
+    /// Like KnownHosts, but unknown hosts are added to the file on first connection.
+    AcceptNew(PathBuf),
+    /// The key must have this SHA256 fingerprint.
+    Fingerprint(String),
+    /// Any key is accepted.
+    None,
+}
+
+impl HostKeyCheck {
+    /// Parse the value of --ssh-strict-host-key: yes, accept-new, no or a SHA256: fingerprint.
+    pub fn parse(mode: &str, known_hosts: &str) -> anyhow::Result<Self> {
+        if mode.starts_with("SHA256:") {
+            return Ok(Self::Fingerprint(mode.to_string()));
+        }
+
+        let known_hosts = PathBuf::from(shellexpand::full(known_hosts)?.to_string());
+        match mode {
+            "yes" => Ok(Self::KnownHosts(known_hosts)),
+            "accept-new" => Ok(Self::AcceptNew(known_hosts)),
+            "no" => Ok(Self::None),
+            _ => Err(anyhow::anyhow!(
+                "invalid host key check {}, expected yes, accept-new, no or a SHA256: fingerprint",
+                mode
+            )),
+        }
+    }
+
+    /// Returns the key to pin the connection to, or None if any key is accepted.
+    pub async fn verify(&self, host: &str, port: u16) -> anyhow::Result<Option<String>> {
+        if *self == Self::None {
+            log::warn!("the key of {}:{} is not verified", host, port);
+            return Ok(None);
+        }
+
+        let key = scan(host, port).await?;
+        let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+
+        match self {
+            Self::Fingerprint(expected) => {
+                if fingerprint != *expected {
+                    return Err(anyhow::anyhow!(
+                        "host key of {}:{} has fingerprint {}, expected {}",
+                        host,
+                        port,
+                        fingerprint,
+                        expected
+                    ));
+                }
+            }
+            Self::KnownHosts(path) | Self::AcceptNew(path) => {
+                let known = known_hosts::check_known_hosts_path(host, port, &key, path).map_err(
+                    |e| match e {
+                        russh::keys::Error::KeyChanged { line } => anyhow::anyhow!(
+                            "HOST KEY OF {}:{} HAS CHANGED, it does not match line {} of {}, someone could be eavesdropping",
+                            host,
+                            port,
+                            line,
+                            path.display()
+                        ),
+                        e => anyhow::anyhow!("could not check {}: {}", path.display(), e),
+                    },
+                )?;
+
+                if !known {
+                    if matches!(self, Self::KnownHosts(_)) {
+                        return Err(anyhow::anyhow!(
+                            "host {}:{} with key {} is not in {}",
+                            host,
+                            port,
+                            fingerprint,
+                            path.display()
+                        ));
+                    }
+
+                    log::warn!(
+                        "adding {}:{} with key {} to {}",
+                        host,
+                        port,
+                        fingerprint,
+                        path.display()
+                    );
+                    known_hosts::learn_known_hosts_path(host, port, &key, path).map_err(|e| {
+                        anyhow::anyhow!("could not update {}: {}", path.display(), e)
+                    })?;
+                }
+            }
+            Self::None => unreachable!(),
+        }
+
+        log::debug!("host key of {}:{} verified: {}", host, port, fingerprint);
+        Ok(Some(key.public_key_base64()))
+    }
+}
+
+// collects the key of the server and aborts the handshake
+struct KeyScan(Arc<Mutex<Option<PublicKey>>>);
+
+impl russh::client::Handler for KeyScan {
+    type Error = russh::Error;
+
+    async fn check_server_key(&mut self, key: &PublicKey) -> Result<bool, Self::Error> {
+        *self.0.lock().unwrap() = Some(key.clone());
+        Ok(false)
+    }
+}
+
+async fn scan(host: &str, port: u16) -> anyhow::Result<PublicKey> {
+    let key = Arc::new(Mutex::new(None));
+    let config = Arc::new(russh::client::Config::default());
+
+    // the connection always fails since the key is rejected
+    let result = russh::client::connect(config, (host, port), KeyScan(key.clone())).await;
+
+    let key = key.lock().unwrap().take();
+    key.ok_or_else(|| match result {
+        Err(e) => anyhow::anyhow!("could not get the host key of {}:{}: {}", host, port, e),
+        Ok(_) => anyhow::anyhow!("{}:{} did not send a host key", host, port),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            HostKeyCheck::parse("yes", "/etc/ssh/known_hosts").unwrap(),
+            HostKeyCheck::KnownHosts(PathBuf::from("/etc/ssh/known_hosts"))
+        );
+        assert_eq!(
+            HostKeyCheck::parse("accept-new", "/tmp/known_hosts").unwrap(),
+            HostKeyCheck::AcceptNew(PathBuf::from("/tmp/known_hosts"))
+        );
+        assert_eq!(
+            HostKeyCheck::parse("no", "/tmp/known_hosts").unwrap(),
+            HostKeyCheck::None
+        );
+        assert_eq!(
+            HostKeyCheck::parse("SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s", "").unwrap(),
+            HostKeyCheck::Fingerprint(
+                "SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s".to_string()
+            )
+        );
+        assert!(HostKeyCheck::parse("maybe", "/tmp/known_hosts").is_err());
+    }
+}
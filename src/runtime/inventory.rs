@@ -0,0 +1,248 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, OnceLock},
+};
+
+use serde::Serialize;
+
+use crate::book::{flavors::openai, Book};
+
+use super::{
+    host_key::HostKeyCheck, receipt::Receipt, ssh::SSHConnection, ssh::SSHCredentials,
+    workers::Workers,
+};
+
+static INVENTORY: OnceLock<Inventory> = OnceLock::new();
+
+// every host of the inventory
+const ALL: &str = "all";
+
+/// SSH targets calls can be fanned out to, by target or group name.
+#[derive(Debug, Default)]
+pub(crate) struct Inventory {
+    hosts: BTreeMap<String, SSHConnection>,
+    groups: BTreeMap<String, Vec<String>>,
+}
+
+/// Result of a fanned out call on one of its hosts.
+#[derive(Debug, Serialize)]
+struct HostResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    receipt: Option<Receipt>,
+}
+
+impl Inventory {
+    /// Load an inventory file mapping group names to lists of SSH targets.
+    pub fn load(
+        path: &str,
+        credentials: &SSHCredentials,
+        host_key_check: &HostKeyCheck,
+    ) -> anyhow::Result<Self> {
+        let path = shellexpand::full(path)?.to_string();
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("error while reading {:?}: {}", path, e))?;
+        let groups: BTreeMap<String, Vec<String>> = serde_yaml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("error while parsing {:?}: {}", path, e))?;
+
+        Self::new(groups, credentials, host_key_check)
+    }
+
+    /// An inventory of the given targets, without groups.
+    pub fn from_targets(
+        targets: &[String],
+        credentials: &SSHCredentials,
+        host_key_check: &HostKeyCheck,
+    ) -> anyhow::Result<Self> {
+        Self::new(
+            BTreeMap::from([(ALL.to_string(), targets.to_vec())]),
+            credentials,
+            host_key_check,
+        )
+    }
+
+    fn new(
+        mut groups: BTreeMap<String, Vec<String>>,
+        credentials: &SSHCredentials,
+        host_key_check: &HostKeyCheck,
+    ) -> anyhow::Result<Self> {
+        let mut hosts = BTreeMap::new();
+        for target in groups.values().flatten() {
+            if !hosts.contains_key(target) {
+                let connection = SSHConnection::from_str(target, credentials)?
+                    .with_host_key_check(host_key_check.clone());
+                hosts.insert(target.clone(), connection);
+            }
+        }
+        groups.insert(ALL.to_string(), hosts.keys().cloned().collect());
+
+        Ok(Self { hosts, groups })
+    }
+
+    pub fn num_hosts(&self) -> usize {
+        self.hosts.len()
+    }
+
+    /// Returns the targets of the given host or group names, without duplicates.
+    fn resolve(&self, names: &[String]) -> anyhow::Result<Vec<&SSHConnection>> {
+        let mut targets = BTreeSet::new();
+        for name in names {
+            if let Some(group) = self.groups.get(name) {
+                targets.extend(group.iter());
+            } else if self.hosts.contains_key(name) {
+                targets.insert(name);
+            } else {
+                return Err(anyhow::anyhow!(
+                    "{} is not a host or group of the inventory",
+                    name
+                ));
+            }
+        }
+
+        Ok(targets
+            .into_iter()
+            .map(|target| &self.hosts[target])
+            .collect())
+    }
+}
+
+/// Set the inventory of this process.
+pub(crate) fn set(inventory: Inventory) {
+    log::info!("inventory: {} hosts", inventory.num_hosts());
+    if INVENTORY.set(inventory).is_err() {
+        log::warn!("inventory already set");
+    }
+}
+
+/// Execute the call on each of its hosts, the content of the result maps each host to its result.
+pub(crate) async fn execute(
+    interactive: bool,
+    workers: Arc<Workers>,
+    book: Arc<Book>,
+    mut call: openai::Call,
+) -> anyhow::Result<openai::CallResultMessage> {
+    let inventory = INVENTORY
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("hosts were requested but no inventory is set"))?;
+    let targets = inventory.resolve(&call.hosts)?;
+
+    if book
+        .get_function(&call.function.name)?
+        .function
+        .remote
+        .is_some()
+    {
+        return Err(anyhow::anyhow!(
+            "function {} runs on a remote server and can't be executed on other hosts",
+            call.function.name
+        ));
+    }
+
+    log::info!(
+        "executing {} on {} hosts",
+        call.function.name,
+        targets.len()
+    );
+    call.hosts.clear();
+
+    let run = |ssh: &SSHConnection| {
+        super::execute_call_on(
+            Some(ssh.clone()),
+            true,
+            interactive,
+            workers.clone(),
+            book.clone(),
+            call.clone(),
+        )
+    };
+
+    let results = if interactive {
+        // one confirmation at a time
+        let mut results = Vec::new();
+        for ssh in &targets {
+            results.push(run(ssh).await);
+        }
+        results
+    } else {
+        futures::future::join_all(targets.iter().map(|ssh| run(ssh))).await
+    };
+
+    let results: BTreeMap<String, HostResult> = targets
+        .iter()
+        .zip(results)
+        .map(|(ssh, result)| {
+            let result = match result {
+                Ok(message) => HostResult {
+                    content: Some(message.content),
+                    error: None,
+                    receipt: message.receipt,
+                },
+                Err(e) => HostResult {
+                    content: None,
+                    error: Some(e.to_string()),
+                    receipt: None,
+                },
+            };
+            (ssh.to_string(), result)
+        })
+        .collect();
+
+    Ok(openai::CallResultMessage {
+        role: "tool".to_string(),
+        call_id: call.id,
+        content: serde_json::to_string(&results)?,
+        receipt: None,
+        execution: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory() -> Inventory {
+        let groups = BTreeMap::from([
+            (
+                "web".to_string(),
+                vec!["deploy@web1".to_string(), "deploy@web2:2222".to_string()],
+            ),
+            (
+                "db".to_string(),
+                vec!["root@db1".to_string(), "deploy@web1".to_string()],
+            ),
+        ]);
+        let credentials = SSHCredentials {
+            password: Some("s3cr3t".to_string()),
+            ..Default::default()
+        };
+        Inventory::new(groups, &credentials, &HostKeyCheck::None).unwrap()
+    }
+
+    fn names(targets: Vec<&SSHConnection>) -> Vec<String> {
+        targets.iter().map(|ssh| ssh.to_string()).collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let inventory = inventory();
+        assert_eq!(inventory.num_hosts(), 3);
+
+        assert_eq!(
+            names(inventory.resolve(&["web".to_string()]).unwrap()),
+            vec!["deploy@web1:22", "deploy@web2:2222"]
+        );
+        assert_eq!(
+            names(
+                inventory
+                    .resolve(&["db".to_string(), "deploy@web1".to_string()])
+                    .unwrap()
+            ),
+            vec!["deploy@web1:22", "root@db1:22"]
+        );
+        assert_eq!(inventory.resolve(&["all".to_string()]).unwrap().len(), 3);
+        assert!(inventory.resolve(&["evil@attacker".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::book::{flavors::openai, Book};
+
+use super::{execute_call, ssh::SSHConnection, workers::Workers};
+
+// finished jobs are kept around for this long to be polled
+const FINISHED_JOB_TTL: Duration = Duration::from_secs(3600);
+
+static JOBS: LazyLock<Mutex<BTreeMap<String, Job>>> = LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Outcome of one of the calls of a job.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct JobResult {
+    pub call_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// State of a job as reported to clients, results are in completion order and partial while the
+/// job is running.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct JobInfo {
+    pub id: String,
+    pub status: JobStatus,
+    pub calls: usize,
+    pub elapsed_secs: f64,
+    pub results: Vec<JobResult>,
+}
+
+struct Job {
+    // who submitted the job, only they can poll or cancel it
+    owner: Option<String>,
+    status: JobStatus,
+    calls: usize,
+    started: Instant,
+    finished: Option<Instant>,
+    results: Vec<JobResult>,
+    cancel: CancellationToken,
+}
+
+impl Job {
+    fn info(&self, id: &str) -> JobInfo {
+        JobInfo {
+            id: id.to_string(),
+            status: self.status,
+            calls: self.calls,
+            elapsed_secs: self
+                .finished
+                .unwrap_or_else(Instant::now)
+                .duration_since(self.started)
+                .as_secs_f64(),
+            results: self.results.clone(),
+        }
+    }
+
+    fn finish(&mut self, status: JobStatus) {
+        self.status = status;
+        self.finished = Some(Instant::now());
+    }
+}
+
+fn prune_finished() {
+    JOBS.lock().unwrap().retain(|_, job| {
+        job.finished
+            .is_none_or(|finished| finished.elapsed() < FINISHED_JOB_TTL)
+    });
+}
+
+/// Execute the calls in the background and return the id of the job to poll. The id is random
+/// so that it can't be guessed from the ids of other jobs.
+pub(crate) fn submit(
+    ssh: Option<SSHConnection>,
+    book: Arc<Book>,
+    calls: Vec<openai::Call>,
+    workers: Arc<Workers>,
+    owner: Option<String>,
+) -> String {
+    prune_finished();
+
+    let cancel = CancellationToken::new();
+
+    let id = format!("{:032x}", fastrand::u128(..));
+
+    JOBS.lock().unwrap().insert(
+        id.clone(),
+        Job {
+            owner,
+            status: JobStatus::Running,
+            calls: calls.len(),
+            started: Instant::now(),
+            finished: None,
+            results: Vec::new(),
+            cancel: cancel.clone(),
+        },
+    );
+
+    let job_id = id.clone();
+    tokio::spawn(super::correlation::propagate(async move {
+        let mut pending: futures::stream::FuturesUnordered<_> = calls
+            .into_iter()
+            .map(|call| {
+                let call_id = call.id.clone();
+                let execution = execute_call(
+                    ssh.clone(),
+                    false,
+                    workers.clone(),
+                    book.clone(),
+                    call,
+                    cancel.clone(),
+                );
+                async move { (call_id, execution.await) }
+            })
+            .collect();
+
+        let mut failed = false;
+        while let Some((call_id, result)) = pending.next().await {
+            let result = match result {
+                Ok(message) => JobResult {
+                    call_id,
+                    content: Some(message.content),
+                    error: None,
+                },
+                Err(e) => {
+                    failed = true;
+                    JobResult {
+                        call_id,
+                        content: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+            };
+
+            if let Some(job) = JOBS.lock().unwrap().get_mut(&job_id) {
+                if job.status == JobStatus::Running {
+                    job.results.push(result);
+                }
+            }
+        }
+
+        // the job might have been cancelled in the meantime
+        if let Some(job) = JOBS
+            .lock()
+            .unwrap()
+            .get_mut(&job_id)
+            .filter(|job| job.status == JobStatus::Running)
+        {
+            job.finish(if failed {
+                JobStatus::Failed
+            } else {
+                JobStatus::Completed
+            });
+        }
+        log::info!("job {} finished", job_id);
+    }));
+
+    log::info!("job {} submitted", id);
+
+    id
+}
+
+/// The job with the given id, if it was submitted by the same owner.
+pub(crate) fn get(id: &str, owner: Option<&str>) -> Option<JobInfo> {
+    JOBS.lock()
+        .unwrap()
+        .get(id)
+        .filter(|job| job.owner.as_deref() == owner)
+        .map(|job| job.info(id))
+}
+
+/// Cancel a running job, killing its processes and containers. Finished jobs are left as they are.
+pub(crate) fn cancel(id: &str, owner: Option<&str>) -> Option<JobInfo> {
+    let mut jobs = JOBS.lock().unwrap();
+    let job = jobs
+        .get_mut(id)
+        .filter(|job| job.owner.as_deref() == owner)?;
+
+    if job.status == JobStatus::Running {
+        job.cancel.cancel();
+        job.finish(JobStatus::Cancelled);
+        log::info!("job {} cancelled", id);
+    }
+
+    Some(job.info(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::{runtime::ExecutionContext, Function, Page};
+
+    use super::*;
+
+    fn book(cmdline: &[&str]) -> Arc<Book> {
+        let function = Function {
+            parser: None,
+            destructive: false,
+            diff_previous: false,
+            client_env: Vec::new(),
+            files: Default::default(),
+            tests: Vec::new(),
+            output_format: None,
+            remote: None,
+            examples: None,
+            priority: None,
+            description: "Test function".to_string(),
+            parameters: BTreeMap::new(),
+            execution: ExecutionContext::CommandLine(
+                cmdline.iter().map(|s| s.to_string()).collect(),
+            ),
+            container: None,
+            executor: None,
+            max_concurrency: None,
+            exclusive: false,
+            cache: None,
+        };
+
+        Arc::new(Book {
+            pages: BTreeMap::from([(
+                camino::Utf8PathBuf::from("test_page"),
+                Page {
+                    metadata: Default::default(),
+                    name: "test_page".to_string(),
+                    description: None,
+                    categories: Vec::new(),
+                    functions: BTreeMap::from([("test_function".to_string(), function)]),
+                },
+            )]),
+        })
+    }
+
+    fn call() -> openai::Call {
+        openai::Call {
+            priority: None,
+            id: Some("call1".to_string()),
+            call_type: "function".to_string(),
+            caller: None,
+            env: BTreeMap::new(),
+            hosts: Vec::new(),
+            provenance: BTreeMap::new(),
+            function: openai::FunctionCall {
+                name: "test_function".to_string(),
+                arguments: BTreeMap::new(),
+            },
+        }
+    }
+
+    async fn wait_for(id: &str) -> JobInfo {
+        for _ in 0..100 {
+            let info = get(id, None).unwrap();
+            if info.status != JobStatus::Running {
+                return info;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("job {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_job_completes() {
+        let id = submit(
+            None,
+            book(&["echo", "test"]),
+            vec![call()],
+            Workers::new(10),
+            None,
+        );
+
+        let info = wait_for(&id).await;
+        assert_eq!(info.status, JobStatus::Completed);
+        assert_eq!(info.results.len(), 1);
+        assert_eq!(info.results[0].call_id, Some("call1".to_string()));
+        assert_eq!(info.results[0].content, Some("test\n".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_job_cancel() {
+        let id = submit(
+            None,
+            book(&["sleep", "30"]),
+            vec![call()],
+            Workers::new(10),
+            None,
+        );
+
+        let info = cancel(&id, None).unwrap();
+        assert_eq!(info.status, JobStatus::Cancelled);
+        assert!(info.results.is_empty());
+
+        assert!(get("nope", None).is_none());
+        assert!(cancel("nope", None).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_job_owner() {
+        let owner = Some("alice".to_string());
+        let id = submit(
+            None,
+            book(&["sleep", "30"]),
+            vec![call()],
+            Workers::new(10),
+            owner,
+        );
+
+        // other owners can't tell the job exists
+        assert!(get(&id, Some("bob")).is_none());
+        assert!(get(&id, None).is_none());
+        assert!(cancel(&id, Some("bob")).is_none());
+        assert_eq!(get(&id, Some("alice")).unwrap().status, JobStatus::Running);
+
+        let info = cancel(&id, Some("alice")).unwrap();
+        assert_eq!(info.status, JobStatus::Cancelled);
+    }
+}
@@ -1,18 +1,131 @@
-use std::sync::{atomic::AtomicUsize, Arc};
+use std::{
+    collections::BTreeMap,
+    sync::{atomic::AtomicU64, Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
 
-use crate::book::{flavors::openai, Book};
+use futures::StreamExt;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
 
+use crate::book::{flavors::openai, remote::RemoteFunction, Book};
+
+pub(crate) mod approval;
+pub(crate) mod audit;
+pub(crate) mod backend;
+mod builds;
+pub(crate) mod cache;
 mod cmd;
+mod concurrency;
+pub(crate) mod correlation;
 mod docker;
+#[cfg(feature = "bollard")]
+pub(crate) mod engine;
+pub(crate) mod env;
+pub(crate) mod executor;
+pub(crate) mod faults;
+pub(crate) mod history;
+pub(crate) mod hooks;
+pub(crate) mod host_key;
+pub(crate) mod inventory;
+pub(crate) mod jobs;
+pub(crate) mod persistent;
+mod pipeline;
+pub(crate) mod policy;
+pub(crate) mod pool;
 
+pub(crate) mod notifications;
+pub(crate) mod paths;
+pub(crate) mod progress;
 pub(crate) mod prompt;
+pub(crate) mod receipt;
+pub(crate) mod safe_mode;
+pub(crate) mod sandbox;
+pub(crate) mod scope;
+pub(crate) mod signing;
 pub(crate) mod ssh;
+pub(crate) mod workers;
 
-pub(crate) use cmd::CommandLine;
-pub(crate) use docker::{get_container_runtime, ContainerSource};
+pub(crate) use cmd::{CommandLine, PathArgument};
+pub(crate) use docker::{
+    get_container_runtime, host_arch, image_digest, is_emulated_platform, is_windows_platform,
+    kill_container, list_containers, list_dangling_images, pinned_digest, remove_dangling_images,
+    remove_orphans, repo_digest, session, translate_guest_path, ContainerSource, TrackedContainer,
+    Volume, FUNCTION_LABEL, SESSION_LABEL,
+};
 use ssh::SSHConnection;
+use workers::Workers;
+
+// first line of the output of failed commands, followed by the exit code
+pub(crate) const EXIT_CODE_PREFIX: &str = "EXIT CODE: ";
+
+// calls currently being executed, by execution id
+static RUNNING_CALLS: LazyLock<Mutex<BTreeMap<u64, RunningCall>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+static NEXT_EXECUTION_ID: AtomicU64 = AtomicU64::new(0);
+
+struct RunningCall {
+    function: String,
+    call_id: Option<String>,
+    started: Instant,
+}
+
+/// A call being executed, as reported to operators.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ActiveCall {
+    pub id: u64,
+    pub function: String,
+    pub call_id: Option<String>,
+    pub elapsed_secs: f64,
+}
+
+/// How the command of a call was executed.
+#[derive(Debug, Clone)]
+pub(crate) struct Execution {
+    pub command: String,
+    pub elapsed: Duration,
+    pub exit_code: Option<i64>,
+    /// What the command printed on each stream, only known for commands executed on this host.
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    /// Where the arguments and the interpolated variables come from.
+    pub provenance: BTreeMap<String, audit::Provenance>,
+}
+
+pub(crate) fn active_calls() -> Vec<ActiveCall> {
+    RUNNING_CALLS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, call)| ActiveCall {
+            id: *id,
+            function: call.function.clone(),
+            call_id: call.call_id.clone(),
+            elapsed_secs: call.started.elapsed().as_secs_f64(),
+        })
+        .collect()
+}
 
-static ACTIVE_TASKS: AtomicUsize = AtomicUsize::new(0);
+/// Returns the exit code reported in the output of a command, 0 if it didn't fail.
+pub(crate) fn exit_code(output: &str) -> Option<i64> {
+    match output.strip_prefix(EXIT_CODE_PREFIX) {
+        Some(rest) => rest
+            .lines()
+            .next()
+            .and_then(|code| code.trim().parse().ok()),
+        None => Some(0),
+    }
+}
+
+/// Forget the resolved images and the cached results, and remove the idle warm and persistent
+/// containers, returns the number of containers removed.
+pub(crate) fn flush_caches() -> usize {
+    docker::forget_resolved_images();
+    signing::forget_verified();
+    cache::clear(None);
+    pool::flush() + persistent::flush()
+}
 
 // https://stackoverflow.com/questions/29963449/golang-like-defer-in-rust
 struct ScopeCall<F: FnOnce()> {
@@ -37,71 +150,321 @@ macro_rules! defer {
     )
 }
 
-async fn wait_for_available_tasks(max_running_tasks: usize) {
-    let wait = std::time::Duration::from_secs(1);
-    loop {
-        let active_tasks = ACTIVE_TASKS.load(std::sync::atomic::Ordering::Relaxed);
-        if active_tasks < max_running_tasks {
-            break;
-        }
+/// Returns the names of the execution backends available to this process.
+pub(crate) async fn available_backends(ssh: Option<&SSHConnection>) -> Vec<String> {
+    let mut backends = vec!["host".to_string()];
 
-        log::debug!("waiting for our turn, {} active tasks", active_tasks);
-        tokio::time::sleep(wait).await;
+    let runtime = get_container_runtime();
+    if which::which(&runtime).is_ok() {
+        backends.push(runtime);
     }
+
+    #[cfg(feature = "bollard")]
+    if engine::get().await.is_some() {
+        backends.push("docker-api".to_string());
+    }
+
+    if let Some(ssh) = ssh {
+        backends.push(format!("ssh ({})", ssh));
+    }
+
+    backends
+}
+
+async fn execute_remote(
+    book: &Book,
+    function: &crate::book::runtime::FunctionRef<'_>,
+    remote: &RemoteFunction,
+    call: openai::Call,
+) -> anyhow::Result<openai::CallResultMessage> {
+    log::warn!("forwarding to {}", remote);
+
+    let receipt = receipt::Receipt::new(book, function, receipt::Target::Remote(remote)).await;
+    let started = std::time::Instant::now();
+    let content = remote.call(&call.function.arguments).await;
+
+    audit::record(&audit::Entry {
+        caller: call.caller.as_deref(),
+        call_id: call.id.as_deref(),
+        exit_code: content.as_ref().ok().and_then(|output| exit_code(output)),
+        error: content.as_ref().err().map(|e| e.to_string()),
+        ..audit::Entry::new(&call.function.name, &remote.to_string(), started.elapsed())
+    });
+
+    Ok(openai::CallResultMessage {
+        role: "tool".to_string(),
+        call_id: call.id.clone(),
+        content: content?,
+        receipt: Some(receipt),
+        execution: None,
+    })
 }
 
+/// Execute the call until it completes or the token is cancelled.
 pub(crate) async fn execute_call(
     ssh: Option<SSHConnection>,
     interactive: bool,
-    max_running_tasks: usize,
+    workers: Arc<Workers>,
     book: Arc<Book>,
     call: openai::Call,
+    cancel: CancellationToken,
 ) -> anyhow::Result<openai::CallResultMessage> {
-    wait_for_available_tasks(max_running_tasks).await;
+    let function = call.function.name.clone();
+    let call_id = call.id.clone();
+    let execution = async move {
+        let call = hooks::before(call).await?;
 
-    // increment the active tasks counter
-    ACTIVE_TASKS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-    defer! {
-        log::debug!("decrementing active tasks counter");
-        ACTIVE_TASKS.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
-    }
+        let result = if !call.hosts.is_empty() {
+            inventory::execute(interactive, workers, book, call.clone()).await?
+        } else {
+            execute_call_on(ssh, false, interactive, workers, book, call.clone()).await?
+        };
 
-    log::debug!("processing call: {:?}", call);
+        hooks::after(&call, result).await
+    };
 
+    // the logs of the call can be traced back to it and its request
+    correlation::with_call(call_id, async move {
+        tokio::select! {
+            result = execution => result,
+            // dropping the execution kills its processes and removes its containers
+            _ = cancel.cancelled() => {
+                log::warn!("call to {} cancelled", function);
+                Err(anyhow::anyhow!("call to {} cancelled", function))
+            }
+        }
+    })
+    .await
+}
+
+/// Execute the call, if require_ssh is set it fails instead of running locally when the command
+/// is not available on the SSH host.
+async fn execute_call_on(
+    ssh: Option<SSHConnection>,
+    require_ssh: bool,
+    interactive: bool,
+    workers: Arc<Workers>,
+    book: Arc<Book>,
+    call: openai::Call,
+) -> anyhow::Result<openai::CallResultMessage> {
     let function = book.get_function(&call.function.name)?;
 
+    safe_mode::check(&function)?;
+
+    // functions of remote servers are scheduled and executed by them
+    if let Some(remote) = &function.function.remote {
+        return execute_remote(&book, &function, remote, call).await;
+    }
+
+    // pipelines don't take a worker, each of their steps is scheduled as a call of its own
+    if let crate::book::runtime::ExecutionContext::Pipeline(_) = &function.function.execution {
+        return pipeline::execute(
+            ssh,
+            require_ssh,
+            interactive,
+            workers.clone(),
+            book.clone(),
+            &function,
+            &call,
+        )
+        .await;
+    }
+
+    log::debug!("processing call: {:?}", call);
+
     log::debug!("{} resolved to: {:?}", &call.function.name, &function);
 
+    // convert the provided values to strings according to the declared parameter types
+    let arguments = function.coerce_arguments(&call.function.arguments)?;
+
     // fail early if the arguments are invalid
-    function.validate_arguments(&call.function.arguments)?;
+    function.validate_arguments(&arguments)?;
+
+    // refuse targets outside of the engagement scope
+    scope::check_arguments(&function, &arguments)?;
+
+    function.check_client_env(&call.env)?;
+
+    // the calls of the server can be approved from its queue instead of the terminal
+    let can_approve = interactive || approval::queue_enabled();
+
+    // without a user to ask, refuse what the sandbox profile requires to approve
+    if !can_approve && sandbox::requires_approval(function.function.destructive) {
+        return Err(anyhow::anyhow!(
+            "function {} requires approval by the sandbox profile, run it interactively",
+            call.function.name
+        ));
+    }
 
     log::debug!("arguments validated");
 
-    let command_line = function.resolve_command_line(&call.function.arguments)?;
+    // failures injected for testing, if enabled, either replace the execution or corrupt its output
+    let fault = faults::draw(&call.function.name);
+    if let Some(fault) = &fault {
+        if let Some(content) = fault.replace_execution(&call.function.name).await {
+            return content.map(|content| openai::CallResultMessage {
+                role: "tool".to_string(),
+                call_id: call.id.clone(),
+                content,
+                receipt: None,
+                execution: None,
+            });
+        }
+    }
+
+    let command_line = function.resolve_command_line(&arguments, &call.env)?;
 
     log::debug!("command line: {:?}", command_line);
 
+    // the rules of the policy can match the binary, so it's only checked once it's resolved
+    let action = policy::check(
+        &policy::Request {
+            function: &function,
+            arguments: &arguments,
+            command_line: &command_line,
+        },
+        can_approve,
+    )?;
+
+    // identical calls on the same host share the key of their cached result and previous output
+    let run_key = cache::key(
+        &call.function.name,
+        &arguments,
+        &call.env,
+        ssh.as_ref().map(|ssh| ssh.to_string()).as_deref(),
+    );
+
+    // identical calls within the ttl of the function reuse its result, once they are allowed
+    let cache_key = function.function.cache.as_ref().map(|_| run_key.clone());
+    if let (Some(policy), Some(key)) = (&function.function.cache, &cache_key) {
+        if let Some(cached) = cache::get(key, policy) {
+            log::info!("using the cached result of {}", &call.function.name);
+            return Ok(openai::CallResultMessage {
+                role: "tool".to_string(),
+                call_id: call.id.clone(),
+                content: cached.content,
+                receipt: cached.receipt,
+                execution: None,
+            });
+        }
+    }
+
+    // what the policy or the sandbox profile require to approve is always confirmed
+    let auto_approved = can_approve
+        && action != policy::Action::Ask
+        && !sandbox::requires_approval(function.function.destructive)
+        && approval::is_approved(&function, &command_line);
+
+    // asked before taking a worker, the user can take minutes to decide
+    if can_approve
+        && !auto_approved
+        && !approval::confirm(
+            &call.function.name,
+            &command_line.to_string(),
+            call.caller.as_deref(),
+        )
+        .await?
+    {
+        return Ok(openai::CallResultMessage {
+            role: "tool".to_string(),
+            call_id: call.id.clone(),
+            content: "<command execution cancelled by user>".to_string(),
+            receipt: None,
+            execution: None,
+        });
+    }
+
+    let priority = call
+        .priority
+        .or(function.function.priority)
+        .unwrap_or_default();
+
+    // calls waiting for other calls of the same function don't take a worker
+    let _permit =
+        concurrency::acquire(&call.function.name, function.function.concurrency_limit()).await?;
+
+    let _worker = workers.acquire(priority).await;
+
+    let execution_id = NEXT_EXECUTION_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    RUNNING_CALLS.lock().unwrap().insert(
+        execution_id,
+        RunningCall {
+            function: call.function.name.clone(),
+            call_id: call.id.clone(),
+            started: Instant::now(),
+        },
+    );
+    defer! {
+        RUNNING_CALLS.lock().unwrap().remove(&execution_id);
+    }
+
+    let provenance = audit::provenance(&function, &call, &arguments, &command_line.env);
+
+    let files = function.resolve_files(&arguments)?;
+
+    // a registered executor selected by the function decides where the command runs
+    let custom = match &function.function.executor {
+        Some(name) => {
+            if require_ssh {
+                return Err(anyhow::anyhow!(
+                    "function {} runs on executor {}, not on ssh hosts",
+                    call.function.name,
+                    name
+                ));
+            }
+            Some(executor::get(name)?)
+        }
+        None => None,
+    };
+
     // validate runtime requirements
     let container = function.function.container.as_ref();
     let mut needs_container = false;
     let mut can_ssh = false;
 
+    // modules are sandboxed on this host, they are not on the ssh hosts
+    let ssh = match &function.function.execution {
+        crate::book::runtime::ExecutionContext::Wasm(wasm) => {
+            if require_ssh {
+                return Err(anyhow::anyhow!(
+                    "wasm module {} can't be executed on ssh hosts",
+                    wasm.module
+                ));
+            }
+            None
+        }
+        _ if custom.is_some() => None,
+        _ => ssh,
+    };
+
     // if --ssh was provided
     if let Some(ssh) = ssh.as_ref() {
         // check if the app is in $PATH on the ssh host
         can_ssh = ssh.app_in_path(&command_line.app).await?;
         if !can_ssh {
-            log::warn!(
-                "{} not found in $PATH on {}",
-                command_line.app,
-                ssh.to_string()
-            );
+            if require_ssh {
+                return Err(anyhow::anyhow!(
+                    "{} not found in $PATH on {}",
+                    command_line.app,
+                    ssh
+                ));
+            }
+            log::warn!("{} not found in $PATH on {}", command_line.app, ssh);
         }
     }
 
     // we are not going to use ssh, so we need to check if we need a container
     if !can_ssh {
-        if command_line.sudo && !interactive {
+        if !files.is_empty() {
+            log::warn!(
+                "files of function {} are only staged on ssh hosts",
+                call.function.name
+            );
+        }
+
+        if custom.is_some() {
+            // the executor decides whether to use a container
+        } else if command_line.sudo && !interactive {
             // we're running in non-interactive mode, can't sudo
             needs_container = true;
         } else if !command_line.app_in_path {
@@ -113,6 +476,18 @@ pub(crate) async fn execute_call(
         }
     }
 
+    // with the engine api the container is created directly, otherwise the command line is wrapped
+    #[cfg(feature = "bollard")]
+    let use_engine = match container {
+        Some(container) if needs_container => engine::can_run(container).await,
+        _ => false,
+    };
+    #[cfg(not(feature = "bollard"))]
+    let use_engine = false;
+
+    // keeps the persistent container of the function until the call completes
+    let mut _lease = None;
+
     // wrap the command line in a container if needed
     let command_line = if needs_container {
         let container = match container {
@@ -127,11 +502,46 @@ pub(crate) async fn execute_call(
 
         log::debug!("using container: {:?}", container);
 
+        // anyone on the host can read the arguments of the container runtime, not its env file
+        if command_line.has_secret_args() {
+            return Err(anyhow::anyhow!(
+                "sensitive arguments of function {} are passed to containers as variables, use them instead of interpolating the arguments",
+                call.function.name
+            ));
+        }
+
         // build or pull the image if needed
         container.resolve().await?;
 
-        // wrap the command line
-        container.wrap(command_line)?
+        // running containers can't mount the paths passed as arguments
+        let can_exec = command_line.paths.is_empty();
+
+        if can_exec && persistent::supports(container) {
+            let lease = persistent::lease(&call.function.name, container).await?;
+            log::debug!("using persistent container {}", &lease.name);
+            let wrapped = container.wrap_exec(&lease.name, &lease.entrypoint, command_line)?;
+            _lease = Some(lease);
+            wrapped
+        } else if use_engine {
+            log::debug!(
+                "executing via docker engine api in {}",
+                container.source.image()
+            );
+            command_line
+        } else if let Some(warm) = can_exec
+            .then(|| pool::take(&call.function.name, container))
+            .flatten()
+        {
+            log::debug!("using warm container {}", &warm.tracked.name);
+            let mut wrapped =
+                container.wrap_exec(&warm.tracked.name, &warm.entrypoint, command_line)?;
+            // removed once the command completes
+            wrapped.container = Some(warm.tracked);
+            wrapped
+        } else {
+            // wrap the command line
+            container.wrap(&call.function.name, command_line)?
+        }
     } else {
         // keep it as it is
         command_line
@@ -140,61 +550,200 @@ pub(crate) async fn execute_call(
     if can_ssh {
         log::warn!(
             "executing (as {}): {}",
-            ssh.as_ref().unwrap().to_string(),
+            ssh.as_ref().unwrap(),
             &command_line
         );
     } else {
         log::warn!("executing: {}", &command_line);
     }
 
-    if interactive
-        && prompt::ask(
-            ">> enter 'y' to proceed or any other key to cancel: ",
-            &["y", "n"],
-        )? != "y"
-    {
-        return Ok(openai::CallResultMessage {
-            role: "tool".to_string(),
-            call_id: call.id.clone(),
-            content: "<command execution cancelled by user>".to_string(),
-        });
-    }
+    let target = if let Some(name) = &function.function.executor {
+        receipt::Target::Executor(name)
+    } else if can_ssh {
+        receipt::Target::Ssh(ssh.as_ref().unwrap())
+    } else if needs_container {
+        receipt::Target::Container(container.unwrap())
+    } else {
+        receipt::Target::Host
+    };
+    let receipt = receipt::Receipt::new(&book, &function, target).await;
 
     // finally execute the command line
-    let content = if can_ssh {
-        // execute via ssh
-        ssh.as_ref()
-            .unwrap()
-            .execute(command_line.sudo, &command_line.app, &command_line.args)
-            .await?
+    let command = command_line.to_string();
+    let secrets = command_line.secrets.clone();
+    progress::emit(progress::Event::Executing {
+        function: &call.function.name,
+        command: &command,
+    });
+    let executor: Arc<dyn executor::Executor> = match custom {
+        Some(executor) => executor,
+        None if can_ssh => Arc::new(executor::Ssh(ssh.clone().unwrap())),
+        None if use_engine => Arc::new(executor::Engine),
+        None => executor::get(executor::HOST)?,
+    };
+    let started = std::time::Instant::now();
+    let mut streams = None;
+    let content = executor
+        .execute(executor::Job {
+            function: &call.function.name,
+            command_line,
+            container,
+            files: &files,
+        })
+        .await
+        .map(|executed| {
+            streams = executed.output;
+            executed.content
+        });
+
+    let elapsed = started.elapsed();
+    // errors might include the sensitive arguments
+    let content = content.map_err(|e| anyhow::anyhow!(cmd::redact(&e.to_string(), &secrets)));
+    let execution = Execution {
+        exit_code: content.as_ref().ok().and_then(|output| exit_code(output)),
+        stdout: streams.as_ref().map(|output| output.stdout.clone()),
+        stderr: streams.map(|output| output.stderr),
+        command: command.clone(),
+        elapsed,
+        provenance,
+    };
+    progress::emit(progress::Event::Executed {
+        function: &call.function.name,
+        exit_code: execution.exit_code,
+        duration_ms: elapsed.as_millis(),
+    });
+
+    audit::record(&audit::Entry {
+        caller: call.caller.as_deref(),
+        call_id: call.id.as_deref(),
+        exit_code: content.as_ref().ok().and_then(|output| exit_code(output)),
+        error: content.as_ref().err().map(|e| e.to_string()),
+        provenance: Some(&execution.provenance),
+        ..audit::Entry::new(&call.function.name, &command, elapsed)
+    });
+
+    history::record(history::Record {
+        call_id: call.id.clone(),
+        caller: call.caller.clone(),
+        arguments: arguments
+            .iter()
+            .map(|(name, value)| (name.clone(), cmd::redact(value, &secrets)))
+            .collect(),
+        exit_code: execution.exit_code,
+        output: content
+            .as_ref()
+            .ok()
+            .map(|output| cmd::redact(output, &secrets)),
+        error: content.as_ref().err().map(|e| e.to_string()),
+        provenance: execution.provenance.clone(),
+        ..history::Record::new(&call.function.name, &command, elapsed)
+    });
+
+    notifications::notify_executed(
+        &call.function.name,
+        call.id.as_ref(),
+        function.function.destructive,
+        elapsed,
+        content.as_ref().err(),
+    );
+
+    let content = match &fault {
+        Some(fault) => fault.corrupt(content?),
+        None => content?,
+    };
+
+    // reduce the output tokens if a parser is set
+    let content = match &function.function.parser {
+        Some(parser) => parser.apply(&content).unwrap_or_else(|e| {
+            log::warn!(
+                "parser of function {} failed, returning the raw output: {}",
+                &call.function.name,
+                e
+            );
+            content
+        }),
+        // without a parser, at least remove the formatting of structured output
+        None => match function.function.output_format {
+            Some(format) => format.compact(&content),
+            None => content,
+        },
+    };
+
+    // only return what changed since the last run with the same arguments
+    let content = if function.function.diff_previous {
+        let previous = history::previous_output(&run_key);
+        if let Err(e) = history::record_output(&run_key, &content) {
+            log::warn!(
+                "could not record the output of {}: {}",
+                &call.function.name,
+                e
+            );
+        }
+        match previous {
+            Some(previous) => history::diff(&previous, &content),
+            None => content,
+        }
     } else {
-        // execute locally
-        command_line.execute().await?
+        content
     };
 
+    // failures are executed again
+    if let (Some(policy), Some(key)) = (&function.function.cache, &cache_key) {
+        if execution.exit_code == Some(0) {
+            cache::put(key, &call.function.name, policy, &content, Some(&receipt));
+        }
+    }
+
     Ok(openai::CallResultMessage {
         role: "tool".to_string(),
         call_id: call.id.clone(),
         content,
+        receipt: Some(receipt),
+        execution: Some(execution),
     })
 }
 
+#[cfg(feature = "bollard")]
+async fn execute_with_engine(
+    container: &crate::book::Container,
+    function_name: &str,
+    command_line: CommandLine,
+) -> anyhow::Result<String> {
+    engine::run(container, function_name, command_line).await
+}
+
+#[cfg(not(feature = "bollard"))]
+async fn execute_with_engine(
+    _container: &crate::book::Container,
+    _function_name: &str,
+    _command_line: CommandLine,
+) -> anyhow::Result<String> {
+    Err(anyhow::anyhow!(
+        "compiled without docker engine api support"
+    ))
+}
+
 pub(crate) async fn execute(
     ssh: Option<SSHConnection>,
     interactive: bool,
     book: Arc<Book>,
     calls: Vec<openai::Call>,
-    max_running_tasks: usize,
+    workers: Arc<Workers>,
 ) -> anyhow::Result<Vec<openai::CallResultMessage>> {
+    // the calls are cancelled if this is dropped, such as when the client disconnects
+    let cancel = CancellationToken::new();
+    let _cancel_on_drop = cancel.clone().drop_guard();
+
     let mut futures = Vec::new();
     for call in calls {
-        futures.push(tokio::spawn(execute_call(
+        futures.push(tokio::spawn(correlation::propagate(execute_call(
             ssh.clone(),
             interactive,
-            max_running_tasks,
+            workers.clone(),
             book.clone(),
             call,
-        )));
+            cancel.clone(),
+        ))));
     }
 
     let mut results = Vec::new();
@@ -211,6 +760,45 @@ pub(crate) async fn execute(
     Ok(results)
 }
 
+/// Execute the calls concurrently, yielding each result as soon as its call completes. The calls
+/// still running are cancelled if the stream is dropped.
+pub(crate) fn execute_stream(
+    ssh: Option<SSHConnection>,
+    book: Arc<Book>,
+    calls: Vec<openai::Call>,
+    workers: Arc<Workers>,
+) -> impl futures::Stream<Item = (Option<String>, anyhow::Result<openai::CallResultMessage>)> {
+    let cancel = CancellationToken::new();
+    let cancel_on_drop = cancel.clone().drop_guard();
+
+    let pending: futures::stream::FuturesUnordered<_> = calls
+        .into_iter()
+        .map(|call| {
+            let call_id = call.id.clone();
+            let handle = tokio::spawn(correlation::propagate(execute_call(
+                ssh.clone(),
+                false,
+                workers.clone(),
+                book.clone(),
+                call,
+                cancel.clone(),
+            )));
+            async move {
+                let result = match handle.await {
+                    Ok(result) => result,
+                    Err(e) => Err(anyhow!("error joining task: {:?}", e)),
+                };
+                (call_id, result)
+            }
+        })
+        .collect();
+
+    pending.map(move |result| {
+        let _ = &cancel_on_drop;
+        result
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::book::{runtime::ExecutionContext, Function, Page};
@@ -221,8 +809,13 @@ mod tests {
     #[tokio::test]
     async fn test_execute_call() {
         let call = openai::Call {
+            priority: None,
             id: Some("test_call".to_string()),
             call_type: "function".to_string(),
+            caller: None,
+            env: BTreeMap::new(),
+            hosts: Vec::new(),
+            provenance: BTreeMap::new(),
             function: openai::FunctionCall {
                 name: "test_function".to_string(),
                 arguments: BTreeMap::new(),
@@ -230,6 +823,7 @@ mod tests {
         };
 
         let mock_page = Page {
+            metadata: Default::default(),
             name: "test_page".to_string(),
             description: Some("Test page".to_string()),
             categories: Vec::new(),
@@ -238,6 +832,16 @@ mod tests {
                 map.insert(
                     "test_function".to_string(),
                     Function {
+                        parser: None,
+                        destructive: false,
+                        diff_previous: false,
+                        client_env: Vec::new(),
+                        files: Default::default(),
+                        tests: Vec::new(),
+                        output_format: None,
+                        remote: None,
+                        examples: None,
+                        priority: None,
                         description: "Test function".to_string(),
                         parameters: BTreeMap::new(),
                         execution: ExecutionContext::CommandLine(vec![
@@ -245,6 +849,10 @@ mod tests {
                             "test".to_string(),
                         ]),
                         container: None,
+                        executor: None,
+                        max_concurrency: None,
+                        exclusive: false,
+                        cache: None,
                     },
                 );
                 map
@@ -259,7 +867,16 @@ mod tests {
             },
         });
 
-        let result = execute_call(None, false, 10, book, call).await.unwrap();
+        let result = execute_call(
+            None,
+            false,
+            Workers::new(10),
+            book,
+            call,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(result.role, "tool");
         assert_eq!(result.call_id, Some("test_call".to_string()));
@@ -270,16 +887,26 @@ mod tests {
     async fn test_execute() {
         let calls = vec![
             openai::Call {
+                priority: None,
                 id: Some("call1".to_string()),
                 call_type: "function".to_string(),
+                caller: None,
+                env: BTreeMap::new(),
+                hosts: Vec::new(),
+                provenance: BTreeMap::new(),
                 function: openai::FunctionCall {
                     name: "echo1".to_string(),
                     arguments: BTreeMap::new(),
                 },
             },
             openai::Call {
+                priority: None,
                 id: Some("call2".to_string()),
                 call_type: "function".to_string(),
+                caller: None,
+                env: BTreeMap::new(),
+                hosts: Vec::new(),
+                provenance: BTreeMap::new(),
                 function: openai::FunctionCall {
                     name: "echo2".to_string(),
                     arguments: BTreeMap::new(),
@@ -288,6 +915,7 @@ mod tests {
         ];
 
         let mock_page = Page {
+            metadata: Default::default(),
             name: "test_page".to_string(),
             description: Some("Test page".to_string()),
             categories: Vec::new(),
@@ -296,6 +924,16 @@ mod tests {
                 map.insert(
                     "echo1".to_string(),
                     Function {
+                        parser: None,
+                        destructive: false,
+                        diff_previous: false,
+                        client_env: Vec::new(),
+                        files: Default::default(),
+                        tests: Vec::new(),
+                        output_format: None,
+                        remote: None,
+                        examples: None,
+                        priority: None,
                         description: "Echo 1".to_string(),
                         parameters: BTreeMap::new(),
                         execution: ExecutionContext::CommandLine(vec![
@@ -303,11 +941,25 @@ mod tests {
                             "test1".to_string(),
                         ]),
                         container: None,
+                        executor: None,
+                        max_concurrency: None,
+                        exclusive: false,
+                        cache: None,
                     },
                 );
                 map.insert(
                     "echo2".to_string(),
                     Function {
+                        parser: None,
+                        destructive: false,
+                        diff_previous: false,
+                        client_env: Vec::new(),
+                        files: Default::default(),
+                        tests: Vec::new(),
+                        output_format: None,
+                        remote: None,
+                        examples: None,
+                        priority: None,
                         description: "Echo 2".to_string(),
                         parameters: BTreeMap::new(),
                         execution: ExecutionContext::CommandLine(vec![
@@ -315,6 +967,10 @@ mod tests {
                             "test2".to_string(),
                         ]),
                         container: None,
+                        executor: None,
+                        max_concurrency: None,
+                        exclusive: false,
+                        cache: None,
                     },
                 );
                 map
@@ -329,7 +985,9 @@ mod tests {
             },
         });
 
-        let results = execute(None, false, book, calls, 10).await.unwrap();
+        let results = execute(None, false, book, calls, Workers::new(10))
+            .await
+            .unwrap();
 
         assert_eq!(results.len(), 2);
         assert_eq!(results[0].content, "test1\n");
@@ -344,6 +1002,7 @@ mod tests {
                 map.insert(
                     camino::Utf8PathBuf::from("test_page"),
                     Page {
+                        metadata: Default::default(),
                         name: "test_page".to_string(),
                         description: Some("Test page".to_string()),
                         categories: Vec::new(),
@@ -355,18 +1014,67 @@ mod tests {
         });
 
         let calls = vec![openai::Call {
+            priority: None,
             id: Some("call1".to_string()),
             call_type: "function".to_string(),
+            caller: None,
+            env: BTreeMap::new(),
+            hosts: Vec::new(),
+            provenance: BTreeMap::new(),
             function: openai::FunctionCall {
                 name: "non_existent_function".to_string(),
                 arguments: BTreeMap::new(),
             },
         }];
 
-        let result = execute(None, false, Arc::clone(&book), calls, 10).await;
+        let result = execute(None, false, Arc::clone(&book), calls, Workers::new(10)).await;
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code("hello"), Some(0));
+        assert_eq!(exit_code("EXIT CODE: 2\nERROR: nope"), Some(2));
+        assert_eq!(exit_code("EXIT CODE: signal: 9 (SIGKILL)"), None);
+    }
+
+    #[tokio::test]
+    async fn test_execute_stream_reports_each_call() {
+        use futures::StreamExt;
+
+        let book = Arc::new(Book {
+            pages: BTreeMap::new(),
+        });
+
+        let calls = ["call1", "call2"]
+            .iter()
+            .map(|id| openai::Call {
+                priority: None,
+                id: Some(id.to_string()),
+                call_type: "function".to_string(),
+                caller: None,
+                env: BTreeMap::new(),
+                hosts: Vec::new(),
+                provenance: BTreeMap::new(),
+                function: openai::FunctionCall {
+                    name: "non_existent_function".to_string(),
+                    arguments: BTreeMap::new(),
+                },
+            })
+            .collect();
+
+        let mut results: Vec<_> = execute_stream(None, book, calls, Workers::new(10))
+            .collect()
+            .await;
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, Some("call1".to_string()));
+        assert!(results[0].1.is_err());
+        assert_eq!(results[1].0, Some("call2".to_string()));
+        assert!(results[1].1.is_err());
+    }
+
     #[tokio::test]
     async fn test_execute_with_non_existent_command() {
         let book = Arc::new(Book {
@@ -375,6 +1083,7 @@ mod tests {
                 map.insert(
                     camino::Utf8PathBuf::from("test_page"),
                     Page {
+                        metadata: Default::default(),
                         name: "test_page".to_string(),
                         description: Some("Test page".to_string()),
                         categories: Vec::new(),
@@ -383,12 +1092,26 @@ mod tests {
                             map.insert(
                                 "non_existent".to_string(),
                                 Function {
+                                    parser: None,
+                                    destructive: false,
+                                    diff_previous: false,
+                                    client_env: Vec::new(),
+                                    files: Default::default(),
+                                    tests: Vec::new(),
+                                    output_format: None,
+                                    remote: None,
+                                    examples: None,
+                                    priority: None,
                                     description: "Non-existent command".to_string(),
                                     parameters: BTreeMap::new(),
                                     execution: ExecutionContext::CommandLine(vec![
                                         "non_existent_command".to_string(),
                                     ]),
                                     container: None,
+                                    executor: None,
+                                    max_concurrency: None,
+                                    exclusive: false,
+                                    cache: None,
                                 },
                             );
                             map
@@ -400,15 +1123,20 @@ mod tests {
         });
 
         let calls = vec![openai::Call {
+            priority: None,
             id: Some("call1".to_string()),
             call_type: "function".to_string(),
+            caller: None,
+            env: BTreeMap::new(),
+            hosts: Vec::new(),
+            provenance: BTreeMap::new(),
             function: openai::FunctionCall {
                 name: "non_existent".to_string(),
                 arguments: BTreeMap::new(),
             },
         }];
 
-        let result = execute(None, false, Arc::clone(&book), calls, 10).await;
+        let result = execute(None, false, Arc::clone(&book), calls, Workers::new(10)).await;
         assert!(result.is_err());
     }
 }
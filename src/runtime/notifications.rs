@@ -0,0 +1,246 @@
+use std::{sync::OnceLock, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+static CONFIG: OnceLock<NotificationsConfig> = OnceLock::new();
+
+/// Events operators can be notified about.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Event {
+    /// A function marked as destructive was executed.
+    Destructive,
+    /// The command of a function failed.
+    Failed,
+    /// A command took longer than the configured threshold.
+    LongCall,
+}
+
+impl Event {
+    fn all() -> Vec<Self> {
+        vec![Self::Destructive, Self::Failed, Self::LongCall]
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub(crate) enum Channel {
+    /// Slack incoming webhook.
+    Slack { url: String },
+    /// The notification is posted as JSON.
+    Webhook { url: String },
+    /// Desktop notification via notify-send or osascript.
+    Desktop,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Notifier {
+    #[serde(flatten)]
+    pub channel: Channel,
+    /// Events to notify, all by default.
+    #[serde(default = "Event::all")]
+    pub events: Vec<Event>,
+}
+
+/// Set in the notifications section of the configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct NotificationsConfig {
+    /// Calls taking longer than this number of seconds trigger a long_call event.
+    pub long_call_secs: u64,
+    pub notifiers: Vec<Notifier>,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            long_call_secs: 300,
+            notifiers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Notification {
+    pub event: Event,
+    pub function: String,
+    pub call_id: Option<String>,
+    pub elapsed_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Notification {
+    fn message(&self) -> String {
+        let what = match self.event {
+            Event::Destructive => "destructive function executed",
+            Event::Failed => "call failed",
+            Event::LongCall => "long call finished",
+        };
+        let mut message = format!(
+            "robopages: {} {} ({:.1}s)",
+            what, self.function, self.elapsed_secs
+        );
+        if let Some(error) = &self.error {
+            message.push_str(&format!(": {}", error));
+        }
+        message
+    }
+}
+
+pub(crate) fn set(config: NotificationsConfig) {
+    if !config.notifiers.is_empty() {
+        log::info!("{} notifiers configured", config.notifiers.len());
+    }
+    if CONFIG.set(config).is_err() {
+        log::warn!("notifications already configured");
+    }
+}
+
+// the events triggered by an executed call
+fn events_for(
+    config: &NotificationsConfig,
+    destructive: bool,
+    elapsed: Duration,
+    failed: bool,
+) -> Vec<Event> {
+    let mut events = Vec::new();
+    if destructive {
+        events.push(Event::Destructive);
+    }
+    if failed {
+        events.push(Event::Failed);
+    }
+    if elapsed.as_secs() >= config.long_call_secs {
+        events.push(Event::LongCall);
+    }
+    events
+}
+
+/// Notify the configured channels about an executed call, in the background.
+pub(crate) fn notify_executed(
+    function: &str,
+    call_id: Option<&String>,
+    destructive: bool,
+    elapsed: Duration,
+    error: Option<&anyhow::Error>,
+) {
+    let Some(config) = CONFIG.get() else {
+        return;
+    };
+
+    for event in events_for(config, destructive, elapsed, error.is_some()) {
+        let notification = Notification {
+            event,
+            function: function.to_string(),
+            call_id: call_id.cloned(),
+            elapsed_secs: elapsed.as_secs_f64(),
+            error: error.map(|e| e.to_string()),
+        };
+
+        for notifier in config
+            .notifiers
+            .iter()
+            .filter(|notifier| notifier.events.contains(&event))
+        {
+            let channel = notifier.channel.clone();
+            let notification = notification.clone();
+            tokio::spawn(async move {
+                if let Err(e) = send(&channel, &notification).await {
+                    log::error!(
+                        "failed to send {:?} notification: {}",
+                        notification.event,
+                        e
+                    );
+                }
+            });
+        }
+    }
+}
+
+async fn send(channel: &Channel, notification: &Notification) -> anyhow::Result<()> {
+    match channel {
+        Channel::Slack { url } => {
+            post(url, &serde_json::json!({ "text": notification.message() })).await
+        }
+        Channel::Webhook { url } => post(url, &serde_json::to_value(notification)?).await,
+        Channel::Desktop => desktop(&notification.message()).await,
+    }
+}
+
+async fn post(url: &str, body: &serde_json::Value) -> anyhow::Result<()> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("{} returned {}", url, response.status()));
+    }
+    Ok(())
+}
+
+async fn desktop(message: &str) -> anyhow::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.args([
+            "-e",
+            &format!(
+                "display notification {:?} with title \"robopages\"",
+                message
+            ),
+        ]);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["robopages", message]);
+        command
+    };
+
+    let status = command.status().await?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("desktop notification failed: {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notifications_config() {
+        let config: NotificationsConfig = serde_yaml::from_str(
+            r#"
+long_call_secs: 60
+notifiers:
+  - type: slack
+    url: https://hooks.slack.com/services/x
+    events: [destructive, failed]
+  - type: desktop
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.long_call_secs, 60);
+        assert!(matches!(config.notifiers[0].channel, Channel::Slack { .. }));
+        assert_eq!(
+            config.notifiers[0].events,
+            vec![Event::Destructive, Event::Failed]
+        );
+        assert!(matches!(config.notifiers[1].channel, Channel::Desktop));
+        assert_eq!(config.notifiers[1].events, Event::all());
+    }
+
+    #[test]
+    fn test_events_for() {
+        let config = NotificationsConfig::default();
+        assert!(events_for(&config, false, Duration::from_secs(1), false).is_empty());
+        assert_eq!(
+            events_for(&config, true, Duration::from_secs(301), true),
+            vec![Event::Destructive, Event::Failed, Event::LongCall]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::Deserialize;
+
+static ALLOWED_ROOTS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// Which host paths can be passed as arguments of the path type.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct PathsConfig {
+    /// Directories the paths must be in, no path is accepted if empty.
+    pub allowed_roots: Vec<String>,
+}
+
+/// Set the directories the paths must be in, missing ones are ignored.
+pub(crate) fn set(config: &PathsConfig) -> anyhow::Result<()> {
+    let mut roots = Vec::new();
+    for root in &config.allowed_roots {
+        let expanded = shellexpand::full(root)
+            .map_err(|e| anyhow::anyhow!("failed to expand path {}: {}", root, e))?;
+        match Path::new(expanded.as_ref()).canonicalize() {
+            Ok(root) => roots.push(root),
+            Err(e) => log::warn!("ignoring allowed root {}: {}", root, e),
+        }
+    }
+
+    if ALLOWED_ROOTS.set(roots).is_err() {
+        log::warn!("allowed roots already set");
+    }
+    Ok(())
+}
+
+/// Returns the canonical host path of the argument if it's in one of the allowed roots. Outputs
+/// can be created by the command, so only their directory must exist.
+pub(crate) fn check(path: &str, output: bool) -> anyhow::Result<PathBuf> {
+    check_in(
+        path,
+        output,
+        ALLOWED_ROOTS.get().map(Vec::as_slice).unwrap_or_default(),
+    )
+}
+
+fn check_in(path: &str, output: bool, roots: &[PathBuf]) -> anyhow::Result<PathBuf> {
+    // a drive letter is the only colon that doesn't change the volume specification
+    let has_drive = path.as_bytes().get(1) == Some(&b':');
+    let rest = if has_drive && cfg!(windows) {
+        &path[2..]
+    } else {
+        path
+    };
+    if rest.contains(':') {
+        return Err(anyhow::anyhow!("invalid path {}", path));
+    }
+
+    let expanded = PathBuf::from(shellexpand::tilde(path).to_string());
+    let host = match expanded.canonicalize() {
+        Ok(host) => host,
+        Err(_) if output => {
+            let name = match expanded.components().next_back() {
+                Some(Component::Normal(name)) => name,
+                _ => return Err(anyhow::anyhow!("invalid path {}", path)),
+            };
+            std::path::absolute(&expanded)?
+                .parent()
+                .and_then(|parent| parent.canonicalize().ok())
+                .ok_or_else(|| anyhow::anyhow!("directory of {} does not exist", path))?
+                .join(name)
+        }
+        Err(_) => return Err(anyhow::anyhow!("path {} does not exist", path)),
+    };
+
+    if roots.iter().any(|root| host.starts_with(root)) {
+        Ok(host)
+    } else {
+        Err(anyhow::anyhow!(
+            "path {} is not in the allowed roots of the configuration",
+            path
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let roots = vec![root.join("work")];
+        std::fs::create_dir(&roots[0]).unwrap();
+        std::fs::write(roots[0].join("targets.txt"), "127.0.0.1").unwrap();
+
+        let targets = roots[0].join("targets.txt").display().to_string();
+        assert_eq!(
+            check_in(&targets, false, &roots).unwrap(),
+            roots[0].join("targets.txt")
+        );
+        // nothing is allowed without roots
+        assert!(check_in(&targets, false, &[]).is_err());
+
+        // outputs don't exist yet
+        let output = roots[0].join("scan.xml").display().to_string();
+        assert!(check_in(&output, false, &roots).is_err());
+        assert_eq!(
+            check_in(&output, true, &roots).unwrap(),
+            roots[0].join("scan.xml")
+        );
+
+        // outside of the roots, including through ..
+        assert!(check_in("/", false, &roots).is_err());
+        assert!(check_in(&root.display().to_string(), false, &roots).is_err());
+        let escape = format!("{}/../targets.txt", roots[0].display());
+        assert!(check_in(&escape, true, &roots).is_err());
+        let parent = format!("{}/..", roots[0].display());
+        assert!(check_in(&parent, true, &roots).is_err());
+
+        // colons change the volume specification
+        std::fs::write(roots[0].join("a:b"), "").unwrap();
+        let colon = roots[0].join("a:b").display().to_string();
+        assert!(check_in(&colon, false, &roots).is_err());
+    }
+}
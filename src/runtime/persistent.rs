@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::OnceCell;
+
+use crate::book::Container;
+
+use super::pool::{self, WarmContainer};
+
+// the persistent container of each function
+static CONTAINERS: LazyLock<Mutex<HashMap<String, Arc<Persistent>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+struct Persistent {
+    // configuration the container was started with
+    key: String,
+    warm: OnceCell<WarmContainer>,
+    usage: Mutex<Usage>,
+}
+
+#[derive(Clone, Copy)]
+struct Usage {
+    running: usize,
+    last_used: Instant,
+}
+
+/// A call executed in the persistent container of a function, which is not removed while any is
+/// running.
+pub(crate) struct Lease {
+    persistent: Arc<Persistent>,
+    /// Name of the container.
+    pub name: String,
+    /// Entrypoint of the image, to execute the command line with.
+    pub entrypoint: Vec<String>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut usage = self.persistent.usage.lock().unwrap();
+        usage.running -= 1;
+        usage.last_used = Instant::now();
+    }
+}
+
+/// Returns true if the calls of the function are executed in its persistent container.
+pub(crate) fn supports(container: &Container) -> bool {
+    if !container.persistent {
+        return false;
+    }
+    let supported = pool::can_keep_alive(container);
+    if !supported {
+        log::warn!(
+            "{} can't be kept running, starting a container for each call",
+            container.source.image()
+        );
+    }
+    supported
+}
+
+/// Take the persistent container of the function, starting it if needed.
+pub(crate) async fn lease(function: &str, container: &Container) -> anyhow::Result<Lease> {
+    let idle_timeout = container.idle_timeout()?;
+    let key = pool::pool_key(container);
+
+    let persistent = {
+        let mut containers = CONTAINERS.lock().unwrap();
+        let persistent = containers
+            .entry(function.to_string())
+            // replaced if the function changed, the previous one is removed once idle
+            .and_modify(|persistent| {
+                if persistent.key != key {
+                    *persistent = Persistent::new(&key);
+                }
+            })
+            .or_insert_with(|| Persistent::new(&key))
+            .clone();
+        persistent.usage.lock().unwrap().running += 1;
+        persistent
+    };
+
+    // the lease keeps the container from being removed while it starts
+    let mut lease = Lease {
+        persistent: persistent.clone(),
+        name: String::new(),
+        entrypoint: Vec::new(),
+    };
+
+    let warm = persistent
+        .warm
+        .get_or_try_init(|| async {
+            let labeled = pool::labeled(function, container);
+
+            log::info!(
+                "starting persistent container for {} from {}",
+                function,
+                container.source.image()
+            );
+            let warm = pool::start(&labeled).await?;
+            reap_when_idle(
+                function.to_string(),
+                Arc::downgrade(&persistent),
+                idle_timeout,
+            );
+            Ok::<_, anyhow::Error>(warm)
+        })
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "failed to start the persistent container of {}: {}",
+                function,
+                e
+            )
+        })?;
+
+    lease.name = warm.tracked.name.clone();
+    lease.entrypoint = warm.entrypoint.clone();
+    Ok(lease)
+}
+
+impl Persistent {
+    fn new(key: &str) -> Arc<Self> {
+        Arc::new(Self {
+            key: key.to_string(),
+            warm: OnceCell::new(),
+            usage: Mutex::new(Usage {
+                running: 0,
+                last_used: Instant::now(),
+            }),
+        })
+    }
+
+    // how long until the container can be removed, none if it can be now
+    fn idle_for(&self, idle_timeout: Duration) -> Option<Duration> {
+        let usage = *self.usage.lock().unwrap();
+        if usage.running > 0 {
+            Some(idle_timeout)
+        } else {
+            idle_timeout.checked_sub(usage.last_used.elapsed())
+        }
+    }
+}
+
+// the container is removed once the last reference to it is dropped
+fn reap_when_idle(function: String, persistent: Weak<Persistent>, idle_timeout: Duration) {
+    tokio::spawn(async move {
+        loop {
+            // already replaced or flushed
+            let Some(persistent) = persistent.upgrade() else {
+                return;
+            };
+
+            let Some(remaining) = persistent.idle_for(idle_timeout) else {
+                log::info!(
+                    "removing the persistent container of {} after {:?} without calls",
+                    function,
+                    idle_timeout
+                );
+                let mut containers = CONTAINERS.lock().unwrap();
+                if containers
+                    .get(&function)
+                    .is_some_and(|current| Arc::ptr_eq(current, &persistent))
+                {
+                    containers.remove(&function);
+                }
+                return;
+            };
+
+            drop(persistent);
+            tokio::time::sleep(remaining.max(Duration::from_secs(1))).await;
+        }
+    });
+}
+
+/// Remove the persistent containers without running calls, returns how many were removed.
+pub(crate) fn flush() -> usize {
+    let mut containers = CONTAINERS.lock().unwrap();
+    let before = containers.len();
+    containers.retain(|_, persistent| persistent.usage.lock().unwrap().running > 0);
+    before - containers.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_for() {
+        let persistent = Persistent::new("key");
+        let timeout = Duration::from_secs(60);
+        assert!(persistent.idle_for(timeout).unwrap() <= timeout);
+
+        persistent.usage.lock().unwrap().last_used = Instant::now() - Duration::from_secs(120);
+        assert!(persistent.idle_for(timeout).is_none());
+
+        // never removed while calls are running
+        persistent.usage.lock().unwrap().running = 1;
+        assert_eq!(persistent.idle_for(timeout), Some(timeout));
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let container: Container =
+            serde_yaml::from_str("image: alpine\npersistent: true\nidle_timeout: 30m").unwrap();
+        assert!(container.persistent);
+        assert_eq!(container.idle_timeout().unwrap(), Duration::from_secs(1800));
+
+        let container: Container = serde_yaml::from_str("image: alpine").unwrap();
+        assert!(!container.persistent);
+        assert_eq!(container.idle_timeout().unwrap(), Duration::from_secs(600));
+    }
+}
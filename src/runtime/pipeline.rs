@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use crate::book::{
+    flavors::openai,
+    runtime::{ExecutionContext, FunctionRef},
+    Book,
+};
+
+use super::{
+    approval, audit::Provenance, exit_code, sandbox, scope, ssh::SSHConnection, workers::Workers,
+};
+
+/// Execute the steps of a pipeline in sequence, each as a call of its own function, and returns
+/// the output of the last one. The pipeline stops at the first step that fails.
+pub(crate) async fn execute(
+    ssh: Option<SSHConnection>,
+    require_ssh: bool,
+    interactive: bool,
+    workers: Arc<Workers>,
+    book: Arc<Book>,
+    function: &FunctionRef<'_>,
+    call: &openai::Call,
+) -> anyhow::Result<openai::CallResultMessage> {
+    let ExecutionContext::Pipeline(steps) = &function.function.execution else {
+        return Err(anyhow::anyhow!(
+            "function {} is not a pipeline",
+            &function.name
+        ));
+    };
+    book.check_pipeline(&function.name)?;
+
+    let arguments = function.coerce_arguments(&call.function.arguments)?;
+    function.validate_arguments(&arguments)?;
+    scope::check_arguments(function, &arguments)?;
+    function.check_client_env(&call.env)?;
+
+    // the same condition as the calls of the steps, which can be approved from the server queue
+    let can_approve = interactive || approval::queue_enabled();
+    if !can_approve && sandbox::requires_approval(function.function.destructive) {
+        return Err(anyhow::anyhow!(
+            "function {} requires approval by the sandbox profile, run it interactively",
+            call.function.name
+        ));
+    }
+
+    let mut outputs = Vec::new();
+    let mut result = None;
+    for (index, step) in steps.iter().enumerate() {
+        let step_function = book.get_function(&step.function)?;
+        log::info!(
+            "pipeline {} step {}: {}",
+            &function.name,
+            index,
+            &step.function
+        );
+
+        let step_call = openai::Call {
+            id: call.id.clone(),
+            call_type: call.call_type.clone(),
+            function: openai::FunctionCall {
+                name: step.function.clone(),
+                arguments: step.resolve_arguments(&arguments, &outputs, &call.env)?,
+            },
+            priority: call.priority,
+            caller: call.caller.clone(),
+            // each step only gets the variables its function accepts
+            env: call
+                .env
+                .iter()
+                .filter(|(name, _)| step_function.function.client_env.contains(name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            hosts: Vec::new(),
+            provenance: step
+                .arguments
+                .keys()
+                .map(|name| (name.clone(), Provenance::Step))
+                .collect(),
+        };
+
+        let message = Box::pin(super::execute_call_on(
+            ssh.clone(),
+            require_ssh,
+            interactive,
+            workers.clone(),
+            book.clone(),
+            step_call,
+        ))
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "step {} ({}) of pipeline {} failed: {}",
+                index,
+                &step.function,
+                &function.name,
+                e
+            )
+        })?;
+
+        if exit_code(&message.content).is_some_and(|code| code != 0) {
+            log::warn!(
+                "step {} ({}) of pipeline {} failed, stopping",
+                index,
+                &step.function,
+                &function.name
+            );
+            return Ok(message);
+        }
+
+        outputs.push(message.content.clone());
+        result = Some(message);
+    }
+
+    result.ok_or_else(|| anyhow::anyhow!("pipeline {} has no steps", &function.name))
+}
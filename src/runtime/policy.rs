@@ -0,0 +1,389 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::book::runtime::FunctionRef;
+
+use super::{
+    safe_mode::glob_regex,
+    scope::{Scope, TARGET_TYPE},
+    CommandLine,
+};
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// What happens to the calls matching a rule.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Action {
+    #[default]
+    Allow,
+    Deny,
+    /// Executed only if approved by the user, denied when not interactive.
+    Ask,
+}
+
+/// A rule matching the calls that meet all of its conditions.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Rule {
+    pub action: Action,
+    /// Told to the caller when the call is denied.
+    pub reason: Option<String>,
+    /// Names of the functions, a * matches any characters.
+    pub functions: Vec<String>,
+    /// Binaries executed by the functions, by name or path, a * matches any characters.
+    pub binaries: Vec<String>,
+    /// Regular expressions that values of the arguments must contain, by argument name or * for
+    /// any of them.
+    pub arguments: BTreeMap<String, String>,
+    /// Networks and domains of the target arguments.
+    pub targets: Vec<String>,
+
+    #[serde(skip)]
+    compiled: Compiled,
+}
+
+#[derive(Debug, Default)]
+struct Compiled {
+    functions: Vec<regex::Regex>,
+    binaries: Vec<regex::Regex>,
+    arguments: Vec<(String, regex::Regex)>,
+    targets: Option<Scope>,
+}
+
+/// Rules deciding which calls are executed, the first matching one applies.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Policy {
+    /// Action for the calls matching no rule.
+    pub default: Action,
+    pub rules: Vec<Rule>,
+}
+
+/// What a call needs to execute the command.
+pub(crate) struct Request<'a> {
+    pub function: &'a FunctionRef<'a>,
+    pub arguments: &'a BTreeMap<String, String>,
+    pub command_line: &'a CommandLine,
+}
+
+impl Rule {
+    fn compile(&mut self) -> anyhow::Result<()> {
+        self.compiled = Compiled {
+            functions: self
+                .functions
+                .iter()
+                .map(|pattern| glob_regex(pattern))
+                .collect::<anyhow::Result<_>>()?,
+            binaries: self
+                .binaries
+                .iter()
+                .map(|pattern| glob_regex(pattern))
+                .collect::<anyhow::Result<_>>()?,
+            arguments: self
+                .arguments
+                .iter()
+                .map(|(name, pattern)| {
+                    regex::Regex::new(pattern)
+                        .map(|regex| (name.clone(), regex))
+                        .map_err(|e| {
+                            anyhow::anyhow!(
+                                "invalid pattern {} of argument {}: {}",
+                                pattern,
+                                name,
+                                e
+                            )
+                        })
+                })
+                .collect::<anyhow::Result<_>>()?,
+            targets: if self.targets.is_empty() {
+                None
+            } else {
+                Some(Scope::from_targets(&self.targets)?)
+            },
+        };
+        Ok(())
+    }
+
+    fn matches(&self, request: &Request) -> bool {
+        let compiled = &self.compiled;
+
+        if !compiled.functions.is_empty()
+            && !compiled
+                .functions
+                .iter()
+                .any(|pattern| pattern.is_match(&request.function.name))
+        {
+            return false;
+        }
+
+        if !compiled.binaries.is_empty() {
+            let app = &request.command_line.app;
+            let name = std::path::Path::new(app)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if !compiled
+                .binaries
+                .iter()
+                .any(|pattern| pattern.is_match(app) || pattern.is_match(&name))
+            {
+                return false;
+            }
+        }
+
+        for (name, pattern) in &compiled.arguments {
+            let matched = request
+                .arguments
+                .iter()
+                .filter(|(arg_name, _)| name == "*" || *arg_name == name)
+                .any(|(_, value)| pattern.is_match(value));
+            if !matched {
+                return false;
+            }
+        }
+
+        if let Some(targets) = &compiled.targets {
+            // lists of targets are joined with commas
+            let values: Vec<&str> = request
+                .arguments
+                .iter()
+                .filter(|(name, value)| {
+                    !value.is_empty()
+                        && request
+                            .function
+                            .function
+                            .parameters
+                            .get(*name)
+                            .is_some_and(|param| param.param_type == TARGET_TYPE)
+                })
+                .flat_map(|(_, value)| value.split(','))
+                .collect();
+            let in_targets = |target: &&str| targets.check(target).is_ok();
+            // a call is only allowed if none of its targets is outside of the rule, and denied if
+            // any of them is inside
+            let matched = !values.is_empty()
+                && if self.action == Action::Allow {
+                    values.iter().all(in_targets)
+                } else {
+                    values.iter().any(in_targets)
+                };
+            if !matched {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl Policy {
+    pub fn from_path(path: &str) -> anyhow::Result<Self> {
+        let path = shellexpand::full(path)
+            .map_err(|e| anyhow::anyhow!("failed to expand path {}: {}", path, e))?
+            .to_string();
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("error while reading {}: {}", path, e))?;
+
+        Self::from_str(&data).map_err(|e| anyhow::anyhow!("error while parsing {}: {}", path, e))
+    }
+
+    fn from_str(data: &str) -> anyhow::Result<Self> {
+        let mut policy: Self = serde_yaml::from_str(data)?;
+        for (index, rule) in policy.rules.iter_mut().enumerate() {
+            rule.compile()
+                .map_err(|e| anyhow::anyhow!("rule {}: {}", index, e))?;
+        }
+        Ok(policy)
+    }
+
+    /// The action for the request, with the index of the rule deciding it if any.
+    fn decide(&self, request: &Request) -> (Action, Option<(usize, &Rule)>) {
+        match self
+            .rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(request))
+        {
+            Some((index, rule)) => (rule.action, Some((index, rule))),
+            None => (self.default, None),
+        }
+    }
+}
+
+/// Set the policy deciding which calls of this process are executed.
+pub(crate) fn set(policy: Policy) {
+    log::info!(
+        "policy: {} rules, {:?} by default",
+        policy.rules.len(),
+        policy.default
+    );
+    if POLICY.set(policy).is_err() {
+        log::warn!("policy already set");
+    }
+}
+
+/// Returns an error if the policy, if set, denies the call or requires an approval that can't be
+/// asked for, otherwise the action of the policy.
+pub(crate) fn check(request: &Request, interactive: bool) -> anyhow::Result<Action> {
+    let Some(policy) = POLICY.get() else {
+        return Ok(Action::Allow);
+    };
+
+    let (action, rule) = policy.decide(request);
+    let decided_by = match rule {
+        Some((index, rule)) => match &rule.reason {
+            Some(reason) => format!("rule {} ({})", index, reason),
+            None => format!("rule {}", index),
+        },
+        None => "default".to_string(),
+    };
+    log::debug!(
+        "policy {:?} for function {} by {}",
+        action,
+        &request.function.name,
+        &decided_by
+    );
+
+    match action {
+        Action::Allow => Ok(action),
+        Action::Ask if interactive => Ok(action),
+        Action::Ask => Err(anyhow::anyhow!(
+            "function {} requires approval by policy {}, run it interactively",
+            &request.function.name,
+            decided_by
+        )),
+        Action::Deny => Err(anyhow::anyhow!(
+            "function {} denied by policy {}",
+            &request.function.name,
+            decided_by
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+    use crate::book::Page;
+
+    const PAGE: &str = r#"
+functions:
+  curl_get:
+    description: Fetch an URL.
+    parameters:
+      url:
+        type: target
+        description: The URL.
+    cmdline: [curl, "${url}"]
+  wipe:
+    description: Remove a path.
+    parameters:
+      path:
+        type: string
+        description: The path.
+    cmdline: [rm, -rf, "${path}"]
+"#;
+
+    const POLICY: &str = r#"
+default: ask
+rules:
+  - action: deny
+    binaries: [rm]
+    reason: nothing gets removed
+  - action: deny
+    arguments: {"*": "^file://"}
+  - action: allow
+    functions: [curl_*]
+    targets: [10.0.0.0/8, example.com]
+"#;
+
+    fn decide(policy: &Policy, page: &Page, name: &str, value: &str) -> (Action, Option<usize>) {
+        let path = Utf8PathBuf::from("test.yml");
+        let function = FunctionRef {
+            name: name.to_string(),
+            path: &path,
+            page,
+            function: &page.functions[name],
+        };
+        let arguments = function
+            .function
+            .parameters
+            .keys()
+            .map(|name| (name.clone(), value.to_string()))
+            .collect();
+        let command_line = function
+            .resolve_command_line(&arguments, &BTreeMap::new())
+            .unwrap();
+        let request = Request {
+            function: &function,
+            arguments: &arguments,
+            command_line: &command_line,
+        };
+        let (action, rule) = policy.decide(&request);
+        (action, rule.map(|(index, _)| index))
+    }
+
+    #[test]
+    fn test_decide() {
+        let page: Page = serde_yaml::from_str(PAGE).unwrap();
+        let policy = Policy::from_str(POLICY).unwrap();
+
+        assert_eq!(
+            decide(&policy, &page, "wipe", "/tmp/x"),
+            (Action::Deny, Some(0))
+        );
+        assert_eq!(
+            decide(&policy, &page, "curl_get", "file:///etc/passwd"),
+            (Action::Deny, Some(1))
+        );
+        assert_eq!(
+            decide(&policy, &page, "curl_get", "https://www.example.com/"),
+            (Action::Allow, Some(2))
+        );
+        assert_eq!(
+            decide(&policy, &page, "curl_get", "http://10.1.2.3:8080"),
+            (Action::Allow, Some(2))
+        );
+        assert_eq!(
+            decide(&policy, &page, "curl_get", "https://example.org/"),
+            (Action::Ask, None)
+        );
+    }
+
+    #[test]
+    fn test_decide_mixed_targets() {
+        let page: Page = serde_yaml::from_str(PAGE).unwrap();
+        let policy = Policy::from_str(
+            "default: ask\nrules:\n  - action: allow\n    targets: [10.0.0.0/8]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            decide(&policy, &page, "curl_get", "10.0.0.1,10.0.0.2"),
+            (Action::Allow, Some(0))
+        );
+        // one target out of the network is enough to not be allowed
+        assert_eq!(
+            decide(&policy, &page, "curl_get", "10.0.0.1,8.8.8.8"),
+            (Action::Ask, None)
+        );
+
+        let policy = Policy::from_str(
+            "default: allow\nrules:\n  - action: deny\n    targets: [10.0.0.0/8]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            decide(&policy, &page, "curl_get", "8.8.8.8,10.0.0.1"),
+            (Action::Deny, Some(0))
+        );
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        assert!(Policy::from_str("rules:\n  - action: deny\n    arguments: {url: '('}\n").is_err());
+        assert!(Policy::from_str("rules:\n  - action: maybe\n").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        LazyLock, Mutex,
+    },
+};
+
+use tokio::process::Command;
+
+use crate::book::Container;
+
+use super::{get_container_runtime, TrackedContainer, FUNCTION_LABEL};
+
+// number of idle containers to keep for each container configuration, 0 to disable
+static POOL_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+static POOLS: LazyLock<Mutex<HashMap<String, Pool>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Default)]
+struct Pool {
+    idle: Vec<WarmContainer>,
+    starting: usize,
+    // a container failed to start, such as for images without sleep, it's not refilled anymore
+    failed: bool,
+}
+
+/// An idle container waiting for a command to execute.
+pub(crate) struct WarmContainer {
+    pub tracked: TrackedContainer,
+    /// Entrypoint of the image, replaced by sleep to keep the container alive.
+    pub entrypoint: Vec<String>,
+}
+
+pub(crate) fn set_size(size: usize) {
+    POOL_SIZE.store(size, Ordering::Relaxed);
+}
+
+fn size() -> usize {
+    POOL_SIZE.load(Ordering::Relaxed)
+}
+
+/// Returns true if calls for this container can be executed in a warm container.
+fn supports(container: &Container) -> bool {
+    size() > 0 && can_keep_alive(container)
+}
+
+/// Returns true if containers of this configuration can be kept running to execute calls in them.
+pub(super) fn can_keep_alive(container: &Container) -> bool {
+    super::backend::is_docker_compatible()
+        // the container is kept alive by replacing its entrypoint with sleep
+        && !container.is_windows()
+        && !container
+            .args
+            .as_ref()
+            .is_some_and(|args| args.iter().any(|arg| arg.starts_with("--entrypoint")))
+}
+
+// containers can only be shared by functions with the same configuration
+pub(super) fn pool_key(container: &Container) -> String {
+    serde_yaml::to_string(container).unwrap_or_default()
+}
+
+/// The configuration labeled with the function, to show up with it in the containers list.
+pub(super) fn labeled(function: &str, container: &Container) -> Container {
+    let mut labeled = container.clone();
+    labeled
+        .labels
+        .get_or_insert_with(Default::default)
+        .insert(FUNCTION_LABEL.to_string(), function.to_string());
+    labeled
+}
+
+/// Remove all the idle containers, returns how many were removed. Configurations that failed to
+/// start are tried again.
+pub(crate) fn flush() -> usize {
+    // dropping the tracked containers removes them
+    POOLS
+        .lock()
+        .unwrap()
+        .values_mut()
+        .map(|pool| {
+            pool.failed = false;
+            pool.idle.drain(..).count()
+        })
+        .sum()
+}
+
+/// Take an idle container of the function if any, the pool is refilled in the background.
+pub(crate) fn take(function: &str, container: &Container) -> Option<WarmContainer> {
+    if !supports(container) {
+        return None;
+    }
+
+    let container = &labeled(function, container);
+    let key = pool_key(container);
+    let warm = POOLS
+        .lock()
+        .unwrap()
+        .entry(key.clone())
+        .or_default()
+        .idle
+        .pop();
+
+    refill(key, container.clone());
+
+    if warm.is_none() {
+        log::debug!(
+            "no warm container available for {}",
+            container.source.image()
+        );
+    }
+
+    warm
+}
+
+fn refill(key: String, container: Container) {
+    let missing = {
+        let mut pools = POOLS.lock().unwrap();
+        let pool = pools.entry(key.clone()).or_default();
+        if pool.failed {
+            return;
+        }
+        let missing = size().saturating_sub(pool.idle.len() + pool.starting);
+        pool.starting += missing;
+        missing
+    };
+
+    for _ in 0..missing {
+        let key = key.clone();
+        let container = container.clone();
+        tokio::spawn(async move {
+            let started = start(&container).await;
+
+            let mut pools = POOLS.lock().unwrap();
+            let pool = pools.entry(key).or_default();
+            pool.starting -= 1;
+
+            match started {
+                Ok(warm) => pool.idle.push(warm),
+                Err(e) => {
+                    pool.failed = true;
+                    log::error!("failed to start warm container, not trying again: {}", e);
+                }
+            }
+        });
+    }
+}
+
+pub(super) async fn start(container: &Container) -> anyhow::Result<WarmContainer> {
+    let runtime = get_container_runtime();
+    let image = container.run_image()?;
+
+    let entrypoint = get_entrypoint(&runtime, &image).await?;
+
+    // removed once the command executed in it completes
+    let tracked = TrackedContainer::disposable();
+
+    let mut args = vec![
+        "run".to_string(),
+        "-d".to_string(),
+        format!("--name={}", &tracked.name),
+    ];
+    args.extend(container.run_options());
+    args.extend([
+        "--entrypoint".to_string(),
+        "sleep".to_string(),
+        image.clone(),
+        "infinity".to_string(),
+    ]);
+
+    log::debug!("starting warm container {} for {}", &tracked.name, image);
+
+    let output = Command::new(&runtime)
+        .args(&args)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", runtime, e))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{} {} failed: {}",
+            runtime,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(WarmContainer {
+        tracked,
+        entrypoint,
+    })
+}
+
+async fn get_entrypoint(runtime: &str, image: &str) -> anyhow::Result<Vec<String>> {
+    let output = Command::new(runtime)
+        .args([
+            "image",
+            "inspect",
+            "-f",
+            "{{json .Config.Entrypoint}}",
+            image,
+        ])
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", runtime, e))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "failed to inspect image {}: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    parse_entrypoint(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_entrypoint(json: &str) -> anyhow::Result<Vec<String>> {
+    let entrypoint: Option<Vec<String>> = serde_json::from_str(json.trim())
+        .map_err(|e| anyhow::anyhow!("invalid entrypoint '{}': {}", json.trim(), e))?;
+    Ok(entrypoint.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entrypoint() {
+        assert_eq!(parse_entrypoint("null\n").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            parse_entrypoint(r#"["/usr/bin/nmap","-v"]"#).unwrap(),
+            vec!["/usr/bin/nmap", "-v"]
+        );
+        assert!(parse_entrypoint("not json").is_err());
+    }
+
+    #[test]
+    fn test_labeled() {
+        let container: Container = serde_yaml::from_str("image: nmap").unwrap();
+        let labels = labeled("nmap_scan", &container).labels.unwrap();
+        assert_eq!(labels.get(FUNCTION_LABEL), Some(&"nmap_scan".to_string()));
+        // functions don't share their warm containers
+        assert_ne!(
+            pool_key(&labeled("nmap_scan", &container)),
+            pool_key(&labeled("nmap_ping", &container))
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::{sync::OnceLock, time::Instant};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+static PROGRESS: OnceLock<Progress> = OnceLock::new();
+
+/// How progress is reported to the programs wrapping the command line.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ProgressFormat {
+    /// One JSON object per line on stderr.
+    Json,
+}
+
+#[derive(Debug)]
+struct Progress {
+    format: ProgressFormat,
+    started: Instant,
+}
+
+/// A step of the command, reported as {"event": "snake_case_name", ...fields}.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event<'a> {
+    LoadingBook {
+        path: &'a str,
+    },
+    BookLoaded {
+        pages: usize,
+        functions: usize,
+    },
+    Downloading {
+        source: &'a str,
+    },
+    Extracting {
+        path: &'a str,
+    },
+    Validating {
+        function: &'a str,
+        /// Starting from 1, out of total.
+        current: usize,
+        total: usize,
+    },
+    ResolvingContainer {
+        image: &'a str,
+    },
+    PullingImage {
+        image: &'a str,
+    },
+    BuildingImage {
+        image: &'a str,
+    },
+    ContainerResolved {
+        image: &'a str,
+        /// Starting from 1, out of total.
+        current: usize,
+        total: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Executing {
+        function: &'a str,
+        command: &'a str,
+    },
+    Executed {
+        function: &'a str,
+        exit_code: Option<i64>,
+        duration_ms: u128,
+    },
+    /// Always the last event.
+    Completed {
+        success: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+    /// Since the command started.
+    elapsed_ms: u128,
+}
+
+/// Report the progress of this process in the format.
+pub(crate) fn set(format: ProgressFormat) {
+    let progress = Progress {
+        format,
+        started: Instant::now(),
+    };
+    if PROGRESS.set(progress).is_err() {
+        log::warn!("progress format already set");
+    }
+}
+
+/// Report the event, if progress reporting is enabled.
+pub(crate) fn emit(event: Event) {
+    let Some(progress) = PROGRESS.get() else {
+        return;
+    };
+
+    match progress.format {
+        ProgressFormat::Json => {
+            let line = Line {
+                event: &event,
+                elapsed_ms: progress.started.elapsed().as_millis(),
+            };
+            match serde_json::to_string(&line) {
+                Ok(line) => eprintln!("{}", line),
+                Err(e) => log::error!("could not serialize progress event {:?}: {}", event, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_events() {
+        let line = Line {
+            event: &Event::Executed {
+                function: "nmap_tcp_ports",
+                exit_code: Some(0),
+                duration_ms: 1200,
+            },
+            elapsed_ms: 1500,
+        };
+        assert_eq!(
+            serde_json::to_string(&line).unwrap(),
+            r#"{"event":"executed","function":"nmap_tcp_ports","exit_code":0,"duration_ms":1200,"elapsed_ms":1500}"#
+        );
+
+        let line = Line {
+            event: &Event::Completed {
+                success: true,
+                error: None,
+            },
+            elapsed_ms: 1600,
+        };
+        assert_eq!(
+            serde_json::to_string(&line).unwrap(),
+            r#"{"event":"completed","success":true,"elapsed_ms":1600}"#
+        );
+    }
+}
@@ -17,3 +17,55 @@ pub(crate) fn ask<'a>(prompt: &str, choices: &'a [&'a str]) -> anyhow::Result<St
         }
     }
 }
+
+/// Ask for a value without echoing it, such as a password.
+pub(crate) fn ask_secret(prompt: &str) -> anyhow::Result<String> {
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+
+    let mut user_input = String::new();
+    {
+        #[cfg(unix)]
+        let _echo = EchoOff::new();
+        std::io::stdin().read_line(&mut user_input)?;
+    }
+    println!();
+
+    Ok(user_input.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Disables the echo of the terminal until dropped.
+#[cfg(unix)]
+struct EchoOff(Option<nix::sys::termios::Termios>);
+
+#[cfg(unix)]
+impl EchoOff {
+    fn new() -> Self {
+        use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+
+        // not a terminal, nothing to hide
+        let Ok(original) = tcgetattr(std::io::stdin()) else {
+            return Self(None);
+        };
+
+        let mut silent = original.clone();
+        silent.local_flags.remove(LocalFlags::ECHO);
+        match tcsetattr(std::io::stdin(), SetArg::TCSANOW, &silent) {
+            Ok(_) => Self(Some(original)),
+            Err(_) => Self(None),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        if let Some(original) = &self.0 {
+            let _ = nix::sys::termios::tcsetattr(
+                std::io::stdin(),
+                nix::sys::termios::SetArg::TCSANOW,
+                original,
+            );
+        }
+    }
+}
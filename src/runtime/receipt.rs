@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::book::{
+    remote::RemoteFunction, revision::Revision, runtime::FunctionRef, Book, Container,
+};
+
+use super::{backend, docker, ssh::SSHConnection};
+
+// images are not pulled again once present, so their digest is only inspected once
+static IMAGE_DIGESTS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+/// Where the command of a call was executed.
+pub(crate) enum Target<'a> {
+    Host,
+    Ssh(&'a SSHConnection),
+    Container(&'a Container),
+    Remote(&'a RemoteFunction),
+    /// A registered executor selected by the function.
+    Executor(&'a str),
+}
+
+/// What produced the output of a call, to trace a finding back to the exact tool version.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Receipt {
+    pub robopages_version: String,
+    pub executed_at: String,
+    /// Revision of the book, as in the ETag of the tool listings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub book_revision: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_sha256: Option<String>,
+    /// host, ssh, container, remote or the name of the executor selected by the function.
+    pub executor: String,
+    /// Local host name, SSH target or remote server.
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_digest: Option<String>,
+}
+
+impl Receipt {
+    pub async fn new(book: &Book, function: &FunctionRef<'_>, target: Target<'_>) -> Self {
+        let (executor, host) = match &target {
+            Target::Host => ("host", hostname()),
+            Target::Container(_) => ("container", hostname()),
+            Target::Ssh(ssh) => ("ssh", ssh.to_string()),
+            Target::Remote(remote) => ("remote", remote.url().to_string()),
+            Target::Executor(name) => (*name, hostname()),
+        };
+
+        let (image, image_digest) = match &target {
+            Target::Container(container) => {
+                let image = container.source.image().to_string();
+                let digest = image_digest(&image).await;
+                (Some(image), digest)
+            }
+            _ => (None, None),
+        };
+
+        let is_remote = matches!(target, Target::Remote(_));
+
+        Self {
+            robopages_version: env!("CARGO_PKG_VERSION").to_string(),
+            executed_at: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            book_revision: Some(Revision::of(book).id),
+            page: (!is_remote).then(|| function.path.to_string()),
+            page_sha256: if is_remote {
+                None
+            } else {
+                file_sha256(function.path.as_std_path())
+            },
+            executor: executor.to_string(),
+            host,
+            image,
+            image_digest,
+        }
+    }
+}
+
+fn file_sha256(path: &std::path::Path) -> Option<String> {
+    // pages of a bundle are hashed as the bundle they were loaded from
+    let path = path.ancestors().find(|path| path.is_file()).unwrap_or(path);
+    let data = std::fs::read(path)
+        .map_err(|e| log::warn!("could not hash {}: {}", path.display(), e))
+        .ok()?;
+    Some(format!("{:x}", Sha256::digest(&data)))
+}
+
+async fn image_digest(image: &str) -> Option<String> {
+    // there's no local image to inspect with kubectl
+    if !backend::is_docker_compatible() {
+        return None;
+    }
+
+    let digests = IMAGE_DIGESTS.get_or_init(Default::default);
+    if let Some(digest) = digests.lock().unwrap().get(image) {
+        return Some(digest.clone());
+    }
+
+    match docker::image_digest(image).await {
+        Ok(digest) => {
+            digests
+                .lock()
+                .unwrap()
+                .insert(image.to_string(), digest.clone());
+            Some(digest)
+        }
+        Err(e) => {
+            log::warn!("could not get the digest of {}: {}", image, e);
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    nix::unistd::gethostname()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "localhost".to_string())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_host_receipt() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+        let page = "functions:\n  hello:\n    description: Say hello.\n    parameters: {}\n    cmdline: [echo, hello]\n";
+        std::fs::write(root.join("hello.yml"), page).unwrap();
+
+        let book = Book::from_path(root.clone(), None).unwrap();
+        let function = book.get_function("hello").unwrap();
+        let receipt = Receipt::new(&book, &function, Target::Host).await;
+
+        assert_eq!(receipt.executor, "host");
+        assert_eq!(receipt.robopages_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(receipt.book_revision, Some(Revision::of(&book).id));
+        assert_eq!(
+            receipt.page_sha256.unwrap(),
+            format!("{:x}", Sha256::digest(page.as_bytes()))
+        );
+        assert!(receipt.image.is_none());
+    }
+}
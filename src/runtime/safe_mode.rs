@@ -0,0 +1,189 @@
+use std::{collections::BTreeSet, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::book::{runtime::FunctionRef, Book, Function, Page};
+
+static SAFE_MODE: OnceLock<SafeMode> = OnceLock::new();
+
+/// Safe mode settings of the configuration.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct SafeModeConfig {
+    /// Enable the safe mode even without --safe-mode.
+    pub enabled: bool,
+    /// Names of the functions blocked as if they were destructive, a * matches any characters.
+    pub deny: Vec<String>,
+}
+
+/// Hides and refuses to execute the destructive functions and the ones matching the deny patterns,
+/// unless their category is explicitly allowed.
+#[derive(Debug)]
+pub(crate) struct SafeMode {
+    deny: Vec<regex::Regex>,
+    allowed_categories: Vec<String>,
+}
+
+// a * matches any characters, everything else literally
+pub(crate) fn glob_regex(pattern: &str) -> anyhow::Result<regex::Regex> {
+    let regex = pattern
+        .split('*')
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(".*");
+    regex::Regex::new(&format!("^{}$", regex))
+        .map_err(|e| anyhow::anyhow!("invalid pattern {}: {}", pattern, e))
+}
+
+impl SafeMode {
+    pub fn new(config: &SafeModeConfig, allowed_categories: Vec<String>) -> anyhow::Result<Self> {
+        Ok(Self {
+            deny: config
+                .deny
+                .iter()
+                .map(|pattern| glob_regex(pattern))
+                .collect::<anyhow::Result<_>>()?,
+            allowed_categories: allowed_categories
+                .into_iter()
+                .map(|category| category.trim_matches('/').to_string())
+                .collect(),
+        })
+    }
+
+    // allowing a category allows its subcategories
+    fn is_allowed(&self, page: &Page) -> bool {
+        let group = page.group();
+        self.allowed_categories.iter().any(|category| {
+            group == *category
+                || group
+                    .strip_prefix(category.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    /// Returns why the function is blocked, or None if it can be executed.
+    pub fn blocked(&self, name: &str, page: &Page, function: &Function) -> Option<String> {
+        let reason = if function.destructive {
+            "it is destructive".to_string()
+        } else if let Some(pattern) = self.deny.iter().find(|deny| deny.is_match(name)) {
+            format!("it matches the deny pattern {}", pattern)
+        } else {
+            return None;
+        };
+
+        if self.is_allowed(page) {
+            return None;
+        }
+        Some(reason)
+    }
+}
+
+/// Enable the safe mode for this process.
+pub(crate) fn set(safe_mode: SafeMode) {
+    log::info!(
+        "safe mode enabled, allowed categories: {}",
+        if safe_mode.allowed_categories.is_empty() {
+            "none".to_string()
+        } else {
+            safe_mode.allowed_categories.join(", ")
+        }
+    );
+    if SAFE_MODE.set(safe_mode).is_err() {
+        log::warn!("safe mode already set");
+    }
+}
+
+/// Names of the functions of the book blocked by the safe mode, if enabled.
+pub(crate) fn hidden(book: &Book) -> BTreeSet<String> {
+    let Some(safe_mode) = SAFE_MODE.get() else {
+        return BTreeSet::new();
+    };
+
+    book.pages
+        .values()
+        .flat_map(|page| {
+            page.functions
+                .iter()
+                .filter(|(name, function)| safe_mode.blocked(name, page, function).is_some())
+                .map(|(name, _)| name.clone())
+        })
+        .collect()
+}
+
+/// Returns an error if the safe mode is enabled and blocks the function.
+pub(crate) fn check(function: &FunctionRef) -> anyhow::Result<()> {
+    let Some(safe_mode) = SAFE_MODE.get() else {
+        return Ok(());
+    };
+
+    match safe_mode.blocked(&function.name, function.page, function.function) {
+        Some(reason) => Err(anyhow::anyhow!(
+            "function {} is blocked by the safe mode because {}, allow its category with --unsafe-allow {}",
+            &function.name,
+            reason,
+            function.page.group()
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"
+categories: [offensive, network]
+functions:
+  nmap_tcp_ports:
+    description: Scan ports.
+    parameters:
+      target:
+        type: target
+        description: The target.
+    cmdline: [nmap, "${target}"]
+  msf_exploit:
+    description: Exploit a service.
+    parameters:
+      target:
+        type: target
+        description: The target.
+    cmdline: [msfconsole, "${target}"]
+  wipe_logs:
+    description: Wipe the logs.
+    destructive: true
+    parameters:
+      path:
+        type: string
+        description: The path.
+    cmdline: [rm, "${path}"]
+"#;
+
+    #[test]
+    fn test_blocked() {
+        let page: Page = serde_yaml::from_str(PAGE).unwrap();
+        let config = SafeModeConfig {
+            enabled: true,
+            deny: vec!["*_exploit*".to_string()],
+        };
+        let blocked = |safe_mode: &SafeMode, name: &str| {
+            safe_mode
+                .blocked(name, &page, &page.functions[name])
+                .is_some()
+        };
+
+        let safe_mode = SafeMode::new(&config, Vec::new()).unwrap();
+        assert!(!blocked(&safe_mode, "nmap_tcp_ports"));
+        assert!(blocked(&safe_mode, "msf_exploit"));
+        assert!(blocked(&safe_mode, "wipe_logs"));
+
+        let safe_mode = SafeMode::new(&config, vec!["offensive".to_string()]).unwrap();
+        assert!(!blocked(&safe_mode, "msf_exploit"));
+        assert!(!blocked(&safe_mode, "wipe_logs"));
+
+        let safe_mode = SafeMode::new(&config, vec!["offensive/network".to_string()]).unwrap();
+        assert!(!blocked(&safe_mode, "wipe_logs"));
+
+        let safe_mode = SafeMode::new(&config, vec!["offensive/net".to_string()]).unwrap();
+        assert!(blocked(&safe_mode, "wipe_logs"));
+    }
+}
@@ -0,0 +1,184 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use serde::Deserialize;
+
+static PROFILE: OnceLock<SandboxProfile> = OnceLock::new();
+
+// always passed to host commands when the environment is cleaned, or most tools would break
+const BASE_ENV: &[&str] = &["PATH", "HOME", "USER", "LANG", "TERM", "TMPDIR"];
+
+/// Which functions need to be approved by a user before being executed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Approval {
+    #[default]
+    None,
+    Destructive,
+    All,
+}
+
+/// Hardening defaults applied to every function.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct SandboxProfile {
+    /// Container network (--network), for instance none or bridge.
+    pub network: Option<String>,
+    /// Container memory limit (--memory), for instance 512m.
+    pub memory: Option<String>,
+    /// Container CPUs limit (--cpus).
+    pub cpus: Option<String>,
+    /// Container processes limit (--pids-limit).
+    pub pids_limit: Option<u64>,
+    /// Only pass the base variables (PATH, HOME ...) and these ones to host commands.
+    pub clean_env: bool,
+    pub allowed_env: Vec<String>,
+    pub approval: Approval,
+}
+
+impl SandboxProfile {
+    /// The built in profiles, strict, standard and yolo.
+    pub fn builtin() -> BTreeMap<String, Self> {
+        BTreeMap::from([
+            (
+                "strict".to_string(),
+                Self {
+                    network: Some("none".to_string()),
+                    memory: Some("1g".to_string()),
+                    cpus: Some("1".to_string()),
+                    pids_limit: Some(256),
+                    clean_env: true,
+                    allowed_env: Vec::new(),
+                    approval: Approval::All,
+                },
+            ),
+            (
+                "standard".to_string(),
+                Self {
+                    network: None,
+                    memory: Some("4g".to_string()),
+                    cpus: Some("2".to_string()),
+                    pids_limit: Some(1024),
+                    clean_env: true,
+                    allowed_env: vec![
+                        "HTTP_PROXY".to_string(),
+                        "HTTPS_PROXY".to_string(),
+                        "NO_PROXY".to_string(),
+                    ],
+                    approval: Approval::Destructive,
+                },
+            ),
+            ("yolo".to_string(), Self::default()),
+        ])
+    }
+
+    /// Returns the profile with the given name, profiles in the configuration override the built
+    /// in ones.
+    pub fn named(name: &str, configured: &BTreeMap<String, Self>) -> anyhow::Result<Self> {
+        configured
+            .get(name)
+            .cloned()
+            .or_else(|| Self::builtin().remove(name))
+            .ok_or_else(|| anyhow::anyhow!("unknown sandbox profile: {}", name))
+    }
+
+    fn container_options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        if let Some(network) = &self.network {
+            options.push(format!("--network={}", network));
+        }
+        if let Some(memory) = &self.memory {
+            options.push(format!("--memory={}", memory));
+        }
+        if let Some(cpus) = &self.cpus {
+            options.push(format!("--cpus={}", cpus));
+        }
+        if let Some(pids_limit) = self.pids_limit {
+            options.push(format!("--pids-limit={}", pids_limit));
+        }
+        options
+    }
+
+    fn allows_env(&self, name: &str) -> bool {
+        BASE_ENV.contains(&name) || self.allowed_env.iter().any(|allowed| allowed == name)
+    }
+}
+
+/// Set the sandbox profile for this process.
+pub(crate) fn set(name: &str, profile: SandboxProfile) {
+    log::info!("sandbox profile: {}", name);
+    if PROFILE.set(profile).is_err() {
+        log::warn!("sandbox profile already set");
+    }
+}
+
+/// Options for the container runtime enforcing the limits of the profile, if set.
+pub(crate) fn container_options() -> Vec<String> {
+    PROFILE
+        .get()
+        .map(|profile| profile.container_options())
+        .unwrap_or_default()
+}
+
+/// Returns the environment variables of this process to pass to host commands, or None if the
+/// environment is not cleaned.
+pub(crate) fn allowed_env() -> Option<Vec<(String, String)>> {
+    let profile = PROFILE.get().filter(|profile| profile.clean_env)?;
+    Some(
+        std::env::vars()
+            .filter(|(name, _)| profile.allows_env(name))
+            .collect(),
+    )
+}
+
+/// Returns true if the function needs to be approved by a user before being executed.
+pub(crate) fn requires_approval(destructive: bool) -> bool {
+    match PROFILE.get().map(|profile| profile.approval) {
+        Some(Approval::All) => true,
+        Some(Approval::Destructive) => destructive,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_profiles() {
+        let strict = SandboxProfile::named("strict", &BTreeMap::new()).unwrap();
+        assert_eq!(
+            strict.container_options(),
+            vec![
+                "--network=none",
+                "--memory=1g",
+                "--cpus=1",
+                "--pids-limit=256"
+            ]
+        );
+        assert!(strict.clean_env);
+        assert_eq!(strict.approval, Approval::All);
+
+        let yolo = SandboxProfile::named("yolo", &BTreeMap::new()).unwrap();
+        assert!(yolo.container_options().is_empty());
+        assert!(!yolo.clean_env);
+
+        let configured: BTreeMap<String, SandboxProfile> =
+            serde_yaml::from_str("strict:\n  network: bridge\n  approval: destructive\n").unwrap();
+        let strict = SandboxProfile::named("strict", &configured).unwrap();
+        assert_eq!(strict.container_options(), vec!["--network=bridge"]);
+        assert_eq!(strict.approval, Approval::Destructive);
+
+        assert!(SandboxProfile::named("nope", &configured).is_err());
+    }
+
+    #[test]
+    fn test_allows_env() {
+        let profile = SandboxProfile {
+            allowed_env: vec!["SHODAN_API_KEY".to_string()],
+            ..Default::default()
+        };
+        assert!(profile.allows_env("PATH"));
+        assert!(profile.allows_env("SHODAN_API_KEY"));
+        assert!(!profile.allows_env("AWS_SECRET_ACCESS_KEY"));
+    }
+}
@@ -0,0 +1,258 @@
+use std::{collections::BTreeMap, net::IpAddr, sync::OnceLock};
+
+use ipnet::IpNet;
+use serde::Deserialize;
+
+use crate::book::runtime::FunctionRef;
+
+/// Parameter type of the values that must be within the engagement scope.
+pub(crate) const TARGET_TYPE: &str = "target";
+
+static SCOPE: OnceLock<Scope> = OnceLock::new();
+
+/// The networks and domains that functions are allowed to target.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Scope {
+    /// Allowed networks, single addresses are accepted as well.
+    pub cidrs: Vec<String>,
+    /// Allowed domains, including their subdomains.
+    pub domains: Vec<String>,
+    #[serde(skip)]
+    networks: Vec<IpNet>,
+}
+
+impl Scope {
+    pub fn from_path(path: &str) -> anyhow::Result<Self> {
+        let path = shellexpand::full(path)
+            .map_err(|e| anyhow::anyhow!("failed to expand path {}: {}", path, e))?
+            .to_string();
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("error while reading {}: {}", path, e))?;
+
+        Self::from_str(&data).map_err(|e| anyhow::anyhow!("error while parsing {}: {}", path, e))
+    }
+
+    /// A scope of networks and domains mixed in the same list.
+    pub fn from_targets(targets: &[String]) -> anyhow::Result<Self> {
+        let (cidrs, domains) = targets
+            .iter()
+            .cloned()
+            .partition(|target| parse_network(target).is_ok());
+        Self {
+            cidrs,
+            domains,
+            networks: Vec::new(),
+        }
+        .parsed()
+    }
+
+    fn from_str(data: &str) -> anyhow::Result<Self> {
+        let scope: Self = serde_yaml::from_str(data)?;
+        scope.parsed()
+    }
+
+    fn parsed(mut self) -> anyhow::Result<Self> {
+        self.networks = self
+            .cidrs
+            .iter()
+            .map(|cidr| parse_network(cidr))
+            .collect::<anyhow::Result<_>>()?;
+        self.domains = self
+            .domains
+            .iter()
+            .map(|domain| normalize_domain(domain))
+            .collect();
+        Ok(self)
+    }
+
+    /// Check a target, either an address, a network, a hostname or an URL.
+    pub fn check(&self, target: &str) -> anyhow::Result<()> {
+        // what the tools could parse differently than host_of, such as several hosts separated by
+        // spaces or backslashes taken as slashes, is refused before matching
+        let host = host_of(target);
+        if !is_valid_target(target) || !is_valid_host(host) {
+            return Err(anyhow::anyhow!("'{}' is not a valid target", target));
+        }
+
+        // networks must be checked before the host is taken as the part preceding a path
+        let in_scope = if let Ok(network) = target.parse::<IpNet>() {
+            self.networks
+                .iter()
+                .any(|allowed| allowed.contains(&network))
+        } else if let Ok(address) = host.parse::<IpAddr>() {
+            self.networks
+                .iter()
+                .any(|allowed| allowed.contains(&address))
+        } else {
+            let host = normalize_domain(host);
+            self.domains
+                .iter()
+                .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+        };
+
+        if in_scope {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("target {} is out of scope", target))
+        }
+    }
+}
+
+fn parse_network(cidr: &str) -> anyhow::Result<IpNet> {
+    if let Ok(network) = cidr.parse::<IpNet>() {
+        return Ok(network);
+    }
+    cidr.parse::<IpAddr>()
+        .map(IpNet::from)
+        .map_err(|_| anyhow::anyhow!("'{}' is not a valid network", cidr))
+}
+
+fn normalize_domain(domain: &str) -> String {
+    domain
+        .trim()
+        .trim_start_matches("*.")
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
+// only the characters of addresses, networks, hostnames and URLs
+fn is_valid_target(target: &str) -> bool {
+    target
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-._~:/?#[]@!$&'()*+,;=%".contains(c))
+}
+
+fn is_valid_host(host: &str) -> bool {
+    host.parse::<IpAddr>().is_ok()
+        || (!host.is_empty()
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_')))
+}
+
+// extract the host from an URL or a host:port pair
+fn host_of(target: &str) -> &str {
+    let target = target
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(target);
+    let target = target.split(['/', '?', '#']).next().unwrap_or_default();
+    let target = target
+        .rsplit_once('@')
+        .map(|(_, host)| host)
+        .unwrap_or(target);
+
+    if let Some(rest) = target.strip_prefix('[') {
+        // [ipv6]:port
+        rest.split(']').next().unwrap_or_default()
+    } else if target.matches(':').count() == 1 {
+        target.split(':').next().unwrap_or_default()
+    } else {
+        target
+    }
+}
+
+/// Set the engagement scope for this process, calls with targets outside of it are rejected.
+pub(crate) fn set(scope: Scope) {
+    log::info!(
+        "engagement scope: {} networks, {} domains",
+        scope.networks.len(),
+        scope.domains.len()
+    );
+    if SCOPE.set(scope).is_err() {
+        log::warn!("engagement scope already set");
+    }
+}
+
+/// Check the target arguments of a function call against the engagement scope, if set.
+pub(crate) fn check_arguments(
+    function: &FunctionRef,
+    arguments: &BTreeMap<String, String>,
+) -> anyhow::Result<()> {
+    let Some(scope) = SCOPE.get() else {
+        return Ok(());
+    };
+
+    for (arg_name, value) in arguments {
+        let is_target = function
+            .function
+            .parameters
+            .get(arg_name)
+            .is_some_and(|param| param.param_type == TARGET_TYPE);
+
+        // lists of targets are joined with commas
+        if is_target && !value.is_empty() {
+            for target in value.split(',') {
+                scope.check(target).map_err(|e| {
+                    anyhow::anyhow!(
+                        "argument {} of function {}: {}",
+                        arg_name,
+                        &function.name,
+                        e
+                    )
+                })?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_check() {
+        let scope = Scope::from_str(
+            "cidrs:\n  - 10.0.0.0/24\n  - 192.168.1.10\n  - fd00::/8\ndomains:\n  - Example.com\n  - '*.test.org'\n",
+        )
+        .unwrap();
+
+        for target in [
+            "10.0.0.1",
+            "10.0.0.0/28",
+            "192.168.1.10",
+            "fd00::1",
+            "example.com",
+            "www.example.com",
+            "https://api.example.com:8443/v1?x=1",
+            "10.0.0.5:22",
+            "[fd00::1]:443",
+            "admin.test.org",
+        ] {
+            assert!(scope.check(target).is_ok(), "{} should be in scope", target);
+        }
+
+        for target in [
+            "10.0.1.1",
+            "10.0.0.0/16",
+            "192.168.1.11",
+            "notexample.com",
+            "example.com.evil.io",
+            "http://8.8.8.8/",
+            "",
+            // several hosts in one target
+            "8.8.8.8 www.example.com",
+            "www.example.com\t8.8.8.8",
+            "evil.com;example.com",
+            " example.com",
+            // curl connects to evil.com, taking the backslash as a slash
+            "http://evil.com\\@example.com",
+            "http://evil.com\\.example.com",
+            "exämple.com",
+        ] {
+            assert!(
+                scope.check(target).is_err(),
+                "{} should be out of scope",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_scope_invalid_network() {
+        assert!(Scope::from_str("cidrs: [10.0.0.0/33]").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex, OnceLock,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::book::Container;
+
+use super::{repo_digest, ContainerSource};
+
+// refuse the images of containers without a signature
+static REQUIRED: AtomicBool = AtomicBool::new(false);
+
+// signers trusted by the operator, the signatures declared by the pages must be one of them
+static TRUSTED: OnceLock<Vec<Signature>> = OnceLock::new();
+
+// images verified by this process with their signature, and the digest they were verified at
+static VERIFIED: LazyLock<Mutex<HashMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// How the image of a container is signed, checked with cosign before it's used. Either a public
+/// key, or the identity and issuer of the certificate for keyless signatures.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Signature {
+    /// Path or URL of the public key, or a KMS reference.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Identity of the signer, such as the email or workflow url.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// OIDC issuer of the certificate, such as https://token.actions.githubusercontent.com.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+}
+
+impl Signature {
+    /// Returns an error if neither a key nor an identity and an issuer are set.
+    pub fn check(&self) -> anyhow::Result<()> {
+        match (&self.key, &self.identity, &self.issuer) {
+            (Some(_), None, None) | (None, Some(_), Some(_)) => Ok(()),
+            (Some(_), _, _) => Err(anyhow::anyhow!(
+                "signature key and identity are mutually exclusive"
+            )),
+            _ => Err(anyhow::anyhow!(
+                "signature requires either a key or both an identity and an issuer"
+            )),
+        }
+    }
+
+    fn cosign_args(&self, image: &str) -> Vec<String> {
+        let mut args = vec!["verify".to_string()];
+        if let Some(key) = &self.key {
+            args.push(format!("--key={}", key));
+        }
+        if let Some(identity) = &self.identity {
+            args.push(format!("--certificate-identity={}", identity));
+        }
+        if let Some(issuer) = &self.issuer {
+            args.push(format!("--certificate-oidc-issuer={}", issuer));
+        }
+        args.push(image.to_string());
+        args
+    }
+}
+
+/// Set the signers trusted by the operator, read from the trust section of the configuration.
+pub(crate) fn set_trusted(signers: &[Signature]) -> anyhow::Result<()> {
+    for signer in signers {
+        signer.check()?;
+    }
+    if TRUSTED.set(signers.to_vec()).is_err() {
+        log::warn!("trusted image signers already set");
+    }
+    Ok(())
+}
+
+fn is_trusted(signature: &Signature) -> bool {
+    TRUSTED
+        .get()
+        .is_some_and(|signers| signers.contains(signature))
+}
+
+/// Refuse the images of the containers without a signature.
+pub(crate) fn set_required(required: bool) {
+    if required {
+        log::info!("only signed images are allowed");
+    }
+    REQUIRED.store(required, Ordering::Relaxed);
+}
+
+/// Verify the images again on their next use.
+pub(crate) fn forget_verified() {
+    VERIFIED.lock().unwrap().clear();
+}
+
+fn verified_key(image: &str, signature: &Signature) -> anyhow::Result<String> {
+    Ok(format!("{}|{}", image, serde_json::to_string(signature)?))
+}
+
+/// The reference of the image pinned to the digest its signature was verified at, if it was.
+pub(crate) fn verified(container: &Container) -> Option<String> {
+    let signature = container.signature.as_ref()?;
+    let key = verified_key(container.source.image(), signature).ok()?;
+    VERIFIED.lock().unwrap().get(&key).cloned()
+}
+
+/// Verify the signature of the image of the container, if any or if required. The image is
+/// verified at its digest, to be run by it.
+pub(crate) async fn verify(container: &Container) -> anyhow::Result<()> {
+    let image = container.source.image();
+    let Some(signature) = &container.signature else {
+        if REQUIRED.load(Ordering::Relaxed) {
+            return Err(anyhow::anyhow!(
+                "image {} has no signature and only signed images are allowed",
+                image
+            ));
+        }
+        return Ok(());
+    };
+
+    if let ContainerSource::Build(_) = &container.source {
+        return Err(anyhow::anyhow!(
+            "image {} is built locally and can't be verified",
+            image
+        ));
+    }
+    signature.check()?;
+    // the page could declare any key, only the ones of the operator are accepted
+    if !is_trusted(signature) {
+        return Err(anyhow::anyhow!(
+            "signer of image {} is not in the trusted image signers of the configuration",
+            image
+        ));
+    }
+
+    let key = verified_key(image, signature)?;
+    if VERIFIED.lock().unwrap().contains_key(&key) {
+        return Ok(());
+    }
+
+    // the tag could be moved between the verification and the run, so both use the digest
+    let pinned = repo_digest(image).await?;
+
+    log::info!("verifying the signature of {} ...", &pinned);
+    let args = signature.cosign_args(&pinned);
+    let output = Command::new("cosign")
+        .args(&args)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run cosign: {}", e))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "signature of image {} could not be verified: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    VERIFIED.lock().unwrap().insert(key, pinned);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let keyed: Signature = serde_yaml::from_str("key: cosign.pub").unwrap();
+        assert!(keyed.check().is_ok());
+        assert_eq!(
+            keyed.cosign_args("nmap"),
+            vec!["verify", "--key=cosign.pub", "nmap"]
+        );
+
+        let keyless: Signature = serde_yaml::from_str(
+            "identity: https://github.com/org/repo/.github/workflows/release.yml@refs/heads/main\nissuer: https://token.actions.githubusercontent.com",
+        )
+        .unwrap();
+        assert!(keyless.check().is_ok());
+        assert_eq!(keyless.cosign_args("nmap").len(), 4);
+
+        let incomplete: Signature = serde_yaml::from_str("identity: me@example.com").unwrap();
+        assert!(incomplete.check().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_signer() {
+        let container: Container =
+            serde_yaml::from_str("image: nmap\nsignature:\n  key: attacker.pub").unwrap();
+        let err = verify(&container).await.unwrap_err();
+        assert!(err.to_string().contains("not in the trusted image signers"));
+        assert!(verified(&container).is_none());
+    }
+}
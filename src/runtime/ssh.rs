@@ -1,6 +1,117 @@
-use std::{borrow::Cow, path::PathBuf};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use async_ssh2_tokio::{AuthMethod, Client, ServerCheckMethod};
+use tokio::sync::OnceCell;
+
+use super::host_key::HostKeyCheck;
+use crate::book::{Files, Transfer};
+
+// keys looked for in ~/.ssh when none is set, in order of preference
+const DEFAULT_KEYS: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// How to authenticate with the SSH server.
+#[derive(Debug, Default)]
+pub(crate) struct SSHCredentials {
+    /// Private key file, or public key file of an identity held by the ssh-agent. If not set, the
+    /// default keys are used via the ssh-agent if running, or directly otherwise.
+    pub key: Option<String>,
+    pub passphrase: Option<String>,
+    /// Password authentication, prompted for if empty.
+    pub password: Option<String>,
+}
+
+impl SSHCredentials {
+    /// Ask for the password now if it is to be prompted for, to only ask once for many hosts.
+    pub fn prompt_password(&mut self) -> anyhow::Result<()> {
+        if self
+            .password
+            .as_ref()
+            .is_some_and(|password| password.is_empty())
+        {
+            self.password = Some(super::prompt::ask_secret("ssh password: ")?);
+        }
+        Ok(())
+    }
+
+    fn auth_method(&self) -> anyhow::Result<AuthMethod> {
+        if let Some(password) = &self.password {
+            let password = if password.is_empty() {
+                super::prompt::ask_secret("ssh password: ")?
+            } else {
+                password.clone()
+            };
+            return Ok(AuthMethod::with_password(&password));
+        }
+
+        let agent = cfg!(not(target_os = "windows")) && std::env::var_os("SSH_AUTH_SOCK").is_some();
+
+        let key = match &self.key {
+            Some(key) => {
+                let key = PathBuf::from(shellexpand::full(key)?.to_string());
+                if !key.exists() {
+                    return Err(anyhow::anyhow!("key file {} does not exist", key.display()));
+                }
+                key.canonicalize()?
+            }
+            None => {
+                let ssh_dir = PathBuf::from(shellexpand::full("~/.ssh")?.to_string());
+                let key = DEFAULT_KEYS
+                    .iter()
+                    .map(|name| ssh_dir.join(name))
+                    .find(|key| {
+                        if agent {
+                            key.with_extension("pub").exists()
+                        } else {
+                            key.exists()
+                        }
+                    });
+
+                match key {
+                    Some(key) if agent => key.with_extension("pub"),
+                    Some(key) => key,
+                    None => {
+                        return Err(anyhow::anyhow!(
+                            "no SSH key found in {}, set --ssh-key or --ssh-password",
+                            ssh_dir.display()
+                        ))
+                    }
+                }
+            }
+        };
+
+        // public keys are only usable through the agent holding their private key
+        if key.extension().is_some_and(|ext| ext == "pub") {
+            if !agent {
+                return Err(anyhow::anyhow!(
+                    "{} is a public key, which requires a running ssh-agent (SSH_AUTH_SOCK)",
+                    key.display()
+                ));
+            }
+
+            log::debug!("authenticating via ssh-agent with {}", key.display());
+            return agent_auth(&key);
+        }
+
+        log::debug!("authenticating with key file {}", key.display());
+        Ok(AuthMethod::with_key_file(&key, self.passphrase.as_deref()))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn agent_auth(public_key: &Path) -> anyhow::Result<AuthMethod> {
+    Ok(AuthMethod::with_public_key_file(public_key))
+}
+
+#[cfg(target_os = "windows")]
+fn agent_auth(_public_key: &Path) -> anyhow::Result<AuthMethod> {
+    Err(anyhow::anyhow!(
+        "ssh-agent authentication is not supported on windows"
+    ))
+}
 
 #[derive(Debug, Clone)]
 pub struct SSHConnection {
@@ -8,10 +119,13 @@ pub struct SSHConnection {
     port: u16,
     user: String,
     auth_method: AuthMethod,
+    host_key_check: HostKeyCheck,
+    // verified once and then pinned for the following connections
+    host_key: Arc<OnceCell<Option<String>>>,
 }
 
 impl SSHConnection {
-    pub fn from_str(s: &str, public_key: &str, passphrase: Option<String>) -> anyhow::Result<Self> {
+    pub fn from_str(s: &str, credentials: &SSHCredentials) -> anyhow::Result<Self> {
         let mut user = std::env::var("USER").unwrap_or_else(|_| "root".to_string());
         let host;
         let mut port = 22;
@@ -51,32 +165,41 @@ impl SSHConnection {
             _ => return Err(anyhow::anyhow!("invalid SSH connection string format")),
         }
 
-        let public_key = shellexpand::full(public_key)?.to_string();
-        let public_key = PathBuf::from(public_key);
-        if !public_key.exists() {
-            return Err(anyhow::anyhow!(
-                "public key file {} does not exist",
-                public_key.display()
-            ));
-        }
-        let public_key = public_key.canonicalize()?.to_string_lossy().to_string();
-
-        let auth_method = AuthMethod::with_key_file(&public_key, passphrase.as_deref());
+        let auth_method = credentials.auth_method()?;
 
         Ok(Self {
             host,
             port,
             user,
             auth_method,
+            host_key_check: HostKeyCheck::AcceptNew(PathBuf::from(
+                shellexpand::full("~/.ssh/known_hosts")?.to_string(),
+            )),
+            host_key: Arc::new(OnceCell::new()),
         })
     }
 
+    /// Verify the key of the server with the given method instead of accept-new.
+    pub fn with_host_key_check(mut self, host_key_check: HostKeyCheck) -> Self {
+        self.host_key_check = host_key_check;
+        self
+    }
+
     async fn client(&self) -> anyhow::Result<Client> {
+        let host_key = self
+            .host_key
+            .get_or_try_init(|| self.host_key_check.verify(&self.host, self.port))
+            .await?;
+        let server_check = match host_key {
+            Some(key) => ServerCheckMethod::with_public_key(key),
+            None => ServerCheckMethod::NoCheck,
+        };
+
         Client::connect(
             (self.host.as_str(), self.port),
             self.user.as_str(),
             self.auth_method.clone(),
-            ServerCheckMethod::NoCheck,
+            server_check,
         )
         .await
         .map_err(|e| anyhow::anyhow!("failed to connect to SSH server: {:?}", e))
@@ -98,19 +221,47 @@ impl SSHConnection {
         command
     }
 
+    /// Execute the command, staging the files on the host before and collecting them after.
     pub(crate) async fn execute(
         &self,
         with_sudo: bool,
         app: &str,
         args: &Vec<String>,
+        files: &Files,
     ) -> anyhow::Result<String> {
+        let client = self.client().await?;
+
+        for transfer in &files.upload {
+            self.upload(&client, transfer).await?;
+        }
+
         let command_line = Self::create_command_line(with_sudo, app, args);
-        let result = self.client().await?.execute(&command_line).await?;
+        let result = client.execute(&command_line).await?;
+
+        // artifacts are collected even if the command failed, they might tell why
+        for transfer in &files.download {
+            log::info!(
+                "downloading {}:{} to {}",
+                self,
+                &transfer.remote,
+                &transfer.local
+            );
+            if let Err(e) = client
+                .download_file(transfer.remote.as_str(), &transfer.local)
+                .await
+            {
+                log::warn!("could not download {}: {:?}", &transfer.remote, e);
+            }
+        }
 
         let mut parts = vec![];
 
         if result.exit_status != 0 {
-            parts.push(format!("EXIT CODE: {}", &result.exit_status));
+            parts.push(format!(
+                "{}{}",
+                super::EXIT_CODE_PREFIX,
+                &result.exit_status
+            ));
         }
 
         if !result.stdout.is_empty() {
@@ -128,6 +279,42 @@ impl SSHConnection {
         Ok(parts.join("\n"))
     }
 
+    async fn upload(&self, client: &Client, transfer: &Transfer) -> anyhow::Result<()> {
+        if !Path::new(&transfer.local).is_file() {
+            return Err(anyhow::anyhow!("{} is not a file", &transfer.local));
+        }
+
+        // sftp does not create the parent directories
+        if let Some(parent) = Path::new(&transfer.remote)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            let parent = parent.to_string_lossy();
+            client
+                .execute(&format!("mkdir -p {}", shell_escape::escape(parent)))
+                .await?;
+        }
+
+        log::info!(
+            "uploading {} to {}:{}",
+            &transfer.local,
+            self,
+            &transfer.remote
+        );
+        client
+            .upload_file(&transfer.local, transfer.remote.as_str())
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "could not upload {} to {}:{}: {:?}",
+                    &transfer.local,
+                    self,
+                    &transfer.remote,
+                    e
+                )
+            })
+    }
+
     pub(crate) async fn test_connection(&self) -> anyhow::Result<()> {
         log::info!("testing ssh connection to {}:{} ...", self.host, self.port);
         let result = self.client().await?.execute("echo robopages").await?;
@@ -141,10 +328,11 @@ impl SSHConnection {
     }
 
     pub(crate) async fn app_in_path(&self, app: &str) -> anyhow::Result<bool> {
+        // windows hosts have where instead of which, both cmd and powershell understand ||
         let result = self
             .client()
             .await?
-            .execute(&format!("which {}", app))
+            .execute(&format!("which {} || where {}", app, app))
             .await?;
 
         Ok(result.exit_status == 0)
@@ -161,9 +349,16 @@ impl std::fmt::Display for SSHConnection {
 mod tests {
     use super::*;
 
+    fn key_file(path: &str) -> SSHCredentials {
+        SSHCredentials {
+            key: Some(path.to_string()),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_from_str_host_only() {
-        let conn = SSHConnection::from_str("example.com", "/dev/null", None).unwrap();
+        let conn = SSHConnection::from_str("example.com", &key_file("/dev/null")).unwrap();
         assert_eq!(conn.host, "example.com");
         assert_eq!(conn.port, 22);
         assert_eq!(
@@ -174,7 +369,7 @@ mod tests {
 
     #[test]
     fn test_from_str_host_and_port() {
-        let conn = SSHConnection::from_str("example.com:2222", "/dev/null", None).unwrap();
+        let conn = SSHConnection::from_str("example.com:2222", &key_file("/dev/null")).unwrap();
         assert_eq!(conn.host, "example.com");
         assert_eq!(conn.port, 2222);
         assert_eq!(
@@ -185,7 +380,7 @@ mod tests {
 
     #[test]
     fn test_from_str_user_and_host() {
-        let conn = SSHConnection::from_str("testuser@example.com", "/dev/null", None).unwrap();
+        let conn = SSHConnection::from_str("testuser@example.com", &key_file("/dev/null")).unwrap();
         assert_eq!(conn.host, "example.com");
         assert_eq!(conn.port, 22);
         assert_eq!(conn.user, "testuser");
@@ -193,7 +388,8 @@ mod tests {
 
     #[test]
     fn test_from_str_full() {
-        let conn = SSHConnection::from_str("testuser@example.com:2222", "/dev/null", None).unwrap();
+        let conn =
+            SSHConnection::from_str("testuser@example.com:2222", &key_file("/dev/null")).unwrap();
         assert_eq!(conn.host, "example.com");
         assert_eq!(conn.port, 2222);
         assert_eq!(conn.user, "testuser");
@@ -201,22 +397,41 @@ mod tests {
 
     #[test]
     fn test_from_str_empty() {
-        assert!(SSHConnection::from_str("", "/dev/null", None).is_err());
+        assert!(SSHConnection::from_str("", &key_file("/dev/null")).is_err());
     }
 
     #[test]
     fn test_from_str_invalid_port() {
-        assert!(SSHConnection::from_str("example.com:invalid", "/dev/null", None).is_err());
+        assert!(SSHConnection::from_str("example.com:invalid", &key_file("/dev/null")).is_err());
     }
 
     #[test]
     fn test_from_str_invalid_format() {
-        assert!(SSHConnection::from_str("user@host@extra", "/dev/null", None).is_err());
-        assert!(SSHConnection::from_str("host:port:extra", "/dev/null", None).is_err());
+        assert!(SSHConnection::from_str("user@host@extra", &key_file("/dev/null")).is_err());
+        assert!(SSHConnection::from_str("host:port:extra", &key_file("/dev/null")).is_err());
+    }
+
+    #[test]
+    fn test_auth_method() {
+        let password = SSHCredentials {
+            password: Some("s3cr3t".to_string()),
+            ..key_file("/nonexistent/key/path")
+        };
+        assert!(matches!(
+            password.auth_method().unwrap(),
+            AuthMethod::Password(_)
+        ));
+
+        assert!(matches!(
+            key_file("/dev/null").auth_method().unwrap(),
+            AuthMethod::PrivateKeyFile { .. }
+        ));
     }
 
     #[test]
     fn test_from_str_nonexistent_key() {
-        assert!(SSHConnection::from_str("example.com", "/nonexistent/key/path", None).is_err());
+        assert!(
+            SSHConnection::from_str("example.com", &key_file("/nonexistent/key/path")).is_err()
+        );
     }
 }
@@ -0,0 +1,266 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+use crate::book::Priority;
+
+/// Snapshot of the scheduler counters.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct QueueStats {
+    pub active: usize,
+    pub queued: usize,
+    pub rejected: usize,
+}
+
+/// The workers executing the calls of a server or of a command. When all of them are busy, calls
+/// wait for one by priority, and in order of arrival for the same priority.
+pub(crate) struct Workers {
+    permits: Arc<Semaphore>,
+    size: Mutex<usize>,
+    active: AtomicUsize,
+    // number of calls waiting for a worker, for each priority
+    waiting: [AtomicUsize; 3],
+    // only the first waiting call of each priority competes for the workers
+    lanes: [Semaphore; 3],
+    rejected: AtomicUsize,
+    // notified when calls start or stop waiting
+    changed: Notify,
+}
+
+/// A worker taken by a call, released when dropped.
+pub(crate) struct Worker {
+    _permit: OwnedSemaphorePermit,
+    workers: Arc<Workers>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.workers.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// a call waiting for a worker
+struct Waiting<'a> {
+    workers: &'a Workers,
+    priority: Priority,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.workers.waiting[self.priority as usize].fetch_sub(1, Ordering::Relaxed);
+        self.workers.changed.notify_waiters();
+    }
+}
+
+impl Workers {
+    pub fn new(size: usize) -> Arc<Self> {
+        Arc::new(Self {
+            permits: Arc::new(Semaphore::new(size)),
+            size: Mutex::new(size),
+            active: AtomicUsize::new(0),
+            waiting: Default::default(),
+            lanes: std::array::from_fn(|_| Semaphore::new(1)),
+            rejected: AtomicUsize::new(0),
+            changed: Notify::new(),
+        })
+    }
+
+    /// Maximum number of calls running at the same time.
+    pub fn size(&self) -> usize {
+        *self.size.lock().unwrap()
+    }
+
+    /// Change the number of workers, calls already running keep theirs until they complete.
+    pub fn set_size(&self, size: usize) {
+        let mut current = self.size.lock().unwrap();
+        if size > *current {
+            self.permits.add_permits(size - *current);
+        } else if size < *current {
+            let excess = *current - size;
+            let forgotten = self.permits.forget_permits(excess);
+            if forgotten < excess {
+                // the busy workers are removed once released
+                let permits = self.permits.clone();
+                tokio::spawn(async move {
+                    if let Ok(permit) = permits
+                        .acquire_many_owned((excess - forgotten) as u32)
+                        .await
+                    {
+                        permit.forget();
+                    }
+                });
+            }
+        }
+        *current = size;
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            active: self.active.load(Ordering::Relaxed),
+            queued: self
+                .waiting
+                .iter()
+                .map(|waiting| waiting.load(Ordering::Relaxed))
+                .sum(),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns true if the given number of calls can be either executed or queued without
+    /// exceeding the maximum queue size, otherwise counts them as rejected.
+    pub fn can_accept(&self, calls: usize, max_queued_tasks: usize) -> bool {
+        let stats = self.stats();
+        let available = self.size().saturating_sub(stats.active + stats.queued);
+        let to_queue = calls.saturating_sub(available);
+
+        if to_queue > 0 && stats.queued + to_queue > max_queued_tasks {
+            self.rejected.fetch_add(calls, Ordering::Relaxed);
+            false
+        } else {
+            true
+        }
+    }
+
+    fn higher_priority_waiting(&self, priority: Priority) -> bool {
+        Priority::all()
+            .iter()
+            .filter(|other| **other > priority)
+            .any(|other| self.waiting[*other as usize].load(Ordering::Relaxed) > 0)
+    }
+
+    fn take(self: &Arc<Self>, permit: OwnedSemaphorePermit) -> Worker {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        Worker {
+            _permit: permit,
+            workers: self.clone(),
+        }
+    }
+
+    /// Waits for a worker, to be kept until the call completes.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> Worker {
+        if self.stats().queued == 0 {
+            if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+                return self.take(permit);
+            }
+        }
+
+        self.waiting[priority as usize].fetch_add(1, Ordering::Relaxed);
+        self.changed.notify_waiters();
+        let _waiting = Waiting {
+            workers: self,
+            priority,
+        };
+
+        let _lane = self.lanes[priority as usize]
+            .acquire()
+            .await
+            .expect("lanes are never closed");
+
+        loop {
+            let notified = self.changed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            // lower priority calls yield to the higher priority ones
+            if self.higher_priority_waiting(priority) {
+                log::debug!("waiting for the higher priority calls");
+                notified.await;
+                continue;
+            }
+
+            tokio::select! {
+                permit = self.permits.clone().acquire_owned() => {
+                    return self.take(permit.expect("workers are never closed"));
+                }
+                // a higher priority call might have started waiting meanwhile
+                _ = &mut notified => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acquire_by_priority() {
+        let workers = Workers::new(1);
+        let busy = workers.acquire(Priority::Normal).await;
+        assert_eq!(workers.stats().active, 1);
+        assert!(!workers.higher_priority_waiting(Priority::High));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut waiting = vec![];
+        for (name, priority) in [
+            ("low", Priority::Low),
+            ("normal 1", Priority::Normal),
+            ("high", Priority::High),
+            ("normal 2", Priority::Normal),
+        ] {
+            let workers = workers.clone();
+            let tx = tx.clone();
+            waiting.push(tokio::spawn(async move {
+                let _worker = workers.acquire(priority).await;
+                tx.send(name).unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }));
+            // keep the order of arrival
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(workers.stats().queued, 4);
+
+        drop(busy);
+        for task in waiting {
+            task.await.unwrap();
+        }
+
+        let mut order = vec![];
+        while let Ok(name) = rx.try_recv() {
+            order.push(name);
+        }
+        assert_eq!(order, vec!["high", "normal 1", "normal 2", "low"]);
+        assert_eq!(workers.stats().active, 0);
+        assert_eq!(workers.stats().queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_set_size() {
+        let workers = Workers::new(1);
+        let first = workers.acquire(Priority::Normal).await;
+
+        workers.set_size(2);
+        let second =
+            tokio::time::timeout(Duration::from_millis(50), workers.acquire(Priority::Normal))
+                .await
+                .unwrap();
+
+        workers.set_size(1);
+        drop(first);
+        drop(second);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let _only = workers.acquire(Priority::Normal).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), workers.acquire(Priority::Normal))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_can_accept() {
+        let workers = Workers::new(usize::MAX >> 4);
+        assert!(workers.can_accept(1, 0));
+
+        let workers = Workers::new(0);
+        assert!(!workers.can_accept(1, 0));
+        assert_eq!(workers.stats().rejected, 1);
+        assert!(workers.can_accept(1, 1));
+    }
+}